@@ -0,0 +1,64 @@
+//! Compares polling through the wrappers with polling the stream itself. The wrappers' layout
+//! guarantee, one word of overhead on top of the closure, is asserted at compile time in the
+//! crate itself.
+
+use std::{
+    hint::black_box,
+    task::{Context, Poll, Waker},
+};
+
+use criterion::{criterion_group, criterion_main, BatchSize, Criterion};
+use drop_stream::DropStreamExt;
+use futures::{stream, Stream};
+
+const ITEMS: u64 = 10_000;
+
+/// Polls `stream` to completion with a waker that does nothing, returning the sum of its items.
+fn drain(stream: impl Stream<Item = u64>) -> u64 {
+    let mut stream = Box::pin(stream);
+    let mut context = Context::from_waker(Waker::noop());
+    let mut sum = 0;
+
+    while let Poll::Ready(Some(item)) = stream.as_mut().poll_next(&mut context) {
+        sum += black_box(item);
+    }
+
+    sum
+}
+
+fn source() -> impl Stream<Item = u64> {
+    stream::iter(0..black_box(ITEMS))
+}
+
+fn poll(c: &mut Criterion) {
+    let mut group = c.benchmark_group("poll");
+
+    group.bench_function("unwrapped", |b| b.iter(|| drain(black_box(source()))));
+    group.bench_function("on_drop", |b| {
+        b.iter(|| drain(black_box(source().on_drop(|| {}))))
+    });
+    group.bench_function("on_drop_ctx", |b| {
+        b.iter(|| {
+            drain(black_box(
+                source().on_drop_ctx(|context| drop(black_box(context))),
+            ))
+        })
+    });
+    group.finish();
+}
+
+fn create_and_drop(c: &mut Criterion) {
+    let mut group = c.benchmark_group("create_and_drop");
+
+    group.bench_function("unwrapped", |b| {
+        b.iter_batched(source, drop, BatchSize::SmallInput)
+    });
+    group.bench_function("on_drop", |b| {
+        b.iter_batched(|| source().on_drop(|| {}), drop, BatchSize::SmallInput)
+    });
+
+    group.finish();
+}
+
+criterion_group!(benches, poll, create_and_drop);
+criterion_main!(benches);
@@ -0,0 +1,192 @@
+use futures_core::Stream;
+use pin_project::pin_project;
+use std::{
+    pin::Pin,
+    sync::Arc,
+    task::{Context, Poll},
+};
+
+use crate::{dropper::ReasonDropper, signal::Signal, DropReason};
+
+/// Wraps a stream so that it can be aborted through the returned [`AbortHandle`], calling
+/// `dropper` with the reason once the wrapper is dropped.
+///
+/// This combines `futures::stream::abortable` with a drop closure, without the ambiguity of
+/// composing the two: the closure runs exactly once, on drop, with [`DropReason::Stopped`] if the
+/// stream was aborted before it finished, [`DropReason::Completed`] if it finished, and
+/// [`DropReason::Cancelled`] if the consumer dropped it first. An aborted stream yields `None` on
+/// its next poll, and any task waiting on it is woken.
+///
+/// Example
+/// ```
+/// use futures::{executor::block_on_stream, stream};
+/// use drop_stream::{abortable_on_drop, DropReason};
+///
+/// let mut reason = None;
+/// let reason_ref = &mut reason;
+/// let (stream, handle) = abortable_on_drop(stream::repeat(true), move |r| *reason_ref = Some(r));
+///
+/// handle.abort();
+/// assert_eq!(block_on_stream(stream).count(), 0);
+/// assert_eq!(reason, Some(DropReason::Stopped));
+/// ```
+pub fn abortable_on_drop<S: Stream, U: FnOnce(DropReason)>(
+    stream: S,
+    dropper: U,
+) -> (AbortableDropStream<S, U>, AbortHandle) {
+    let signal = Arc::new(Signal::default());
+
+    let stream = AbortableDropStream {
+        dropper: AbortDropper {
+            dropper: ReasonDropper::new(dropper),
+            signal: signal.clone(),
+        },
+        stream,
+    };
+
+    (stream, AbortHandle { signal })
+}
+
+/// Reports [`DropReason::Stopped`] instead of [`DropReason::Cancelled`] if the stream was aborted.
+struct AbortDropper<U: FnOnce(DropReason)> {
+    dropper: ReasonDropper<U>,
+    signal: Arc<Signal>,
+}
+
+impl<U: FnOnce(DropReason)> Drop for AbortDropper<U> {
+    fn drop(&mut self) {
+        // Runs before the `ReasonDropper` field is dropped, which calls the closure.
+        if self.dropper.reason == DropReason::Cancelled && self.signal.is_fired() {
+            self.dropper.reason = DropReason::Stopped;
+        }
+    }
+}
+
+/// A stream that ends once its [`AbortHandle`] is used. Created by [`abortable_on_drop`].
+#[pin_project]
+pub struct AbortableDropStream<S: Stream, U: FnOnce(DropReason)> {
+    // Declared before the stream so the closure runs before the inner stream is dropped.
+    dropper: AbortDropper<U>,
+    #[pin]
+    stream: S,
+}
+
+impl<S: Stream, U: FnOnce(DropReason)> AbortableDropStream<S, U> {
+    /// Returns true if the stream has been aborted.
+    pub fn is_aborted(&self) -> bool {
+        self.dropper.signal.is_fired()
+    }
+}
+
+impl<S: Stream, U: FnOnce(DropReason)> Stream for AbortableDropStream<S, U> {
+    type Item = S::Item;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let this = self.project();
+
+        if this.dropper.signal.poll_fired(cx).is_ready() {
+            return Poll::Ready(None);
+        }
+
+        let poll = this.stream.poll_next(cx);
+        if let Poll::Ready(None) = poll {
+            this.dropper.dropper.reason = DropReason::Completed;
+        }
+
+        poll
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        if self.is_aborted() {
+            return (0, Some(0));
+        }
+
+        // The stream may be aborted at any point.
+        (0, self.stream.size_hint().1)
+    }
+}
+
+/// Aborts the [`AbortableDropStream`] it was created with. Unlike a
+/// [`RemoteHandle`](crate::RemoteHandle), dropping it does nothing, and it can be cloned.
+#[derive(Debug, Clone)]
+pub struct AbortHandle {
+    signal: Arc<Signal>,
+}
+
+impl AbortHandle {
+    /// Ends the stream on its next poll.
+    pub fn abort(&self) {
+        self.signal.fire();
+    }
+
+    /// Returns true if the stream has been aborted.
+    pub fn is_aborted(&self) -> bool {
+        self.signal.is_fired()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::task::Poll;
+
+    use crate::{abortable_on_drop, DropReason};
+    use futures::{executor::block_on_stream, stream::iter, Stream};
+
+    #[test]
+    fn reason_tells_abort_from_consumer_drop() {
+        let mut reasons = Vec::new();
+
+        for abort in [true, false] {
+            let reasons_ref = &mut reasons;
+            let (drop_stream, handle) = abortable_on_drop(iter([1, 2]), move |r| {
+                reasons_ref.push(r);
+            });
+            let mut drop_stream = Box::pin(drop_stream);
+
+            let waker = futures::task::noop_waker();
+            let mut context = futures::task::Context::from_waker(&waker);
+            assert_eq!(
+                drop_stream.as_mut().poll_next(&mut context),
+                Poll::Ready(Some(1))
+            );
+
+            if abort {
+                handle.abort();
+            }
+        }
+
+        let reasons_ref = &mut reasons;
+        let (drop_stream, handle) = abortable_on_drop(iter([1, 2]), move |r| reasons_ref.push(r));
+        let mut drop_stream = block_on_stream(drop_stream);
+        assert_eq!(drop_stream.by_ref().count(), 2);
+        // Aborting after the stream finished doesn't change how it ended.
+        handle.abort();
+        drop(drop_stream);
+
+        assert_eq!(
+            reasons,
+            [
+                DropReason::Stopped,
+                DropReason::Cancelled,
+                DropReason::Completed
+            ]
+        );
+    }
+
+    #[test]
+    fn abort_wakes_waiting_task() {
+        let (drop_stream, handle) = abortable_on_drop(futures::stream::pending::<()>(), |_| {});
+        let mut drop_stream = Box::pin(drop_stream);
+
+        let (waker, count) = futures_test::task::new_count_waker();
+        let mut context = futures::task::Context::from_waker(&waker);
+        assert_eq!(drop_stream.as_mut().poll_next(&mut context), Poll::Pending);
+
+        handle.abort();
+        assert_eq!(count, 1);
+        assert_eq!(
+            drop_stream.as_mut().poll_next(&mut context),
+            Poll::Ready(None)
+        );
+    }
+}
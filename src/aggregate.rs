@@ -0,0 +1,217 @@
+use std::{
+    collections::HashMap,
+    fmt,
+    sync::{Arc, Mutex, MutexGuard},
+    time::Duration,
+};
+
+use crate::{DropContext, DropEvent, DropEventHandler, StreamObserver};
+
+#[derive(Debug, Default)]
+struct Group {
+    cancelled: usize,
+    lifetimes: Vec<Duration>,
+}
+
+/// Rolls up drops by the value of one label, for periodic summaries without a metrics stack.
+///
+/// Drops are recorded by registering the aggregator with
+/// [`register_drop_handler`](crate::register_drop_handler), or by handing it to
+/// [`observe`](crate::DropStreamExt::observe) for a single stream. Drops without the label are
+/// grouped under an empty value. Every lifetime is kept until [`take`](DropAggregator::take) is
+/// called, so long-running services should take a summary periodically rather than only
+/// [`snapshot`](DropAggregator::snapshot) one.
+///
+/// Example
+/// ```
+/// use drop_stream::{DropAggregator, DropStreamExt};
+///
+/// let aggregator = DropAggregator::by_label("route");
+/// for _ in 0..3 {
+///     let stream = futures::stream::repeat(true)
+///         .observe(aggregator.clone())
+///         .label("route", "/events");
+///     drop(stream);
+/// }
+///
+/// let summary = &aggregator.take()[0];
+/// assert_eq!(summary.label(), "/events");
+/// assert_eq!(summary.count(), 3);
+/// assert_eq!(summary.cancel_ratio(), 1.0);
+/// ```
+#[derive(Clone)]
+pub struct DropAggregator {
+    key: &'static str,
+    groups: Arc<Mutex<HashMap<String, Group>>>,
+}
+
+impl DropAggregator {
+    /// Creates an aggregator that groups drops by the value of the label `key`.
+    pub fn by_label(key: &'static str) -> Self {
+        Self {
+            key,
+            groups: Arc::default(),
+        }
+    }
+
+    fn lock(&self) -> MutexGuard<'_, HashMap<String, Group>> {
+        self.groups.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// Adds a drop to the summary of its group.
+    pub fn record(&self, context: &DropContext) {
+        let label = context.label(self.key).unwrap_or_default();
+
+        let mut groups = self.lock();
+        let group = match groups.get_mut(label) {
+            Some(group) => group,
+            None => groups.entry(label.to_owned()).or_default(),
+        };
+
+        if context.reason().is_cancelled() {
+            group.cancelled += 1;
+        }
+        group.lifetimes.push(context.lifetime());
+    }
+
+    /// Returns a summary of every group recorded so far, sorted by label.
+    pub fn snapshot(&self) -> Vec<LabelSummary> {
+        summarize(self.lock().iter_mut())
+    }
+
+    /// Like [`snapshot`](Self::snapshot), but also clears the recorded drops, so the next summary
+    /// only covers the drops after this one.
+    pub fn take(&self) -> Vec<LabelSummary> {
+        summarize(std::mem::take(&mut *self.lock()).iter_mut())
+    }
+}
+
+fn summarize<'a>(groups: impl Iterator<Item = (&'a String, &'a mut Group)>) -> Vec<LabelSummary> {
+    let mut summaries: Vec<_> = groups
+        .map(|(label, group)| {
+            group.lifetimes.sort_unstable();
+            LabelSummary {
+                label: label.clone(),
+                count: group.lifetimes.len(),
+                cancelled: group.cancelled,
+                p50: percentile(&group.lifetimes, 50),
+                p99: percentile(&group.lifetimes, 99),
+            }
+        })
+        .collect();
+
+    summaries.sort_unstable_by(|a, b| a.label.cmp(&b.label));
+    summaries
+}
+
+/// The nearest-rank percentile of sorted `values`.
+fn percentile(values: &[Duration], percent: usize) -> Duration {
+    let rank = (values.len() * percent).div_ceil(100).max(1);
+    values.get(rank - 1).copied().unwrap_or_default()
+}
+
+impl DropEventHandler for DropAggregator {
+    fn handle(&self, event: DropEvent) {
+        self.record(event.context())
+    }
+}
+
+impl<T> StreamObserver<T> for DropAggregator {
+    fn on_drop(&mut self, context: &DropContext) {
+        self.record(context)
+    }
+}
+
+impl fmt::Debug for DropAggregator {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("DropAggregator")
+            .field("key", &self.key)
+            .field("groups", &self.lock().len())
+            .finish()
+    }
+}
+
+/// The drops of one label value recorded by a [`DropAggregator`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LabelSummary {
+    label: String,
+    count: usize,
+    cancelled: usize,
+    p50: Duration,
+    p99: Duration,
+}
+
+impl LabelSummary {
+    /// The value of the label the drops were grouped by.
+    pub fn label(&self) -> &str {
+        &self.label
+    }
+
+    /// The number of drops.
+    pub fn count(&self) -> usize {
+        self.count
+    }
+
+    /// The number of drops before the stream finished.
+    pub fn cancelled(&self) -> usize {
+        self.cancelled
+    }
+
+    /// The share of drops before the stream finished, between 0 and 1.
+    pub fn cancel_ratio(&self) -> f64 {
+        self.cancelled as f64 / self.count as f64
+    }
+
+    /// The median lifetime.
+    pub fn p50(&self) -> Duration {
+        self.p50
+    }
+
+    /// The 99th percentile lifetime.
+    pub fn p99(&self) -> Duration {
+        self.p99
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::percentile;
+    use crate::{DropAggregator, DropStreamExt};
+    use futures::stream::{iter, repeat};
+
+    #[test]
+    fn groups_by_label_and_take_resets() {
+        let aggregator = DropAggregator::by_label("route");
+
+        let observed = |route| {
+            repeat(true)
+                .observe(aggregator.clone())
+                .label("route", route)
+        };
+        drop(observed("/a"));
+        drop(observed("/b"));
+        drop(observed("/b"));
+        let completed = iter([1]).observe(aggregator.clone());
+        assert_eq!(futures::executor::block_on_stream(completed).count(), 1);
+
+        let summaries = aggregator.take();
+        let counts: Vec<_> = summaries
+            .iter()
+            .map(|s| (s.label(), s.count(), s.cancelled()))
+            .collect();
+        assert_eq!(counts, [("", 1, 0), ("/a", 1, 1), ("/b", 2, 2)]);
+
+        assert!(aggregator.snapshot().is_empty());
+    }
+
+    #[test]
+    fn nearest_rank_percentiles() {
+        let values: Vec<_> = (1..=100).map(Duration::from_millis).collect();
+
+        assert_eq!(percentile(&values, 50), Duration::from_millis(50));
+        assert_eq!(percentile(&values, 99), Duration::from_millis(99));
+        assert_eq!(percentile(&values[..1], 99), Duration::from_millis(1));
+    }
+}
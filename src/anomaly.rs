@@ -0,0 +1,46 @@
+use std::sync::{Arc, RwLock};
+
+use crate::DropContext;
+
+type AnomalyHandler = Arc<dyn Fn(&DropContext) + Send + Sync>;
+
+static ANOMALY_HANDLER: RwLock<Option<AnomalyHandler>> = RwLock::new(None);
+
+/// Sets the crate-level handler for streams that were cancelled before they completed despite
+/// being expected to, replacing the previous one.
+///
+/// Streams are marked with [`DropStreamBuilder::expect_completion`](crate::DropStreamBuilder::expect_completion).
+/// This is meant for error-reporting backends, which would otherwise have to filter every teardown
+/// for the few drops that are actually unexpected. By default anomalies are ignored.
+///
+/// Example
+/// ```
+/// use std::sync::{Arc, Mutex};
+/// use drop_stream::{set_anomaly_handler, DropStream};
+///
+/// let reported = Arc::new(Mutex::new(Vec::new()));
+/// let reported_ref = reported.clone();
+/// set_anomaly_handler(move |context| reported_ref.lock().unwrap().push(context.items()));
+///
+/// let stream = DropStream::builder(futures::stream::repeat(true))
+///     .expect_completion()
+///     .build();
+///
+/// drop(stream);
+/// assert_eq!(*reported.lock().unwrap(), [0]);
+/// ```
+pub fn set_anomaly_handler(handler: impl Fn(&DropContext) + Send + Sync + 'static) {
+    *ANOMALY_HANDLER.write().unwrap_or_else(|e| e.into_inner()) = Some(Arc::new(handler));
+}
+
+/// Reports `context` to the handler set with [`set_anomaly_handler`].
+pub fn report_anomaly(context: &DropContext) {
+    // Cloned out so the handler can set another handler, or drop streams of its own.
+    let handler = ANOMALY_HANDLER
+        .read()
+        .unwrap_or_else(|e| e.into_inner())
+        .clone();
+    if let Some(handler) = handler {
+        handler(context)
+    }
+}
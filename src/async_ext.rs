@@ -0,0 +1,140 @@
+use futures_core::{Future, Stream};
+
+use crate::{ContextDropStream, DropContext, DropStream, Spawn};
+
+/// The async counterpart of [`DropStreamExt`](crate::DropStreamExt), for closures that return the
+/// cleanup future instead of running the cleanup themselves.
+///
+/// The closure is still called synchronously once the stream is dropped, and the future it
+/// returns is handed to the spawner, so cleanup such as a goodbye call to a remote peer can await.
+/// Any `Fn(BoxFuture)` closure is a spawner, and with the `tokio` feature enabled so is a
+/// [`tokio::runtime::Handle`]. For cleanup that can fail and should be reported, see
+/// [`on_drop_reaped`](crate::DropStreamExt::on_drop_reaped), and for cleanup that must not run
+/// for longer than a budget, see [`on_drop_teardown`](crate::DropStreamExt::on_drop_teardown).
+///
+/// Example
+/// ```
+/// use std::sync::{Arc, Mutex};
+/// use drop_stream::{BoxFuture, OnDropAsyncExt};
+///
+/// let tasks = Arc::new(Mutex::new(Vec::new()));
+/// let spawner = {
+///     let tasks = tasks.clone();
+///     move |future: BoxFuture| tasks.lock().unwrap().push(future)
+/// };
+///
+/// let (sender, receiver) = futures::channel::oneshot::channel();
+/// let stream = futures::stream::repeat(true).on_drop_async(spawner, || async move {
+///     // Close a remote session...
+///     sender.send("closed").unwrap();
+/// });
+///
+/// drop(stream);
+/// for task in tasks.lock().unwrap().drain(..) {
+///     futures::executor::block_on(task);
+/// }
+/// assert_eq!(futures::executor::block_on(receiver), Ok("closed"));
+/// ```
+pub trait OnDropAsyncExt: Stream + Sized {
+    /// Spawns the future returned by the closure once the stream is dropped.
+    fn on_drop_async<Sp, U, F>(
+        self,
+        spawner: Sp,
+        dropper: U,
+    ) -> DropStream<Self, Self::Item, impl FnOnce()>
+    where
+        Sp: Spawn,
+        U: FnOnce() -> F,
+        F: Future<Output = ()> + Send + 'static;
+
+    /// Spawns the future returned by the closure, which is called with a [`DropContext`], once
+    /// the stream is dropped. See [`ContextDropStream`].
+    #[track_caller]
+    fn on_drop_ctx_async<Sp, U, F>(
+        self,
+        spawner: Sp,
+        dropper: U,
+    ) -> ContextDropStream<Self, impl FnOnce(DropContext)>
+    where
+        Sp: Spawn,
+        U: FnOnce(DropContext) -> F,
+        F: Future<Output = ()> + Send + 'static;
+}
+
+impl<T> OnDropAsyncExt for T
+where
+    T: Stream + Sized,
+{
+    fn on_drop_async<Sp, U, F>(
+        self,
+        spawner: Sp,
+        dropper: U,
+    ) -> DropStream<T, T::Item, impl FnOnce()>
+    where
+        Sp: Spawn,
+        U: FnOnce() -> F,
+        F: Future<Output = ()> + Send + 'static,
+    {
+        DropStream::new(self, move || spawner.spawn(Box::pin(dropper())))
+    }
+
+    #[track_caller]
+    fn on_drop_ctx_async<Sp, U, F>(
+        self,
+        spawner: Sp,
+        dropper: U,
+    ) -> ContextDropStream<T, impl FnOnce(DropContext)>
+    where
+        Sp: Spawn,
+        U: FnOnce(DropContext) -> F,
+        F: Future<Output = ()> + Send + 'static,
+    {
+        ContextDropStream::new(self, move |context| {
+            spawner.spawn(Box::pin(dropper(context)))
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{Arc, Mutex};
+
+    use crate::{BoxFuture, DropReason, OnDropAsyncExt};
+    use futures::{executor::block_on, executor::block_on_stream, stream::iter};
+
+    #[test]
+    fn context_future_is_spawned_on_drop() {
+        let tasks = Arc::new(Mutex::new(Vec::<BoxFuture>::new()));
+        let report = Arc::new(Mutex::new(None));
+
+        let tasks_ref = tasks.clone();
+        let report_ref = report.clone();
+        let stream = iter([1, 2]).on_drop_ctx_async(
+            move |future| tasks_ref.lock().unwrap().push(future),
+            move |context| async move {
+                *report_ref.lock().unwrap() = Some((context.reason(), context.items()));
+            },
+        );
+
+        assert_eq!(block_on_stream(stream).count(), 2);
+        assert!(report.lock().unwrap().is_none());
+
+        for task in tasks.lock().unwrap().drain(..) {
+            block_on(task);
+        }
+        assert_eq!(*report.lock().unwrap(), Some((DropReason::Completed, 2)));
+    }
+
+    #[cfg(feature = "tokio")]
+    #[tokio::test]
+    async fn tokio_handle_is_a_spawner() {
+        let (sender, receiver) = tokio::sync::oneshot::channel();
+
+        let stream = iter([1]).on_drop_async(tokio::runtime::Handle::current(), || async move {
+            sender.send(()).unwrap();
+        });
+        drop(stream);
+
+        receiver.await.unwrap();
+    }
+}
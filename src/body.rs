@@ -0,0 +1,158 @@
+use bytes::Buf;
+use http_body::{Body, Frame, SizeHint};
+use pin_project::pin_project;
+use std::{
+    pin::Pin,
+    task::{Context, Poll},
+};
+
+use crate::context::{ContextDropFn, ContextDropper};
+
+/// An HTTP body that is called with a [`DropContext`](crate::DropContext) once it is dropped, the
+/// counterpart of [`ContextDropStream`](crate::ContextDropStream) for `http_body::Body`.
+///
+/// Every frame counts as an item, or as an error if the body failed, and the bytes of every data
+/// frame are counted. The body completes once it yields its last frame, so a response body that is
+/// dropped with [`DropReason::Cancelled`](crate::DropReason::Cancelled) means the client went away
+/// before reading all of it. Names, labels and IDs work as for other context wrappers.
+///
+/// Example
+/// ```
+/// use drop_stream::{DropBody, DropContext, DropReason};
+/// use http_body_util::{BodyExt, Full};
+///
+/// let mut report = None;
+/// let report_ref = &mut report;
+/// let body = Full::new(bytes::Bytes::from("hello"));
+/// let body = DropBody::new(body, move |context: DropContext| {
+///     *report_ref = Some((context.reason(), context.bytes()));
+/// });
+///
+/// futures::executor::block_on(body.collect()).unwrap();
+/// assert_eq!(report, Some((DropReason::Completed, 5)));
+/// ```
+#[pin_project]
+pub struct DropBody<B, U: ContextDropFn> {
+    // Declared before the body so the closure runs before the inner body is dropped.
+    dropper: ContextDropper<U>,
+    #[pin]
+    body: B,
+}
+
+impl<B: Body, U: ContextDropFn> DropBody<B, U> {
+    #[track_caller]
+    pub fn new(body: B, dropper: U) -> Self {
+        Self {
+            dropper: ContextDropper::new(dropper),
+            body,
+        }
+    }
+
+    /// Returns the ID of the wrapper, see [`ContextDropStream::id`](crate::ContextDropStream::id).
+    pub fn id(&self) -> u64 {
+        self.dropper.stats.id()
+    }
+
+    /// Names the wrapper in its context, replacing any previous name.
+    pub fn name(mut self, name: impl Into<String>) -> Self {
+        self.dropper.stats.set_name(name.into());
+        self
+    }
+
+    /// Adds a label to the wrapper's context.
+    pub fn label(mut self, key: &'static str, value: impl Into<String>) -> Self {
+        self.dropper.stats.add_label(key, value.into());
+        self
+    }
+}
+
+impl<B: Body, U: ContextDropFn> Body for DropBody<B, U> {
+    type Data = B::Data;
+    type Error = B::Error;
+
+    fn poll_frame(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<Result<Frame<Self::Data>, Self::Error>>> {
+        let this = self.project();
+
+        let poll = this.body.poll_frame(cx);
+        match &poll {
+            Poll::Ready(Some(Ok(frame))) => {
+                this.dropper.stats.record_item();
+                if let Some(data) = frame.data_ref() {
+                    this.dropper.stats.record_bytes(data.remaining());
+                }
+            }
+            Poll::Ready(Some(Err(_))) => this.dropper.stats.record_error(),
+            Poll::Ready(None) => this.dropper.stats.record_end(),
+            Poll::Pending => this.dropper.stats.record_pending(),
+        }
+
+        poll
+    }
+
+    fn is_end_stream(&self) -> bool {
+        self.body.is_end_stream()
+    }
+
+    fn size_hint(&self) -> SizeHint {
+        self.body.size_hint()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::task::Poll;
+
+    use crate::{DropBody, DropContext, DropReason};
+    use bytes::Bytes;
+    use http_body::Body;
+    use http_body_util::{BodyExt, StreamBody};
+
+    #[test]
+    fn body_dropped_early_is_cancelled() {
+        let mut context = None::<DropContext>;
+
+        {
+            let context_ref = &mut context;
+            let frames = futures::stream::iter([
+                Ok::<_, std::convert::Infallible>(http_body::Frame::data(Bytes::from("ab"))),
+                Ok(http_body::Frame::data(Bytes::from("cde"))),
+            ]);
+            let body = DropBody::new(StreamBody::new(frames), move |c: DropContext| {
+                *context_ref = Some(c)
+            })
+            .label("route", "/download");
+            let mut body = Box::pin(body);
+
+            let waker = futures::task::noop_waker();
+            let mut cx = futures::task::Context::from_waker(&waker);
+            assert!(matches!(
+                body.as_mut().poll_frame(&mut cx),
+                Poll::Ready(Some(Ok(_)))
+            ));
+        }
+
+        let context = context.unwrap();
+        assert_eq!(context.reason(), DropReason::Cancelled);
+        assert_eq!((context.items(), context.bytes()), (1, 2));
+        assert_eq!(context.label("route"), Some("/download"));
+    }
+
+    #[test]
+    fn collected_body_completes() {
+        let mut reason = None;
+
+        {
+            let reason_ref = &mut reason;
+            let body = DropBody::new(
+                http_body_util::Full::new(Bytes::from("hello")),
+                move |c: DropContext| *reason_ref = Some(c.reason()),
+            );
+            futures::executor::block_on(body.collect()).unwrap();
+        }
+
+        assert_eq!(reason, Some(DropReason::Completed));
+    }
+}
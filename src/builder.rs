@@ -0,0 +1,312 @@
+use futures_core::Stream;
+use pin_project::pin_project;
+use std::{
+    pin::Pin,
+    task::{Context, Poll},
+};
+
+use crate::{context::Stats, panic::run_hook, report_anomaly, DropStream, PanicPolicy};
+
+type Hook<'a> = Box<dyn FnOnce() + Send + 'a>;
+type ItemHook<'a, T> = Box<dyn FnMut(&T) + Send + 'a>;
+
+/// The hooks configured on a [`DropStreamBuilder`], run by the [`HookedStream`] it builds.
+struct Hooks<'a, T> {
+    on_first_poll: Option<Hook<'a>>,
+    on_item: Option<ItemHook<'a, T>>,
+    on_complete: Option<Hook<'a>>,
+    on_cancel: Option<Hook<'a>>,
+    on_drop: Option<Hook<'a>>,
+    completed: bool,
+    expect_completion: bool,
+    panic_policy: PanicPolicy,
+    stats: Stats,
+}
+
+impl<T> Drop for Hooks<'_, T> {
+    fn drop(&mut self) {
+        // Built for every drop, which reports it to the scope and the drop event handlers.
+        let context = self.stats.context();
+
+        if !self.completed {
+            if self.expect_completion {
+                run_hook(self.panic_policy, || report_anomaly(&context))
+            }
+
+            if let Some(on_cancel) = self.on_cancel.take() {
+                run_hook(self.panic_policy, on_cancel)
+            }
+        }
+
+        if let Some(on_drop) = self.on_drop.take() {
+            run_hook(self.panic_policy, on_drop)
+        }
+    }
+}
+
+impl<S: Stream<Item = T>, T> DropStream<S, T, fn()> {
+    /// Starts configuring a wrapper with several lifecycle hooks at once. See
+    /// [`DropStreamBuilder`].
+    #[track_caller]
+    pub fn builder<'a>(stream: S) -> DropStreamBuilder<'a, S> {
+        DropStreamBuilder::new(stream)
+    }
+}
+
+/// Configures a [`HookedStream`] with any combination of lifecycle hooks, instead of stacking
+/// several adapters with incompatible generic signatures.
+///
+/// Setting a hook again replaces the previous one. Hooks are boxed, so they must be `Send` for
+/// the stream to stay `Send`, and the stream is not `UnwindSafe`; wrap it in `AssertUnwindSafe` to
+/// poll it within `catch_unwind`.
+///
+/// Example
+/// ```
+/// use futures::{executor::block_on_stream, stream};
+/// use drop_stream::DropStream;
+///
+/// let events = std::sync::Mutex::new(Vec::new());
+/// let stream = DropStream::builder(stream::iter([1, 2]))
+///     .on_first_poll(|| events.lock().unwrap().push("first poll".to_string()))
+///     .on_item(|item| events.lock().unwrap().push(format!("item {item}")))
+///     .on_complete(|| events.lock().unwrap().push("complete".to_string()))
+///     .on_cancel(|| events.lock().unwrap().push("cancel".to_string()))
+///     .build();
+///
+/// assert_eq!(block_on_stream(stream).count(), 2);
+/// assert_eq!(
+///     *events.lock().unwrap(),
+///     ["first poll", "item 1", "item 2", "complete"]
+/// );
+/// ```
+pub struct DropStreamBuilder<'a, S: Stream> {
+    stream: S,
+    hooks: Hooks<'a, S::Item>,
+}
+
+impl<'a, S: Stream> DropStreamBuilder<'a, S> {
+    #[track_caller]
+    pub fn new(stream: S) -> Self {
+        Self {
+            stream,
+            hooks: Hooks {
+                on_first_poll: None,
+                on_item: None,
+                on_complete: None,
+                on_cancel: None,
+                on_drop: None,
+                completed: false,
+                expect_completion: false,
+                panic_policy: PanicPolicy::Abort,
+                stats: Stats::new(),
+            },
+        }
+    }
+
+    /// Called the first time the stream is polled.
+    pub fn on_first_poll(mut self, hook: impl FnOnce() + Send + 'a) -> Self {
+        self.hooks.on_first_poll = Some(Box::new(hook));
+        self
+    }
+
+    /// Called with every item the stream yields.
+    pub fn on_item(mut self, hook: impl FnMut(&S::Item) + Send + 'a) -> Self {
+        self.hooks.on_item = Some(Box::new(hook));
+        self
+    }
+
+    /// Called as soon as the inner stream finishes.
+    pub fn on_complete(mut self, hook: impl FnOnce() + Send + 'a) -> Self {
+        self.hooks.on_complete = Some(Box::new(hook));
+        self
+    }
+
+    /// Called if the stream is dropped before it finished.
+    pub fn on_cancel(mut self, hook: impl FnOnce() + Send + 'a) -> Self {
+        self.hooks.on_cancel = Some(Box::new(hook));
+        self
+    }
+
+    /// Called once the stream is dropped, after `on_cancel`.
+    pub fn on_drop(mut self, hook: impl FnOnce() + Send + 'a) -> Self {
+        self.hooks.on_drop = Some(Box::new(hook));
+        self
+    }
+
+    /// Marks the stream as expected to run to completion, so being dropped before that is
+    /// reported to the handler set with [`set_anomaly_handler`](crate::set_anomaly_handler),
+    /// before `on_cancel` runs.
+    pub fn expect_completion(mut self) -> Self {
+        self.hooks.expect_completion = true;
+        self
+    }
+
+    /// Sets what `on_cancel` or `on_drop` panicking while the thread is already unwinding leads
+    /// to, instead of aborting the process. See [`PanicPolicy`].
+    pub fn panic_policy(mut self, policy: PanicPolicy) -> Self {
+        self.hooks.panic_policy = policy;
+        self
+    }
+
+    pub fn build(self) -> HookedStream<'a, S> {
+        HookedStream {
+            hooks: self.hooks,
+            stream: self.stream,
+        }
+    }
+}
+
+/// A stream that runs the lifecycle hooks configured with a [`DropStreamBuilder`].
+#[pin_project]
+pub struct HookedStream<'a, S: Stream> {
+    // Declared before the stream so the hooks run before the inner stream is dropped.
+    hooks: Hooks<'a, S::Item>,
+    #[pin]
+    stream: S,
+}
+
+impl<S: Stream> Stream for HookedStream<'_, S> {
+    type Item = S::Item;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let this = self.project();
+
+        if let Some(on_first_poll) = this.hooks.on_first_poll.take() {
+            on_first_poll()
+        }
+
+        let poll = this.stream.poll_next(cx);
+        match &poll {
+            Poll::Ready(Some(item)) => {
+                this.hooks.stats.record_item();
+                if let Some(on_item) = this.hooks.on_item.as_mut() {
+                    on_item(item)
+                }
+            }
+            Poll::Ready(None) => {
+                this.hooks.completed = true;
+                this.hooks.stats.record_end();
+                if let Some(on_complete) = this.hooks.on_complete.take() {
+                    on_complete()
+                }
+            }
+            Poll::Pending => this.hooks.stats.record_pending(),
+        }
+
+        poll
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.stream.size_hint()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{sync::Mutex, task::Poll};
+
+    use crate::{register_drop_handler, set_anomaly_handler, DropEvent, DropReason, DropStream};
+    use futures::{executor::block_on_stream, stream::iter, Stream};
+
+    #[test]
+    fn hooks_run_in_lifecycle_order() {
+        let events = Mutex::new(Vec::new());
+
+        let drop_stream = DropStream::builder(iter([1, 2]))
+            .on_first_poll(|| events.lock().unwrap().push("first poll"))
+            .on_item(|_| events.lock().unwrap().push("item"))
+            .on_complete(|| events.lock().unwrap().push("complete"))
+            .on_cancel(|| events.lock().unwrap().push("cancel"))
+            .on_drop(|| events.lock().unwrap().push("drop"))
+            .build();
+
+        assert_eq!(block_on_stream(drop_stream).count(), 2);
+        assert_eq!(
+            *events.lock().unwrap(),
+            ["first poll", "item", "item", "complete", "drop"]
+        );
+    }
+
+    #[test]
+    fn cancel_runs_when_dropped_early() {
+        let events = Mutex::new(Vec::new());
+
+        {
+            let drop_stream = DropStream::builder(iter([1, 2]))
+                .on_complete(|| events.lock().unwrap().push("complete"))
+                .on_cancel(|| events.lock().unwrap().push("cancel"))
+                .build();
+
+            let mut drop_stream = Box::pin(drop_stream);
+
+            let waker = futures::task::noop_waker();
+            let mut context = futures::task::Context::from_waker(&waker);
+            assert_eq!(
+                drop_stream.as_mut().poll_next(&mut context),
+                Poll::Ready(Some(1))
+            );
+        }
+
+        assert_eq!(*events.lock().unwrap(), ["cancel"]);
+    }
+
+    #[test]
+    fn every_drop_reports_its_context() {
+        let reasons = std::sync::Arc::new(Mutex::new(Vec::new()));
+        let (drop_stream, line) = (DropStream::builder(iter([1, 2])).build(), line!());
+
+        let reasons_ref = reasons.clone();
+        register_drop_handler(move |event: DropEvent| {
+            let location = event.context().location();
+            if location.file() == file!() && location.line() == line {
+                reasons_ref.lock().unwrap().push(event.context().reason());
+            }
+        });
+        assert_eq!(block_on_stream(drop_stream).count(), 2);
+
+        assert_eq!(*reasons.lock().unwrap(), [DropReason::Completed]);
+    }
+
+    #[test]
+    fn only_expected_streams_report_anomalies() {
+        let reported = std::sync::Arc::new(Mutex::new(Vec::new()));
+        let reported_ref = reported.clone();
+        set_anomaly_handler(move |context| reported_ref.lock().unwrap().push(context.items()));
+
+        let drop_stream = DropStream::builder(iter([1, 2]))
+            .expect_completion()
+            .build();
+        let mut drop_stream = Box::pin(drop_stream);
+        let waker = futures::task::noop_waker();
+        let mut context = futures::task::Context::from_waker(&waker);
+        assert_eq!(
+            drop_stream.as_mut().poll_next(&mut context),
+            Poll::Ready(Some(1))
+        );
+        drop(drop_stream);
+
+        drop(DropStream::builder(iter([1, 2])).build());
+        assert_eq!(
+            block_on_stream(
+                DropStream::builder(iter([1, 2]))
+                    .expect_completion()
+                    .build()
+            )
+            .count(),
+            2
+        );
+
+        assert_eq!(*reported.lock().unwrap(), [1]);
+
+        // The handler doesn't run under the registry lock, so it can replace itself.
+        let reported_ref = reported.clone();
+        set_anomaly_handler(move |_| {
+            let reported_ref = reported_ref.clone();
+            set_anomaly_handler(move |context| reported_ref.lock().unwrap().push(context.items()));
+        });
+        drop(DropStream::builder(iter([1])).expect_completion().build());
+        drop(DropStream::builder(iter([1])).expect_completion().build());
+
+        assert_eq!(*reported.lock().unwrap(), [1, 0]);
+    }
+}
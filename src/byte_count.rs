@@ -0,0 +1,158 @@
+use futures_core::Stream;
+use pin_project::pin_project;
+use std::{
+    pin::Pin,
+    task::{Context, Poll},
+};
+
+use crate::context::{ContextDropFn, ContextDropper};
+
+/// The length function used by [`on_drop_with_bytes`](crate::DropStreamExt::on_drop_with_bytes).
+pub type ByteLenFn<T> = fn(&T) -> usize;
+
+/// A stream that adds up the length of every item it yields and reports the total as
+/// [`DropContext::bytes`](crate::DropContext::bytes) once it is dropped, so bandwidth accounting per connection falls out of
+/// the drop closure.
+///
+/// Streams of `Bytes`, `Vec<u8>` or `String` are counted with
+/// [`on_drop_with_bytes`](crate::DropStreamExt::on_drop_with_bytes). Any other item, such as the
+/// `Result<Bytes, E>` of a body stream, is counted with
+/// [`on_drop_with_bytes_by`](crate::DropStreamExt::on_drop_with_bytes_by).
+///
+/// Example
+/// ```
+/// use futures::{executor::block_on_stream, stream};
+/// use drop_stream::DropStreamExt;
+///
+/// let mut transferred = 0;
+/// let transferred_ref = &mut transferred;
+/// let stream = stream::iter([vec![0u8; 3], vec![0u8; 5]])
+///     .on_drop_with_bytes(move |context| *transferred_ref = context.bytes());
+///
+/// assert_eq!(block_on_stream(stream).count(), 2);
+/// assert_eq!(transferred, 8);
+/// ```
+#[pin_project]
+pub struct ByteCountStream<S, L, U>
+where
+    S: Stream,
+    L: FnMut(&S::Item) -> usize,
+    U: ContextDropFn,
+{
+    // Declared before the stream so the closure runs before the inner stream is dropped.
+    dropper: ContextDropper<U>,
+    len: L,
+    #[pin]
+    stream: S,
+}
+
+impl<S, L, U> ByteCountStream<S, L, U>
+where
+    S: Stream,
+    L: FnMut(&S::Item) -> usize,
+    U: ContextDropFn,
+{
+    #[track_caller]
+    pub fn new(stream: S, len: L, dropper: U) -> Self {
+        Self {
+            dropper: ContextDropper::new(dropper),
+            len,
+            stream,
+        }
+    }
+
+    /// Returns the ID of the stream, reported as [`DropContext::id`](crate::DropContext::id).
+    pub fn id(&self) -> u64 {
+        self.dropper.stats.id()
+    }
+
+    /// Returns the number of bytes yielded so far.
+    pub fn bytes(&self) -> u64 {
+        self.dropper.stats.bytes()
+    }
+}
+
+impl<S, L, U> Stream for ByteCountStream<S, L, U>
+where
+    S: Stream,
+    L: FnMut(&S::Item) -> usize,
+    U: ContextDropFn,
+{
+    type Item = S::Item;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let this = self.project();
+
+        let poll = this.stream.poll_next(cx);
+        match &poll {
+            Poll::Ready(Some(item)) => {
+                this.dropper.stats.record_item();
+                this.dropper.stats.record_bytes((this.len)(item));
+            }
+            Poll::Ready(None) => this.dropper.stats.record_end(),
+            Poll::Pending => this.dropper.stats.record_pending(),
+        }
+
+        poll
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.stream.size_hint()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::task::Poll;
+
+    use crate::{DropReason, DropStreamExt};
+    use futures::{stream::iter, Stream};
+
+    #[test]
+    fn bytes_are_counted_until_drop() {
+        let mut context = None;
+
+        {
+            let context_ref = &mut context;
+            let drop_stream =
+                iter(["ping", "pong", "end"]).on_drop_with_bytes(move |c| *context_ref = Some(c));
+
+            let mut drop_stream = Box::pin(drop_stream);
+
+            let waker = futures::task::noop_waker();
+            let mut cx = futures::task::Context::from_waker(&waker);
+            assert_eq!(
+                drop_stream.as_mut().poll_next(&mut cx),
+                Poll::Ready(Some("ping"))
+            );
+            assert_eq!(
+                drop_stream.as_mut().poll_next(&mut cx),
+                Poll::Ready(Some("pong"))
+            );
+            assert_eq!(drop_stream.bytes(), 8);
+        }
+
+        let context = context.unwrap();
+        assert_eq!(context.reason(), DropReason::Cancelled);
+        assert_eq!(context.items(), 2);
+        assert_eq!(context.bytes(), 8);
+    }
+
+    #[test]
+    fn bytes_by_counts_ok_items() {
+        let mut bytes = 0;
+
+        {
+            let bytes_ref = &mut bytes;
+            let items: [Result<&[u8], ()>; 3] = [Ok(b"abc"), Err(()), Ok(b"de")];
+            let drop_stream = iter(items).on_drop_with_bytes_by(
+                |item| item.map_or(0, <[u8]>::len),
+                move |c| *bytes_ref = c.bytes(),
+            );
+
+            futures::executor::block_on_stream(drop_stream).for_each(drop);
+        }
+
+        assert_eq!(bytes, 5);
+    }
+}
@@ -0,0 +1,180 @@
+use futures_core::Stream;
+use pin_project::pin_project;
+use std::{
+    pin::Pin,
+    task::{Context, Poll},
+};
+
+use crate::{dropper::ReasonDropper, DropReason};
+
+/// A stream that, once its inner stream finishes, yields the items produced by a finalizer
+/// closure (e.g. a trailer or summary frame) before ending, and runs a closure with the
+/// [`DropReason`] when dropped.
+///
+/// The drop closure is only called with [`DropReason::Completed`] if the finalizer items were
+/// consumed as well, so a consumer that leaves before reading the trailer counts as
+/// [`DropReason::Cancelled`].
+///
+/// Example
+/// ```
+/// use futures::{executor::block_on_stream, stream};
+/// use drop_stream::{DropReason, DropStreamExt};
+///
+/// let mut reason = None;
+/// let reason_ref = &mut reason;
+/// let stream = stream::iter([1, 2])
+///     .chain_on_end(|| [0], move |r| *reason_ref = Some(r));
+///
+/// assert_eq!(block_on_stream(stream).collect::<Vec<_>>(), vec![1, 2, 0]);
+/// assert_eq!(reason, Some(DropReason::Completed));
+/// ```
+#[pin_project]
+pub struct ChainOnEnd<S, F, I, U>
+where
+    S: Stream,
+    F: FnOnce() -> I,
+    I: IntoIterator<Item = S::Item>,
+    U: FnOnce(DropReason),
+{
+    // Declared before the stream so the closure runs before the inner stream is dropped.
+    dropper: ReasonDropper<U>,
+    #[pin]
+    stream: S,
+    // Taken once the inner stream has finished.
+    finalizer: Option<F>,
+    finalizer_items: Option<I::IntoIter>,
+    completed: bool,
+}
+
+impl<S, F, I, U> ChainOnEnd<S, F, I, U>
+where
+    S: Stream,
+    F: FnOnce() -> I,
+    I: IntoIterator<Item = S::Item>,
+    U: FnOnce(DropReason),
+{
+    pub fn new(stream: S, finalizer: F, dropper: U) -> Self {
+        Self {
+            dropper: ReasonDropper::new(dropper),
+            stream,
+            finalizer: Some(finalizer),
+            finalizer_items: None,
+            completed: false,
+        }
+    }
+}
+
+impl<S, F, I, U> Stream for ChainOnEnd<S, F, I, U>
+where
+    S: Stream,
+    F: FnOnce() -> I,
+    I: IntoIterator<Item = S::Item>,
+    U: FnOnce(DropReason),
+{
+    type Item = S::Item;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let this = self.project();
+
+        if *this.completed {
+            return Poll::Ready(None);
+        }
+
+        if let Some(finalizer) = this.finalizer.take() {
+            match this.stream.poll_next(cx) {
+                Poll::Ready(None) => *this.finalizer_items = Some(finalizer().into_iter()),
+                poll => {
+                    *this.finalizer = Some(finalizer);
+                    return poll;
+                }
+            }
+        }
+
+        let item = this.finalizer_items.as_mut().and_then(Iterator::next);
+        if item.is_none() {
+            *this.finalizer_items = None;
+            *this.completed = true;
+            this.dropper.reason = DropReason::Completed;
+        }
+
+        Poll::Ready(item)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        if self.completed {
+            return (0, Some(0));
+        }
+
+        match &self.finalizer_items {
+            Some(finalizer_items) => finalizer_items.size_hint(),
+            // The number of finalizer items isn't known until the finalizer is called.
+            None => (self.stream.size_hint().0, None),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::task::Poll;
+
+    use crate::{DropReason, DropStreamExt};
+    use futures::{stream::iter, Stream};
+
+    #[test]
+    fn finalizer_items_follow_inner_items() {
+        let mut reason = None;
+
+        {
+            let reason_ref = &mut reason;
+            let drop_stream = iter([1, 2]).chain_on_end(
+                || vec![3, 4],
+                move |r| {
+                    *reason_ref = Some(r);
+                },
+            );
+
+            let mut drop_stream = Box::pin(drop_stream);
+
+            let waker = futures::task::noop_waker();
+            let mut context = futures::task::Context::from_waker(&waker);
+            for expected in [Some(1), Some(2), Some(3), Some(4), None, None] {
+                assert_eq!(
+                    drop_stream.as_mut().poll_next(&mut context),
+                    Poll::Ready(expected)
+                );
+            }
+        }
+
+        assert_eq!(reason, Some(DropReason::Completed));
+    }
+
+    #[test]
+    fn unconsumed_finalizer_counts_as_cancelled() {
+        let mut reason = None;
+
+        {
+            let reason_ref = &mut reason;
+            let drop_stream = iter([1]).chain_on_end(
+                || [2],
+                move |r| {
+                    *reason_ref = Some(r);
+                },
+            );
+
+            let mut drop_stream = Box::pin(drop_stream);
+
+            let waker = futures::task::noop_waker();
+            let mut context = futures::task::Context::from_waker(&waker);
+            assert_eq!(
+                drop_stream.as_mut().poll_next(&mut context),
+                Poll::Ready(Some(1))
+            );
+            assert_eq!(
+                drop_stream.as_mut().poll_next(&mut context),
+                Poll::Ready(Some(2))
+            );
+        }
+
+        assert_eq!(reason, Some(DropReason::Cancelled));
+    }
+}
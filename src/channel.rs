@@ -0,0 +1,290 @@
+use futures_core::{Future, Stream};
+use pin_project::pin_project;
+use std::{
+    fmt,
+    pin::Pin,
+    sync::{Arc, Mutex},
+    task::{Context, Poll},
+};
+
+use crate::signal::Signal;
+
+/// Creates a pair of connected [`DropEndpoint`]s, where each side can observe when the other side
+/// is dropped.
+///
+/// This tracks the liveness of a whole connection rather than one direction of it: attach one
+/// endpoint to the read half of a transport and the other to the write half, and each half learns
+/// when its counterpart goes away.
+///
+/// Example
+/// ```
+/// use std::sync::{atomic::{AtomicBool, Ordering}, Arc};
+/// use drop_stream::drop_channel;
+///
+/// let (a, b) = drop_channel();
+///
+/// let notified = Arc::new(AtomicBool::new(false));
+/// let notified_ref = notified.clone();
+/// a.on_peer_drop(move || {
+///     notified_ref.store(true, Ordering::SeqCst);
+/// });
+///
+/// assert!(!a.is_peer_dropped());
+/// drop(b);
+/// assert!(a.is_peer_dropped());
+/// assert!(notified.load(Ordering::SeqCst));
+/// ```
+pub fn drop_channel() -> (DropEndpoint, DropEndpoint) {
+    let shared = Arc::new(Shared::default());
+
+    (
+        DropEndpoint {
+            shared: shared.clone(),
+            side: 0,
+        },
+        DropEndpoint { shared, side: 1 },
+    )
+}
+
+type Callback = Box<dyn FnOnce() + Send>;
+
+#[derive(Default)]
+struct Side {
+    dropped: Signal,
+    // Locked while firing `dropped`, so registering a callback cannot race with the drop.
+    on_drop: Mutex<Option<Callback>>,
+}
+
+#[derive(Default)]
+struct Shared {
+    sides: [Side; 2],
+}
+
+/// One side of a pair created by [`drop_channel`].
+pub struct DropEndpoint {
+    shared: Arc<Shared>,
+    side: usize,
+}
+
+impl DropEndpoint {
+    fn peer(&self) -> &Side {
+        &self.shared.sides[1 - self.side]
+    }
+
+    /// Returns true if the other endpoint has been dropped.
+    pub fn is_peer_dropped(&self) -> bool {
+        self.peer().dropped.is_fired()
+    }
+
+    /// Runs `callback` once the other endpoint is dropped, replacing any previously registered
+    /// callback. If the other endpoint is already gone, `callback` runs immediately.
+    pub fn on_peer_drop(&self, callback: impl FnOnce() + Send + 'static) {
+        let peer = self.peer();
+
+        let mut on_drop = peer.on_drop.lock().unwrap_or_else(|e| e.into_inner());
+        if peer.dropped.is_fired() {
+            drop(on_drop);
+            callback();
+        } else {
+            *on_drop = Some(Box::new(callback));
+        }
+    }
+
+    /// Returns a future that resolves once the other endpoint is dropped.
+    pub fn peer_dropped(&self) -> PeerDropped {
+        PeerDropped {
+            shared: self.shared.clone(),
+            side: 1 - self.side,
+        }
+    }
+
+    /// Attaches this endpoint to `inner`, so the other side is notified once `inner` is dropped.
+    pub fn attach<T>(self, inner: T) -> Attached<T> {
+        Attached {
+            inner,
+            endpoint: self,
+        }
+    }
+}
+
+impl fmt::Debug for DropEndpoint {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("DropEndpoint")
+            .field("side", &self.side)
+            .field("peer_dropped", &self.is_peer_dropped())
+            .finish()
+    }
+}
+
+impl Drop for DropEndpoint {
+    fn drop(&mut self) {
+        let side = &self.shared.sides[self.side];
+
+        let callback = {
+            let mut on_drop = side.on_drop.lock().unwrap_or_else(|e| e.into_inner());
+            side.dropped.fire();
+            on_drop.take()
+        };
+
+        if let Some(callback) = callback {
+            callback();
+        }
+    }
+}
+
+/// A future that resolves once the other endpoint of a [`drop_channel`] pair is dropped. Created
+/// by [`DropEndpoint::peer_dropped`].
+pub struct PeerDropped {
+    shared: Arc<Shared>,
+    side: usize,
+}
+
+impl Future for PeerDropped {
+    type Output = ();
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        self.shared.sides[self.side].dropped.poll_fired(cx)
+    }
+}
+
+impl fmt::Debug for PeerDropped {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("PeerDropped").finish_non_exhaustive()
+    }
+}
+
+/// A value with a [`DropEndpoint`] attached to it. Created by [`DropEndpoint::attach`].
+///
+/// Forwards [`Stream`], and `Sink` with the `sink` feature, to the inner value, and notifies the
+/// other endpoint once dropped.
+#[pin_project]
+#[derive(Debug)]
+pub struct Attached<T> {
+    #[pin]
+    inner: T,
+    endpoint: DropEndpoint,
+}
+
+impl<T> Attached<T> {
+    /// Returns the endpoint attached to the inner value.
+    pub fn endpoint(&self) -> &DropEndpoint {
+        &self.endpoint
+    }
+}
+
+impl<S: Stream> Stream for Attached<S> {
+    type Item = S::Item;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        self.project().inner.poll_next(cx)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.inner.size_hint()
+    }
+}
+
+#[cfg(feature = "sink")]
+impl<Si: futures_sink::Sink<I>, I> futures_sink::Sink<I> for Attached<Si> {
+    type Error = Si::Error;
+
+    fn poll_ready(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.project().inner.poll_ready(cx)
+    }
+
+    fn start_send(self: Pin<&mut Self>, item: I) -> Result<(), Self::Error> {
+        self.project().inner.start_send(item)
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.project().inner.poll_flush(cx)
+    }
+
+    fn poll_close(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.project().inner.poll_close(cx)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        sync::{
+            atomic::{AtomicUsize, Ordering},
+            Arc,
+        },
+        task::Poll,
+    };
+
+    use crate::drop_channel;
+    use futures::{stream::repeat, Future, Stream};
+
+    #[test]
+    fn each_side_observes_the_other() {
+        let (a, b) = drop_channel();
+
+        let a_stream = a.attach(repeat(true));
+        assert!(!a_stream.endpoint().is_peer_dropped());
+        assert!(!b.is_peer_dropped());
+
+        drop(a_stream);
+        assert!(b.is_peer_dropped());
+    }
+
+    #[test]
+    fn callback_runs_once_peer_dropped() {
+        let (a, b) = drop_channel();
+
+        let runs = Arc::new(AtomicUsize::new(0));
+        let runs_ref = runs.clone();
+        b.on_peer_drop(move || {
+            runs_ref.fetch_add(1, Ordering::SeqCst);
+        });
+
+        drop(a);
+        assert_eq!(runs.load(Ordering::SeqCst), 1);
+
+        // Registered after the peer already dropped.
+        let runs_ref = runs.clone();
+        b.on_peer_drop(move || {
+            runs_ref.fetch_add(1, Ordering::SeqCst);
+        });
+        assert_eq!(runs.load(Ordering::SeqCst), 2);
+    }
+
+    #[test]
+    fn peer_dropped_future_resolves() {
+        let (a, b) = drop_channel();
+
+        let mut peer_dropped = Box::pin(a.peer_dropped());
+
+        let (waker, count) = futures_test::task::new_count_waker();
+        let mut context = futures::task::Context::from_waker(&waker);
+        assert_eq!(peer_dropped.as_mut().poll(&mut context), Poll::Pending);
+
+        let mut b_stream = Box::pin(b.attach(repeat(true)));
+        assert_eq!(
+            b_stream.as_mut().poll_next(&mut context),
+            Poll::Ready(Some(true))
+        );
+        drop(b_stream);
+
+        assert_eq!(count, 1);
+        assert_eq!(peer_dropped.as_mut().poll(&mut context), Poll::Ready(()));
+    }
+
+    #[cfg(feature = "sink")]
+    #[test]
+    fn attached_sink_forwards_items() {
+        use futures::{executor::block_on, SinkExt, StreamExt};
+
+        let (a, b) = drop_channel();
+        let (sender, receiver) = futures::channel::mpsc::unbounded();
+
+        let mut sender = a.attach(sender);
+        block_on(sender.send(1)).unwrap();
+        drop(sender);
+
+        assert!(b.is_peer_dropped());
+        assert_eq!(block_on(receiver.collect::<Vec<_>>()), [1]);
+    }
+}
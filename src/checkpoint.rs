@@ -0,0 +1,187 @@
+use futures_core::Stream;
+use pin_project::{pin_project, pinned_drop};
+use std::{
+    fmt,
+    pin::Pin,
+    task::{Context, Poll},
+};
+
+/// Persists the position a resumable consumer reached, such as a Kafka offset, a database cursor
+/// or a replication LSN. See [`Checkpointed`].
+///
+/// Implemented for closures taking the position.
+pub trait Checkpointer<P> {
+    fn checkpoint(&mut self, position: P);
+}
+
+impl<P, F: FnMut(P)> Checkpointer<P> for F {
+    fn checkpoint(&mut self, position: P) {
+        self(position)
+    }
+}
+
+/// A stream that keeps the position of the last item it yielded and hands it to a
+/// [`Checkpointer`] once it is dropped, so the progress of a resumable consumer is persisted
+/// whenever it stops.
+///
+/// The position is projected from every item as it is yielded. Nothing is checkpointed if no item
+/// was yielded, so a consumer that stopped right away doesn't overwrite an earlier checkpoint.
+/// [`checkpoint`](Self::checkpoint) persists the current position early, such as at every commit
+/// interval, and is skipped on drop if nothing was yielded since.
+///
+/// Example
+/// ```
+/// use futures::{executor::block_on_stream, stream};
+/// use drop_stream::DropStreamExt;
+///
+/// let mut committed = Vec::new();
+/// let committed_ref = &mut committed;
+/// let records = stream::iter([(40, "a"), (41, "b"), (42, "c")])
+///     .checkpoint_on_drop(|(offset, _)| *offset, move |offset| committed_ref.push(offset));
+///
+/// assert_eq!(block_on_stream(records).take(2).count(), 2);
+/// assert_eq!(committed, [41]);
+/// ```
+#[pin_project(PinnedDrop)]
+pub struct Checkpointed<S, F, P, C>
+where
+    S: Stream,
+    F: FnMut(&S::Item) -> P,
+    C: Checkpointer<P>,
+{
+    #[pin]
+    stream: S,
+    position: F,
+    // The position of the last item, until it was checkpointed.
+    pending: Option<P>,
+    checkpointer: C,
+}
+
+impl<S, F, P, C> Checkpointed<S, F, P, C>
+where
+    S: Stream,
+    F: FnMut(&S::Item) -> P,
+    C: Checkpointer<P>,
+{
+    pub fn new(stream: S, position: F, checkpointer: C) -> Self {
+        Self {
+            stream,
+            position,
+            pending: None,
+            checkpointer,
+        }
+    }
+
+    /// Returns the position of the last item yielded since the last checkpoint.
+    pub fn pending(&self) -> Option<&P> {
+        self.pending.as_ref()
+    }
+
+    /// Checkpoints the position of the last item yielded, if it wasn't yet.
+    pub fn checkpoint(self: Pin<&mut Self>) {
+        let this = self.project();
+
+        if let Some(position) = this.pending.take() {
+            this.checkpointer.checkpoint(position);
+        }
+    }
+
+    /// Returns the checkpointer.
+    pub fn checkpointer(&self) -> &C {
+        &self.checkpointer
+    }
+}
+
+impl<S, F, P, C> Stream for Checkpointed<S, F, P, C>
+where
+    S: Stream,
+    F: FnMut(&S::Item) -> P,
+    C: Checkpointer<P>,
+{
+    type Item = S::Item;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let this = self.project();
+
+        let poll = this.stream.poll_next(cx);
+        if let Poll::Ready(Some(item)) = &poll {
+            *this.pending = Some((this.position)(item));
+        }
+
+        poll
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.stream.size_hint()
+    }
+}
+
+#[pinned_drop]
+impl<S, F, P, C> PinnedDrop for Checkpointed<S, F, P, C>
+where
+    S: Stream,
+    F: FnMut(&S::Item) -> P,
+    C: Checkpointer<P>,
+{
+    fn drop(self: Pin<&mut Self>) {
+        self.checkpoint();
+    }
+}
+
+impl<S, F, P, C> fmt::Debug for Checkpointed<S, F, P, C>
+where
+    S: Stream,
+    F: FnMut(&S::Item) -> P,
+    P: fmt::Debug,
+    C: Checkpointer<P>,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Checkpointed")
+            .field("pending", &self.pending)
+            .finish_non_exhaustive()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{Checkpointer, DropStreamExt};
+    use futures::{executor::block_on_stream, stream::iter, StreamExt};
+
+    #[derive(Default)]
+    struct Offsets(Vec<u64>);
+
+    impl Checkpointer<u64> for &mut Offsets {
+        fn checkpoint(&mut self, position: u64) {
+            self.0.push(position);
+        }
+    }
+
+    #[test]
+    fn explicit_checkpoint_is_not_repeated_on_drop() {
+        let mut offsets = Offsets::default();
+
+        {
+            let records = iter([1u64, 2, 3]).checkpoint_on_drop(|offset| *offset, &mut offsets);
+            let mut records = Box::pin(records);
+
+            futures::executor::block_on(async {
+                records.next().await;
+                records.next().await;
+            });
+            records.as_mut().checkpoint();
+            assert_eq!(records.checkpointer().0, [2]);
+        }
+
+        assert_eq!(offsets.0, [2]);
+    }
+
+    #[test]
+    fn nothing_is_checkpointed_without_items() {
+        let mut offsets = Offsets::default();
+
+        let records = iter([1u64, 2, 3]).checkpoint_on_drop(|offset| *offset, &mut offsets);
+        drop(block_on_stream(records));
+
+        assert!(offsets.0.is_empty());
+    }
+}
@@ -0,0 +1,287 @@
+use futures_core::Stream;
+use futures_sink::Sink;
+use pin_project::{pin_project, pinned_drop};
+use std::{
+    fmt,
+    future::poll_fn,
+    pin::Pin,
+    task::{ready, Context, Poll},
+};
+
+use crate::{dropper::Once, Spawn};
+
+/// A sink that sends a final message, such as a goodbye frame or a status code, before it is
+/// closed, whether it is closed explicitly or dropped.
+///
+/// Closing the sink through `poll_close` sends the message first and then closes the inner sink.
+/// If the sink is dropped without being closed, the sink and the message are handed to a
+/// background task that does the same, so the protocol is wound down properly without an async
+/// `Drop`. Errors from that task are discarded, as the sink is being thrown away. Wrapping a
+/// duplex keeps its stream half.
+///
+/// Example
+/// ```
+/// use std::sync::{Arc, Mutex};
+/// use drop_stream::{BoxFuture, CloseWith};
+///
+/// let tasks = Arc::new(Mutex::new(Vec::new()));
+/// let spawner = {
+///     let tasks = tasks.clone();
+///     move |future: BoxFuture| tasks.lock().unwrap().push(future)
+/// };
+///
+/// let sent = Arc::new(Mutex::new(Vec::new()));
+/// let sink = futures::sink::unfold(sent.clone(), |sent, frame: &str| async move {
+///     sent.lock().unwrap().push(frame);
+///     Ok::<_, std::convert::Infallible>(sent)
+/// });
+///
+/// drop(CloseWith::new(Box::pin(sink), "goodbye", spawner));
+/// for task in tasks.lock().unwrap().drain(..) {
+///     futures::executor::block_on(task);
+/// }
+///
+/// assert_eq!(*sent.lock().unwrap(), ["goodbye"]);
+/// ```
+#[pin_project(PinnedDrop)]
+pub struct CloseWith<Si, I, Sp>
+where
+    Si: Sink<I> + Unpin + Send + 'static,
+    I: Send + 'static,
+    Sp: Spawn,
+{
+    // Held together so both can be moved into the background task when dropped.
+    inner: Once<(Si, Option<I>)>,
+    spawner: Sp,
+    closed: bool,
+}
+
+impl<Si, I, Sp> CloseWith<Si, I, Sp>
+where
+    Si: Sink<I> + Unpin + Send + 'static,
+    I: Send + 'static,
+    Sp: Spawn,
+{
+    pub fn new(sink: Si, message: I, spawner: Sp) -> Self {
+        Self {
+            inner: Once::new((sink, Some(message))),
+            spawner,
+            closed: false,
+        }
+    }
+
+    /// Returns the inner sink.
+    pub fn get_ref(&self) -> Option<&Si> {
+        self.inner.get().map(|(sink, _)| sink)
+    }
+}
+
+/// Sends `message` into `sink` if it wasn't yet, then closes it.
+fn poll_close_with<Si: Sink<I> + Unpin, I>(
+    sink: &mut Si,
+    message: &mut Option<I>,
+    cx: &mut Context<'_>,
+) -> Poll<Result<(), Si::Error>> {
+    if message.is_some() {
+        ready!(Pin::new(&mut *sink).poll_ready(cx))?;
+        if let Some(message) = message.take() {
+            Pin::new(&mut *sink).start_send(message)?;
+        }
+    }
+
+    Pin::new(sink).poll_close(cx)
+}
+
+impl<Si, I, Sp> Sink<I> for CloseWith<Si, I, Sp>
+where
+    Si: Sink<I> + Unpin + Send + 'static,
+    I: Send + 'static,
+    Sp: Spawn,
+{
+    type Error = Si::Error;
+
+    fn poll_ready(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        match self.project().inner.get_mut() {
+            Some((sink, _)) => Pin::new(sink).poll_ready(cx),
+            None => Poll::Ready(Ok(())),
+        }
+    }
+
+    fn start_send(self: Pin<&mut Self>, item: I) -> Result<(), Self::Error> {
+        match self.project().inner.get_mut() {
+            Some((sink, _)) => Pin::new(sink).start_send(item),
+            None => Ok(()),
+        }
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        match self.project().inner.get_mut() {
+            Some((sink, _)) => Pin::new(sink).poll_flush(cx),
+            None => Poll::Ready(Ok(())),
+        }
+    }
+
+    fn poll_close(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        let this = self.project();
+
+        // Only taken in the drop method.
+        let Some((sink, message)) = this.inner.get_mut() else {
+            return Poll::Ready(Ok(()));
+        };
+
+        let poll = poll_close_with(sink, message, cx);
+        if let Poll::Ready(Ok(())) = poll {
+            *this.closed = true;
+        }
+
+        poll
+    }
+}
+
+impl<Si, I, Sp> Stream for CloseWith<Si, I, Sp>
+where
+    Si: Stream + Sink<I> + Unpin + Send + 'static,
+    I: Send + 'static,
+    Sp: Spawn,
+{
+    type Item = Si::Item;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        match self.project().inner.get_mut() {
+            Some((stream, _)) => Pin::new(stream).poll_next(cx),
+            None => Poll::Ready(None),
+        }
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.inner
+            .get()
+            .map_or((0, Some(0)), |(stream, _)| stream.size_hint())
+    }
+}
+
+#[pinned_drop]
+impl<Si, I, Sp> PinnedDrop for CloseWith<Si, I, Sp>
+where
+    Si: Sink<I> + Unpin + Send + 'static,
+    I: Send + 'static,
+    Sp: Spawn,
+{
+    fn drop(self: Pin<&mut Self>) {
+        let this = self.project();
+
+        if *this.closed {
+            return;
+        }
+        let Some((mut sink, mut message)) = this.inner.take() else {
+            return;
+        };
+
+        this.spawner.spawn(Box::pin(async move {
+            let _ = poll_fn(|cx| poll_close_with(&mut sink, &mut message, cx)).await;
+        }));
+    }
+}
+
+impl<Si, I, Sp> fmt::Debug for CloseWith<Si, I, Sp>
+where
+    Si: Sink<I> + Unpin + Send + 'static,
+    I: Send + 'static,
+    Sp: Spawn,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("CloseWith")
+            .field("closed", &self.closed)
+            .finish_non_exhaustive()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        convert::Infallible,
+        pin::Pin,
+        sync::{Arc, Mutex},
+        task::{Context, Poll},
+    };
+
+    use crate::{BoxFuture, DropStreamExt};
+    use futures::{executor::block_on, Sink, SinkExt, Stream};
+
+    /// A duplex that records the frames sent into it and never yields any.
+    #[derive(Clone, Default)]
+    struct Recorder(Arc<Mutex<Vec<&'static str>>>);
+
+    impl Stream for Recorder {
+        type Item = ();
+
+        fn poll_next(self: Pin<&mut Self>, _: &mut Context<'_>) -> Poll<Option<()>> {
+            Poll::Pending
+        }
+    }
+
+    impl Sink<&'static str> for Recorder {
+        type Error = Infallible;
+
+        fn poll_ready(self: Pin<&mut Self>, _: &mut Context<'_>) -> Poll<Result<(), Infallible>> {
+            Poll::Ready(Ok(()))
+        }
+
+        fn start_send(self: Pin<&mut Self>, frame: &'static str) -> Result<(), Infallible> {
+            self.0.lock().unwrap().push(frame);
+            Ok(())
+        }
+
+        fn poll_flush(self: Pin<&mut Self>, _: &mut Context<'_>) -> Poll<Result<(), Infallible>> {
+            Poll::Ready(Ok(()))
+        }
+
+        fn poll_close(self: Pin<&mut Self>, _: &mut Context<'_>) -> Poll<Result<(), Infallible>> {
+            self.0.lock().unwrap().push("closed");
+            Poll::Ready(Ok(()))
+        }
+    }
+
+    #[test]
+    fn explicit_close_sends_message_once() {
+        let spawned = Arc::new(Mutex::new(0));
+        let spawned_ref = spawned.clone();
+        let recorder = Recorder::default();
+
+        let mut duplex = recorder
+            .clone()
+            .on_drop(|| {})
+            .close_with("bye", move |_: BoxFuture| {
+                *spawned_ref.lock().unwrap() += 1;
+            });
+        block_on(async {
+            duplex.send("hello").await.unwrap();
+            duplex.close().await.unwrap();
+        });
+        drop(duplex);
+
+        assert_eq!(*recorder.0.lock().unwrap(), ["hello", "bye", "closed"]);
+        assert_eq!(*spawned.lock().unwrap(), 0);
+    }
+
+    #[test]
+    fn drop_closes_in_background_before_dropper() {
+        let tasks = Arc::new(Mutex::new(Vec::<BoxFuture>::new()));
+        let tasks_ref = tasks.clone();
+        let recorder = Recorder::default();
+
+        let frames = recorder.0.clone();
+        let duplex = recorder
+            .clone()
+            .on_drop(move || frames.lock().unwrap().push("dropped"))
+            .close_with("bye", move |future| tasks_ref.lock().unwrap().push(future));
+
+        drop(duplex);
+        assert!(recorder.0.lock().unwrap().is_empty());
+
+        for task in tasks.lock().unwrap().drain(..) {
+            block_on(task);
+        }
+        assert_eq!(*recorder.0.lock().unwrap(), ["bye", "closed", "dropped"]);
+    }
+}
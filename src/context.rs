@@ -0,0 +1,526 @@
+use std::{
+    collections::VecDeque,
+    fmt,
+    panic::Location,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, OnceLock,
+    },
+    time::{Duration, Instant, SystemTime},
+};
+
+use crate::{
+    dropper::Once,
+    event,
+    scope::{self, ScopeState},
+    DropReason, Latency, QueueDepth,
+};
+
+/// Information about a stream's lifetime, handed to drop closures that take one.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DropContext {
+    id: u64,
+    reason: DropReason,
+    items: usize,
+    errors: usize,
+    bytes: u64,
+    size: u64,
+    created_at: Instant,
+    dropped_at: Instant,
+    name: Option<String>,
+    labels: Vec<(&'static str, String)>,
+    location: &'static Location<'static>,
+    latency: Option<Latency>,
+    // The number of items yielded within the throughput window before the drop, and the window.
+    window: Option<(usize, Duration)>,
+    pending: bool,
+    last_item_at: Option<Instant>,
+    queue_depth: Option<QueueDepth>,
+}
+
+impl DropContext {
+    /// The ID of the wrapper, see [`ContextDropStream::id`](crate::ContextDropStream::id).
+    pub fn id(&self) -> u64 {
+        self.id
+    }
+
+    /// A key identifying the wrapper across process restarts, to deduplicate cleanup that hits
+    /// external systems.
+    pub fn idempotency_key(&self) -> IdempotencyKey {
+        IdempotencyKey {
+            id: self.id,
+            generation: generation(),
+        }
+    }
+
+    /// Why the closure is being run.
+    pub fn reason(&self) -> DropReason {
+        self.reason
+    }
+
+    /// The number of items the stream yielded, including errors, or for a sink the number of items
+    /// sent into it.
+    pub fn items(&self) -> usize {
+        self.items
+    }
+
+    /// The number of `Err` items the stream yielded, or for a sink the number of items it rejected.
+    pub fn errors(&self) -> usize {
+        self.errors
+    }
+
+    /// The number of bytes the stream yielded, for wrappers that count them such as
+    /// [`ByteCountStream`](crate::ByteCountStream), or that a wrapped reader or writer read or
+    /// wrote, see [`Instrumented`](crate::Instrumented). Zero otherwise.
+    pub fn bytes(&self) -> u64 {
+        self.bytes
+    }
+
+    /// The total size of the items the stream yielded, as measured by the wrapper's measure
+    /// function, such as [`ContextDropStream::measure`](crate::ContextDropStream::measure). Zero
+    /// otherwise.
+    pub fn size(&self) -> u64 {
+        self.size
+    }
+
+    /// Whether the last poll returned `Pending`, meaning the consumer was still waiting for the
+    /// next item when the wrapper was dropped, as opposed to dropping it right after an item.
+    pub fn was_pending(&self) -> bool {
+        self.pending
+    }
+
+    /// When the wrapper was created.
+    pub fn created_at(&self) -> Instant {
+        self.created_at
+    }
+
+    /// When the wrapper was dropped, or ended if the closure runs before that.
+    pub fn dropped_at(&self) -> Instant {
+        self.dropped_at
+    }
+
+    /// When the stream last yielded an item, or for a sink when an item was last sent into it.
+    pub fn last_item_at(&self) -> Option<Instant> {
+        self.last_item_at
+    }
+
+    /// How long before the drop the last item was yielded, or `None` if there wasn't one.
+    ///
+    /// For streaming responses this tells a client that read everything and then left, with a
+    /// short interval, from one that stopped reading long ago while the server kept producing.
+    pub fn since_last_item(&self) -> Option<Duration> {
+        self.last_item_at.map(|at| self.dropped_at - at)
+    }
+
+    /// How long the wrapper was alive, from [`created_at`](Self::created_at) to
+    /// [`dropped_at`](Self::dropped_at).
+    pub fn lifetime(&self) -> Duration {
+        self.dropped_at - self.created_at
+    }
+
+    /// The name the wrapper was given, if any.
+    pub fn name(&self) -> Option<&str> {
+        self.name.as_deref()
+    }
+
+    /// The labels the wrapper was given, in the order they were added.
+    pub fn labels(&self) -> &[(&'static str, String)] {
+        &self.labels
+    }
+
+    /// Returns the value of the first label with `key`.
+    pub fn label(&self, key: &str) -> Option<&str> {
+        self.labels
+            .iter()
+            .find(|(k, _)| *k == key)
+            .map(|(_, value)| value.as_str())
+    }
+
+    /// Where in the source the wrapper was created.
+    pub fn location(&self) -> &'static Location<'static> {
+        self.location
+    }
+
+    /// The average number of items yielded per second over the wrapper's
+    /// [`lifetime`](Self::lifetime), or `None` if no time has passed.
+    pub fn items_per_second(&self) -> Option<f64> {
+        rate(self.items, self.lifetime())
+    }
+
+    /// The number of items yielded per second over the sliding window that came right before the
+    /// drop, if the wrapper was asked to track one, e.g. with
+    /// [`ContextDropStream::throughput_window`](crate::ContextDropStream::throughput_window).
+    ///
+    /// If the wrapper was alive for less than the window, this is the same as
+    /// [`items_per_second`](Self::items_per_second).
+    pub fn window_items_per_second(&self) -> Option<f64> {
+        let (items, window) = self.window?;
+        rate(items, window.min(self.lifetime()))
+    }
+
+    /// The per-poll timings of the stream, if the wrapper was asked to record them, e.g. with
+    /// [`ContextDropStream::record_latency`](crate::ContextDropStream::record_latency).
+    pub fn latency(&self) -> Option<&Latency> {
+        self.latency.as_ref()
+    }
+
+    /// How full the channel the stream receives from was when it was dropped, for wrappers that
+    /// probe it such as [`QueueDepthStream`](crate::QueueDepthStream).
+    pub fn queue_depth(&self) -> Option<QueueDepth> {
+        self.queue_depth
+    }
+
+    /// Adds a label that only became known once the wrapper was dropped.
+    #[cfg(feature = "wasm-streams")]
+    pub(crate) fn add_label(&mut self, key: &'static str, value: String) {
+        self.labels.push((key, value));
+    }
+}
+
+/// Identifies a wrapper across process restarts: its ID, which is only unique within a process,
+/// along with the generation of the process. Created by [`DropContext::idempotency_key`].
+///
+/// Formats as `generation-id`, such as for an `Idempotency-Key` header or a unique database key,
+/// so an external system can recognize a cleanup request it has already handled.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct IdempotencyKey {
+    id: u64,
+    generation: u64,
+}
+
+impl IdempotencyKey {
+    /// The ID of the wrapper.
+    pub fn id(&self) -> u64 {
+        self.id
+    }
+
+    /// The generation of the process, which is the time it first built a key at, in nanoseconds
+    /// since the Unix epoch.
+    pub fn generation(&self) -> u64 {
+        self.generation
+    }
+}
+
+impl fmt::Display for IdempotencyKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:x}-{:x}", self.generation, self.id)
+    }
+}
+
+fn generation() -> u64 {
+    static GENERATION: OnceLock<u64> = OnceLock::new();
+
+    *GENERATION.get_or_init(|| {
+        SystemTime::now()
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .map_or(0, |since| since.as_nanos() as u64)
+    })
+}
+
+fn rate(items: usize, over: Duration) -> Option<f64> {
+    (!over.is_zero()).then(|| items as f64 / over.as_secs_f64())
+}
+
+/// A closure called with a [`DropContext`] once a wrapper is dropped.
+///
+/// Implemented for every `FnOnce(DropContext)` closure, and for the crate's own dropper types such
+/// as [`OnReason`], which can be named in type signatures.
+pub trait ContextDropFn {
+    fn call(self, context: DropContext);
+}
+
+impl<F: FnOnce(DropContext)> ContextDropFn for F {
+    fn call(self, context: DropContext) {
+        self(context)
+    }
+}
+
+/// A dropper that only calls its closure if the [`DropReason`] matches, created by the outcome
+/// combinators on [`DropTryStreamExt`](crate::DropTryStreamExt).
+pub struct OnReason<F: FnOnce(DropContext)> {
+    matches: fn(DropReason) -> bool,
+    dropper: F,
+}
+
+impl<F: FnOnce(DropContext)> OnReason<F> {
+    pub fn new(matches: fn(DropReason) -> bool, dropper: F) -> Self {
+        Self { matches, dropper }
+    }
+}
+
+impl<F: FnOnce(DropContext)> ContextDropFn for OnReason<F> {
+    fn call(self, context: DropContext) {
+        if (self.matches)(context.reason()) {
+            (self.dropper)(context)
+        }
+    }
+}
+
+/// The counters a wrapper keeps up to date while it is polled, used to build its [`DropContext`].
+#[derive(Debug)]
+pub(crate) struct Stats {
+    id: u64,
+    items: usize,
+    errors: usize,
+    bytes: u64,
+    size: u64,
+    completed: bool,
+    created_at: Instant,
+    name: Option<String>,
+    labels: Vec<(&'static str, String)>,
+    location: &'static Location<'static>,
+    latency: Option<Latency>,
+    window: Option<ThroughputWindow>,
+    pending: bool,
+    last_item_at: Option<Instant>,
+    queue_depth: Option<QueueDepth>,
+    scope: Option<Arc<ScopeState>>,
+}
+
+/// The times of the items yielded within the last `length`, for sliding window throughput.
+#[derive(Debug)]
+struct ThroughputWindow {
+    length: Duration,
+    items: VecDeque<Instant>,
+}
+
+impl ThroughputWindow {
+    fn expire(&mut self, now: Instant) {
+        while self
+            .items
+            .front()
+            .is_some_and(|&at| now.duration_since(at) > self.length)
+        {
+            self.items.pop_front();
+        }
+    }
+}
+
+/// Returns the current time for the timestamps of a context, which tests move forward with
+/// `clock::advance` instead of sleeping.
+fn now() -> Instant {
+    #[cfg(test)]
+    return Instant::now() + clock::offset();
+
+    #[cfg(not(test))]
+    Instant::now()
+}
+
+/// The ID handed to the next wrapper that builds a context.
+static NEXT_ID: AtomicU64 = AtomicU64::new(1);
+
+impl Stats {
+    #[track_caller]
+    pub(crate) fn new() -> Self {
+        let scope = scope::current();
+
+        Self {
+            id: NEXT_ID.fetch_add(1, Ordering::Relaxed),
+            items: 0,
+            errors: 0,
+            bytes: 0,
+            size: 0,
+            completed: false,
+            created_at: now(),
+            name: None,
+            labels: scope
+                .as_ref()
+                .map_or_else(Vec::new, |scope| scope.labels().to_vec()),
+            location: Location::caller(),
+            latency: None,
+            window: None,
+            pending: false,
+            last_item_at: None,
+            queue_depth: None,
+            scope,
+        }
+    }
+
+    pub(crate) fn set_throughput_window(&mut self, length: Duration) {
+        self.window = Some(ThroughputWindow {
+            length,
+            items: VecDeque::new(),
+        });
+    }
+
+    pub(crate) fn enable_latency(&mut self) {
+        self.latency.get_or_insert_with(Latency::default);
+    }
+
+    /// Returns the time a poll starts at, if latency is being recorded.
+    #[inline]
+    pub(crate) fn poll_started(&self) -> Option<Instant> {
+        self.latency.as_ref().map(|_| Instant::now())
+    }
+
+    #[inline]
+    pub(crate) fn record_poll(&mut self, started: Option<Instant>, yielded_item: bool) {
+        if let (Some(latency), Some(started)) = (self.latency.as_mut(), started) {
+            latency.record_poll(started, yielded_item);
+        }
+    }
+
+    pub(crate) fn id(&self) -> u64 {
+        self.id
+    }
+
+    pub(crate) fn set_id(&mut self, id: u64) {
+        self.id = id;
+    }
+
+    pub(crate) fn set_name(&mut self, name: String) {
+        self.name = Some(name);
+    }
+
+    pub(crate) fn add_label(&mut self, key: &'static str, value: String) {
+        self.labels.push((key, value));
+    }
+
+    #[inline]
+    pub(crate) fn record_item(&mut self) {
+        self.items += 1;
+        self.pending = false;
+
+        let now = now();
+        self.last_item_at = Some(now);
+        if let Some(window) = self.window.as_mut() {
+            window.expire(now);
+            window.items.push_back(now);
+        }
+    }
+
+    #[inline]
+    pub(crate) fn record_error(&mut self) {
+        self.record_item();
+        self.errors += 1;
+    }
+
+    #[inline]
+    pub(crate) fn record_bytes(&mut self, bytes: usize) {
+        self.bytes += bytes as u64;
+    }
+
+    #[inline]
+    pub(crate) fn record_size(&mut self, size: usize) {
+        self.size += size as u64;
+    }
+
+    pub(crate) fn bytes(&self) -> u64 {
+        self.bytes
+    }
+
+    pub(crate) fn set_queue_depth(&mut self, depth: QueueDepth) {
+        self.queue_depth = Some(depth);
+    }
+
+    #[inline]
+    pub(crate) fn record_pending(&mut self) {
+        self.pending = true;
+    }
+
+    #[inline]
+    pub(crate) fn record_end(&mut self) {
+        self.completed = true;
+        self.pending = false;
+    }
+
+    /// Returns true if the wrapped value ran to its end.
+    pub(crate) fn is_completed(&self) -> bool {
+        self.completed
+    }
+
+    /// Builds the context handed to the drop closure, first reporting it to the
+    /// [`DropScope`](crate::DropScope) the wrapper was created in, if any, and to the registered
+    /// [`DropEventHandler`](crate::DropEventHandler)s.
+    pub(crate) fn context(&mut self) -> DropContext {
+        let dropped_at = now();
+        let window = self.window.as_mut().map(|window| {
+            window.expire(dropped_at);
+            (window.items.len(), window.length)
+        });
+
+        let reason = match (self.completed, self.errors > 0) {
+            (true, false) => DropReason::Completed,
+            (true, true) => DropReason::CompletedWithError,
+            (false, false) => DropReason::Cancelled,
+            (false, true) => DropReason::CancelledAfterError,
+        };
+
+        let context = DropContext {
+            id: self.id,
+            reason,
+            items: self.items,
+            errors: self.errors,
+            bytes: self.bytes,
+            size: self.size,
+            created_at: self.created_at,
+            dropped_at,
+            name: self.name.clone(),
+            labels: self.labels.clone(),
+            location: self.location,
+            latency: self.latency.clone(),
+            window,
+            pending: self.pending,
+            last_item_at: self.last_item_at,
+            queue_depth: self.queue_depth,
+        };
+
+        if let Some(scope) = self.scope.as_ref() {
+            scope.report(&context);
+        }
+        event::dispatch(&context);
+
+        context
+    }
+}
+
+/// Holds a closure taking a [`DropContext`] along with the [`Stats`] it is built from, and calls
+/// the closure once the holder is dropped. The context counterpart of `Dropper`.
+pub(crate) struct ContextDropper<U: ContextDropFn> {
+    pub(crate) stats: Stats,
+    // Skips the closure, but not the reporting of the context, if the wrapped value completed.
+    pub(crate) skip_on_complete: bool,
+    dropper: Once<U>,
+}
+
+impl<U: ContextDropFn> ContextDropper<U> {
+    #[track_caller]
+    pub(crate) fn new(dropper: U) -> Self {
+        Self {
+            stats: Stats::new(),
+            skip_on_complete: false,
+            dropper: Once::new(dropper),
+        }
+    }
+}
+
+impl<U: ContextDropFn> Drop for ContextDropper<U> {
+    fn drop(&mut self) {
+        if let Some(dropper) = self.dropper.take() {
+            let skip = self.skip_on_complete && self.stats.is_completed();
+            let context = self.stats.context();
+            if !skip {
+                dropper.call(context)
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod clock {
+    use std::{cell::Cell, time::Duration};
+
+    thread_local! {
+        // Per thread, so tests running in parallel don't move each other's clocks.
+        static OFFSET: Cell<Duration> = const { Cell::new(Duration::ZERO) };
+    }
+
+    pub(super) fn offset() -> Duration {
+        OFFSET.get()
+    }
+
+    /// Moves the clock of the current thread forward by `by`.
+    pub(crate) fn advance(by: Duration) {
+        OFFSET.set(OFFSET.get() + by);
+    }
+}
@@ -0,0 +1,489 @@
+use futures_core::{Future, Stream};
+use pin_project::pin_project;
+use std::{
+    future::poll_fn,
+    pin::{pin, Pin},
+    task::{Context, Poll},
+};
+
+use crate::context::{ContextDropFn, ContextDropper};
+
+/// The measure function of a [`ContextDropStream`] that hasn't been given one with
+/// [`ContextDropStream::measure`].
+pub type MeasureFn<T> = fn(&T) -> usize;
+
+/// A stream that wraps another stream with a closure that is called with a
+/// [`DropContext`](crate::DropContext) once it is dropped.
+///
+/// Besides why the stream ended and how many items it yielded, the context carries when the
+/// stream was created and dropped, where in the source it was created, and the
+/// [`name`](ContextDropStream::name) and [`labels`](ContextDropStream::label) it was given, so
+/// closures don't need to capture that state themselves.
+///
+/// Example
+/// ```
+/// use futures::{executor::block_on_stream, stream};
+/// use drop_stream::DropStreamExt;
+///
+/// let mut report = None;
+/// let report_ref = &mut report;
+/// let stream = stream::iter([1, 2, 3])
+///     .on_drop_ctx(move |context| {
+///         *report_ref = Some(format!(
+///             "{} ({}) yielded {} items",
+///             context.name().unwrap(),
+///             context.label("route").unwrap(),
+///             context.items(),
+///         ))
+///     })
+///     .name("events")
+///     .label("route", "/feed");
+///
+/// assert_eq!(block_on_stream(stream).count(), 3);
+/// assert_eq!(report.as_deref(), Some("events (/feed) yielded 3 items"));
+/// ```
+#[pin_project]
+pub struct ContextDropStream<S, U, M = MeasureFn<<S as Stream>::Item>>
+where
+    S: Stream,
+    U: ContextDropFn,
+    M: FnMut(&S::Item) -> usize,
+{
+    // Declared before the stream so the closure runs before the inner stream is dropped.
+    dropper: ContextDropper<U>,
+    measure: Option<M>,
+    #[pin]
+    stream: S,
+}
+
+impl<S: Stream, U: ContextDropFn> ContextDropStream<S, U> {
+    #[track_caller]
+    pub fn new(stream: S, dropper: U) -> Self {
+        Self {
+            dropper: ContextDropper::new(dropper),
+            measure: None,
+            stream,
+        }
+    }
+}
+
+impl<S, U, M> ContextDropStream<S, U, M>
+where
+    S: Stream,
+    U: ContextDropFn,
+    M: FnMut(&S::Item) -> usize,
+{
+    /// Adds up `measure(&item)` for every yielded item, reported as
+    /// [`DropContext::size`](crate::DropContext::size), replacing any previous measure function.
+    ///
+    /// This generalizes [`on_drop_with_bytes`](crate::DropStreamExt::on_drop_with_bytes) to any
+    /// notion of size, such as the encoded length of a message or the rows in a batch.
+    pub fn measure<M2: FnMut(&S::Item) -> usize>(self, measure: M2) -> ContextDropStream<S, U, M2> {
+        let ContextDropStream {
+            dropper, stream, ..
+        } = self;
+
+        ContextDropStream {
+            dropper,
+            measure: Some(measure),
+            stream,
+        }
+    }
+
+    /// Returns the ID of the stream, reported as [`DropContext::id`](crate::DropContext::id).
+    ///
+    /// Unless set with [`with_id`](Self::with_id), every wrapper that builds a context is given
+    /// the next ID of a process-wide counter when it is created.
+    pub fn id(&self) -> u64 {
+        self.dropper.stats.id()
+    }
+
+    /// Replaces the ID of the stream, such as with a request ID to correlate it with other logs.
+    pub fn with_id(mut self, id: u64) -> Self {
+        self.dropper.stats.set_id(id);
+        self
+    }
+
+    /// Names the stream in its context, replacing any previous name.
+    pub fn name(mut self, name: impl Into<String>) -> Self {
+        self.dropper.stats.set_name(name.into());
+        self
+    }
+
+    /// Records how long each poll of the inner stream takes and the gaps between items, reported
+    /// as [`DropContext::latency`](crate::DropContext::latency).
+    pub fn record_latency(mut self) -> Self {
+        self.dropper.stats.enable_latency();
+        self
+    }
+
+    /// Tracks throughput over a sliding window of `length`, reported as
+    /// [`DropContext::window_items_per_second`](crate::DropContext::window_items_per_second).
+    pub fn throughput_window(mut self, length: std::time::Duration) -> Self {
+        self.dropper.stats.set_throughput_window(length);
+        self
+    }
+
+    /// Adds a label to the stream's context.
+    pub fn label(mut self, key: &'static str, value: impl Into<String>) -> Self {
+        self.dropper.stats.add_label(key, value.into());
+        self
+    }
+
+    /// Only calls the closure if the stream is dropped before it ended, so cleanup that is only
+    /// needed for a cancelled stream doesn't have to check the reason itself. The context is
+    /// still reported to the [`DropScope`](crate::DropScope) and observers either way.
+    pub fn skip_on_complete(mut self) -> Self {
+        self.dropper.skip_on_complete = true;
+        self
+    }
+
+    /// Collects every item of the stream, as `StreamExt::collect` does.
+    ///
+    /// The stream runs to its end and is dropped before the collection is returned, so the
+    /// closure has been called with [`DropReason::Completed`](crate::DropReason::Completed) and
+    /// the final counts once the future resolves.
+    pub async fn collect<C: Default + Extend<S::Item>>(self) -> C {
+        let mut stream = pin!(self);
+        let mut collection = C::default();
+        while let Some(item) = poll_fn(|cx| stream.as_mut().poll_next(cx)).await {
+            collection.extend([item]);
+        }
+
+        collection
+    }
+
+    /// Runs `f` for every item of the stream and awaits the future it returns, as
+    /// `StreamExt::for_each` does. The closure has been called once the future resolves, see
+    /// [`collect`](Self::collect).
+    pub async fn for_each<F, Fut>(self, mut f: F)
+    where
+        F: FnMut(S::Item) -> Fut,
+        Fut: Future<Output = ()>,
+    {
+        let mut stream = pin!(self);
+        while let Some(item) = poll_fn(|cx| stream.as_mut().poll_next(cx)).await {
+            f(item).await;
+        }
+    }
+
+    /// Sends every item of a fallible stream into `sink` and closes it, as `StreamExt::forward`
+    /// does. The closure has been called once the future resolves, see
+    /// [`collect`](Self::collect).
+    ///
+    /// If the stream yields an error or the sink fails, the error is returned and the stream is
+    /// dropped before it ended.
+    #[cfg(feature = "sink")]
+    pub async fn forward<Si, T, E>(self, sink: Si) -> Result<(), E>
+    where
+        S: Stream<Item = Result<T, E>>,
+        Si: futures_sink::Sink<T, Error = E>,
+    {
+        let mut stream = pin!(self);
+        let mut sink = pin!(sink);
+        loop {
+            poll_fn(|cx| sink.as_mut().poll_ready(cx)).await?;
+            match poll_fn(|cx| stream.as_mut().poll_next(cx)).await {
+                Some(Ok(item)) => sink.as_mut().start_send(item)?,
+                Some(Err(error)) => return Err(error),
+                None => break,
+            }
+        }
+
+        poll_fn(|cx| sink.as_mut().poll_close(cx)).await
+    }
+}
+
+impl<S, U, M> Stream for ContextDropStream<S, U, M>
+where
+    S: Stream,
+    U: ContextDropFn,
+    M: FnMut(&S::Item) -> usize,
+{
+    type Item = S::Item;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let this = self.project();
+
+        let started = this.dropper.stats.poll_started();
+        let poll = this.stream.poll_next(cx);
+        this.dropper
+            .stats
+            .record_poll(started, matches!(poll, Poll::Ready(Some(_))));
+
+        match &poll {
+            Poll::Ready(Some(item)) => {
+                this.dropper.stats.record_item();
+                if let Some(measure) = this.measure.as_mut() {
+                    this.dropper.stats.record_size(measure(item));
+                }
+            }
+            Poll::Ready(None) => this.dropper.stats.record_end(),
+            Poll::Pending => this.dropper.stats.record_pending(),
+        }
+
+        poll
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.stream.size_hint()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{task::Poll, time::Duration};
+
+    use crate::{context::clock, DropReason, DropStreamExt};
+    use futures::{
+        stream::{iter, repeat},
+        Stream, StreamExt,
+    };
+
+    #[test]
+    fn context_carries_name_labels_and_location() {
+        let mut context = None;
+        let line;
+
+        {
+            let context_ref = &mut context;
+            let drop_stream = iter([1, 2, 3]).on_drop_ctx(move |c| *context_ref = Some(c));
+            line = line!() - 1;
+            let drop_stream = drop_stream
+                .name("numbers")
+                .label("shard", "7")
+                .label("tenant", String::from("acme"));
+
+            let mut drop_stream = Box::pin(drop_stream);
+
+            let waker = futures::task::noop_waker();
+            let mut cx = futures::task::Context::from_waker(&waker);
+            assert_eq!(
+                drop_stream.as_mut().poll_next(&mut cx),
+                Poll::Ready(Some(1))
+            );
+        }
+
+        let context = context.unwrap();
+        assert_eq!(context.reason(), DropReason::Cancelled);
+        assert_eq!(context.items(), 1);
+        assert_eq!(context.name(), Some("numbers"));
+        assert_eq!(context.label("tenant"), Some("acme"));
+        assert_eq!(
+            context.labels(),
+            [("shard", "7".to_string()), ("tenant", "acme".to_string())]
+        );
+        assert_eq!(context.location().file(), file!());
+        assert_eq!(context.location().line(), line);
+    }
+
+    #[test]
+    fn skip_on_complete_only_runs_for_cancelled_streams() {
+        let mut reasons = Vec::new();
+
+        {
+            let reasons_ref = std::sync::Mutex::new(&mut reasons);
+            let finished = iter([1, 2])
+                .on_drop_ctx(|c| reasons_ref.lock().unwrap().push(c.reason()))
+                .skip_on_complete();
+            assert_eq!(futures::executor::block_on_stream(finished).count(), 2);
+
+            let cancelled = repeat(1)
+                .on_drop_ctx(|c| reasons_ref.lock().unwrap().push(c.reason()))
+                .skip_on_complete();
+            assert_eq!(
+                futures::executor::block_on_stream(cancelled).next(),
+                Some(1)
+            );
+        }
+
+        assert_eq!(reasons, [DropReason::Cancelled]);
+    }
+
+    #[test]
+    fn terminal_helpers_run_closure_before_resolving() {
+        let reasons = std::sync::Mutex::new(Vec::new());
+
+        futures::executor::block_on(async {
+            let collected: Vec<_> = iter([1, 2, 3])
+                .on_drop_ctx(|c| reasons.lock().unwrap().push((c.reason(), c.items())))
+                .collect()
+                .await;
+            assert_eq!(collected, [1, 2, 3]);
+            assert_eq!(reasons.lock().unwrap().len(), 1);
+
+            let mut sum = 0;
+            iter([4, 5])
+                .on_drop_ctx(|c| reasons.lock().unwrap().push((c.reason(), c.items())))
+                .for_each(|item| {
+                    sum += item;
+                    futures::future::ready(())
+                })
+                .await;
+            assert_eq!(sum, 9);
+        });
+
+        assert_eq!(
+            *reasons.lock().unwrap(),
+            [(DropReason::Completed, 3), (DropReason::Completed, 2)]
+        );
+    }
+
+    #[cfg(feature = "sink")]
+    #[test]
+    fn forward_stops_at_the_first_error() {
+        let mut reason = None;
+        let mut sent = Vec::new();
+
+        let result = futures::executor::block_on(
+            iter([Ok(1), Err("broken"), Ok(2)])
+                .on_drop_ctx(|c| reason = Some(c.reason()))
+                .forward(futures::sink::unfold((), |(), item| {
+                    sent.push(item);
+                    futures::future::ready(Ok(()))
+                })),
+        );
+
+        assert_eq!(result, Err("broken"));
+        assert_eq!(sent, [1]);
+        assert_eq!(reason, Some(DropReason::Cancelled));
+    }
+
+    #[test]
+    fn timestamps_cover_the_lifetime() {
+        let mut context = None;
+
+        {
+            let context_ref = &mut context;
+            let _drop_stream = iter([1]).on_drop_ctx(move |c| *context_ref = Some(c));
+            clock::advance(Duration::from_secs(5));
+        }
+
+        let context = context.unwrap();
+        assert!(context.dropped_at() >= context.created_at());
+        assert!(context.lifetime() >= Duration::from_secs(5));
+    }
+
+    #[test]
+    fn latency_is_only_recorded_when_enabled() {
+        let mut contexts = Vec::new();
+
+        {
+            let contexts_ref = std::sync::Mutex::new(&mut contexts);
+            let recorded = iter([1, 2, 3])
+                .on_drop_ctx(|c| contexts_ref.lock().unwrap().push(c))
+                .record_latency();
+            let plain = iter([1]).on_drop_ctx(|c| contexts_ref.lock().unwrap().push(c));
+
+            assert_eq!(futures::executor::block_on_stream(recorded).count(), 3);
+            drop(plain);
+        }
+
+        let latency = contexts[0].latency().unwrap();
+        assert_eq!(latency.poll().count(), 4);
+        assert_eq!(latency.item_gaps().count(), 2);
+        assert!(contexts[1].latency().is_none());
+    }
+
+    #[test]
+    fn throughput_over_lifetime_and_window() {
+        let mut context = None;
+
+        {
+            let context_ref = &mut context;
+            let drop_stream = iter([1, 2, 3])
+                .on_drop_ctx(move |c| *context_ref = Some(c))
+                .throughput_window(Duration::from_secs(10));
+
+            let mut drop_stream = futures::executor::block_on_stream(drop_stream);
+            assert_eq!(drop_stream.next(), Some(1));
+            clock::advance(Duration::from_secs(60));
+            assert_eq!(drop_stream.next(), Some(2));
+        }
+
+        let context = context.unwrap();
+        let lifetime = context.lifetime().as_secs_f64();
+        assert!(lifetime >= 60.0);
+        assert_eq!(context.items_per_second(), Some(2.0 / lifetime));
+        // Only the second item was yielded within the window.
+        assert_eq!(context.window_items_per_second(), Some(1.0 / 10.0));
+    }
+
+    #[test]
+    fn measure_adds_up_item_sizes() {
+        let mut size = 0;
+
+        {
+            let size_ref = &mut size;
+            let rows = iter([vec![1, 2], vec![3], vec![4, 5, 6]])
+                .on_drop_ctx(move |c| *size_ref = c.size())
+                .measure(Vec::len);
+
+            assert_eq!(futures::executor::block_on_stream(rows).count(), 3);
+        }
+
+        assert_eq!(size, 6);
+    }
+
+    #[test]
+    fn ids_are_unique_unless_supplied() {
+        let first = repeat(true).on_drop_ctx(|_| {});
+        let second = repeat(true).on_drop_ctx(|_| {});
+        assert!(second.id() > first.id());
+
+        let mut id = 0;
+        {
+            let id_ref = &mut id;
+            let drop_stream = repeat(true)
+                .on_drop_ctx(move |c| *id_ref = c.id())
+                .with_id(42);
+            assert_eq!(drop_stream.id(), 42);
+        }
+
+        assert_eq!(id, 42);
+    }
+
+    #[test]
+    fn context_records_whether_the_last_poll_was_pending() {
+        let mut pending = Vec::new();
+
+        for polls in [1, 2] {
+            let pending_ref = &mut pending;
+            let drop_stream = iter([1])
+                .chain(futures::stream::pending())
+                .on_drop_ctx(move |c| pending_ref.push(c.was_pending()));
+
+            let mut drop_stream = Box::pin(drop_stream);
+
+            let waker = futures::task::noop_waker();
+            let mut cx = futures::task::Context::from_waker(&waker);
+            for _ in 0..polls {
+                let _ = drop_stream.as_mut().poll_next(&mut cx);
+            }
+        }
+
+        assert_eq!(pending, [false, true]);
+    }
+
+    #[test]
+    fn since_last_item_measures_time_before_drop() {
+        let mut contexts = Vec::new();
+
+        {
+            let contexts_ref = std::sync::Mutex::new(&mut contexts);
+            let read = iter([1]).on_drop_ctx(|c| contexts_ref.lock().unwrap().push(c));
+            let unread = iter([1]).on_drop_ctx(|c| contexts_ref.lock().unwrap().push(c));
+
+            let mut read = futures::executor::block_on_stream(read);
+            assert_eq!(read.next(), Some(1));
+            clock::advance(Duration::from_secs(5));
+            drop(read);
+            drop(unread);
+        }
+
+        let since_last_item = contexts[0].since_last_item().unwrap();
+        assert!(since_last_item >= Duration::from_secs(5));
+        assert!(since_last_item <= contexts[0].lifetime());
+        assert_eq!(contexts[1].since_last_item(), None);
+    }
+}
@@ -0,0 +1,197 @@
+use futures_core::Stream;
+use pin_project::pin_project;
+use std::{
+    fmt,
+    pin::Pin,
+    sync::{Arc, Mutex, MutexGuard},
+    task::{Context, Poll},
+};
+
+type Callback = Box<dyn FnOnce() + Send>;
+
+#[derive(Default)]
+struct Shared {
+    callback: Mutex<Option<Callback>>,
+}
+
+impl Shared {
+    fn lock(&self) -> MutexGuard<'_, Option<Callback>> {
+        self.callback.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// Runs the callback if it is still armed, outside of the lock.
+    fn fire(&self) -> bool {
+        let callback = self.lock().take();
+        let fired = callback.is_some();
+        if let Some(callback) = callback {
+            callback();
+        }
+
+        fired
+    }
+}
+
+/// Runs the callback on drop, unless the paired [`DropControl`] already disarmed or fired it.
+struct ControlDropper {
+    shared: Arc<Shared>,
+}
+
+impl Drop for ControlDropper {
+    fn drop(&mut self) {
+        self.shared.fire();
+    }
+}
+
+/// A stream whose drop closure can be overridden at runtime through the paired [`DropControl`].
+///
+/// Supervisors that own the teardown policy of many streams can disarm a stream's closure, for
+/// example because its resources were already handed elsewhere, or run it early, for example to
+/// release a slot, while the stream stays usable. Either way the closure runs at most once.
+///
+/// Example
+/// ```
+/// use std::sync::{atomic::{AtomicUsize, Ordering}, Arc};
+/// use drop_stream::DropStreamExt;
+///
+/// let runs = Arc::new(AtomicUsize::new(0));
+/// let runs_ref = runs.clone();
+/// let (stream, control) = futures::stream::repeat(true).on_drop_controlled(move || {
+///     runs_ref.fetch_add(1, Ordering::SeqCst);
+/// });
+///
+/// assert!(control.fire());
+/// assert_eq!(runs.load(Ordering::SeqCst), 1);
+///
+/// // The stream is still usable, and dropping it doesn't run the closure again.
+/// assert_eq!(futures::executor::block_on_stream(stream).next(), Some(true));
+/// assert_eq!(runs.load(Ordering::SeqCst), 1);
+/// ```
+#[pin_project]
+pub struct ControlledDropStream<S> {
+    // Declared before the stream so the closure runs before the inner stream is dropped.
+    dropper: ControlDropper,
+    #[pin]
+    stream: S,
+}
+
+impl<S: Stream> ControlledDropStream<S> {
+    pub fn new<U: FnOnce() + Send + 'static>(stream: S, dropper: U) -> (Self, DropControl) {
+        let shared = Arc::new(Shared {
+            callback: Mutex::new(Some(Box::new(dropper))),
+        });
+
+        let stream = Self {
+            dropper: ControlDropper {
+                shared: shared.clone(),
+            },
+            stream,
+        };
+
+        (stream, DropControl { shared })
+    }
+
+    /// Returns a handle controlling the closure of this stream.
+    pub fn control(&self) -> DropControl {
+        DropControl {
+            shared: self.dropper.shared.clone(),
+        }
+    }
+}
+
+impl<S: Stream> Stream for ControlledDropStream<S> {
+    type Item = S::Item;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        self.project().stream.poll_next(cx)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.stream.size_hint()
+    }
+}
+
+impl<S> fmt::Debug for ControlledDropStream<S> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ControlledDropStream")
+            .field("armed", &self.dropper.shared.lock().is_some())
+            .finish_non_exhaustive()
+    }
+}
+
+/// Disarms or fires the closure of a [`ControlledDropStream`]. Dropping the handle does nothing,
+/// and it can be cloned.
+#[derive(Clone)]
+pub struct DropControl {
+    shared: Arc<Shared>,
+}
+
+impl DropControl {
+    /// Discards the closure without running it. Returns false if it already ran or was
+    /// disarmed.
+    pub fn disarm(&self) -> bool {
+        self.shared.lock().take().is_some()
+    }
+
+    /// Runs the closure now instead of when the stream is dropped. Returns false if it already
+    /// ran or was disarmed.
+    pub fn fire(&self) -> bool {
+        self.shared.fire()
+    }
+
+    /// Returns true if the closure will still run.
+    pub fn is_armed(&self) -> bool {
+        self.shared.lock().is_some()
+    }
+}
+
+impl fmt::Debug for DropControl {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("DropControl")
+            .field("armed", &self.is_armed())
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    };
+
+    use crate::DropStreamExt;
+    use futures::stream::repeat;
+
+    #[test]
+    fn disarmed_closure_never_runs() {
+        let runs = Arc::new(AtomicUsize::new(0));
+        let runs_ref = runs.clone();
+
+        let (stream, control) = repeat(true).on_drop_controlled(move || {
+            runs_ref.fetch_add(1, Ordering::SeqCst);
+        });
+        assert!(stream.control().is_armed());
+
+        assert!(control.disarm());
+        assert!(!control.disarm());
+        assert!(!control.fire());
+        drop(stream);
+
+        assert_eq!(runs.load(Ordering::SeqCst), 0);
+    }
+
+    #[test]
+    fn armed_closure_runs_on_drop() {
+        let runs = Arc::new(AtomicUsize::new(0));
+        let runs_ref = runs.clone();
+
+        let (stream, control) = repeat(true).on_drop_controlled(move || {
+            runs_ref.fetch_add(1, Ordering::SeqCst);
+        });
+        drop(stream);
+
+        assert_eq!(runs.load(Ordering::SeqCst), 1);
+        assert!(!control.is_armed());
+        assert!(!control.fire());
+    }
+}
@@ -0,0 +1,246 @@
+use futures_core::Stream;
+use pin_project::{pin_project, pinned_drop};
+use std::{
+    collections::BTreeMap,
+    fmt,
+    ops::Deref,
+    pin::Pin,
+    sync::{Arc, Mutex, MutexGuard},
+    task::{Context, Poll},
+};
+
+use crate::TrySend;
+
+struct State<T> {
+    // Clones of the yielded items that were not acknowledged yet, by delivery order.
+    in_flight: BTreeMap<u64, T>,
+    next_id: u64,
+    // Set once the unacknowledged items were forwarded.
+    closed: bool,
+}
+
+struct Shared<T>(Mutex<State<T>>);
+
+impl<T> Shared<T> {
+    fn lock(&self) -> MutexGuard<'_, State<T>> {
+        self.0.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+/// A stream for at-least-once pipelines that forwards every item it yielded but that was not
+/// acknowledged to a dead-letter sender once it is dropped, so a consumer that crashes or
+/// disconnects mid-batch doesn't lose the items it was working on.
+///
+/// Items are yielded as a [`Delivery`], which is acknowledged with [`Delivery::ack`] once the item
+/// has been fully processed. A clone of every unacknowledged item is kept, and these are handed to
+/// the sender in the order they were yielded when the stream is dropped. Items the sender rejects
+/// are discarded. Acknowledging a delivery after that does nothing, as its item has already been
+/// forwarded.
+///
+/// Example
+/// ```
+/// use futures::{executor::block_on_stream, stream};
+/// use drop_stream::DropStreamExt;
+///
+/// let (dead_letters, receiver) = std::sync::mpsc::channel();
+/// let jobs = stream::iter(["resize", "encode", "upload"]).dead_letter_on_drop(dead_letters);
+///
+/// let mut jobs = block_on_stream(jobs);
+/// jobs.next().unwrap().ack();
+/// let _crashed = jobs.next().unwrap();
+/// drop(jobs);
+///
+/// assert_eq!(receiver.try_iter().collect::<Vec<_>>(), ["encode"]);
+/// ```
+#[pin_project(PinnedDrop)]
+pub struct DeadLetterStream<S, Tx>
+where
+    S: Stream,
+    S::Item: Clone,
+    Tx: TrySend<S::Item>,
+{
+    #[pin]
+    stream: S,
+    shared: Arc<Shared<S::Item>>,
+    sender: Tx,
+}
+
+impl<S, Tx> DeadLetterStream<S, Tx>
+where
+    S: Stream,
+    S::Item: Clone,
+    Tx: TrySend<S::Item>,
+{
+    pub fn new(stream: S, sender: Tx) -> Self {
+        Self {
+            stream,
+            shared: Arc::new(Shared(Mutex::new(State {
+                in_flight: BTreeMap::new(),
+                next_id: 0,
+                closed: false,
+            }))),
+            sender,
+        }
+    }
+
+    /// Returns the number of yielded items that were not acknowledged yet.
+    pub fn in_flight(&self) -> usize {
+        self.shared.lock().in_flight.len()
+    }
+}
+
+impl<S, Tx> Stream for DeadLetterStream<S, Tx>
+where
+    S: Stream,
+    S::Item: Clone,
+    Tx: TrySend<S::Item>,
+{
+    type Item = Delivery<S::Item>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let this = self.project();
+
+        let Poll::Ready(item) = this.stream.poll_next(cx) else {
+            return Poll::Pending;
+        };
+
+        Poll::Ready(item.map(|item| {
+            let mut state = this.shared.lock();
+            let id = state.next_id;
+            state.next_id += 1;
+            state.in_flight.insert(id, item.clone());
+
+            Delivery {
+                item,
+                id,
+                shared: this.shared.clone(),
+            }
+        }))
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.stream.size_hint()
+    }
+}
+
+#[pinned_drop]
+impl<S, Tx> PinnedDrop for DeadLetterStream<S, Tx>
+where
+    S: Stream,
+    S::Item: Clone,
+    Tx: TrySend<S::Item>,
+{
+    fn drop(self: Pin<&mut Self>) {
+        let this = self.project();
+
+        let in_flight = {
+            let mut state = this.shared.lock();
+            state.closed = true;
+            std::mem::take(&mut state.in_flight)
+        };
+
+        for item in in_flight.into_values() {
+            // Rejected items are discarded, as there is nowhere else to put them.
+            let _ = this.sender.try_send(item);
+        }
+    }
+}
+
+impl<S, Tx> fmt::Debug for DeadLetterStream<S, Tx>
+where
+    S: Stream,
+    S::Item: Clone,
+    Tx: TrySend<S::Item>,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("DeadLetterStream")
+            .field("in_flight", &self.in_flight())
+            .finish_non_exhaustive()
+    }
+}
+
+/// An item yielded by a [`DeadLetterStream`], which is forwarded to the dead-letter sender unless
+/// it is acknowledged before the stream is dropped.
+///
+/// Dropping a delivery without acknowledging it leaves it unacknowledged.
+pub struct Delivery<T> {
+    item: T,
+    id: u64,
+    shared: Arc<Shared<T>>,
+}
+
+impl<T> Delivery<T> {
+    /// Acknowledges the item, so it is not forwarded, and returns it.
+    pub fn ack(self) -> T {
+        self.shared.lock().in_flight.remove(&self.id);
+        self.item
+    }
+
+    /// Returns true if the stream was dropped and the item was forwarded, unless it was
+    /// acknowledged before.
+    pub fn is_dead_lettered(&self) -> bool {
+        let state = self.shared.lock();
+        state.closed && !state.in_flight.contains_key(&self.id)
+    }
+
+    /// Returns the item without acknowledging it.
+    pub fn into_inner(self) -> T {
+        self.item
+    }
+}
+
+impl<T> Deref for Delivery<T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        &self.item
+    }
+}
+
+impl<T: fmt::Debug> fmt::Debug for Delivery<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Delivery")
+            .field("item", &self.item)
+            .field("id", &self.id)
+            .finish_non_exhaustive()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::DropStreamExt;
+    use futures::{executor::block_on_stream, stream::iter};
+
+    #[test]
+    fn unacked_items_are_forwarded_in_order() {
+        let mut dead_letters = Vec::new();
+
+        {
+            let jobs = iter([1, 2, 3, 4]).dead_letter_on_drop(|job| dead_letters.push(job));
+            let mut jobs = block_on_stream(jobs);
+
+            let first = jobs.next().unwrap();
+            let second = jobs.next().unwrap();
+            assert_eq!(*jobs.next().unwrap(), 3);
+            assert_eq!(second.ack(), 2);
+            // Dropping a delivery leaves it unacknowledged.
+            drop(first);
+        }
+
+        assert_eq!(dead_letters, [1, 3]);
+    }
+
+    #[test]
+    fn late_ack_after_drop_is_ignored() {
+        let (sender, receiver) = std::sync::mpsc::channel();
+        let jobs = iter(["a"]).dead_letter_on_drop(sender);
+
+        let mut jobs = block_on_stream(jobs);
+        let delivery = jobs.next().unwrap();
+        drop(jobs);
+
+        assert!(delivery.is_dead_lettered());
+        assert_eq!(delivery.ack(), "a");
+        assert_eq!(receiver.try_iter().collect::<Vec<_>>(), ["a"]);
+    }
+}
@@ -0,0 +1,332 @@
+use futures_core::Stream;
+use pin_project::{pin_project, pinned_drop};
+use std::{
+    fmt,
+    ops::{Deref, DerefMut},
+    pin::Pin,
+    task::{Context, Poll},
+    time::Duration,
+};
+use tokio::time::{Instant, Interval};
+use tokio_util::time::{delay_queue::Expired, DelayQueue};
+
+use crate::dropper::Once;
+
+/// A `DelayQueue` that hands its still scheduled entries to a closure once it is dropped, so a
+/// scheduler can persist or reschedule the pending work instead of losing it.
+///
+/// The closure receives the entries in the order they would have expired, each with its deadline,
+/// and is called with no entries if the queue was empty. Entries that expired but were not yielded
+/// yet come first. The wrapper dereferences to the queue, so entries can be inserted, reset and
+/// removed as usual, and [`into_inner`](Self::into_inner) takes the queue back out without calling
+/// the closure.
+///
+/// Example
+/// ```
+/// # #[tokio::main(flavor = "current_thread", start_paused = true)]
+/// # async fn main() {
+/// use std::time::Duration;
+/// use drop_stream::DropDelayQueue;
+/// use tokio_util::time::DelayQueue;
+///
+/// let mut pending = Vec::new();
+/// {
+///     let mut retries = DropDelayQueue::new(DelayQueue::new(), |entries| {
+///         pending = entries.into_iter().map(|entry| entry.into_inner()).collect();
+///     });
+///     retries.insert("upload-2", Duration::from_secs(20));
+///     retries.insert("upload-1", Duration::from_secs(10));
+/// }
+///
+/// assert_eq!(pending, ["upload-1", "upload-2"]);
+/// # }
+/// ```
+#[pin_project(PinnedDrop)]
+pub struct DropDelayQueue<T, U: FnOnce(Vec<Expired<T>>)> {
+    queue: DelayQueue<T>,
+    dropper: Once<U>,
+}
+
+impl<T, U: FnOnce(Vec<Expired<T>>)> DropDelayQueue<T, U> {
+    pub fn new(queue: DelayQueue<T>, dropper: U) -> Self {
+        Self {
+            queue,
+            dropper: Once::new(dropper),
+        }
+    }
+
+    /// Returns the queue without calling the closure.
+    pub fn into_inner(mut self) -> DelayQueue<T> {
+        self.dropper.take();
+        std::mem::take(&mut self.queue)
+    }
+}
+
+impl<T, U: FnOnce(Vec<Expired<T>>)> Deref for DropDelayQueue<T, U> {
+    type Target = DelayQueue<T>;
+
+    fn deref(&self) -> &DelayQueue<T> {
+        &self.queue
+    }
+}
+
+impl<T, U: FnOnce(Vec<Expired<T>>)> DerefMut for DropDelayQueue<T, U> {
+    fn deref_mut(&mut self) -> &mut DelayQueue<T> {
+        &mut self.queue
+    }
+}
+
+impl<T, U: FnOnce(Vec<Expired<T>>)> Stream for DropDelayQueue<T, U> {
+    type Item = Expired<T>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        self.project().queue.poll_expired(cx)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.queue.len(), None)
+    }
+}
+
+#[pinned_drop]
+impl<T, U: FnOnce(Vec<Expired<T>>)> PinnedDrop for DropDelayQueue<T, U> {
+    fn drop(self: Pin<&mut Self>) {
+        let this = self.project();
+
+        let Some(dropper) = this.dropper.take() else {
+            return;
+        };
+
+        let mut entries = Vec::with_capacity(this.queue.len());
+        while let Some(key) = this.queue.peek() {
+            entries.push(this.queue.remove(&key));
+        }
+        dropper(entries)
+    }
+}
+
+impl<T, U: FnOnce(Vec<Expired<T>>)> fmt::Debug for DropDelayQueue<T, U> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("DropDelayQueue")
+            .field("len", &self.queue.len())
+            .finish_non_exhaustive()
+    }
+}
+
+/// A tokio `Interval` that tells a closure which ticks it missed once it is dropped, so a scheduler
+/// can catch up on them, or persist the next deadline, instead of silently skipping them.
+///
+/// The stream yields the deadline of every tick. Once it is dropped, the closure receives the
+/// [`Ticks`] it yielded, along with the next deadline and how many ticks were due by then without
+/// being yielded. Both are worked out from the last yielded tick and the period, as the default
+/// [`MissedTickBehavior::Burst`](tokio::time::MissedTickBehavior::Burst) would schedule them, and
+/// are unknown if no tick was yielded. The wrapper dereferences to the interval, but resetting it
+/// only shows in the next deadline once the stream yielded another tick.
+///
+/// Example
+/// ```
+/// # #[tokio::main(flavor = "current_thread", start_paused = true)]
+/// # async fn main() {
+/// use std::time::Duration;
+/// use drop_stream::{DropInterval, Ticks};
+/// use futures::StreamExt;
+///
+/// let mut missed = 0;
+/// {
+///     let mut ticks = DropInterval::new(tokio::time::interval(Duration::from_secs(10)), |ticks: Ticks| {
+///         missed = ticks.missed();
+///     });
+///     ticks.next().await;
+///     tokio::time::advance(Duration::from_secs(25)).await;
+/// }
+///
+/// assert_eq!(missed, 2);
+/// # }
+/// ```
+#[pin_project(PinnedDrop)]
+pub struct DropInterval<U: FnOnce(Ticks)> {
+    interval: Interval,
+    yielded: u64,
+    last: Option<Instant>,
+    dropper: Once<U>,
+}
+
+impl<U: FnOnce(Ticks)> DropInterval<U> {
+    pub fn new(interval: Interval, dropper: U) -> Self {
+        Self {
+            interval,
+            yielded: 0,
+            last: None,
+            dropper: Once::new(dropper),
+        }
+    }
+}
+
+impl<U: FnOnce(Ticks)> Deref for DropInterval<U> {
+    type Target = Interval;
+
+    fn deref(&self) -> &Interval {
+        &self.interval
+    }
+}
+
+impl<U: FnOnce(Ticks)> DerefMut for DropInterval<U> {
+    fn deref_mut(&mut self) -> &mut Interval {
+        &mut self.interval
+    }
+}
+
+impl<U: FnOnce(Ticks)> Stream for DropInterval<U> {
+    type Item = Instant;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let this = self.project();
+
+        let tick = std::task::ready!(this.interval.poll_tick(cx));
+        *this.yielded += 1;
+        *this.last = Some(tick);
+        Poll::Ready(Some(tick))
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (usize::MAX, None)
+    }
+}
+
+#[pinned_drop]
+impl<U: FnOnce(Ticks)> PinnedDrop for DropInterval<U> {
+    fn drop(self: Pin<&mut Self>) {
+        let this = self.project();
+
+        let Some(dropper) = this.dropper.take() else {
+            return;
+        };
+
+        dropper(Ticks {
+            yielded: *this.yielded,
+            next: this.last.map(|last| last + this.interval.period()),
+            period: this.interval.period(),
+            dropped_at: Instant::now(),
+        })
+    }
+}
+
+impl<U: FnOnce(Ticks)> fmt::Debug for DropInterval<U> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("DropInterval")
+            .field("period", &self.interval.period())
+            .field("yielded", &self.yielded)
+            .finish_non_exhaustive()
+    }
+}
+
+/// The ticks of a [`DropInterval`], as of when it was dropped.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Ticks {
+    yielded: u64,
+    next: Option<Instant>,
+    period: Duration,
+    dropped_at: Instant,
+}
+
+impl Ticks {
+    /// Returns how many ticks the stream yielded.
+    pub fn yielded(&self) -> u64 {
+        self.yielded
+    }
+
+    /// Returns when the tick after the last yielded one was due, or `None` if no tick was yielded.
+    pub fn next_deadline(&self) -> Option<Instant> {
+        self.next
+    }
+
+    /// Returns how many ticks were due by the time the stream was dropped without being yielded.
+    /// Zero if no tick was yielded.
+    pub fn missed(&self) -> u64 {
+        match self.next {
+            Some(next) if next <= self.dropped_at => {
+                let behind = (self.dropped_at - next).as_nanos() / self.period.as_nanos();
+                u64::try_from(behind).unwrap_or(u64::MAX - 1) + 1
+            }
+            _ => 0,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use crate::{DropDelayQueue, DropInterval, Ticks};
+    use futures::{Stream, StreamExt};
+    use tokio_util::time::DelayQueue;
+
+    #[tokio::test(start_paused = true)]
+    async fn yielded_entries_are_not_handed_over() {
+        let mut pending = Vec::new();
+
+        {
+            let mut queue = DropDelayQueue::new(DelayQueue::new(), |entries| {
+                pending = entries
+                    .into_iter()
+                    .map(|entry| (entry.deadline(), entry.into_inner()))
+                    .collect::<Vec<_>>();
+            });
+            queue.insert(1, Duration::from_secs(1));
+            let third = queue.insert(3, Duration::from_secs(3));
+            queue.insert(2, Duration::from_secs(2));
+
+            assert_eq!(queue.next().await.unwrap().into_inner(), 1);
+            queue.reset(&third, Duration::from_secs(5));
+        }
+
+        let values: Vec<_> = pending.iter().map(|(_, value)| *value).collect();
+        assert_eq!(values, [2, 3]);
+        assert!(pending[0].0 < pending[1].0);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn into_inner_skips_the_closure() {
+        let mut runs = 0;
+
+        let mut queue = DropDelayQueue::new(DelayQueue::new(), |_| runs += 1);
+        queue.insert("kept", Duration::from_secs(1));
+        assert_eq!(queue.size_hint(), (1, None));
+        let mut queue = queue.into_inner();
+
+        assert_eq!(queue.next().await.unwrap().into_inner(), "kept");
+        assert_eq!(runs, 0);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn dropped_interval_reports_missed_ticks() {
+        let mut ticks = None;
+
+        {
+            let interval = tokio::time::interval(Duration::from_secs(10));
+            let mut stream = DropInterval::new(interval, |t: Ticks| ticks = Some(t));
+            let first = stream.next().await.unwrap();
+            assert_eq!(
+                stream.next().await.unwrap(),
+                first + Duration::from_secs(10)
+            );
+            tokio::time::advance(Duration::from_secs(35)).await;
+        }
+
+        let ticks = ticks.unwrap();
+        assert_eq!(ticks.yielded(), 2);
+        assert_eq!(ticks.missed(), 3);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn unticked_interval_has_no_deadline() {
+        let mut ticks = None;
+
+        let interval = tokio::time::interval(Duration::from_secs(1));
+        drop(DropInterval::new(interval, |t: Ticks| ticks = Some(t)));
+
+        let ticks = ticks.unwrap();
+        assert_eq!(ticks.next_deadline(), None);
+        assert_eq!(ticks.missed(), 0);
+    }
+}
@@ -0,0 +1,182 @@
+use futures_core::{Stream, TryStream};
+use pin_project::{pin_project, pinned_drop};
+use std::{
+    fmt,
+    pin::Pin,
+    task::{Context, Poll},
+};
+
+use crate::{context::Stats, dropper::Once, DropContext};
+
+/// A stream of file chunks sent as a download response, that hands the offset reached in the file
+/// to a closure once it is dropped, together with a [`DropContext`].
+///
+/// The offset is the one the download started at, such as the start of a resumed range, plus the
+/// bytes of every chunk yielded so far, which is where the next attempt has to resume. The reason
+/// of a download that was dropped early is
+/// [`is_cancelled`](crate::DropReason::is_cancelled), so resumable-download servers can record the progress and purge the partial artifact
+/// from there. Chunks still buffered by the server when the client disconnects count as sent.
+///
+/// Example
+/// ```
+/// use futures::executor::block_on_stream;
+/// use drop_stream::DropTryStreamExt;
+///
+/// let chunks = futures::stream::iter([Ok::<_, std::io::Error>(vec![0; 512]), Ok(vec![0; 512])]);
+///
+/// let mut resume_at = None;
+/// let resume_at_ref = &mut resume_at;
+/// let download = chunks.on_download_drop(1024, move |context, offset| {
+///     if context.reason().is_cancelled() {
+///         *resume_at_ref = Some(offset);
+///     }
+/// });
+///
+/// let mut download = block_on_stream(download);
+/// assert!(download.next().is_some());
+/// drop(download);
+/// assert_eq!(resume_at, Some(1536));
+/// ```
+#[pin_project(PinnedDrop)]
+pub struct Download<S, U>
+where
+    S: TryStream,
+    S::Ok: AsRef<[u8]>,
+    U: FnOnce(DropContext, u64),
+{
+    #[pin]
+    stream: S,
+    stats: Stats,
+    start: u64,
+    dropper: Once<U>,
+}
+
+impl<S, U> Download<S, U>
+where
+    S: TryStream,
+    S::Ok: AsRef<[u8]>,
+    U: FnOnce(DropContext, u64),
+{
+    #[track_caller]
+    pub fn new(stream: S, start: u64, dropper: U) -> Self {
+        Self {
+            stream,
+            stats: Stats::new(),
+            start,
+            dropper: Once::new(dropper),
+        }
+    }
+
+    /// Returns the offset in the file the download has reached.
+    pub fn offset(&self) -> u64 {
+        self.start + self.stats.bytes()
+    }
+
+    /// Names the download in its context, such as after the file, replacing any previous name.
+    pub fn name(mut self, name: impl Into<String>) -> Self {
+        self.stats.set_name(name.into());
+        self
+    }
+}
+
+impl<S, U> Stream for Download<S, U>
+where
+    S: TryStream,
+    S::Ok: AsRef<[u8]>,
+    U: FnOnce(DropContext, u64),
+{
+    type Item = Result<S::Ok, S::Error>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let this = self.project();
+
+        let poll = this.stream.try_poll_next(cx);
+        match &poll {
+            Poll::Ready(Some(Ok(chunk))) => {
+                this.stats.record_item();
+                this.stats.record_bytes(chunk.as_ref().len());
+            }
+            Poll::Ready(Some(Err(_))) => this.stats.record_error(),
+            Poll::Ready(None) => this.stats.record_end(),
+            Poll::Pending => this.stats.record_pending(),
+        }
+
+        poll
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.stream.size_hint()
+    }
+}
+
+#[pinned_drop]
+impl<S, U> PinnedDrop for Download<S, U>
+where
+    S: TryStream,
+    S::Ok: AsRef<[u8]>,
+    U: FnOnce(DropContext, u64),
+{
+    fn drop(self: Pin<&mut Self>) {
+        let this = self.project();
+
+        if let Some(dropper) = this.dropper.take() {
+            let offset = *this.start + this.stats.bytes();
+            dropper(this.stats.context(), offset)
+        }
+    }
+}
+
+impl<S, U> fmt::Debug for Download<S, U>
+where
+    S: TryStream,
+    S::Ok: AsRef<[u8]>,
+    U: FnOnce(DropContext, u64),
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Download")
+            .field("offset", &self.offset())
+            .finish_non_exhaustive()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{DropReason, DropTryStreamExt};
+    use futures::{executor::block_on_stream, stream::iter};
+
+    #[test]
+    fn completed_download_reaches_end_of_file() {
+        let mut report = None;
+
+        {
+            let report_ref = &mut report;
+            let chunks = iter([Ok::<_, ()>("abc"), Ok("de")]);
+            let download = chunks
+                .on_download_drop(0, move |c, offset| *report_ref = Some((c.reason(), offset)))
+                .name("video.mp4");
+            assert_eq!(download.offset(), 0);
+            assert_eq!(block_on_stream(download).count(), 2);
+        }
+
+        assert_eq!(report, Some((DropReason::Completed, 5)));
+    }
+
+    #[test]
+    fn failed_chunks_do_not_advance_offset() {
+        let mut report = None;
+
+        {
+            let report_ref = &mut report;
+            let chunks = iter([Ok::<_, ()>("abc"), Err(()), Ok("de")]);
+            let mut download = block_on_stream(
+                chunks.on_download_drop(10, move |c, offset| *report_ref = Some((c, offset))),
+            );
+            download.next();
+            download.next();
+        }
+
+        let (context, offset) = report.unwrap();
+        assert_eq!(context.reason(), DropReason::CancelledAfterError);
+        assert_eq!((context.errors(), offset), (1, 13));
+    }
+}
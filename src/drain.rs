@@ -0,0 +1,273 @@
+use futures_core::Stream;
+use pin_project::{pin_project, pinned_drop};
+use std::{
+    pin::Pin,
+    task::{Context, Poll},
+};
+
+use crate::{dropper::Once, Spawn};
+
+/// How far a [`DrainOnDrop`] stream got when draining its inner stream.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DrainReport {
+    /// The number of items that were polled from the inner stream after the wrapper was dropped.
+    pub items: usize,
+    /// True if the inner stream finished, either before the drop or while being drained.
+    pub completed: bool,
+}
+
+/// A stream that, instead of discarding its inner stream on drop, hands it to a background task
+/// that keeps polling it to completion before the closure is called.
+///
+/// Some protocols require reading trailers even if the consumer gives up early. Draining stops
+/// once `budget` items have been polled, at which point the inner stream is dropped as usual.
+/// Items polled while draining are discarded.
+///
+/// Example
+/// ```
+/// use std::sync::{Arc, Mutex};
+/// use drop_stream::{BoxFuture, DropStreamExt};
+///
+/// let tasks = Arc::new(Mutex::new(Vec::new()));
+/// let spawner = {
+///     let tasks = tasks.clone();
+///     move |future: BoxFuture| tasks.lock().unwrap().push(future)
+/// };
+///
+/// let report = Arc::new(Mutex::new(None));
+/// let report_ref = report.clone();
+/// let stream = futures::stream::iter([1, 2, 3]).drain_on_drop(spawner, 10, move |r| {
+///     *report_ref.lock().unwrap() = Some(r);
+/// });
+///
+/// drop(stream);
+/// for task in tasks.lock().unwrap().drain(..) {
+///     futures::executor::block_on(task);
+/// }
+///
+/// let report = report.lock().unwrap().unwrap();
+/// assert_eq!(report.items, 3);
+/// assert!(report.completed);
+/// ```
+#[pin_project(PinnedDrop)]
+pub struct DrainOnDrop<S, Sp, U>
+where
+    S: Stream + Unpin + Send + 'static,
+    Sp: Spawn,
+    U: FnOnce(DrainReport) + Send + 'static,
+{
+    // Held together so both can be moved into the background task when dropped.
+    inner: Once<(S, U)>,
+    spawner: Sp,
+    budget: usize,
+    completed: bool,
+}
+
+impl<S, Sp, U> DrainOnDrop<S, Sp, U>
+where
+    S: Stream + Unpin + Send + 'static,
+    Sp: Spawn,
+    U: FnOnce(DrainReport) + Send + 'static,
+{
+    pub fn new(stream: S, spawner: Sp, budget: usize, dropper: U) -> Self {
+        Self {
+            inner: Once::new((stream, dropper)),
+            spawner,
+            budget,
+            completed: false,
+        }
+    }
+}
+
+impl<S, Sp, U> Stream for DrainOnDrop<S, Sp, U>
+where
+    S: Stream + Unpin + Send + 'static,
+    Sp: Spawn,
+    U: FnOnce(DrainReport) + Send + 'static,
+{
+    type Item = S::Item;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let this = self.project();
+
+        // Only taken in the drop method.
+        let Some((stream, _)) = this.inner.get_mut() else {
+            return Poll::Ready(None);
+        };
+
+        let poll = Pin::new(stream).poll_next(cx);
+        if let Poll::Ready(None) = poll {
+            *this.completed = true;
+        }
+
+        poll
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.inner
+            .get()
+            .map_or((0, Some(0)), |(stream, _)| stream.size_hint())
+    }
+}
+
+#[pinned_drop]
+impl<S, Sp, U> PinnedDrop for DrainOnDrop<S, Sp, U>
+where
+    S: Stream + Unpin + Send + 'static,
+    Sp: Spawn,
+    U: FnOnce(DrainReport) + Send + 'static,
+{
+    fn drop(self: Pin<&mut Self>) {
+        let this = self.project();
+
+        let Some((mut stream, dropper)) = this.inner.take() else {
+            return;
+        };
+
+        if *this.completed {
+            drop(stream);
+            dropper(DrainReport {
+                items: 0,
+                completed: true,
+            });
+            return;
+        }
+
+        let budget = *this.budget;
+        this.spawner.spawn(Box::pin(async move {
+            let mut items = 0;
+            let completed = std::future::poll_fn(|cx| loop {
+                if items == budget {
+                    return Poll::Ready(false);
+                }
+
+                match Pin::new(&mut stream).poll_next(cx) {
+                    Poll::Ready(Some(_)) => items += 1,
+                    Poll::Ready(None) => return Poll::Ready(true),
+                    Poll::Pending => return Poll::Pending,
+                }
+            })
+            .await;
+
+            drop(stream);
+            dropper(DrainReport { items, completed });
+        }));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        sync::{Arc, Mutex},
+        task::Poll,
+    };
+
+    use crate::{BoxFuture, DrainReport, DropStreamExt};
+    use futures::{
+        stream::{iter, repeat},
+        Stream,
+    };
+
+    fn spawner() -> (impl Fn(BoxFuture), Arc<Mutex<Vec<BoxFuture>>>) {
+        let tasks = Arc::new(Mutex::new(Vec::new()));
+        let tasks_ref = tasks.clone();
+        (move |future| tasks_ref.lock().unwrap().push(future), tasks)
+    }
+
+    #[test]
+    fn drains_remaining_items_in_background() {
+        let (spawner, tasks) = spawner();
+        let report = Arc::new(Mutex::new(None));
+
+        {
+            let report_ref = report.clone();
+            let drop_stream = iter([1, 2, 3]).drain_on_drop(spawner, 10, move |r| {
+                *report_ref.lock().unwrap() = Some(r);
+            });
+
+            let mut drop_stream = Box::pin(drop_stream);
+
+            let waker = futures::task::noop_waker();
+            let mut context = futures::task::Context::from_waker(&waker);
+            assert_eq!(
+                drop_stream.as_mut().poll_next(&mut context),
+                Poll::Ready(Some(1))
+            );
+        }
+
+        // The dropper only runs once the inner stream has been drained.
+        assert_eq!(*report.lock().unwrap(), None);
+
+        for task in tasks.lock().unwrap().drain(..) {
+            futures::executor::block_on(task);
+        }
+
+        assert_eq!(
+            *report.lock().unwrap(),
+            Some(DrainReport {
+                items: 2,
+                completed: true
+            })
+        );
+    }
+
+    #[test]
+    fn draining_stops_at_budget() {
+        let (spawner, tasks) = spawner();
+        let report = Arc::new(Mutex::new(None));
+
+        {
+            let report_ref = report.clone();
+            let _drop_stream = repeat(true).drain_on_drop(spawner, 5, move |r| {
+                *report_ref.lock().unwrap() = Some(r);
+            });
+        }
+
+        for task in tasks.lock().unwrap().drain(..) {
+            futures::executor::block_on(task);
+        }
+
+        assert_eq!(
+            *report.lock().unwrap(),
+            Some(DrainReport {
+                items: 5,
+                completed: false
+            })
+        );
+    }
+
+    #[test]
+    fn completed_stream_is_not_spawned() {
+        let (spawner, tasks) = spawner();
+        let report = Arc::new(Mutex::new(None));
+
+        {
+            let report_ref = report.clone();
+            let drop_stream = iter([1]).drain_on_drop(spawner, 10, move |r| {
+                *report_ref.lock().unwrap() = Some(r);
+            });
+
+            let mut drop_stream = Box::pin(drop_stream);
+
+            let waker = futures::task::noop_waker();
+            let mut context = futures::task::Context::from_waker(&waker);
+            assert_eq!(
+                drop_stream.as_mut().poll_next(&mut context),
+                Poll::Ready(Some(1))
+            );
+            assert_eq!(
+                drop_stream.as_mut().poll_next(&mut context),
+                Poll::Ready(None)
+            );
+        }
+
+        assert!(tasks.lock().unwrap().is_empty());
+        assert_eq!(
+            *report.lock().unwrap(),
+            Some(DrainReport {
+                items: 0,
+                completed: true
+            })
+        );
+    }
+}
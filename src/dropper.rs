@@ -0,0 +1,112 @@
+use crate::DropReason;
+
+/// Holds a value that can be taken out at most once, such as a closure that has to be called by
+/// value from `Drop::drop()`.
+///
+/// Taking is the only way to get at the value, so there is no state in which the value is known
+/// to be present but has to be unwrapped.
+pub(crate) struct Once<T> {
+    value: Option<T>,
+}
+
+impl<T> Once<T> {
+    pub(crate) fn new(value: T) -> Self {
+        Self { value: Some(value) }
+    }
+
+    pub(crate) fn get(&self) -> Option<&T> {
+        self.value.as_ref()
+    }
+
+    pub(crate) fn get_mut(&mut self) -> Option<&mut T> {
+        self.value.as_mut()
+    }
+
+    /// Takes the value, or returns `None` if it was already taken.
+    pub(crate) fn take(&mut self) -> Option<T> {
+        self.value.take()
+    }
+
+    /// Moves the value into a new holder as `map(value)`, leaving this one empty.
+    pub(crate) fn take_map<T2>(&mut self, map: impl FnOnce(T) -> T2) -> Once<T2> {
+        Once {
+            value: self.value.take().map(map),
+        }
+    }
+}
+
+/// Holds a closure and calls it once the holder is dropped.
+///
+/// Wrappers keep their closure in one of these instead of implementing `Drop` themselves, so that
+/// they can still be taken apart by value, e.g. to replace their inner stream, without running or
+/// losing the closure. Declare it before the inner value so the closure runs before the inner
+/// value is dropped.
+pub(crate) struct Dropper<U: FnOnce()> {
+    dropper: Once<U>,
+}
+
+impl<U: FnOnce()> Dropper<U> {
+    pub(crate) fn new(dropper: U) -> Self {
+        Self {
+            dropper: Once::new(dropper),
+        }
+    }
+}
+
+impl<U: FnOnce()> Dropper<U> {
+    /// Returns whether the closure is still to be called, i.e. it wasn't disarmed.
+    pub(crate) fn is_armed(&self) -> bool {
+        self.dropper.get().is_some()
+    }
+
+    /// Drops the closure without calling it.
+    pub(crate) fn disarm(&mut self) {
+        self.dropper.take();
+    }
+
+    /// Replaces the closure with `map(closure)` without calling it.
+    pub(crate) fn map<U2: FnOnce()>(mut self, map: impl FnOnce(U) -> U2) -> Dropper<U2> {
+        Dropper {
+            dropper: self.dropper.take_map(map),
+        }
+    }
+}
+
+impl<U: FnOnce()> Drop for Dropper<U> {
+    fn drop(&mut self) {
+        if let Some(dropper) = self.dropper.take() {
+            dropper()
+        }
+    }
+}
+
+/// Holds a closure taking a [`DropReason`] along with the reason to call it with, and calls it
+/// once the holder is dropped unless it was already called early with [`fire`](Self::fire). The
+/// reason counterpart of [`Dropper`].
+pub(crate) struct ReasonDropper<U: FnOnce(DropReason)> {
+    pub(crate) reason: DropReason,
+    dropper: Once<U>,
+}
+
+impl<U: FnOnce(DropReason)> ReasonDropper<U> {
+    /// Creates a holder that reports [`DropReason::Cancelled`] unless the reason is changed.
+    pub(crate) fn new(dropper: U) -> Self {
+        Self {
+            reason: DropReason::Cancelled,
+            dropper: Once::new(dropper),
+        }
+    }
+
+    /// Calls the closure with `reason` now instead of when the holder is dropped.
+    pub(crate) fn fire(&mut self, reason: DropReason) {
+        if let Some(dropper) = self.dropper.take() {
+            dropper(reason)
+        }
+    }
+}
+
+impl<U: FnOnce(DropReason)> Drop for ReasonDropper<U> {
+    fn drop(&mut self) {
+        self.fire(self.reason)
+    }
+}
@@ -0,0 +1,173 @@
+use futures_core::Stream;
+use pin_project::{pin_project, pinned_drop};
+use std::{
+    fmt,
+    pin::Pin,
+    task::{Context, Poll},
+};
+
+use crate::{context::Stats, dropper::Once, DropContext};
+
+/// A stream of rows encoded through a writer, such as a CSV, Parquet or zip encoder, that hands
+/// the writer to a finalize closure once it is dropped, together with a [`DropContext`].
+///
+/// Every row is passed to the encode closure along with the writer, and whatever it returns, such
+/// as the bytes the writer produced for the row, is yielded. As the writer is only finalized on
+/// drop, footers and indexes get written even when the HTTP client disconnects mid-download, such
+/// as for an archive that is spooled to a cache while being sent. The context tells a finished
+/// download from a cancelled one.
+///
+/// Example
+/// ```
+/// use futures::{executor::block_on_stream, stream};
+/// use drop_stream::DropStreamExt;
+///
+/// let mut archived = None;
+/// let archived_ref = &mut archived;
+/// let rows = stream::iter([("alice", 3), ("bob", 5)]).encode_into(
+///     Vec::new(),
+///     |csv: &mut Vec<u8>, (name, visits)| {
+///         let line = format!("{name},{visits}\n");
+///         csv.extend_from_slice(line.as_bytes());
+///         line
+///     },
+///     move |context, mut csv| {
+///         csv.extend_from_slice(format!("# {} rows\n", context.items()).as_bytes());
+///         *archived_ref = Some(String::from_utf8(csv).unwrap());
+///     },
+/// );
+///
+/// let mut rows = block_on_stream(rows);
+/// assert_eq!(rows.next().as_deref(), Some("alice,3\n"));
+/// // The client disconnects...
+/// drop(rows);
+/// assert_eq!(archived.as_deref(), Some("alice,3\n# 1 rows\n"));
+/// ```
+#[pin_project(PinnedDrop)]
+pub struct Encoded<S, W, E, O, F>
+where
+    S: Stream,
+    E: FnMut(&mut W, S::Item) -> O,
+    F: FnOnce(DropContext, W),
+{
+    #[pin]
+    stream: S,
+    encode: E,
+    stats: Stats,
+    // Only taken in the drop method.
+    writer: Option<W>,
+    finalize: Once<F>,
+}
+
+impl<S, W, E, O, F> Encoded<S, W, E, O, F>
+where
+    S: Stream,
+    E: FnMut(&mut W, S::Item) -> O,
+    F: FnOnce(DropContext, W),
+{
+    #[track_caller]
+    pub fn new(stream: S, writer: W, encode: E, finalize: F) -> Self {
+        Self {
+            stream,
+            encode,
+            stats: Stats::new(),
+            writer: Some(writer),
+            finalize: Once::new(finalize),
+        }
+    }
+
+    /// Returns the writer.
+    pub fn writer(&self) -> &W {
+        // Only taken in the drop method.
+        self.writer.as_ref().expect("writer taken before drop")
+    }
+}
+
+impl<S, W, E, O, F> Stream for Encoded<S, W, E, O, F>
+where
+    S: Stream,
+    E: FnMut(&mut W, S::Item) -> O,
+    F: FnOnce(DropContext, W),
+{
+    type Item = O;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let this = self.project();
+
+        match this.stream.poll_next(cx) {
+            Poll::Ready(Some(row)) => {
+                this.stats.record_item();
+                // Only taken in the drop method.
+                let writer = this.writer.as_mut().expect("writer taken before drop");
+                Poll::Ready(Some((this.encode)(writer, row)))
+            }
+            Poll::Ready(None) => {
+                this.stats.record_end();
+                Poll::Ready(None)
+            }
+            Poll::Pending => {
+                this.stats.record_pending();
+                Poll::Pending
+            }
+        }
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.stream.size_hint()
+    }
+}
+
+#[pinned_drop]
+impl<S, W, E, O, F> PinnedDrop for Encoded<S, W, E, O, F>
+where
+    S: Stream,
+    E: FnMut(&mut W, S::Item) -> O,
+    F: FnOnce(DropContext, W),
+{
+    fn drop(self: Pin<&mut Self>) {
+        let this = self.project();
+
+        if let (Some(finalize), Some(writer)) = (this.finalize.take(), this.writer.take()) {
+            finalize(this.stats.context(), writer)
+        }
+    }
+}
+
+impl<S, W, E, O, F> fmt::Debug for Encoded<S, W, E, O, F>
+where
+    S: Stream,
+    W: fmt::Debug,
+    E: FnMut(&mut W, S::Item) -> O,
+    F: FnOnce(DropContext, W),
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Encoded")
+            .field("writer", &self.writer)
+            .finish_non_exhaustive()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{DropReason, DropStreamExt};
+    use futures::{executor::block_on_stream, stream::iter};
+
+    #[test]
+    fn completed_stream_is_finalized_once() {
+        let mut finalized = Vec::new();
+
+        {
+            let rows = iter([1, 2, 3]).encode_into(
+                0,
+                |sum: &mut i32, row| {
+                    *sum += row;
+                    row * 10
+                },
+                |context, sum| finalized.push((context.reason(), sum)),
+            );
+            assert_eq!(block_on_stream(rows).collect::<Vec<_>>(), [10, 20, 30]);
+        }
+
+        assert_eq!(finalized, [(DropReason::Completed, 6)]);
+    }
+}
@@ -0,0 +1,402 @@
+use futures_core::Stream;
+use std::{
+    collections::VecDeque,
+    pin::Pin,
+    sync::{
+        atomic::{AtomicBool, AtomicU64, Ordering},
+        Arc, Mutex, MutexGuard, RwLock,
+    },
+    task::{Context, Poll, Waker},
+};
+
+use crate::DropContext;
+
+/// A wrapper being dropped, handed to every registered [`DropEventHandler`].
+///
+/// Cheap to clone, so handlers can keep or forward events.
+#[derive(Debug, Clone)]
+pub struct DropEvent {
+    context: Arc<DropContext>,
+}
+
+impl DropEvent {
+    /// The context of the wrapper that was dropped.
+    pub fn context(&self) -> &DropContext {
+        &self.context
+    }
+}
+
+/// Receives the [`DropEvent`] of every wrapper that builds a [`DropContext`], such as
+/// [`on_drop_ctx`](crate::DropStreamExt::on_drop_ctx) or
+/// [`observe`](crate::DropStreamExt::observe), once registered with [`register_drop_handler`].
+///
+/// Lets one handler, shipped once and registered at startup, see every stream's drop in a
+/// uniform shape. Implemented for every `Fn(DropEvent)` closure. With the `log`, `tracing` and
+/// `metrics` features enabled, [`LogHandler`], [`TracingHandler`] and [`MetricsHandler`] report
+/// events to the crates of the same name.
+pub trait DropEventHandler {
+    fn handle(&self, event: DropEvent);
+}
+
+impl<F: Fn(DropEvent)> DropEventHandler for F {
+    fn handle(&self, event: DropEvent) {
+        self(event)
+    }
+}
+
+// Shared so that `dispatch` can call the handlers after releasing the lock.
+type SharedHandler = Arc<dyn DropEventHandler + Send + Sync>;
+
+// Handlers are kept with an ID, so that those registered by `drop_events` can be removed again.
+static HANDLERS: RwLock<Vec<(u64, SharedHandler)>> = RwLock::new(Vec::new());
+static NEXT_HANDLER: AtomicU64 = AtomicU64::new(0);
+// Checked before taking the lock, so drops don't pay for handlers until one is registered.
+static HAS_HANDLERS: AtomicBool = AtomicBool::new(false);
+
+/// Adds a handler that every drop event is handed to from now on, after the handlers registered
+/// before it.
+///
+/// Example
+/// ```
+/// use std::sync::{Arc, Mutex};
+/// use drop_stream::{register_drop_handler, DropEvent, DropStreamExt};
+///
+/// let names = Arc::new(Mutex::new(Vec::new()));
+/// let names_ref = names.clone();
+/// register_drop_handler(move |event: DropEvent| {
+///     let name = event.context().name().unwrap_or("unnamed").to_owned();
+///     names_ref.lock().unwrap().push(name);
+/// });
+///
+/// drop(futures::stream::repeat(true).on_drop_ctx(|_| {}).name("events"));
+/// assert_eq!(*names.lock().unwrap(), ["events"]);
+/// ```
+pub fn register_drop_handler(handler: impl DropEventHandler + Send + Sync + 'static) {
+    register(Arc::new(handler));
+}
+
+fn register(handler: SharedHandler) -> u64 {
+    let id = NEXT_HANDLER.fetch_add(1, Ordering::Relaxed);
+    HANDLERS
+        .write()
+        .unwrap_or_else(|e| e.into_inner())
+        .push((id, handler));
+    HAS_HANDLERS.store(true, Ordering::Release);
+    id
+}
+
+fn unregister(id: u64) {
+    let mut handlers = HANDLERS.write().unwrap_or_else(|e| e.into_inner());
+    handlers.retain(|(handler_id, _)| *handler_id != id);
+    HAS_HANDLERS.store(!handlers.is_empty(), Ordering::Release);
+}
+
+/// Hands `context` to every registered handler.
+///
+/// The handlers are called without holding the lock, so a handler can register handlers, drop a
+/// [`DropEvents`] or drop a context wrapper itself.
+pub(crate) fn dispatch(context: &DropContext) {
+    if !HAS_HANDLERS.load(Ordering::Acquire) {
+        return;
+    }
+
+    let handlers: Vec<SharedHandler> = HANDLERS
+        .read()
+        .unwrap_or_else(|e| e.into_inner())
+        .iter()
+        .map(|(_, handler)| handler.clone())
+        .collect();
+
+    let event = DropEvent {
+        context: Arc::new(context.clone()),
+    };
+    for handler in handlers {
+        handler.handle(event.clone())
+    }
+}
+
+#[derive(Default)]
+struct EventQueue {
+    events: VecDeque<DropEvent>,
+    waker: Option<Waker>,
+}
+
+#[derive(Clone, Default)]
+struct SharedQueue(Arc<Mutex<EventQueue>>);
+
+impl SharedQueue {
+    fn lock(&self) -> MutexGuard<'_, EventQueue> {
+        self.0.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+impl DropEventHandler for SharedQueue {
+    fn handle(&self, event: DropEvent) {
+        let waker = {
+            let mut queue = self.lock();
+            queue.events.push_back(event);
+            queue.waker.take()
+        };
+
+        if let Some(waker) = waker {
+            waker.wake();
+        }
+    }
+}
+
+/// Returns a stream of every drop event from now on, for tasks that react to teardowns such as a
+/// janitor cleaning up after every closed subscription.
+///
+/// Events are queued until the stream is polled, without a bound, so the stream should be polled
+/// promptly. It never ends; dropping it stops the queueing. See [`DropEventHandler`] for which
+/// wrappers produce events.
+///
+/// Example
+/// ```
+/// use futures::{executor::block_on, StreamExt};
+/// use drop_stream::{drop_events, DropStreamExt};
+///
+/// let mut events = drop_events();
+/// drop(futures::stream::repeat(true).on_drop_ctx(|_| {}).name("subscription"));
+///
+/// let event = block_on(events.next()).unwrap();
+/// assert_eq!(event.context().name(), Some("subscription"));
+/// ```
+pub fn drop_events() -> DropEvents {
+    let queue = SharedQueue::default();
+    let id = register(Arc::new(queue.clone()));
+
+    DropEvents { queue, id }
+}
+
+/// A stream of drop events, created by [`drop_events`].
+#[must_use = "streams do nothing unless polled"]
+pub struct DropEvents {
+    queue: SharedQueue,
+    id: u64,
+}
+
+impl Stream for DropEvents {
+    type Item = DropEvent;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<DropEvent>> {
+        let mut queue = self.queue.lock();
+        match queue.events.pop_front() {
+            Some(event) => Poll::Ready(Some(event)),
+            None => {
+                queue.waker = Some(cx.waker().clone());
+                Poll::Pending
+            }
+        }
+    }
+}
+
+impl Drop for DropEvents {
+    fn drop(&mut self) {
+        unregister(self.id);
+    }
+}
+
+impl std::fmt::Debug for DropEvents {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("DropEvents")
+            .field("queued", &self.queue.lock().events.len())
+            .finish()
+    }
+}
+
+/// Logs every drop event to the `log` crate, under the `drop_stream` target.
+#[cfg(feature = "log")]
+#[derive(Debug, Clone, Copy)]
+pub struct LogHandler {
+    level: log::Level,
+}
+
+#[cfg(feature = "log")]
+impl LogHandler {
+    pub fn new(level: log::Level) -> Self {
+        Self { level }
+    }
+}
+
+#[cfg(feature = "log")]
+impl Default for LogHandler {
+    fn default() -> Self {
+        Self::new(log::Level::Debug)
+    }
+}
+
+#[cfg(feature = "log")]
+impl DropEventHandler for LogHandler {
+    fn handle(&self, event: DropEvent) {
+        let context = event.context();
+        log::log!(
+            target: "drop_stream",
+            self.level,
+            "stream {} ({}) dropped: {:?} after {} items in {:?}, created at {}",
+            context.id(),
+            context.name().unwrap_or("unnamed"),
+            context.reason(),
+            context.items(),
+            context.lifetime(),
+            context.location(),
+        );
+    }
+}
+
+/// Emits every drop event as a `tracing` event at debug level, under the `drop_stream` target.
+#[cfg(feature = "tracing")]
+#[derive(Debug, Default, Clone, Copy)]
+pub struct TracingHandler;
+
+#[cfg(feature = "tracing")]
+impl DropEventHandler for TracingHandler {
+    fn handle(&self, event: DropEvent) {
+        let context = event.context();
+        tracing::debug!(
+            target: "drop_stream",
+            id = context.id(),
+            name = context.name(),
+            reason = ?context.reason(),
+            items = context.items(),
+            errors = context.errors(),
+            lifetime = ?context.lifetime(),
+            since_last_item = ?context.since_last_item(),
+            location = %context.location(),
+            "stream dropped"
+        );
+    }
+}
+
+/// Records every drop event to the `metrics` crate, as a `drop_stream_drops_total` counter and a
+/// `drop_stream_lifetime_seconds` histogram, both labelled with the reason and the stream's name.
+/// Drops after at least one item are also recorded in a `drop_stream_since_last_item_seconds`
+/// histogram, see [`DropContext::since_last_item`]. Wrappers that record
+/// [`latency`](DropContext::latency) also report their mean time spent in `poll_next` and mean gap
+/// between items, in `drop_stream_poll_seconds` and `drop_stream_item_gap_seconds` histograms.
+/// The throughput over the lifetime and, if tracked, over the window before the drop go into
+/// `drop_stream_items_per_second` and `drop_stream_window_items_per_second` histograms, see
+/// [`DropContext::items_per_second`].
+#[cfg(feature = "metrics")]
+#[derive(Debug, Default, Clone, Copy)]
+pub struct MetricsHandler;
+
+#[cfg(feature = "metrics")]
+impl DropEventHandler for MetricsHandler {
+    fn handle(&self, event: DropEvent) {
+        let context = event.context();
+        let labels = [
+            ("reason", format!("{:?}", context.reason())),
+            ("name", context.name().unwrap_or("unnamed").to_owned()),
+        ];
+
+        metrics::counter!("drop_stream_drops_total", &labels).increment(1);
+        metrics::histogram!("drop_stream_lifetime_seconds", &labels)
+            .record(context.lifetime().as_secs_f64());
+        if let Some(since_last_item) = context.since_last_item() {
+            metrics::histogram!("drop_stream_since_last_item_seconds", &labels)
+                .record(since_last_item.as_secs_f64());
+        }
+        if let Some(rate) = context.items_per_second() {
+            metrics::histogram!("drop_stream_items_per_second", &labels).record(rate);
+        }
+        if let Some(rate) = context.window_items_per_second() {
+            metrics::histogram!("drop_stream_window_items_per_second", &labels).record(rate);
+        }
+        if let Some(latency) = context.latency() {
+            if let Some(poll) = latency.poll().mean() {
+                metrics::histogram!("drop_stream_poll_seconds", &labels).record(poll.as_secs_f64());
+            }
+            if let Some(gap) = latency.item_gaps().mean() {
+                metrics::histogram!("drop_stream_item_gap_seconds", &labels)
+                    .record(gap.as_secs_f64());
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex,
+    };
+
+    use crate::{drop_events, register_drop_handler, DropEvent, DropReason, DropStreamExt};
+    use futures::{
+        stream::{iter, repeat},
+        Stream,
+    };
+
+    #[test]
+    fn handlers_see_every_context_wrapper() {
+        let reasons = Arc::new(Mutex::new(Vec::new()));
+
+        let reasons_ref = reasons.clone();
+        register_drop_handler(move |event: DropEvent| {
+            // Other tests' streams are dropped through the same handlers.
+            if event.context().label("test") == Some("handlers_see_every_context_wrapper") {
+                reasons_ref.lock().unwrap().push(event.context().reason());
+            }
+        });
+
+        let label = ("test", "handlers_see_every_context_wrapper");
+        drop(repeat(true).on_drop_ctx(|_| {}).label(label.0, label.1));
+        let completed = iter([1]).on_drop_ctx(|_| {}).label(label.0, label.1);
+        assert_eq!(futures::executor::block_on_stream(completed).count(), 1);
+
+        assert_eq!(
+            *reasons.lock().unwrap(),
+            [DropReason::Cancelled, DropReason::Completed]
+        );
+    }
+
+    #[test]
+    fn handlers_can_register_handlers_and_drop_subscriptions() {
+        let subscription = Mutex::new(Some(drop_events()));
+        let registered = Arc::new(AtomicBool::new(false));
+
+        let registered_ref = registered.clone();
+        register_drop_handler(move |event: DropEvent| {
+            if event.context().name() != Some("reentrant_handler") {
+                return;
+            }
+            if let Some(subscription) = subscription.lock().unwrap().take() {
+                drop(subscription);
+                register_drop_handler(|_: DropEvent| {});
+                registered_ref.store(true, Ordering::SeqCst);
+            }
+        });
+
+        drop(repeat(true).on_drop_ctx(|_| {}).name("reentrant_handler"));
+        assert!(registered.load(Ordering::SeqCst));
+    }
+
+    #[test]
+    fn event_stream_yields_events_until_dropped() {
+        let mut events = Box::pin(drop_events());
+
+        let (waker, count) = futures_test::task::new_count_waker();
+        let mut context = futures::task::Context::from_waker(&waker);
+        // Other tests' streams may be dropped in the meantime, so only this test's are counted.
+        let mut ours = || {
+            let mut found = 0;
+            while let std::task::Poll::Ready(Some(event)) = events.as_mut().poll_next(&mut context)
+            {
+                if event.context().name() == Some("event_stream_yields_events") {
+                    found += 1;
+                }
+            }
+            found
+        };
+        assert_eq!(ours(), 0);
+
+        drop(
+            repeat(true)
+                .on_drop_ctx(|_| {})
+                .name("event_stream_yields_events"),
+        );
+        assert!(count.get() >= 1);
+        assert_eq!(ours(), 1);
+    }
+}
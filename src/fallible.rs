@@ -0,0 +1,212 @@
+use futures_core::Stream;
+use pin_project::pin_project;
+use std::{
+    error::Error,
+    pin::Pin,
+    sync::{Arc, RwLock},
+    task::{Context, Poll},
+};
+
+use crate::dropper::Once;
+
+type ErrorHandler = Arc<dyn Fn(&(dyn Error + 'static)) + Send + Sync>;
+
+static ERROR_HANDLER: RwLock<Option<ErrorHandler>> = RwLock::new(None);
+
+/// Sets the crate-level handler for errors returned by fallible drop closures that don't have a
+/// handler of their own, replacing the previous one.
+///
+/// By default errors are emitted as a `tracing` event with the `tracing` feature, or logged with
+/// the `log` feature, under the `drop_stream` target, and are discarded otherwise.
+pub fn set_drop_error_handler(handler: impl Fn(&(dyn Error + 'static)) + Send + Sync + 'static) {
+    *ERROR_HANDLER.write().unwrap_or_else(|e| e.into_inner()) = Some(Arc::new(handler));
+}
+
+/// Reports `error` to the handler set with [`set_drop_error_handler`].
+pub fn report_drop_error<E: Error + 'static>(error: E) {
+    // Cloned out so the handler can set another handler, or drop wrappers of its own.
+    let handler = ERROR_HANDLER
+        .read()
+        .unwrap_or_else(|e| e.into_inner())
+        .clone();
+    match handler {
+        Some(handler) => handler(&error),
+        None => default_report(&error),
+    }
+}
+
+fn default_report(error: &(dyn Error + 'static)) {
+    #[cfg(feature = "tracing")]
+    tracing::warn!(target: "drop_stream", error = %error, "drop closure failed");
+
+    #[cfg(all(feature = "log", not(feature = "tracing")))]
+    log::warn!(target: "drop_stream", "drop closure failed: {error}");
+
+    #[cfg(not(any(feature = "log", feature = "tracing")))]
+    let _ = error;
+}
+
+/// Holds a fallible closure and its error handler, and calls them once the holder is dropped.
+struct FallibleDropper<E, U: FnOnce() -> Result<(), E>, H: FnOnce(E)> {
+    held: Once<(U, H)>,
+}
+
+impl<E, U: FnOnce() -> Result<(), E>, H: FnOnce(E)> Drop for FallibleDropper<E, U, H> {
+    fn drop(&mut self) {
+        let Some((dropper, handler)) = self.held.take() else {
+            return;
+        };
+
+        if let Err(error) = dropper() {
+            handler(error)
+        }
+    }
+}
+
+/// A stream that wraps another stream with a fallible closure that is called once it is dropped.
+///
+/// If the closure returns an error it is passed to the stream's error handler, which by default
+/// reports it to the crate-level handler (see [`set_drop_error_handler`]), so cleanup errors are
+/// reported consistently instead of each closure choosing between `unwrap()` and ignoring them.
+///
+/// Example
+/// ```
+/// use drop_stream::DropStreamExt;
+///
+/// let mut reported = None;
+/// let reported_ref = &mut reported;
+/// let stream = futures::stream::repeat(true)
+///     .on_drop_fallible(|| "not a number".parse::<u32>().map(drop))
+///     .on_drop_error(move |error| *reported_ref = Some(error.to_string()));
+///
+/// drop(stream);
+/// assert_eq!(reported.as_deref(), Some("invalid digit found in string"));
+/// ```
+#[pin_project]
+pub struct FallibleDropStream<S: Stream, E, U: FnOnce() -> Result<(), E>, H: FnOnce(E)> {
+    // Declared before the stream so the closure runs before the inner stream is dropped.
+    dropper: FallibleDropper<E, U, H>,
+    #[pin]
+    stream: S,
+}
+
+impl<S: Stream, E: Error + 'static, U: FnOnce() -> Result<(), E>>
+    FallibleDropStream<S, E, U, fn(E)>
+{
+    pub fn new(stream: S, dropper: U) -> Self {
+        Self {
+            dropper: FallibleDropper {
+                held: Once::new((dropper, report_drop_error::<E>)),
+            },
+            stream,
+        }
+    }
+}
+
+impl<S: Stream, E, U: FnOnce() -> Result<(), E>, H: FnOnce(E)> FallibleDropStream<S, E, U, H> {
+    /// Handles an error returned by the closure with `handler` instead of the crate-level handler.
+    pub fn on_drop_error<H2: FnOnce(E)>(mut self, handler: H2) -> FallibleDropStream<S, E, U, H2> {
+        let held = self
+            .dropper
+            .held
+            .take_map(|(dropper, _)| (dropper, handler));
+        let FallibleDropStream { stream, .. } = self;
+
+        FallibleDropStream {
+            dropper: FallibleDropper { held },
+            stream,
+        }
+    }
+}
+
+impl<S: Stream, E, U: FnOnce() -> Result<(), E>, H: FnOnce(E)> Stream
+    for FallibleDropStream<S, E, U, H>
+{
+    type Item = S::Item;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        self.project().stream.poll_next(cx)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.stream.size_hint()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        fmt,
+        sync::{
+            atomic::{AtomicUsize, Ordering},
+            Arc,
+        },
+    };
+
+    use crate::{report_drop_error, set_drop_error_handler, DropStreamExt};
+    use futures::stream::repeat;
+
+    #[derive(Debug, PartialEq)]
+    struct CleanupError;
+
+    impl fmt::Display for CleanupError {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            f.write_str("cleanup failed")
+        }
+    }
+
+    impl std::error::Error for CleanupError {}
+
+    #[test]
+    fn per_stream_handler_receives_error() {
+        let mut handled = None;
+
+        {
+            let handled_ref = &mut handled;
+            let _drop_stream = repeat(true)
+                .on_drop_fallible(|| Err(CleanupError))
+                .on_drop_error(move |error| *handled_ref = Some(error));
+        }
+
+        assert_eq!(handled, Some(CleanupError));
+    }
+
+    #[test]
+    fn handler_not_called_on_success() {
+        let mut handled = false;
+
+        {
+            let handled_ref = &mut handled;
+            let _drop_stream = repeat(true)
+                .on_drop_fallible(|| Ok::<_, CleanupError>(()))
+                .on_drop_error(move |_| *handled_ref = true);
+        }
+
+        assert!(!handled);
+    }
+
+    #[test]
+    fn crate_level_handler_is_the_default() {
+        let reported = Arc::new(AtomicUsize::new(0));
+        let replaced = Arc::new(AtomicUsize::new(0));
+
+        let (reported_ref, replaced_ref) = (reported.clone(), replaced.clone());
+        set_drop_error_handler(move |error| {
+            if error.to_string() == "cleanup failed" {
+                reported_ref.fetch_add(1, Ordering::SeqCst);
+
+                // The handler doesn't run under the registry lock, so it can replace itself.
+                let replaced_ref = replaced_ref.clone();
+                set_drop_error_handler(move |_| {
+                    replaced_ref.fetch_add(1, Ordering::SeqCst);
+                });
+            }
+        });
+
+        drop(repeat(true).on_drop_fallible(|| Err(CleanupError)));
+        report_drop_error(CleanupError);
+
+        assert_eq!(reported.load(Ordering::SeqCst), 1);
+        assert_eq!(replaced.load(Ordering::SeqCst), 1);
+    }
+}
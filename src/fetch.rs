@@ -0,0 +1,77 @@
+use gloo_net::http::Response;
+use std::fmt;
+use wasm_streams::readable::IntoStream;
+
+use crate::{from_readable_stream, ContextDropFn, ContextDropStream, DropContext};
+
+/// Wraps the body of a fetch response with a closure that is called with a [`DropContext`] once
+/// it is dropped, or returns `None` if the response has no body.
+///
+/// The body yields the raw chunks of the response, and the fetch is cancelled when it is dropped
+/// early, such as when the user navigates away from a streaming view. The context is labeled with
+/// the URL of the response under `"url"`. To also tell the backend, pass a [`NotifyServer`] as the
+/// dropper.
+///
+/// Example
+/// ```no_run
+/// # async fn run() -> Result<(), gloo_net::Error> {
+/// use drop_stream::{fetch_body, DropContext, NotifyServer};
+///
+/// let response = gloo_net::http::Request::get("/events").send().await?;
+/// let mut chunks = 0;
+/// let events = fetch_body(
+///     &response,
+///     NotifyServer::new("/events/abandoned", |context: DropContext| chunks = context.items()),
+/// );
+/// // Render the events until the view is closed, dropping the body...
+/// drop(events);
+/// # Ok(())
+/// # }
+/// ```
+#[track_caller]
+pub fn fetch_body<U: ContextDropFn>(
+    response: &Response,
+    dropper: U,
+) -> Option<ContextDropStream<IntoStream<'static>, U>> {
+    Some(from_readable_stream(response.body()?, dropper).label("url", response.url()))
+}
+
+/// A dropper that sends a beacon to `url` if the stream was dropped before it ended, and then
+/// calls its closure either way.
+///
+/// Beacons are delivered by the browser even while the page is being unloaded, so the backend
+/// learns about streams abandoned by closing the tab as well. Failing to queue the beacon, such as
+/// outside of a window, is ignored.
+pub struct NotifyServer<U: ContextDropFn> {
+    url: String,
+    dropper: U,
+}
+
+impl<U: ContextDropFn> NotifyServer<U> {
+    pub fn new(url: impl Into<String>, dropper: U) -> Self {
+        Self {
+            url: url.into(),
+            dropper,
+        }
+    }
+}
+
+impl<U: ContextDropFn> ContextDropFn for NotifyServer<U> {
+    fn call(self, context: DropContext) {
+        if context.reason().is_cancelled() {
+            if let Some(window) = web_sys::window() {
+                let _ = window.navigator().send_beacon(&self.url);
+            }
+        }
+
+        self.dropper.call(context)
+    }
+}
+
+impl<U: ContextDropFn> fmt::Debug for NotifyServer<U> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("NotifyServer")
+            .field("url", &self.url)
+            .finish_non_exhaustive()
+    }
+}
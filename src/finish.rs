@@ -0,0 +1,299 @@
+use futures_io::{AsyncWrite, IoSlice};
+use std::{
+    fmt,
+    future::poll_fn,
+    io,
+    pin::Pin,
+    task::{Context, Poll},
+};
+
+use crate::{dropper::Once, Spawn};
+
+type OutcomeHook = Box<dyn FnOnce(FinishOutcome) + Send>;
+
+/// How a [`FinishOnDrop`] writer that was dropped without being closed was torn down.
+#[derive(Debug)]
+pub enum FinishOutcome {
+    /// The writer was closed in the background, which wrote the trailer of an encoder.
+    Finished,
+    /// Closing the writer in the background failed.
+    Failed(io::Error),
+    /// The writer was abandoned with [`FinishOnDrop::abandon`] and dropped as is, with the reason.
+    Abandoned(String),
+}
+
+/// A writer, such as an `async-compression` encoder, that is closed in the background if it is
+/// dropped without being closed, so the encoder writes its trailer.
+///
+/// A gzip body that was cut off because the response was cancelled lacks the gzip trailer, and a
+/// cache writing it through an encoder ends up with a corrupt entry. If the response is dropped
+/// before the encoder was closed, the encoder is handed to a spawner which closes it, finishing
+/// the encoding. A writer whose output must not look complete, because its input failed, can be
+/// [`abandon`](Self::abandon)ed instead, which drops it as is. The hook set with
+/// [`on_outcome`](Self::on_outcome) is told what happened. Closing the writer explicitly works as
+/// usual. With the `compression` feature, [`FinishOnDrop::gzip`] wraps the writer in a gzip
+/// encoder.
+///
+/// Example
+/// ```
+/// use std::sync::{Arc, Mutex};
+/// use drop_stream::{BoxFuture, FinishOnDrop, FinishOutcome};
+/// use futures::AsyncWriteExt;
+///
+/// let tasks = Arc::new(Mutex::new(Vec::new()));
+/// let spawner = {
+///     let tasks = tasks.clone();
+///     move |future: BoxFuture| tasks.lock().unwrap().push(future)
+/// };
+///
+/// let outcome = Arc::new(Mutex::new(None));
+/// let outcome_ref = outcome.clone();
+/// // An encoder writing a response to the cache...
+/// let mut encoder = FinishOnDrop::new(futures::io::Cursor::new(Vec::new()), spawner)
+///     .on_outcome(move |o| *outcome_ref.lock().unwrap() = Some(o));
+/// futures::executor::block_on(encoder.write_all(b"partial")).unwrap();
+///
+/// // The response is cancelled...
+/// drop(encoder);
+/// for task in tasks.lock().unwrap().drain(..) {
+///     futures::executor::block_on(task);
+/// }
+/// assert!(matches!(*outcome.lock().unwrap(), Some(FinishOutcome::Finished)));
+/// ```
+pub struct FinishOnDrop<W, Sp>
+where
+    W: AsyncWrite + Unpin + Send + 'static,
+    Sp: Spawn,
+{
+    // Only taken in the drop method.
+    writer: Once<W>,
+    spawner: Sp,
+    on_outcome: Option<OutcomeHook>,
+    closed: bool,
+    abandoned: Option<String>,
+}
+
+impl<W, Sp> FinishOnDrop<W, Sp>
+where
+    W: AsyncWrite + Unpin + Send + 'static,
+    Sp: Spawn,
+{
+    pub fn new(writer: W, spawner: Sp) -> Self {
+        Self {
+            writer: Once::new(writer),
+            spawner,
+            on_outcome: None,
+            closed: false,
+            abandoned: None,
+        }
+    }
+
+    /// Calls `hook` with how the writer was torn down if it is dropped without being closed,
+    /// replacing any previous hook.
+    pub fn on_outcome<H: FnOnce(FinishOutcome) + Send + 'static>(mut self, hook: H) -> Self {
+        self.on_outcome = Some(Box::new(hook));
+        self
+    }
+
+    /// Drops the writer as is once this is dropped, instead of closing it, such as because the
+    /// data written so far is incomplete and must not be finished.
+    pub fn abandon(&mut self, reason: impl Into<String>) {
+        self.abandoned = Some(reason.into());
+    }
+
+    /// Returns the inner writer.
+    pub fn get_ref(&self) -> &W {
+        // Only taken in the drop method.
+        self.writer.get().expect("writer taken before drop")
+    }
+
+    fn writer(&mut self) -> Pin<&mut W> {
+        // Only taken in the drop method.
+        Pin::new(self.writer.get_mut().expect("writer taken before drop"))
+    }
+}
+
+// Nothing is pinned structurally, the writer is `Unpin` and the spawner is never polled.
+impl<W, Sp> Unpin for FinishOnDrop<W, Sp>
+where
+    W: AsyncWrite + Unpin + Send + 'static,
+    Sp: Spawn,
+{
+}
+
+#[cfg(feature = "compression")]
+impl<W, Sp> FinishOnDrop<async_compression::futures::write::GzipEncoder<W>, Sp>
+where
+    W: AsyncWrite + Unpin + Send + 'static,
+    Sp: Spawn,
+{
+    /// Gzip-encodes everything written into `writer`, finishing the encoding once dropped.
+    pub fn gzip(writer: W, spawner: Sp) -> Self {
+        Self::new(
+            async_compression::futures::write::GzipEncoder::new(writer),
+            spawner,
+        )
+    }
+}
+
+impl<W, Sp> AsyncWrite for FinishOnDrop<W, Sp>
+where
+    W: AsyncWrite + Unpin + Send + 'static,
+    Sp: Spawn,
+{
+    fn poll_write(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        self.writer().poll_write(cx, buf)
+    }
+
+    fn poll_write_vectored(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        bufs: &[IoSlice<'_>],
+    ) -> Poll<io::Result<usize>> {
+        self.writer().poll_write_vectored(cx, bufs)
+    }
+
+    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        self.writer().poll_flush(cx)
+    }
+
+    fn poll_close(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        let poll = self.writer().poll_close(cx);
+        if let Poll::Ready(Ok(())) = poll {
+            self.closed = true;
+        }
+
+        poll
+    }
+}
+
+impl<W, Sp> Drop for FinishOnDrop<W, Sp>
+where
+    W: AsyncWrite + Unpin + Send + 'static,
+    Sp: Spawn,
+{
+    fn drop(&mut self) {
+        if self.closed {
+            return;
+        }
+        let Some(mut writer) = self.writer.take() else {
+            return;
+        };
+
+        let on_outcome = self.on_outcome.take();
+        if let Some(reason) = self.abandoned.take() {
+            drop(writer);
+            if let Some(on_outcome) = on_outcome {
+                on_outcome(FinishOutcome::Abandoned(reason));
+            }
+            return;
+        }
+
+        self.spawner.spawn(Box::pin(async move {
+            let outcome = match poll_fn(|cx| Pin::new(&mut writer).poll_close(cx)).await {
+                Ok(()) => FinishOutcome::Finished,
+                Err(error) => FinishOutcome::Failed(error),
+            };
+            if let Some(on_outcome) = on_outcome {
+                on_outcome(outcome);
+            }
+        }));
+    }
+}
+
+impl<W, Sp> fmt::Debug for FinishOnDrop<W, Sp>
+where
+    W: AsyncWrite + Unpin + Send + 'static,
+    Sp: Spawn,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("FinishOnDrop")
+            .field("closed", &self.closed)
+            .field("abandoned", &self.abandoned)
+            .finish_non_exhaustive()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{Arc, Mutex};
+
+    use crate::{BoxFuture, FinishOnDrop, FinishOutcome};
+    use futures::{executor::block_on, io::Cursor, AsyncWriteExt};
+
+    #[test]
+    fn abandoned_writer_is_not_closed() {
+        let spawned = Arc::new(Mutex::new(0));
+        let outcome = Arc::new(Mutex::new(None));
+
+        let spawned_ref = spawned.clone();
+        let outcome_ref = outcome.clone();
+        let mut writer = FinishOnDrop::new(Cursor::new(Vec::new()), move |_: BoxFuture| {
+            *spawned_ref.lock().unwrap() += 1
+        })
+        .on_outcome(move |o| *outcome_ref.lock().unwrap() = Some(o));
+        block_on(writer.write_all(b"half")).unwrap();
+        writer.abandon("upstream failed");
+        drop(writer);
+
+        assert_eq!(*spawned.lock().unwrap(), 0);
+        assert!(matches!(
+            outcome.lock().unwrap().take(),
+            Some(FinishOutcome::Abandoned(reason)) if reason == "upstream failed"
+        ));
+    }
+
+    #[cfg(feature = "compression")]
+    #[test]
+    fn dropped_gzip_encoder_writes_trailer() {
+        use futures::AsyncWrite;
+        use std::{
+            pin::Pin,
+            task::{Context, Poll},
+        };
+
+        /// A writer that shares what was written, as the encoder is moved into the close task.
+        #[derive(Clone, Default)]
+        struct Shared(Arc<Mutex<Vec<u8>>>);
+
+        impl AsyncWrite for Shared {
+            fn poll_write(
+                self: Pin<&mut Self>,
+                _: &mut Context<'_>,
+                buf: &[u8],
+            ) -> Poll<std::io::Result<usize>> {
+                self.0.lock().unwrap().extend_from_slice(buf);
+                Poll::Ready(Ok(buf.len()))
+            }
+
+            fn poll_flush(self: Pin<&mut Self>, _: &mut Context<'_>) -> Poll<std::io::Result<()>> {
+                Poll::Ready(Ok(()))
+            }
+
+            fn poll_close(self: Pin<&mut Self>, _: &mut Context<'_>) -> Poll<std::io::Result<()>> {
+                Poll::Ready(Ok(()))
+            }
+        }
+
+        let tasks = Arc::new(Mutex::new(Vec::<BoxFuture>::new()));
+        let tasks_ref = tasks.clone();
+        let output = Shared::default();
+        let mut encoder = FinishOnDrop::gzip(output.clone(), move |future| {
+            tasks_ref.lock().unwrap().push(future)
+        });
+        block_on(encoder.write_all(b"hello")).unwrap();
+        drop(encoder);
+
+        for task in tasks.lock().unwrap().drain(..) {
+            block_on(task);
+        }
+        let output = output.0.lock().unwrap();
+        // A complete gzip member ends with the CRC32 and the length of the input.
+        assert_eq!(&output[..2], [0x1f, 0x8b]);
+        assert_eq!(&output[output.len() - 4..], 5u32.to_le_bytes());
+    }
+}
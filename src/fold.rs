@@ -0,0 +1,166 @@
+use futures_core::Stream;
+use pin_project::{pin_project, pinned_drop};
+use std::{
+    fmt,
+    pin::Pin,
+    task::{Context, Poll},
+};
+
+use crate::{context::Stats, dropper::Once, DropContext};
+
+/// A stream that folds every item it yields into an accumulator, and hands the final accumulator
+/// by value to a closure once it is dropped, together with a [`DropContext`].
+///
+/// This batches what happened over the stream's lifetime, such as the usage to meter or the
+/// entries of an audit batch, so it can be committed once at teardown without the fold reaching
+/// for shared mutable state.
+///
+/// Example
+/// ```
+/// use futures::{executor::block_on_stream, stream};
+/// use drop_stream::DropStreamExt;
+///
+/// let mut metered = None;
+/// let metered_ref = &mut metered;
+/// let stream = stream::iter(["GET /a", "GET /b", "POST /c"]).fold_on_drop(
+///     (0, 0),
+///     |(reads, writes), request| match request.starts_with("GET") {
+///         true => *reads += 1,
+///         false => *writes += 1,
+///     },
+///     move |_context, usage| *metered_ref = Some(usage),
+/// );
+///
+/// assert_eq!(block_on_stream(stream).count(), 3);
+/// assert_eq!(metered, Some((2, 1)));
+/// ```
+#[pin_project(PinnedDrop)]
+pub struct FoldOnDrop<S, A, F, U>
+where
+    S: Stream,
+    F: FnMut(&mut A, &S::Item),
+    U: FnOnce(DropContext, A),
+{
+    #[pin]
+    stream: S,
+    fold: F,
+    stats: Stats,
+    // Only taken in the drop method.
+    accumulator: Option<A>,
+    dropper: Once<U>,
+}
+
+impl<S, A, F, U> FoldOnDrop<S, A, F, U>
+where
+    S: Stream,
+    F: FnMut(&mut A, &S::Item),
+    U: FnOnce(DropContext, A),
+{
+    #[track_caller]
+    pub fn new(stream: S, init: A, fold: F, dropper: U) -> Self {
+        Self {
+            stream,
+            fold,
+            stats: Stats::new(),
+            accumulator: Some(init),
+            dropper: Once::new(dropper),
+        }
+    }
+
+    /// Returns the accumulator as folded so far.
+    pub fn accumulator(&self) -> &A {
+        // Only taken in the drop method.
+        self.accumulator
+            .as_ref()
+            .expect("accumulator taken before drop")
+    }
+}
+
+impl<S, A, F, U> Stream for FoldOnDrop<S, A, F, U>
+where
+    S: Stream,
+    F: FnMut(&mut A, &S::Item),
+    U: FnOnce(DropContext, A),
+{
+    type Item = S::Item;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let this = self.project();
+
+        let poll = this.stream.poll_next(cx);
+        match &poll {
+            Poll::Ready(Some(item)) => {
+                this.stats.record_item();
+                if let Some(accumulator) = this.accumulator.as_mut() {
+                    (this.fold)(accumulator, item);
+                }
+            }
+            Poll::Ready(None) => this.stats.record_end(),
+            Poll::Pending => this.stats.record_pending(),
+        }
+
+        poll
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.stream.size_hint()
+    }
+}
+
+#[pinned_drop]
+impl<S, A, F, U> PinnedDrop for FoldOnDrop<S, A, F, U>
+where
+    S: Stream,
+    F: FnMut(&mut A, &S::Item),
+    U: FnOnce(DropContext, A),
+{
+    fn drop(self: Pin<&mut Self>) {
+        let this = self.project();
+
+        if let (Some(dropper), Some(accumulator)) = (this.dropper.take(), this.accumulator.take()) {
+            dropper(this.stats.context(), accumulator)
+        }
+    }
+}
+
+impl<S, A, F, U> fmt::Debug for FoldOnDrop<S, A, F, U>
+where
+    S: Stream,
+    A: fmt::Debug,
+    F: FnMut(&mut A, &S::Item),
+    U: FnOnce(DropContext, A),
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("FoldOnDrop")
+            .field("accumulator", &self.accumulator)
+            .finish_non_exhaustive()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{DropReason, DropStreamExt};
+    use futures::{executor::block_on_stream, stream::iter};
+
+    #[test]
+    fn cancelled_stream_commits_partial_batch() {
+        let mut committed = None;
+
+        {
+            let committed_ref = &mut committed;
+            let stream = iter(["a", "b", "c"]).fold_on_drop(
+                Vec::new(),
+                |batch, entry| batch.push(entry.to_uppercase()),
+                move |context, batch| *committed_ref = Some((context.reason(), batch)),
+            );
+
+            let mut stream = block_on_stream(stream);
+            stream.next();
+            stream.next();
+        }
+
+        let (reason, batch) = committed.unwrap();
+        assert_eq!(reason, DropReason::Cancelled);
+        assert_eq!(batch, ["A", "B"]);
+    }
+}
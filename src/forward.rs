@@ -0,0 +1,190 @@
+use futures_core::Stream;
+use pin_project::{pin_project, pinned_drop};
+use std::{
+    pin::Pin,
+    task::{Context, Poll, Waker},
+};
+
+/// A destination that leftover items can be handed to without blocking.
+///
+/// Implemented for any `FnMut(T)` closure, for [`std::sync::mpsc`] senders, and with the `tokio`
+/// feature enabled for tokio's mpsc senders.
+pub trait TrySend<T> {
+    /// Sends `item`, or hands it back if the destination cannot accept it right now.
+    fn try_send(&mut self, item: T) -> Result<(), T>;
+}
+
+impl<T, F: FnMut(T)> TrySend<T> for F {
+    fn try_send(&mut self, item: T) -> Result<(), T> {
+        self(item);
+        Ok(())
+    }
+}
+
+impl<T> TrySend<T> for std::sync::mpsc::Sender<T> {
+    fn try_send(&mut self, item: T) -> Result<(), T> {
+        self.send(item).map_err(|e| e.0)
+    }
+}
+
+impl<T> TrySend<T> for std::sync::mpsc::SyncSender<T> {
+    fn try_send(&mut self, item: T) -> Result<(), T> {
+        std::sync::mpsc::SyncSender::try_send(self, item).map_err(|e| match e {
+            std::sync::mpsc::TrySendError::Full(item)
+            | std::sync::mpsc::TrySendError::Disconnected(item) => item,
+        })
+    }
+}
+
+#[cfg(feature = "tokio")]
+impl<T> TrySend<T> for tokio::sync::mpsc::Sender<T> {
+    fn try_send(&mut self, item: T) -> Result<(), T> {
+        tokio::sync::mpsc::Sender::try_send(self, item).map_err(|e| e.into_inner())
+    }
+}
+
+#[cfg(feature = "tokio")]
+impl<T> TrySend<T> for tokio::sync::mpsc::UnboundedSender<T> {
+    fn try_send(&mut self, item: T) -> Result<(), T> {
+        self.send(item).map_err(|e| e.0)
+    }
+}
+
+/// A stream that, when dropped, moves every item its inner stream can still yield immediately into
+/// a sender, so buffered data isn't silently lost when a consumer disconnects.
+///
+/// Forwarding stops as soon as the inner stream returns `Pending` or finishes, when the sender
+/// rejects an item, or once the optional [`limit`](ForwardOnDrop::limit) is reached. Sources that
+/// never return `Pending` should always set a limit.
+///
+/// Example
+/// ```
+/// use drop_stream::DropStreamExt;
+///
+/// let (sender, receiver) = std::sync::mpsc::channel();
+/// let stream = futures::stream::iter([1, 2, 3]).forward_on_drop(sender);
+///
+/// drop(stream);
+/// assert_eq!(receiver.iter().collect::<Vec<_>>(), vec![1, 2, 3]);
+/// ```
+#[pin_project(PinnedDrop)]
+pub struct ForwardOnDrop<S: Stream, Tx: TrySend<S::Item>> {
+    #[pin]
+    stream: S,
+    sender: Tx,
+    limit: Option<usize>,
+    completed: bool,
+}
+
+impl<S: Stream, Tx: TrySend<S::Item>> ForwardOnDrop<S, Tx> {
+    pub fn new(stream: S, sender: Tx) -> Self {
+        Self {
+            stream,
+            sender,
+            limit: None,
+            completed: false,
+        }
+    }
+
+    /// Forwards at most `limit` items when dropped.
+    pub fn limit(mut self, limit: usize) -> Self {
+        self.limit = Some(limit);
+        self
+    }
+}
+
+impl<S: Stream, Tx: TrySend<S::Item>> Stream for ForwardOnDrop<S, Tx> {
+    type Item = S::Item;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let this = self.project();
+
+        let poll = this.stream.poll_next(cx);
+        if let Poll::Ready(None) = poll {
+            *this.completed = true;
+        }
+
+        poll
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.stream.size_hint()
+    }
+}
+
+#[pinned_drop]
+impl<S: Stream, Tx: TrySend<S::Item>> PinnedDrop for ForwardOnDrop<S, Tx> {
+    fn drop(self: Pin<&mut Self>) {
+        let mut this = self.project();
+
+        if *this.completed {
+            return;
+        }
+
+        // Nothing will be around to be woken, so only items that are ready right now are taken.
+        let mut context = Context::from_waker(Waker::noop());
+        let mut forwarded = 0;
+        while this.limit.is_none_or(|limit| forwarded < limit) {
+            let Poll::Ready(Some(item)) = this.stream.as_mut().poll_next(&mut context) else {
+                break;
+            };
+
+            if this.sender.try_send(item).is_err() {
+                break;
+            }
+
+            forwarded += 1;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::task::Poll;
+
+    use crate::DropStreamExt;
+    use futures::{
+        stream::{iter, repeat},
+        Stream, StreamExt,
+    };
+
+    #[test]
+    fn forwards_ready_items_until_pending() {
+        let mut forwarded = Vec::new();
+
+        {
+            let forwarded_ref = &mut forwarded;
+            let inner = iter([1, 2, 3]).chain(futures::stream::pending());
+            let drop_stream = inner.forward_on_drop(move |item| forwarded_ref.push(item));
+
+            let mut drop_stream = Box::pin(drop_stream);
+
+            let waker = futures::task::noop_waker();
+            let mut context = futures::task::Context::from_waker(&waker);
+            assert_eq!(
+                drop_stream.as_mut().poll_next(&mut context),
+                Poll::Ready(Some(1))
+            );
+        }
+
+        assert_eq!(forwarded, vec![2, 3]);
+    }
+
+    #[test]
+    fn stops_when_sender_is_full() {
+        let (sender, receiver) = std::sync::mpsc::sync_channel(2);
+
+        drop(iter([1, 2, 3, 4]).forward_on_drop(sender));
+
+        assert_eq!(receiver.iter().collect::<Vec<_>>(), vec![1, 2]);
+    }
+
+    #[test]
+    fn limit_bounds_forwarding() {
+        let (sender, receiver) = std::sync::mpsc::channel();
+
+        drop(repeat(true).forward_on_drop(sender).limit(3));
+
+        assert_eq!(receiver.iter().count(), 3);
+    }
+}
@@ -0,0 +1,460 @@
+use futures_core::{future::FusedFuture, Future, TryFuture};
+use pin_project::pin_project;
+use std::{
+    pin::Pin,
+    task::{Context, Poll},
+};
+
+use crate::{dropper::ReasonDropper, DropContext, DropReason, Dropper, Instrumented};
+
+/// A future that wraps another future with a closure that is called once it is dropped.
+///
+/// No [`DropContext`] is built for it; use [`on_drop_ctx`](DropFutureExt::on_drop_ctx) for one.
+///
+/// Example
+/// ```
+/// use drop_stream::DropFutureExt;
+///
+/// let mut has_run = false;
+/// let has_run_ref = &mut has_run;
+/// let future = async { 1 }.on_drop(move || *has_run_ref = true);
+///
+/// assert_eq!(futures::executor::block_on(future), 1);
+/// assert!(has_run);
+/// ```
+#[pin_project]
+pub struct DropFuture<F: Future, U: FnOnce()> {
+    // Declared before the future so the closure runs before the inner future is dropped.
+    dropper: Dropper<U>,
+    #[pin]
+    future: F,
+    // Set once the inner future completed, so the wrapper is fused whatever the inner future is.
+    terminated: bool,
+}
+
+impl<F: Future, U: FnOnce()> DropFuture<F, U> {
+    pub fn new(future: F, dropper: U) -> Self {
+        Self {
+            dropper: Dropper::new(dropper),
+            future,
+            terminated: false,
+        }
+    }
+
+    /// Replaces the closure with `map(closure)`. See
+    /// [`DropStream::map_dropper`](crate::DropStream::map_dropper).
+    pub fn map_dropper<U2: FnOnce()>(self, map: impl FnOnce(U) -> U2) -> DropFuture<F, U2> {
+        let DropFuture {
+            dropper,
+            future,
+            terminated,
+        } = self;
+
+        DropFuture {
+            dropper: dropper.map(map),
+            future,
+            terminated,
+        }
+    }
+
+    /// Calls `hook` with a reference to the output once the inner future completes, before it is
+    /// returned, so one wrapper can both record results and run the closure once dropped. See
+    /// [`OnOutput`].
+    pub fn on_output<H: FnOnce(&F::Output)>(self, hook: H) -> DropFuture<OnOutput<F, H>, U> {
+        let DropFuture {
+            dropper,
+            future,
+            terminated,
+        } = self;
+
+        DropFuture {
+            dropper,
+            future: OnOutput::new(future, hook),
+            terminated,
+        }
+    }
+}
+
+// Unlike `DropStream`, the wrapper tracks whether the inner future completed so that it is fused
+// for any future, which costs the flag rounded up to the alignment of the wrapper. A closure that
+// captures a reference still costs nothing beyond its own size, see the layout assertions for
+// `DropStream`.
+const _: () = {
+    /// Returns how many bytes wrapping an `F` with the closure `U` adds on top of the closure
+    /// itself.
+    const fn overhead<F: Future, U: FnOnce()>(_: &U) -> usize {
+        size_of::<DropFuture<F, U>>() - size_of::<F>() - size_of::<U>()
+    }
+
+    let flag = &mut false;
+    assert!(
+        overhead::<Pin<Box<dyn Future<Output = ()>>>, _>(&|| *flag = true) <= align_of::<usize>()
+    );
+};
+
+impl<F: Future, U: FnOnce()> Future for DropFuture<F, U> {
+    type Output = F::Output;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let this = self.project();
+
+        let poll = this.future.poll(cx);
+        if poll.is_ready() {
+            *this.terminated = true;
+        }
+
+        poll
+    }
+}
+
+/// Terminated once the inner future completed, so the wrapper can be used in `select!` without an
+/// extra `.fuse()`.
+impl<F: Future, U: FnOnce()> FusedFuture for DropFuture<F, U> {
+    fn is_terminated(&self) -> bool {
+        self.terminated
+    }
+}
+
+/// A future that wraps another future with a closure that is only called if it is dropped before
+/// it completed, such as a request handler whose client went away.
+///
+/// The closure is dropped without being called as soon as the inner future completes, so unlike a
+/// plain [`DropFuture`] it doesn't need to track whether the future finished itself.
+///
+/// Example
+/// ```
+/// use drop_stream::DropFutureExt;
+///
+/// let mut cancelled = false;
+/// let cancelled_ref = &mut cancelled;
+/// let future = async { 1 }.on_cancel(move || *cancelled_ref = true);
+///
+/// assert_eq!(futures::executor::block_on(future), 1);
+/// assert!(!cancelled);
+/// ```
+#[pin_project]
+pub struct OnCancel<F: Future, U: FnOnce()> {
+    // Declared before the future so the closure runs before the inner future is dropped.
+    dropper: Dropper<U>,
+    #[pin]
+    future: F,
+}
+
+impl<F: Future, U: FnOnce()> OnCancel<F, U> {
+    pub fn new(future: F, dropper: U) -> Self {
+        Self {
+            dropper: Dropper::new(dropper),
+            future,
+        }
+    }
+
+    /// Calls `hook` with a reference to the output once the inner future completes, so one
+    /// wrapper can both record results and detect cancellation. See [`OnOutput`].
+    pub fn on_output<H: FnOnce(&F::Output)>(self, hook: H) -> OnCancel<OnOutput<F, H>, U> {
+        let OnCancel { dropper, future } = self;
+
+        OnCancel {
+            dropper,
+            future: OnOutput::new(future, hook),
+        }
+    }
+}
+
+impl<F: Future, U: FnOnce()> Future for OnCancel<F, U> {
+    type Output = F::Output;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let this = self.project();
+
+        let poll = this.future.poll(cx);
+        if poll.is_ready() {
+            this.dropper.disarm();
+        }
+
+        poll
+    }
+}
+
+/// Terminated once the inner future completed, which disarming the closure already tracks.
+impl<F: Future, U: FnOnce()> FusedFuture for OnCancel<F, U> {
+    fn is_terminated(&self) -> bool {
+        !self.dropper.is_armed()
+    }
+}
+
+/// A future that calls a hook with a reference to the output of the inner future once it
+/// completes. Created by [`DropFuture::on_output`] and [`OnCancel::on_output`].
+///
+/// Example
+/// ```
+/// use drop_stream::DropFutureExt;
+///
+/// let mut status = None;
+/// let mut cancelled = false;
+/// let future = async { 200 }
+///     .on_cancel(|| cancelled = true)
+///     .on_output(|output| status = Some(*output));
+///
+/// assert_eq!(futures::executor::block_on(future), 200);
+/// assert_eq!((status, cancelled), (Some(200), false));
+/// ```
+#[pin_project]
+pub struct OnOutput<F: Future, H: FnOnce(&F::Output)> {
+    #[pin]
+    future: F,
+    hook: Option<H>,
+}
+
+impl<F: Future, H: FnOnce(&F::Output)> OnOutput<F, H> {
+    pub fn new(future: F, hook: H) -> Self {
+        Self {
+            future,
+            hook: Some(hook),
+        }
+    }
+}
+
+impl<F: Future, H: FnOnce(&F::Output)> Future for OnOutput<F, H> {
+    type Output = F::Output;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let this = self.project();
+
+        let poll = this.future.poll(cx);
+        if let Poll::Ready(output) = &poll {
+            if let Some(hook) = this.hook.take() {
+                hook(output);
+            }
+        }
+
+        poll
+    }
+}
+
+/// Terminated once the inner future completed, which taking the hook already tracks.
+impl<F: Future, H: FnOnce(&F::Output)> FusedFuture for OnOutput<F, H> {
+    fn is_terminated(&self) -> bool {
+        self.hook.is_none()
+    }
+}
+
+/// A [`TryFuture`] wrapper that calls a closure with a [`DropReason`] once it is dropped,
+/// distinguishing an `Ok` output ([`DropReason::Completed`]), an `Err` output
+/// ([`DropReason::CompletedWithError`]) and being dropped before completing
+/// ([`DropReason::Cancelled`]).
+///
+/// Example
+/// ```
+/// use drop_stream::{DropReason, DropFutureExt};
+///
+/// let mut reason = None;
+/// let reason_ref = &mut reason;
+/// let future = async { Err::<(), _>("refused") }.on_try_drop(move |r| *reason_ref = Some(r));
+///
+/// assert_eq!(futures::executor::block_on(future), Err("refused"));
+/// assert_eq!(reason, Some(DropReason::CompletedWithError));
+/// ```
+#[pin_project]
+pub struct DropTryFuture<F: TryFuture, U: FnOnce(DropReason)> {
+    // Declared before the future so the closure runs before the inner future is dropped.
+    dropper: ReasonDropper<U>,
+    #[pin]
+    future: F,
+}
+
+impl<F: TryFuture, U: FnOnce(DropReason)> DropTryFuture<F, U> {
+    pub fn new(future: F, dropper: U) -> Self {
+        Self {
+            dropper: ReasonDropper::new(dropper),
+            future,
+        }
+    }
+}
+
+impl<F: TryFuture, U: FnOnce(DropReason)> Future for DropTryFuture<F, U> {
+    type Output = Result<F::Ok, F::Error>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let this = self.project();
+
+        let poll = this.future.try_poll(cx);
+        match &poll {
+            Poll::Ready(Ok(_)) => this.dropper.reason = DropReason::Completed,
+            Poll::Ready(Err(_)) => this.dropper.reason = DropReason::CompletedWithError,
+            Poll::Pending => {}
+        }
+
+        poll
+    }
+}
+
+/// Terminated once the inner future completed, which the reason already tracks, so the wrapper
+/// can be used in `select!` without an extra `.fuse()`.
+impl<F: TryFuture, U: FnOnce(DropReason)> FusedFuture for DropTryFuture<F, U> {
+    fn is_terminated(&self) -> bool {
+        self.dropper.reason != DropReason::Cancelled
+    }
+}
+
+pub trait DropFutureExt: Future + Sized {
+    /// Wraps the future with a closure that is called once it is dropped. See [`DropFuture`].
+    fn on_drop<U: FnOnce()>(self, dropper: U) -> DropFuture<Self, U>;
+
+    /// Wraps the future with a closure that is only called if it is dropped before it completed.
+    /// See [`OnCancel`].
+    fn on_cancel<U: FnOnce()>(self, dropper: U) -> OnCancel<Self, U>;
+
+    /// Wraps the future with a closure that is called with a [`DropContext`] once it is dropped.
+    /// See [`Instrumented`].
+    fn on_drop_ctx<U: FnOnce(DropContext)>(self, dropper: U) -> Instrumented<Self, U>;
+
+    /// Wraps a fallible future with a closure that is called with the outcome once it is dropped.
+    /// See [`DropTryFuture`].
+    fn on_try_drop<U: FnOnce(DropReason)>(self, dropper: U) -> DropTryFuture<Self, U>
+    where
+        Self: TryFuture;
+}
+
+impl<T> DropFutureExt for T
+where
+    T: Future + Sized,
+{
+    fn on_drop<U: FnOnce()>(self, dropper: U) -> DropFuture<T, U> {
+        DropFuture::new(self, dropper)
+    }
+
+    fn on_cancel<U: FnOnce()>(self, dropper: U) -> OnCancel<T, U> {
+        OnCancel::new(self, dropper)
+    }
+
+    #[track_caller]
+    fn on_drop_ctx<U: FnOnce(DropContext)>(self, dropper: U) -> Instrumented<T, U> {
+        Instrumented::new(self, dropper)
+    }
+
+    fn on_try_drop<U: FnOnce(DropReason)>(self, dropper: U) -> DropTryFuture<T, U>
+    where
+        T: TryFuture,
+    {
+        DropTryFuture::new(self, dropper)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::task::Poll;
+
+    use crate::{DropFutureExt, DropReason};
+    use futures::future::{pending, ready, Future};
+
+    #[test]
+    fn dropper_runs_on_drop() {
+        let mut has_run = false;
+
+        {
+            let has_run_ref = &mut has_run;
+            let _drop_future = pending::<()>().on_drop(move || {
+                *has_run_ref = true;
+            });
+        }
+
+        assert!(has_run)
+    }
+
+    #[test]
+    fn on_cancel_only_runs_before_completion() {
+        let mut cancelled = Vec::new();
+
+        {
+            let cancelled_ref = std::sync::Mutex::new(&mut cancelled);
+            let finished = ready(1).on_cancel(|| cancelled_ref.lock().unwrap().push("finished"));
+            assert_eq!(futures::executor::block_on(finished), 1);
+
+            let mut dropped = Box::pin(
+                pending::<()>().on_cancel(|| cancelled_ref.lock().unwrap().push("dropped")),
+            );
+            let waker = futures::task::noop_waker();
+            let mut context = futures::task::Context::from_waker(&waker);
+            assert_eq!(dropped.as_mut().poll(&mut context), Poll::Pending);
+        }
+
+        assert_eq!(cancelled, ["dropped"]);
+    }
+
+    #[test]
+    fn output_hook_runs_before_the_dropper() {
+        let events = std::sync::Mutex::new(Vec::new());
+
+        let future = ready(3)
+            .on_drop(|| events.lock().unwrap().push(String::from("dropped")))
+            .on_output(|output| events.lock().unwrap().push(format!("output {output}")));
+        assert_eq!(futures::executor::block_on(future), 3);
+
+        assert_eq!(*events.lock().unwrap(), ["output 3", "dropped"]);
+    }
+
+    #[test]
+    fn try_future_reports_outcome() {
+        let mut reasons = Vec::new();
+
+        for future in [ready(Ok::<_, ()>(())), ready(Err(()))] {
+            let reasons_ref = &mut reasons;
+            let drop_future = future.on_try_drop(move |r| reasons_ref.push(r));
+            let _ = futures::executor::block_on(drop_future);
+        }
+
+        assert_eq!(
+            reasons,
+            vec![DropReason::Completed, DropReason::CompletedWithError]
+        );
+    }
+
+    #[test]
+    fn try_future_reports_cancelled() {
+        let mut reason = None;
+
+        {
+            let reason_ref = &mut reason;
+            let drop_future =
+                pending::<Result<(), ()>>().on_try_drop(move |r| *reason_ref = Some(r));
+
+            let mut drop_future = Box::pin(drop_future);
+
+            let waker = futures::task::noop_waker();
+            let mut context = futures::task::Context::from_waker(&waker);
+            assert_eq!(drop_future.as_mut().poll(&mut context), Poll::Pending);
+        }
+
+        assert_eq!(reason, Some(DropReason::Cancelled));
+    }
+
+    #[test]
+    fn wrappers_are_fused_for_select() {
+        use futures::{future::FusedFuture, select};
+
+        let mut reason = None;
+        let mut timer = pending::<()>().on_drop(|| {});
+        let mut request = ready(Ok::<_, ()>(1)).on_try_drop(|r| reason = Some(r));
+        let mut context = ready(2).on_drop_ctx(|_| {});
+        let mut cancel = ready(3).on_cancel(|| {}).on_output(|_| {});
+        assert!(!request.is_terminated() && !cancel.is_terminated());
+
+        let mut sum = 0;
+        futures::executor::block_on(async {
+            for _ in 0..3 {
+                select! {
+                    () = timer => unreachable!(),
+                    value = request => sum += value.unwrap(),
+                    value = context => sum += value,
+                    value = cancel => sum += value,
+                }
+            }
+        });
+
+        assert_eq!(sum, 6);
+        assert!(request.is_terminated() && context.is_terminated() && cancel.is_terminated());
+        assert!(!timer.is_terminated());
+        drop(request);
+        assert_eq!(reason, Some(DropReason::Completed));
+    }
+}
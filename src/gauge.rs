@@ -0,0 +1,154 @@
+use std::{
+    collections::HashMap,
+    fmt,
+    sync::{Arc, Mutex, MutexGuard},
+};
+
+use crate::StreamObserver;
+
+/// Counts the wrapped streams that are currently alive, per label, for exporting as a gauge.
+///
+/// Every [`LiveGuard`] returned by [`track`](LiveGauge::track) counts as one live stream until it
+/// is dropped. It is a [`StreamObserver`], so it is usually handed to
+/// [`observe`](crate::DropStreamExt::observe), and is counted from when the stream is wrapped
+/// until the stream is dropped. Labels are removed once their last stream is gone, so labels
+/// such as per-connection IDs don't pile up.
+///
+/// With the `metrics` feature enabled, every change is also set on a `drop_stream_live` gauge
+/// labelled with the label under `label`, which is set back to zero once the last stream under a
+/// label is gone, so dashboards show the drop back to zero.
+///
+/// Example
+/// ```
+/// use drop_stream::{DropStreamExt, LiveGauge};
+///
+/// let gauge = LiveGauge::new();
+/// let first = futures::stream::repeat(true).observe(gauge.track("/events"));
+/// let second = futures::stream::repeat(true).observe(gauge.track("/events"));
+/// assert_eq!(gauge.get("/events"), 2);
+///
+/// drop(first);
+/// assert_eq!(gauge.get("/events"), 1);
+/// # drop(second);
+/// ```
+#[derive(Clone, Default)]
+pub struct LiveGauge {
+    counts: Arc<Mutex<HashMap<String, usize>>>,
+}
+
+impl LiveGauge {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    fn lock(&self) -> MutexGuard<'_, HashMap<String, usize>> {
+        self.counts.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// Counts one more live stream under `label`, until the returned guard is dropped.
+    pub fn track(&self, label: impl Into<String>) -> LiveGuard {
+        let label = label.into();
+        let mut counts = self.lock();
+        let count = counts.entry(label.clone()).or_default();
+        *count += 1;
+        report(&label, *count);
+        drop(counts);
+
+        LiveGuard {
+            gauge: self.clone(),
+            label,
+        }
+    }
+
+    /// Returns the number of live streams under `label`.
+    pub fn get(&self, label: &str) -> usize {
+        self.lock().get(label).copied().unwrap_or(0)
+    }
+
+    /// Returns the number of live streams across every label.
+    pub fn total(&self) -> usize {
+        self.lock().values().sum()
+    }
+
+    /// Returns the number of live streams for every label with any, sorted by label.
+    pub fn snapshot(&self) -> Vec<(String, usize)> {
+        let mut counts: Vec<_> = self
+            .lock()
+            .iter()
+            .map(|(label, count)| (label.clone(), *count))
+            .collect();
+        counts.sort_unstable();
+        counts
+    }
+}
+
+/// Sets the `drop_stream_live` gauge of `label` to `count`.
+#[cfg(feature = "metrics")]
+fn report(label: &str, count: usize) {
+    metrics::gauge!("drop_stream_live", "label" => label.to_owned()).set(count as f64);
+}
+
+#[cfg(not(feature = "metrics"))]
+fn report(_label: &str, _count: usize) {}
+
+impl fmt::Debug for LiveGauge {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_map().entries(self.snapshot()).finish()
+    }
+}
+
+/// Counts as one live stream in a [`LiveGauge`] until dropped. Created by [`LiveGauge::track`].
+#[derive(Debug)]
+#[must_use = "the stream stops being counted once the guard is dropped"]
+pub struct LiveGuard {
+    gauge: LiveGauge,
+    label: String,
+}
+
+impl LiveGuard {
+    /// The label the stream is counted under.
+    pub fn label(&self) -> &str {
+        &self.label
+    }
+}
+
+impl<T> StreamObserver<T> for LiveGuard {}
+
+impl Drop for LiveGuard {
+    fn drop(&mut self) {
+        let mut counts = self.gauge.lock();
+        let Some(count) = counts.get_mut(&self.label) else {
+            return;
+        };
+
+        *count -= 1;
+        report(&self.label, *count);
+        if *count == 0 {
+            counts.remove(&self.label);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{DropStreamExt, LiveGauge};
+    use futures::stream::repeat;
+
+    #[test]
+    fn counts_live_streams_per_label() {
+        let gauge = LiveGauge::new();
+
+        let events = repeat(true).observe(gauge.track("/events"));
+        let chat = repeat(true).observe(gauge.track("/chat"));
+        let more_chat = repeat(true).observe(gauge.track("/chat"));
+        assert_eq!(gauge.total(), 3);
+
+        drop(chat);
+        drop(events);
+        assert_eq!(gauge.snapshot(), vec![("/chat".to_owned(), 1)]);
+
+        drop(more_chat);
+        assert_eq!(gauge.total(), 0);
+        assert!(gauge.snapshot().is_empty());
+    }
+}
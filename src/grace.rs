@@ -0,0 +1,252 @@
+use futures_core::Stream;
+use pin_project::{pin_project, pinned_drop};
+use std::{
+    fmt,
+    pin::Pin,
+    sync::{Arc, Mutex},
+    task::{Context, Poll},
+    time::Duration,
+};
+use tokio::runtime::Handle;
+
+type Callback = Box<dyn FnOnce() + Send>;
+
+struct State {
+    // Bumped on every subscribe and drop, so a scheduled callback can tell it was superseded.
+    generation: u64,
+    live: usize,
+    callback: Option<Callback>,
+}
+
+struct Shared {
+    delay: Duration,
+    runtime: Handle,
+    state: Mutex<State>,
+}
+
+impl Shared {
+    fn lock(&self) -> std::sync::MutexGuard<'_, State> {
+        self.state.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+/// A stream whose drop callback only runs after a grace period, and is cancelled if a new stream
+/// is subscribed through the paired [`GraceHandle`] in the meantime.
+///
+/// This implements the common "grace period before tearing down server state on reconnect"
+/// pattern: every stream subscribed through the same handle shares one callback, which runs once
+/// the last of them has been dropped for longer than the delay. The callback runs on the tokio
+/// runtime the stream was created in.
+///
+/// # Panics
+///
+/// Panics if created outside of a tokio runtime.
+///
+/// Example
+/// ```
+/// # #[tokio::main(flavor = "current_thread", start_paused = true)]
+/// # async fn main() {
+/// use std::{sync::{atomic::{AtomicBool, Ordering}, Arc}, time::Duration};
+/// use drop_stream::DropStreamExt;
+///
+/// let torn_down = Arc::new(AtomicBool::new(false));
+/// let torn_down_ref = torn_down.clone();
+/// let (stream, handle) = futures::stream::repeat(true)
+///     .on_drop_delayed(Duration::from_secs(30), move || {
+///         torn_down_ref.store(true, Ordering::SeqCst);
+///     });
+///
+/// drop(stream);
+///
+/// // The client reconnects within the grace period.
+/// tokio::time::sleep(Duration::from_secs(10)).await;
+/// let stream = handle.resubscribe(futures::stream::repeat(true));
+///
+/// tokio::time::sleep(Duration::from_secs(60)).await;
+/// assert!(!torn_down.load(Ordering::SeqCst));
+/// # drop(stream);
+/// # }
+/// ```
+#[pin_project(PinnedDrop)]
+pub struct DelayedDrop<S: Stream> {
+    #[pin]
+    stream: S,
+    shared: Arc<Shared>,
+}
+
+impl<S: Stream> DelayedDrop<S> {
+    pub fn new<U: FnOnce() + Send + 'static>(
+        stream: S,
+        delay: Duration,
+        callback: U,
+    ) -> (Self, GraceHandle) {
+        let shared = Arc::new(Shared {
+            delay,
+            runtime: Handle::current(),
+            state: Mutex::new(State {
+                generation: 0,
+                live: 1,
+                callback: Some(Box::new(callback)),
+            }),
+        });
+
+        let handle = GraceHandle {
+            shared: shared.clone(),
+        };
+
+        (Self { stream, shared }, handle)
+    }
+}
+
+impl<S: Stream> Stream for DelayedDrop<S> {
+    type Item = S::Item;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        self.project().stream.poll_next(cx)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.stream.size_hint()
+    }
+}
+
+#[pinned_drop]
+impl<S: Stream> PinnedDrop for DelayedDrop<S> {
+    fn drop(self: Pin<&mut Self>) {
+        let generation = {
+            let mut state = self.shared.lock();
+            state.generation += 1;
+            state.live -= 1;
+            if state.live > 0 {
+                return;
+            }
+            state.generation
+        };
+
+        let shared = self.shared.clone();
+        drop(self.shared.runtime.spawn(async move {
+            tokio::time::sleep(shared.delay).await;
+
+            let callback = {
+                let mut state = shared.lock();
+                if state.generation != generation {
+                    return;
+                }
+                state.callback.take()
+            };
+
+            if let Some(callback) = callback {
+                callback();
+            }
+        }));
+    }
+}
+
+/// The handle paired with a [`DelayedDrop`] stream, used to cancel its pending callback by
+/// subscribing a new stream.
+#[derive(Clone)]
+pub struct GraceHandle {
+    shared: Arc<Shared>,
+}
+
+impl GraceHandle {
+    /// Wraps `stream` so it shares the pending callback, cancelling the scheduled run if one is
+    /// pending. Returns `None` if the callback has already run.
+    pub fn resubscribe<S: Stream>(&self, stream: S) -> Option<DelayedDrop<S>> {
+        let mut state = self.shared.lock();
+        state.callback.as_ref()?;
+        state.generation += 1;
+        state.live += 1;
+        drop(state);
+
+        Some(DelayedDrop {
+            stream,
+            shared: self.shared.clone(),
+        })
+    }
+
+    /// Returns true if the callback has already run.
+    pub fn is_torn_down(&self) -> bool {
+        self.shared.lock().callback.is_none()
+    }
+}
+
+impl fmt::Debug for GraceHandle {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let state = self.shared.lock();
+        f.debug_struct("GraceHandle")
+            .field("delay", &self.shared.delay)
+            .field("live", &state.live)
+            .field("torn_down", &state.callback.is_none())
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        sync::{
+            atomic::{AtomicUsize, Ordering},
+            Arc,
+        },
+        time::Duration,
+    };
+
+    use crate::DropStreamExt;
+    use futures::stream::repeat;
+
+    fn counter() -> (Arc<AtomicUsize>, impl FnOnce() + Send + 'static) {
+        let count = Arc::new(AtomicUsize::new(0));
+        let count_ref = count.clone();
+        (count, move || {
+            count_ref.fetch_add(1, Ordering::SeqCst);
+        })
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn callback_runs_after_delay() {
+        let (count, callback) = counter();
+        let (drop_stream, handle) = repeat(true).on_drop_delayed(Duration::from_secs(10), callback);
+
+        drop(drop_stream);
+        tokio::time::sleep(Duration::from_secs(5)).await;
+        assert_eq!(count.load(Ordering::SeqCst), 0);
+
+        tokio::time::sleep(Duration::from_secs(6)).await;
+        assert_eq!(count.load(Ordering::SeqCst), 1);
+        assert!(handle.is_torn_down());
+        assert!(handle.resubscribe(repeat(true)).is_none());
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn resubscribe_cancels_pending_callback() {
+        let (count, callback) = counter();
+        let (drop_stream, handle) = repeat(true).on_drop_delayed(Duration::from_secs(10), callback);
+
+        drop(drop_stream);
+        tokio::time::sleep(Duration::from_secs(5)).await;
+        let drop_stream = handle.resubscribe(repeat(true)).unwrap();
+
+        tokio::time::sleep(Duration::from_secs(20)).await;
+        assert_eq!(count.load(Ordering::SeqCst), 0);
+
+        drop(drop_stream);
+        tokio::time::sleep(Duration::from_secs(11)).await;
+        assert_eq!(count.load(Ordering::SeqCst), 1);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn waits_for_last_subscriber() {
+        let (count, callback) = counter();
+        let (first, handle) = repeat(true).on_drop_delayed(Duration::from_secs(10), callback);
+        let second = handle.resubscribe(repeat(true)).unwrap();
+
+        drop(first);
+        tokio::time::sleep(Duration::from_secs(20)).await;
+        assert_eq!(count.load(Ordering::SeqCst), 0);
+
+        drop(second);
+        tokio::time::sleep(Duration::from_secs(11)).await;
+        assert_eq!(count.load(Ordering::SeqCst), 1);
+    }
+}
@@ -0,0 +1,110 @@
+use std::{
+    fmt,
+    ops::{Deref, DerefMut},
+};
+
+use crate::dropper::Once;
+
+/// A value that is handed to a closure once the guard is dropped, for values that aren't streams,
+/// futures or sinks, such as a connection or a session.
+///
+/// Unlike the wrappers for those, which keep their value pinned in place and call a closure that
+/// takes no arguments, the guard moves the value into its closure, so the value must not be
+/// pinned. The wrappers are not built on the guard for that reason.
+///
+/// The guard dereferences to the value, so it can be used in its place, and
+/// [`into_inner`](Self::into_inner) takes the value back out without calling the closure.
+///
+/// Example
+/// ```
+/// use drop_stream::DropGuard;
+///
+/// let mut closed = Vec::new();
+/// {
+///     let mut session = DropGuard::new(vec!["hello"], |session| closed = session);
+///     session.push("goodbye");
+///     assert_eq!(session.len(), 2);
+/// }
+///
+/// assert_eq!(closed, ["hello", "goodbye"]);
+/// ```
+pub struct DropGuard<T, F: FnOnce(T)> {
+    // Held together so the value can be moved into the closure when dropped.
+    inner: Once<(T, F)>,
+}
+
+impl<T, F: FnOnce(T)> DropGuard<T, F> {
+    pub fn new(value: T, dropper: F) -> Self {
+        Self {
+            inner: Once::new((value, dropper)),
+        }
+    }
+
+    /// Returns the value without calling the closure.
+    pub fn into_inner(mut self) -> T {
+        // Only taken here and in the drop method.
+        let (value, _) = self.inner.take().expect("value taken before drop");
+        value
+    }
+}
+
+impl<T, F: FnOnce(T)> Deref for DropGuard<T, F> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        // Only taken in `into_inner` and the drop method.
+        let (value, _) = self.inner.get().expect("value taken before drop");
+        value
+    }
+}
+
+impl<T, F: FnOnce(T)> DerefMut for DropGuard<T, F> {
+    fn deref_mut(&mut self) -> &mut T {
+        // Only taken in `into_inner` and the drop method.
+        let (value, _) = self.inner.get_mut().expect("value taken before drop");
+        value
+    }
+}
+
+impl<T, F: FnOnce(T)> Drop for DropGuard<T, F> {
+    fn drop(&mut self) {
+        if let Some((value, dropper)) = self.inner.take() {
+            dropper(value)
+        }
+    }
+}
+
+impl<T: fmt::Debug, F: FnOnce(T)> fmt::Debug for DropGuard<T, F> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_tuple("DropGuard").field(&**self).finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::DropGuard;
+
+    #[test]
+    fn into_inner_skips_the_closure() {
+        let mut runs = 0;
+
+        let guard = DropGuard::new(7, |_| runs += 1);
+        assert_eq!(*guard, 7);
+        assert_eq!(guard.into_inner(), 7);
+
+        assert_eq!(runs, 0);
+    }
+
+    #[test]
+    fn closure_gets_the_mutated_value() {
+        let mut dropped = None;
+
+        {
+            let mut guard = DropGuard::new(String::from("conn"), |value| dropped = Some(value));
+            guard.push_str("-1");
+            assert_eq!(format!("{guard:?}"), r#"DropGuard("conn-1")"#);
+        }
+
+        assert_eq!(dropped.as_deref(), Some("conn-1"));
+    }
+}
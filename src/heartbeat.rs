@@ -0,0 +1,148 @@
+use futures_core::Stream;
+use pin_project::{pin_project, pinned_drop};
+use std::{
+    pin::Pin,
+    sync::{Arc, Mutex},
+    task::{Context, Poll},
+    time::Duration,
+};
+use tokio::{
+    task::JoinHandle,
+    time::{Instant, MissedTickBehavior},
+};
+
+/// A stream that calls a heartbeat closure at a fixed interval for as long as it is alive, and
+/// stops exactly when it is dropped.
+///
+/// Useful for keep-alive or lease renewal of server-side session state. The heartbeat runs on a
+/// task spawned onto the current tokio runtime, first after one interval has passed; missed beats
+/// are skipped rather than bunched up. Once the stream has been dropped the closure is never called
+/// again, even if a beat was already due. To send to a channel instead, send from the closure.
+///
+/// # Panics
+///
+/// Panics if created outside of a tokio runtime.
+///
+/// Example
+/// ```
+/// # #[tokio::main(flavor = "current_thread", start_paused = true)]
+/// # async fn main() {
+/// use std::{sync::{atomic::{AtomicUsize, Ordering}, Arc}, time::Duration};
+/// use drop_stream::DropStreamExt;
+///
+/// let beats = Arc::new(AtomicUsize::new(0));
+/// let beats_ref = beats.clone();
+/// let stream = futures::stream::repeat(true).heartbeat(Duration::from_secs(10), move || {
+///     beats_ref.fetch_add(1, Ordering::SeqCst);
+/// });
+///
+/// tokio::time::sleep(Duration::from_secs(35)).await;
+/// drop(stream);
+/// tokio::time::sleep(Duration::from_secs(35)).await;
+///
+/// assert_eq!(beats.load(Ordering::SeqCst), 3);
+/// # }
+/// ```
+#[pin_project(PinnedDrop)]
+pub struct Heartbeat<S: Stream, H: FnMut() + Send + 'static> {
+    #[pin]
+    stream: S,
+    // Taken under the lock on drop, so no beat can run after the drop has returned.
+    hook: Arc<Mutex<Option<H>>>,
+    task: JoinHandle<()>,
+}
+
+impl<S: Stream, H: FnMut() + Send + 'static> Heartbeat<S, H> {
+    pub fn new(stream: S, interval: Duration, hook: H) -> Self {
+        let hook = Arc::new(Mutex::new(Some(hook)));
+
+        let task = tokio::spawn({
+            let hook = hook.clone();
+            async move {
+                let mut interval = tokio::time::interval_at(Instant::now() + interval, interval);
+                interval.set_missed_tick_behavior(MissedTickBehavior::Skip);
+
+                loop {
+                    interval.tick().await;
+
+                    let mut hook = hook.lock().unwrap_or_else(|e| e.into_inner());
+                    let Some(hook) = hook.as_mut() else {
+                        return;
+                    };
+                    hook();
+                }
+            }
+        });
+
+        Self { stream, hook, task }
+    }
+}
+
+impl<S: Stream, H: FnMut() + Send + 'static> Stream for Heartbeat<S, H> {
+    type Item = S::Item;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        self.project().stream.poll_next(cx)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.stream.size_hint()
+    }
+}
+
+#[pinned_drop]
+impl<S: Stream, H: FnMut() + Send + 'static> PinnedDrop for Heartbeat<S, H> {
+    fn drop(self: Pin<&mut Self>) {
+        self.task.abort();
+        self.hook.lock().unwrap_or_else(|e| e.into_inner()).take();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        sync::{
+            atomic::{AtomicUsize, Ordering},
+            Arc,
+        },
+        time::Duration,
+    };
+
+    use crate::DropStreamExt;
+    use futures::stream::repeat;
+
+    #[tokio::test(start_paused = true)]
+    async fn beats_while_alive_and_stops_on_drop() {
+        let beats = Arc::new(AtomicUsize::new(0));
+
+        let beats_ref = beats.clone();
+        let drop_stream = repeat(true).heartbeat(Duration::from_secs(10), move || {
+            beats_ref.fetch_add(1, Ordering::SeqCst);
+        });
+
+        tokio::time::sleep(Duration::from_secs(5)).await;
+        assert_eq!(beats.load(Ordering::SeqCst), 0);
+
+        tokio::time::sleep(Duration::from_secs(20)).await;
+        assert_eq!(beats.load(Ordering::SeqCst), 2);
+
+        drop(drop_stream);
+        tokio::time::sleep(Duration::from_secs(100)).await;
+        assert_eq!(beats.load(Ordering::SeqCst), 2);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn heartbeat_can_send_to_channel() {
+        let (sender, mut receiver) = tokio::sync::mpsc::unbounded_channel();
+
+        let drop_stream = repeat(true).heartbeat(Duration::from_secs(1), move || {
+            let _ = sender.send(());
+        });
+
+        receiver.recv().await.unwrap();
+        drop(drop_stream);
+
+        // The sender is dropped with the hook, closing the channel.
+        assert_eq!(receiver.recv().await, None);
+    }
+}
@@ -0,0 +1,155 @@
+use std::{
+    fmt,
+    sync::{Arc, Mutex, MutexGuard},
+};
+
+use crate::{ContextDropFn, DropContext, IdempotencyKey};
+
+struct State<F> {
+    // Taken by the first call.
+    cleanup: Option<F>,
+    ran_for: Option<IdempotencyKey>,
+}
+
+/// Guards a cleanup closure so it runs at most once, however many wrappers it is handed to.
+///
+/// Clones share the same closure, so the guard can be given to each of several stacked wrappers
+/// or to every stream sharing a resource, and only the first of them to be dropped runs the
+/// cleanup, with its [`DropContext`]. Later drops do nothing, which matters for cleanup that hits
+/// external systems where running twice is harmful. The
+/// [`idempotency_key`](DropContext::idempotency_key) of the context can additionally be sent
+/// along, so the external system can deduplicate on its side too.
+///
+/// Example
+/// ```
+/// use drop_stream::{ContextDropStream, DropContext, Idempotent};
+///
+/// let mut released = Vec::new();
+/// let release = Idempotent::new(|context: DropContext| {
+///     // Release the lease at the lock service, passing `context.idempotency_key()` along...
+///     released.push(context.idempotency_key());
+/// });
+///
+/// let first = ContextDropStream::new(futures::stream::repeat(1), release.clone());
+/// let second = ContextDropStream::new(futures::stream::repeat(2), release.clone());
+/// drop(first);
+/// drop(second);
+///
+/// assert!(release.has_run());
+/// # drop(release);
+/// assert_eq!(released.len(), 1);
+/// ```
+pub struct Idempotent<F: FnOnce(DropContext)> {
+    state: Arc<Mutex<State<F>>>,
+}
+
+impl<F: FnOnce(DropContext)> Idempotent<F> {
+    pub fn new(cleanup: F) -> Self {
+        Self {
+            state: Arc::new(Mutex::new(State {
+                cleanup: Some(cleanup),
+                ran_for: None,
+            })),
+        }
+    }
+
+    fn lock(&self) -> MutexGuard<'_, State<F>> {
+        self.state.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// Returns true if the cleanup has run.
+    pub fn has_run(&self) -> bool {
+        self.lock().ran_for.is_some()
+    }
+
+    /// Returns the idempotency key of the wrapper the cleanup ran for.
+    pub fn ran_for(&self) -> Option<IdempotencyKey> {
+        self.lock().ran_for
+    }
+}
+
+impl<F: FnOnce(DropContext)> Clone for Idempotent<F> {
+    fn clone(&self) -> Self {
+        Self {
+            state: self.state.clone(),
+        }
+    }
+}
+
+impl<F: FnOnce(DropContext)> ContextDropFn for Idempotent<F> {
+    fn call(self, context: DropContext) {
+        let cleanup = {
+            let mut state = self.lock();
+            let cleanup = state.cleanup.take();
+            if cleanup.is_some() {
+                state.ran_for = Some(context.idempotency_key());
+            }
+            cleanup
+        };
+
+        // Called without the lock held, so the cleanup may drop other wrappers sharing the guard.
+        if let Some(cleanup) = cleanup {
+            cleanup(context);
+        }
+    }
+}
+
+impl<F: FnOnce(DropContext)> fmt::Debug for Idempotent<F> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Idempotent")
+            .field("ran_for", &self.ran_for())
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{Arc, Mutex};
+
+    use crate::{ContextDropStream, DropContext, DropStreamExt, Idempotent};
+    use futures::stream::repeat;
+
+    #[test]
+    fn reentrant_drop_runs_cleanup_once() {
+        let runs = Arc::new(Mutex::new(Vec::new()));
+
+        let inner = Arc::new(Mutex::new(None::<Box<dyn std::any::Any + Send>>));
+        let runs_ref = runs.clone();
+        let inner_ref = inner.clone();
+        let cleanup = Idempotent::new(move |context: DropContext| {
+            runs_ref.lock().unwrap().push(context.id());
+            // Dropping another wrapper sharing the guard from within the cleanup.
+            drop(inner_ref.lock().unwrap().take());
+        });
+
+        let outer = ContextDropStream::new(repeat(1), cleanup.clone());
+        *inner.lock().unwrap() = Some(Box::new(ContextDropStream::new(repeat(2), cleanup.clone())));
+        let outer_id = outer.id();
+        drop(outer);
+
+        assert_eq!(*runs.lock().unwrap(), [outer_id]);
+        assert_eq!(cleanup.ran_for().map(|key| key.id()), Some(outer_id));
+    }
+
+    #[test]
+    fn keys_share_a_generation() {
+        let keys = Arc::new(Mutex::new(Vec::new()));
+
+        for _ in 0..2 {
+            let keys = keys.clone();
+            drop(
+                repeat(1).on_drop_ctx(move |c: DropContext| {
+                    keys.lock().unwrap().push(c.idempotency_key())
+                }),
+            );
+        }
+
+        let keys = keys.lock().unwrap();
+        assert_ne!(keys[0], keys[1]);
+        assert_eq!(keys[0].generation(), keys[1].generation());
+        assert_eq!(
+            keys[0].to_string(),
+            format!("{:x}-{:x}", keys[0].generation(), keys[0].id())
+        );
+    }
+}
@@ -0,0 +1,251 @@
+use futures_core::Stream;
+use pin_project::{pin_project, pinned_drop};
+use std::{
+    pin::Pin,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex,
+    },
+    task::{Context, Poll, Waker},
+    time::Duration,
+};
+use tokio::{sync::Notify, task::JoinHandle, time::Instant};
+
+#[derive(Debug)]
+struct Shared {
+    last_poll: Mutex<Instant>,
+    terminate: AtomicBool,
+    // Set while the hook has fired and the consumer has not polled since.
+    idle: AtomicBool,
+    resumed: Notify,
+    expired: AtomicBool,
+    // The consumer's waker, so it can be woken to observe the end of a terminated stream.
+    waker: Mutex<Option<Waker>>,
+}
+
+/// A stream that runs a hook when it has not been polled for longer than a threshold, to detect
+/// stalled consumers in addition to dropped ones.
+///
+/// The hook runs on a watchdog task spawned onto the current tokio runtime, at most once per idle
+/// period; polling the stream again starts a new one. Time spent waiting on a pending inner stream
+/// counts as idle too. With [`terminate`](IdleTimeout::terminate) set the stream also ends once the
+/// hook has run. The watchdog is stopped when the stream is dropped.
+///
+/// # Panics
+///
+/// Panics if created outside of a tokio runtime.
+///
+/// Example
+/// ```
+/// # #[tokio::main(flavor = "current_thread", start_paused = true)]
+/// # async fn main() {
+/// use std::time::Duration;
+/// use drop_stream::DropStreamExt;
+///
+/// let (sender, receiver) = tokio::sync::oneshot::channel();
+/// let mut sender = Some(sender);
+/// let stream = futures::stream::repeat(true).idle_timeout(Duration::from_secs(30), move || {
+///     if let Some(sender) = sender.take() {
+///         sender.send(()).unwrap();
+///     }
+/// });
+///
+/// // Nobody polls the stream...
+/// receiver.await.unwrap();
+/// # drop(stream);
+/// # }
+/// ```
+#[pin_project(PinnedDrop)]
+pub struct IdleTimeout<S: Stream> {
+    #[pin]
+    stream: S,
+    shared: Arc<Shared>,
+    watchdog: JoinHandle<()>,
+}
+
+impl<S: Stream> IdleTimeout<S> {
+    pub fn new<H: FnMut() + Send + 'static>(stream: S, threshold: Duration, mut hook: H) -> Self {
+        let shared = Arc::new(Shared {
+            last_poll: Mutex::new(Instant::now()),
+            terminate: AtomicBool::new(false),
+            idle: AtomicBool::new(false),
+            resumed: Notify::new(),
+            expired: AtomicBool::new(false),
+            waker: Mutex::new(None),
+        });
+
+        let watchdog = tokio::spawn({
+            let shared = shared.clone();
+            async move {
+                loop {
+                    let deadline =
+                        *shared.last_poll.lock().unwrap_or_else(|e| e.into_inner()) + threshold;
+                    tokio::time::sleep_until(deadline).await;
+
+                    let last_poll = *shared.last_poll.lock().unwrap_or_else(|e| e.into_inner());
+                    if last_poll + threshold > Instant::now() {
+                        continue;
+                    }
+
+                    hook();
+
+                    if shared.terminate.load(Ordering::Acquire) {
+                        shared.expired.store(true, Ordering::Release);
+                        let waker = shared
+                            .waker
+                            .lock()
+                            .unwrap_or_else(|e| e.into_inner())
+                            .take();
+                        if let Some(waker) = waker {
+                            waker.wake();
+                        }
+                        return;
+                    }
+
+                    shared.idle.store(true, Ordering::Release);
+                    shared.resumed.notified().await;
+                }
+            }
+        });
+
+        Self {
+            stream,
+            shared,
+            watchdog,
+        }
+    }
+
+    /// Ends the stream once the idle hook has run.
+    pub fn terminate(self) -> Self {
+        self.shared.terminate.store(true, Ordering::Release);
+        self
+    }
+
+    /// Returns true if the stream was ended because it went idle.
+    pub fn is_expired(&self) -> bool {
+        self.shared.expired.load(Ordering::Acquire)
+    }
+}
+
+impl<S: Stream> Stream for IdleTimeout<S> {
+    type Item = S::Item;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let this = self.project();
+
+        if this.shared.expired.load(Ordering::Acquire) {
+            return Poll::Ready(None);
+        }
+
+        *this
+            .shared
+            .last_poll
+            .lock()
+            .unwrap_or_else(|e| e.into_inner()) = Instant::now();
+        if this.shared.idle.swap(false, Ordering::AcqRel) {
+            this.shared.resumed.notify_one();
+        }
+
+        let poll = this.stream.poll_next(cx);
+        if poll.is_pending() {
+            *this.shared.waker.lock().unwrap_or_else(|e| e.into_inner()) = Some(cx.waker().clone());
+        }
+
+        poll
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        if self.is_expired() {
+            return (0, Some(0));
+        }
+
+        let (lower, upper) = self.stream.size_hint();
+        if self.shared.terminate.load(Ordering::Acquire) {
+            // The stream may be ended by going idle at any point.
+            (0, upper)
+        } else {
+            (lower, upper)
+        }
+    }
+}
+
+#[pinned_drop]
+impl<S: Stream> PinnedDrop for IdleTimeout<S> {
+    fn drop(self: Pin<&mut Self>) {
+        self.watchdog.abort();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        sync::{
+            atomic::{AtomicUsize, Ordering},
+            Arc,
+        },
+        task::Poll,
+        time::Duration,
+    };
+
+    use crate::DropStreamExt;
+    use futures::{stream::repeat, Stream};
+
+    fn counter() -> (Arc<AtomicUsize>, impl FnMut() + Send + 'static) {
+        let count = Arc::new(AtomicUsize::new(0));
+        let count_ref = count.clone();
+        (count, move || {
+            count_ref.fetch_add(1, Ordering::SeqCst);
+        })
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn hook_fires_once_per_idle_period() {
+        let (count, hook) = counter();
+        let mut drop_stream = Box::pin(repeat(true).idle_timeout(Duration::from_secs(10), hook));
+
+        tokio::time::sleep(Duration::from_secs(5)).await;
+        assert_eq!(count.load(Ordering::SeqCst), 0);
+
+        tokio::time::sleep(Duration::from_secs(20)).await;
+        assert_eq!(count.load(Ordering::SeqCst), 1);
+
+        let waker = futures::task::noop_waker();
+        let mut context = futures::task::Context::from_waker(&waker);
+        assert_eq!(
+            drop_stream.as_mut().poll_next(&mut context),
+            Poll::Ready(Some(true))
+        );
+
+        tokio::time::sleep(Duration::from_secs(11)).await;
+        assert_eq!(count.load(Ordering::SeqCst), 2);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn terminate_ends_stream() {
+        let (count, hook) = counter();
+        let drop_stream = repeat(true)
+            .idle_timeout(Duration::from_secs(10), hook)
+            .terminate();
+        let mut drop_stream = Box::pin(drop_stream);
+
+        tokio::time::sleep(Duration::from_secs(11)).await;
+        assert_eq!(count.load(Ordering::SeqCst), 1);
+        assert!(drop_stream.is_expired());
+
+        let waker = futures::task::noop_waker();
+        let mut context = futures::task::Context::from_waker(&waker);
+        assert_eq!(
+            drop_stream.as_mut().poll_next(&mut context),
+            Poll::Ready(None)
+        );
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn dropping_stops_watchdog() {
+        let (count, hook) = counter();
+        drop(repeat(true).idle_timeout(Duration::from_secs(10), hook));
+
+        tokio::time::sleep(Duration::from_secs(11)).await;
+        assert_eq!(count.load(Ordering::SeqCst), 0);
+    }
+}
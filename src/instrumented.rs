@@ -0,0 +1,311 @@
+use futures_core::{future::FusedFuture, Future};
+use pin_project::pin_project;
+use std::{
+    pin::Pin,
+    task::{Context, Poll},
+};
+
+use crate::context::{ContextDropFn, ContextDropper};
+
+/// A future, sink, reader or writer that is called with a [`DropContext`](crate::DropContext)
+/// once it is dropped, the counterpart of [`ContextDropStream`](crate::ContextDropStream) for
+/// everything that isn't a stream.
+///
+/// Both share the same core, so the context has the same shape in a pipeline that mixes them:
+/// names, labels, IDs and [`DropScope`](crate::DropScope)s apply alike, and the drop is reported
+/// to the registered [`DropEventHandler`](crate::DropEventHandler)s. What counts depends on what
+/// is wrapped:
+///
+/// - A future completes once it returns `Ready`.
+/// - With the `sink` feature, a sink counts every item sent as an item, or as an error if the
+///   sink rejected it, and completes once it is closed.
+/// - With the `io` feature, a reader or writer counts the bytes read or written, and completes
+///   once it reaches the end of its input or is closed.
+///
+/// Only the wrappers that hand a context to their closure share this core. Those that take a
+/// plain `FnOnce()`, such as [`DropFuture`](crate::DropFuture),
+/// [`DropAsyncBufRead`](crate::DropAsyncBufRead) and the `Sink` and `AsyncRead` forwarding of
+/// [`DropStream`](crate::DropStream), build no context and report nothing, so they stay as small
+/// as what they wrap; wrap the value in an `Instrumented` instead where the context is wanted.
+///
+/// Example
+/// ```
+/// use drop_stream::{DropFutureExt, DropReason};
+///
+/// let mut report = None;
+/// let report_ref = &mut report;
+/// let future = async { 1 }
+///     .on_drop_ctx(move |context| *report_ref = Some((context.name().unwrap().to_owned(), context.reason())))
+///     .name("lookup");
+///
+/// assert_eq!(futures::executor::block_on(future), 1);
+/// assert_eq!(report, Some(("lookup".to_owned(), DropReason::Completed)));
+/// ```
+#[pin_project]
+pub struct Instrumented<T, U: ContextDropFn> {
+    // Declared before the inner value so the closure runs before it is dropped.
+    dropper: ContextDropper<U>,
+    #[pin]
+    inner: T,
+}
+
+impl<T, U: ContextDropFn> Instrumented<T, U> {
+    #[track_caller]
+    pub fn new(inner: T, dropper: U) -> Self {
+        Self {
+            dropper: ContextDropper::new(dropper),
+            inner,
+        }
+    }
+
+    /// Returns the ID of the wrapper, see [`ContextDropStream::id`](crate::ContextDropStream::id).
+    pub fn id(&self) -> u64 {
+        self.dropper.stats.id()
+    }
+
+    /// Replaces the ID of the wrapper.
+    pub fn with_id(mut self, id: u64) -> Self {
+        self.dropper.stats.set_id(id);
+        self
+    }
+
+    /// Names the wrapper in its context, replacing any previous name.
+    pub fn name(mut self, name: impl Into<String>) -> Self {
+        self.dropper.stats.set_name(name.into());
+        self
+    }
+
+    /// Adds a label to the wrapper's context.
+    pub fn label(mut self, key: &'static str, value: impl Into<String>) -> Self {
+        self.dropper.stats.add_label(key, value.into());
+        self
+    }
+
+    /// Records how long each poll takes, reported as
+    /// [`DropContext::latency`](crate::DropContext::latency).
+    pub fn record_latency(mut self) -> Self {
+        self.dropper.stats.enable_latency();
+        self
+    }
+}
+
+impl<F: Future, U: ContextDropFn> Future for Instrumented<F, U> {
+    type Output = F::Output;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let this = self.project();
+
+        let started = this.dropper.stats.poll_started();
+        let poll = this.inner.poll(cx);
+        this.dropper.stats.record_poll(started, poll.is_ready());
+
+        match poll {
+            Poll::Ready(_) => this.dropper.stats.record_end(),
+            Poll::Pending => this.dropper.stats.record_pending(),
+        }
+
+        poll
+    }
+}
+
+/// Terminated once the inner future completed, so the wrapper can be used in `select!` directly.
+impl<F: Future, U: ContextDropFn> FusedFuture for Instrumented<F, U> {
+    fn is_terminated(&self) -> bool {
+        self.dropper.stats.is_completed()
+    }
+}
+
+#[cfg(feature = "sink")]
+impl<Si: futures_sink::Sink<I>, I, U: ContextDropFn> futures_sink::Sink<I> for Instrumented<Si, U> {
+    type Error = Si::Error;
+
+    fn poll_ready(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.project().inner.poll_ready(cx)
+    }
+
+    fn start_send(self: Pin<&mut Self>, item: I) -> Result<(), Self::Error> {
+        let this = self.project();
+
+        let result = this.inner.start_send(item);
+        match result {
+            Ok(()) => this.dropper.stats.record_item(),
+            Err(_) => this.dropper.stats.record_error(),
+        }
+
+        result
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.project().inner.poll_flush(cx)
+    }
+
+    fn poll_close(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        let this = self.project();
+
+        let poll = this.inner.poll_close(cx);
+        if let Poll::Ready(Ok(())) = poll {
+            this.dropper.stats.record_end();
+        }
+
+        poll
+    }
+}
+
+#[cfg(feature = "io")]
+impl<R: futures_io::AsyncRead, U: ContextDropFn> futures_io::AsyncRead for Instrumented<R, U> {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut [u8],
+    ) -> Poll<std::io::Result<usize>> {
+        let this = self.project();
+
+        let poll = this.inner.poll_read(cx, buf);
+        match poll {
+            Poll::Ready(Ok(read)) => {
+                this.dropper.stats.record_bytes(read);
+                if read == 0 && !buf.is_empty() {
+                    this.dropper.stats.record_end();
+                }
+            }
+            Poll::Ready(Err(_)) => {}
+            Poll::Pending => this.dropper.stats.record_pending(),
+        }
+
+        poll
+    }
+}
+
+#[cfg(feature = "io")]
+impl<R: futures_io::AsyncBufRead, U: ContextDropFn> futures_io::AsyncBufRead
+    for Instrumented<R, U>
+{
+    fn poll_fill_buf(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<std::io::Result<&[u8]>> {
+        let this = self.project();
+
+        let poll = this.inner.poll_fill_buf(cx);
+        if let Poll::Ready(Ok([])) = poll {
+            this.dropper.stats.record_end();
+        }
+
+        poll
+    }
+
+    fn consume(self: Pin<&mut Self>, amt: usize) {
+        let this = self.project();
+
+        this.dropper.stats.record_bytes(amt);
+        this.inner.consume(amt)
+    }
+}
+
+#[cfg(feature = "io")]
+impl<W: futures_io::AsyncWrite, U: ContextDropFn> futures_io::AsyncWrite for Instrumented<W, U> {
+    fn poll_write(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<std::io::Result<usize>> {
+        let this = self.project();
+
+        let poll = this.inner.poll_write(cx, buf);
+        if let Poll::Ready(Ok(written)) = poll {
+            this.dropper.stats.record_bytes(written);
+        }
+
+        poll
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<std::io::Result<()>> {
+        self.project().inner.poll_flush(cx)
+    }
+
+    fn poll_close(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<std::io::Result<()>> {
+        let this = self.project();
+
+        let poll = this.inner.poll_close(cx);
+        if let Poll::Ready(Ok(())) = poll {
+            this.dropper.stats.record_end();
+        }
+
+        poll
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::task::Poll;
+
+    use crate::{DropContext, DropFutureExt, DropReason};
+    use futures::future::{pending, Future};
+
+    #[test]
+    fn cancelled_future_reports_context() {
+        let mut context = None::<DropContext>;
+
+        {
+            let context_ref = &mut context;
+            let drop_future = pending::<()>()
+                .on_drop_ctx(move |c| *context_ref = Some(c))
+                .label("query", "users");
+
+            let mut drop_future = Box::pin(drop_future);
+
+            let waker = futures::task::noop_waker();
+            let mut cx = futures::task::Context::from_waker(&waker);
+            assert_eq!(drop_future.as_mut().poll(&mut cx), Poll::Pending);
+        }
+
+        let context = context.unwrap();
+        assert_eq!(context.reason(), DropReason::Cancelled);
+        assert_eq!(context.label("query"), Some("users"));
+    }
+
+    #[cfg(feature = "sink")]
+    #[test]
+    fn sink_counts_sent_items() {
+        use crate::Instrumented;
+        use futures::SinkExt;
+
+        let mut context = None::<DropContext>;
+
+        {
+            let context_ref = &mut context;
+            let mut sink =
+                Instrumented::new(futures::sink::drain(), move |c| *context_ref = Some(c));
+
+            futures::executor::block_on(async {
+                sink.send(1).await.unwrap();
+                sink.send(2).await.unwrap();
+                sink.close().await.unwrap();
+            });
+        }
+
+        let context = context.unwrap();
+        assert_eq!(context.reason(), DropReason::Completed);
+        assert_eq!(context.items(), 2);
+    }
+
+    #[cfg(feature = "io")]
+    #[test]
+    fn reader_counts_bytes() {
+        use crate::Instrumented;
+        use futures::AsyncReadExt;
+
+        let mut context = None::<DropContext>;
+
+        {
+            let context_ref = &mut context;
+            let mut reader = Instrumented::new(futures::io::Cursor::new(b"hello"), move |c| {
+                *context_ref = Some(c)
+            });
+
+            let mut read = Vec::new();
+            futures::executor::block_on(reader.read_to_end(&mut read)).unwrap();
+        }
+
+        let context = context.unwrap();
+        assert_eq!(context.reason(), DropReason::Completed);
+        assert_eq!(context.bytes(), 5);
+    }
+}
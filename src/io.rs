@@ -0,0 +1,220 @@
+use futures_core::Stream;
+use futures_io::{AsyncBufRead, AsyncRead, AsyncWrite, IoSlice, IoSliceMut};
+use pin_project::pin_project;
+use std::{
+    io,
+    pin::Pin,
+    task::{Context, Poll},
+};
+
+use crate::{DropStream, Dropper};
+
+/// Forwarded from the inner stream, so a wrapped transport can still be used wherever the
+/// original reader was accepted.
+impl<S: Stream<Item = T> + AsyncRead, T, U: FnOnce()> AsyncRead for DropStream<S, T, U> {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut [u8],
+    ) -> Poll<io::Result<usize>> {
+        self.project().stream.poll_read(cx, buf)
+    }
+
+    fn poll_read_vectored(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        bufs: &mut [IoSliceMut<'_>],
+    ) -> Poll<io::Result<usize>> {
+        self.project().stream.poll_read_vectored(cx, bufs)
+    }
+}
+
+/// Forwarded from the inner stream, so a wrapped transport can still be used wherever the
+/// original writer was accepted.
+impl<S: Stream<Item = T> + AsyncWrite, T, U: FnOnce()> AsyncWrite for DropStream<S, T, U> {
+    fn poll_write(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        self.project().stream.poll_write(cx, buf)
+    }
+
+    fn poll_write_vectored(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        bufs: &[IoSlice<'_>],
+    ) -> Poll<io::Result<usize>> {
+        self.project().stream.poll_write_vectored(cx, bufs)
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        self.project().stream.poll_flush(cx)
+    }
+
+    fn poll_close(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        self.project().stream.poll_close(cx)
+    }
+}
+
+/// A buffered reader that wraps another reader with a closure that is called once it is dropped.
+///
+/// Line-based protocol readers work on [`AsyncBufRead`], so this forwards `poll_fill_buf` and
+/// `consume` as well as the plain read methods, instead of downgrading the reader to get drop
+/// instrumentation. No [`DropContext`](crate::DropContext) is built for it; wrap the reader in an
+/// [`Instrumented`](crate::Instrumented) for one.
+///
+/// Example
+/// ```
+/// use futures::{executor::block_on, io::Cursor, AsyncBufReadExt, StreamExt};
+/// use drop_stream::DropAsyncBufRead;
+///
+/// let mut has_run = false;
+/// let has_run_ref = &mut has_run;
+/// let reader = DropAsyncBufRead::new(Cursor::new("first\nsecond\n"), move || {
+///     *has_run_ref = true;
+/// });
+///
+/// let lines = block_on(reader.lines().map(Result::unwrap).collect::<Vec<_>>());
+/// assert_eq!(lines, vec!["first", "second"]);
+/// assert!(has_run);
+/// ```
+#[pin_project]
+pub struct DropAsyncBufRead<R: AsyncBufRead, U: FnOnce()> {
+    // Declared before the reader so the closure runs before the inner reader is dropped.
+    dropper: Dropper<U>,
+    #[pin]
+    reader: R,
+}
+
+impl<R: AsyncBufRead, U: FnOnce()> DropAsyncBufRead<R, U> {
+    pub fn new(reader: R, dropper: U) -> Self {
+        Self {
+            dropper: Dropper::new(dropper),
+            reader,
+        }
+    }
+}
+
+impl<R: AsyncBufRead, U: FnOnce()> AsyncRead for DropAsyncBufRead<R, U> {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut [u8],
+    ) -> Poll<io::Result<usize>> {
+        self.project().reader.poll_read(cx, buf)
+    }
+
+    fn poll_read_vectored(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        bufs: &mut [IoSliceMut<'_>],
+    ) -> Poll<io::Result<usize>> {
+        self.project().reader.poll_read_vectored(cx, bufs)
+    }
+}
+
+impl<R: AsyncBufRead, U: FnOnce()> AsyncBufRead for DropAsyncBufRead<R, U> {
+    fn poll_fill_buf(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<&[u8]>> {
+        self.project().reader.poll_fill_buf(cx)
+    }
+
+    fn consume(self: Pin<&mut Self>, amt: usize) {
+        self.project().reader.consume(amt)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        collections::VecDeque,
+        io,
+        pin::Pin,
+        task::{Context, Poll},
+    };
+
+    use crate::{DropAsyncBufRead, DropStreamExt};
+    use futures::{
+        executor::block_on, io::Cursor, AsyncBufReadExt, AsyncRead, AsyncReadExt, AsyncWrite,
+        AsyncWriteExt, Stream,
+    };
+
+    /// A loopback transport: bytes written into it are read back out.
+    #[derive(Default)]
+    struct Loopback(VecDeque<u8>);
+
+    impl Stream for Loopback {
+        type Item = u8;
+
+        fn poll_next(mut self: Pin<&mut Self>, _: &mut Context<'_>) -> Poll<Option<u8>> {
+            Poll::Ready(self.0.pop_front())
+        }
+    }
+
+    impl AsyncRead for Loopback {
+        fn poll_read(
+            mut self: Pin<&mut Self>,
+            _: &mut Context<'_>,
+            buf: &mut [u8],
+        ) -> Poll<io::Result<usize>> {
+            Poll::Ready(io::Read::read(&mut self.0, buf))
+        }
+    }
+
+    impl AsyncWrite for Loopback {
+        fn poll_write(
+            mut self: Pin<&mut Self>,
+            _: &mut Context<'_>,
+            buf: &[u8],
+        ) -> Poll<io::Result<usize>> {
+            Poll::Ready(io::Write::write(&mut self.0, buf))
+        }
+
+        fn poll_flush(self: Pin<&mut Self>, _: &mut Context<'_>) -> Poll<io::Result<()>> {
+            Poll::Ready(Ok(()))
+        }
+
+        fn poll_close(self: Pin<&mut Self>, _: &mut Context<'_>) -> Poll<io::Result<()>> {
+            Poll::Ready(Ok(()))
+        }
+    }
+
+    #[test]
+    fn read_and_write_are_forwarded() {
+        let mut has_run = false;
+
+        {
+            let has_run_ref = &mut has_run;
+            let mut transport = Loopback::default().on_drop(move || *has_run_ref = true);
+
+            block_on(async {
+                transport.write_all(b"ping").await.unwrap();
+
+                let mut buf = [0; 4];
+                transport.read_exact(&mut buf).await.unwrap();
+                assert_eq!(&buf, b"ping");
+            });
+        }
+
+        assert!(has_run);
+    }
+
+    #[test]
+    fn buf_read_is_forwarded() {
+        let mut has_run = false;
+
+        {
+            let has_run_ref = &mut has_run;
+            let mut reader =
+                DropAsyncBufRead::new(Cursor::new("first\nsecond\n"), move || *has_run_ref = true);
+
+            block_on(async {
+                let mut line = String::new();
+                reader.read_line(&mut line).await.unwrap();
+                assert_eq!(line, "first\n");
+            });
+        }
+
+        assert!(has_run);
+    }
+}
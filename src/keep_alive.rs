@@ -0,0 +1,197 @@
+use futures_core::{Future, Stream};
+use pin_project::pin_project;
+use std::{
+    fmt,
+    pin::Pin,
+    task::{Context, Poll},
+    time::Duration,
+};
+use tokio::time::{Instant, Sleep};
+
+use crate::{dropper::ReasonDropper, DropReason};
+
+/// A stream, such as the event stream of a server-sent events response, that yields a keep-alive
+/// item whenever the inner stream has been quiet for an interval, and calls a closure with the
+/// [`DropReason`] once it is dropped.
+///
+/// The keep-alive item, typically an SSE comment, is created by a closure once the inner stream
+/// has not yielded anything for the interval, and every item restarts the interval. As the
+/// keep-alives are yielded by the stream itself, they stop exactly when the client disconnects
+/// and the response drops the stream, and the same drop runs the closure, with
+/// [`DropReason::Cancelled`] for a disconnect. No keep-alive is yielded once the inner stream has
+/// ended.
+///
+/// # Panics
+///
+/// Panics if created outside of a tokio runtime.
+///
+/// Example
+/// ```
+/// # #[tokio::main(flavor = "current_thread", start_paused = true)]
+/// # async fn main() {
+/// use std::time::Duration;
+/// use drop_stream::{DropReason, DropStreamExt};
+/// use futures::StreamExt;
+///
+/// let (sender, receiver) = tokio::sync::oneshot::channel();
+/// let events = futures::stream::pending::<String>().keep_alive(
+///     Duration::from_secs(15),
+///     || ":keep-alive".to_owned(),
+///     move |reason| sender.send(reason).unwrap(),
+/// );
+///
+/// let mut events = Box::pin(events);
+/// assert_eq!(events.next().await.as_deref(), Some(":keep-alive"));
+///
+/// // The client disconnects...
+/// drop(events);
+/// assert_eq!(receiver.await, Ok(DropReason::Cancelled));
+/// # }
+/// ```
+#[pin_project]
+pub struct KeepAlive<S, K, U>
+where
+    S: Stream,
+    K: FnMut() -> S::Item,
+    U: FnOnce(DropReason),
+{
+    // Declared before the stream so the closure runs before the inner stream is dropped.
+    dropper: ReasonDropper<U>,
+    #[pin]
+    stream: S,
+    #[pin]
+    sleep: Sleep,
+    interval: Duration,
+    keep_alive: K,
+    completed: bool,
+}
+
+impl<S, K, U> KeepAlive<S, K, U>
+where
+    S: Stream,
+    K: FnMut() -> S::Item,
+    U: FnOnce(DropReason),
+{
+    pub fn new(stream: S, interval: Duration, keep_alive: K, dropper: U) -> Self {
+        Self {
+            dropper: ReasonDropper::new(dropper),
+            stream,
+            sleep: tokio::time::sleep(interval),
+            interval,
+            keep_alive,
+            completed: false,
+        }
+    }
+
+    /// Returns how long the inner stream may be quiet before a keep-alive is yielded.
+    pub fn interval(&self) -> Duration {
+        self.interval
+    }
+}
+
+impl<S, K, U> Stream for KeepAlive<S, K, U>
+where
+    S: Stream,
+    K: FnMut() -> S::Item,
+    U: FnOnce(DropReason),
+{
+    type Item = S::Item;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let mut this = self.project();
+
+        if *this.completed {
+            return Poll::Ready(None);
+        }
+
+        match this.stream.poll_next(cx) {
+            Poll::Ready(Some(item)) => {
+                this.sleep.reset(Instant::now() + *this.interval);
+                Poll::Ready(Some(item))
+            }
+            Poll::Ready(None) => {
+                *this.completed = true;
+                this.dropper.reason = DropReason::Completed;
+                Poll::Ready(None)
+            }
+            Poll::Pending => {
+                if this.sleep.as_mut().poll(cx).is_pending() {
+                    return Poll::Pending;
+                }
+
+                this.sleep.reset(Instant::now() + *this.interval);
+                Poll::Ready(Some((this.keep_alive)()))
+            }
+        }
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        if self.completed {
+            return (0, Some(0));
+        }
+
+        // Any number of keep-alives may be yielded in between.
+        (self.stream.size_hint().0, None)
+    }
+}
+
+impl<S, K, U> fmt::Debug for KeepAlive<S, K, U>
+where
+    S: Stream,
+    K: FnMut() -> S::Item,
+    U: FnOnce(DropReason),
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("KeepAlive")
+            .field("interval", &self.interval)
+            .field("completed", &self.completed)
+            .finish_non_exhaustive()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{sync::Mutex, time::Duration};
+
+    use crate::{DropReason, DropStreamExt};
+    use futures::StreamExt;
+
+    #[tokio::test(start_paused = true)]
+    async fn keep_alive_only_while_quiet() {
+        let (sender, receiver) = tokio::sync::mpsc::unbounded_channel();
+        let events =
+            receiver_stream(receiver).keep_alive(Duration::from_secs(10), || "ping", |_| {});
+        let mut events = Box::pin(events);
+
+        tokio::time::sleep(Duration::from_secs(5)).await;
+        sender.send("event").unwrap();
+        assert_eq!(events.next().await, Some("event"));
+
+        // The event restarted the interval.
+        let start = tokio::time::Instant::now();
+        assert_eq!(events.next().await, Some("ping"));
+        assert_eq!(start.elapsed(), Duration::from_secs(10));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn ended_stream_completes_without_keep_alive() {
+        let reason = Mutex::new(None);
+
+        {
+            let events = futures::stream::iter(["a"]).keep_alive(
+                Duration::from_secs(1),
+                || "ping",
+                |r| *reason.lock().unwrap() = Some(r),
+            );
+            assert_eq!(events.collect::<Vec<_>>().await, ["a"]);
+        }
+
+        assert_eq!(*reason.lock().unwrap(), Some(DropReason::Completed));
+    }
+
+    fn receiver_stream<T>(
+        mut receiver: tokio::sync::mpsc::UnboundedReceiver<T>,
+    ) -> impl futures::Stream<Item = T> {
+        futures::stream::poll_fn(move |cx| receiver.poll_recv(cx))
+    }
+}
@@ -0,0 +1,161 @@
+use futures_core::Stream;
+use pin_project::{pin_project, pinned_drop};
+use std::{
+    pin::Pin,
+    task::{Context, Poll},
+};
+
+use crate::{context::Stats, dropper::Once, DropContext};
+
+/// A stream that keeps the last item it yielded and hands it by value to a closure once it is
+/// dropped, together with a [`DropContext`].
+///
+/// For resumable streams this is the cursor or sequence number to persist when the consumer goes
+/// away. Since items are passed on to the consumer, what is kept is a projection of each item: a
+/// clone with [`on_drop_with_last`](crate::DropStreamExt::on_drop_with_last), or anything else,
+/// such as just the sequence number, with
+/// [`on_drop_with_last_by`](crate::DropStreamExt::on_drop_with_last_by).
+///
+/// Example
+/// ```
+/// use futures::{executor::block_on_stream, stream};
+/// use drop_stream::DropStreamExt;
+///
+/// let mut cursor = None;
+/// let cursor_ref = &mut cursor;
+/// let stream = stream::iter([(1, "a"), (2, "b"), (3, "c")])
+///     .on_drop_with_last_by(|(sequence, _)| *sequence, move |_context, last| *cursor_ref = last);
+///
+/// assert_eq!(block_on_stream(stream).take(2).count(), 2);
+/// assert_eq!(cursor, Some(2));
+/// ```
+#[pin_project(PinnedDrop)]
+pub struct LastItemStream<S, P, K, U>
+where
+    S: Stream,
+    P: FnMut(&S::Item) -> K,
+    U: FnOnce(DropContext, Option<K>),
+{
+    #[pin]
+    stream: S,
+    project: P,
+    stats: Stats,
+    last: Option<K>,
+    dropper: Once<U>,
+}
+
+impl<S, P, K, U> LastItemStream<S, P, K, U>
+where
+    S: Stream,
+    P: FnMut(&S::Item) -> K,
+    U: FnOnce(DropContext, Option<K>),
+{
+    #[track_caller]
+    pub fn new(stream: S, project: P, dropper: U) -> Self {
+        Self {
+            stream,
+            project,
+            stats: Stats::new(),
+            last: None,
+            dropper: Once::new(dropper),
+        }
+    }
+
+    /// Returns the kept projection of the last item yielded so far.
+    pub fn last(&self) -> Option<&K> {
+        self.last.as_ref()
+    }
+}
+
+impl<S, P, K, U> Stream for LastItemStream<S, P, K, U>
+where
+    S: Stream,
+    P: FnMut(&S::Item) -> K,
+    U: FnOnce(DropContext, Option<K>),
+{
+    type Item = S::Item;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let this = self.project();
+
+        let poll = this.stream.poll_next(cx);
+        match &poll {
+            Poll::Ready(Some(item)) => {
+                this.stats.record_item();
+                *this.last = Some((this.project)(item));
+            }
+            Poll::Ready(None) => this.stats.record_end(),
+            Poll::Pending => this.stats.record_pending(),
+        }
+
+        poll
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.stream.size_hint()
+    }
+}
+
+#[pinned_drop]
+impl<S, P, K, U> PinnedDrop for LastItemStream<S, P, K, U>
+where
+    S: Stream,
+    P: FnMut(&S::Item) -> K,
+    U: FnOnce(DropContext, Option<K>),
+{
+    fn drop(self: Pin<&mut Self>) {
+        let this = self.project();
+
+        if let Some(dropper) = this.dropper.take() {
+            dropper(this.stats.context(), this.last.take())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::task::Poll;
+
+    use crate::{DropReason, DropStreamExt};
+    use futures::{stream::iter, Stream};
+
+    #[test]
+    fn last_item_is_handed_to_dropper() {
+        let mut last = None;
+
+        {
+            let last_ref = &mut last;
+            let drop_stream = iter(["a", "b", "c"])
+                .on_drop_with_last(move |c, item| *last_ref = Some((c.reason(), item)));
+
+            let mut drop_stream = Box::pin(drop_stream);
+
+            let waker = futures::task::noop_waker();
+            let mut cx = futures::task::Context::from_waker(&waker);
+            assert_eq!(
+                drop_stream.as_mut().poll_next(&mut cx),
+                Poll::Ready(Some("a"))
+            );
+            assert_eq!(
+                drop_stream.as_mut().poll_next(&mut cx),
+                Poll::Ready(Some("b"))
+            );
+            assert_eq!(drop_stream.last(), Some(&"b"));
+        }
+
+        assert_eq!(last, Some((DropReason::Cancelled, Some("b"))));
+    }
+
+    #[test]
+    fn nothing_yielded_hands_none() {
+        let mut last = Some(Some(0));
+
+        {
+            let last_ref = &mut last;
+            let _drop_stream = iter([1, 2])
+                .on_drop_with_last_by(|item| item * 10, move |_, item| *last_ref = Some(item));
+        }
+
+        assert_eq!(last, Some(None));
+    }
+}
@@ -0,0 +1,109 @@
+use std::time::{Duration, Instant};
+
+/// A min/mean/max summary of a set of durations, such as the time spent in a stream's
+/// `poll_next`.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct LatencySummary {
+    count: u64,
+    total: Duration,
+    min: Duration,
+    max: Duration,
+}
+
+impl LatencySummary {
+    pub(crate) fn record(&mut self, duration: Duration) {
+        if self.count == 0 || duration < self.min {
+            self.min = duration;
+        }
+        self.max = self.max.max(duration);
+        self.total += duration;
+        self.count += 1;
+    }
+
+    /// The number of durations recorded.
+    pub fn count(&self) -> u64 {
+        self.count
+    }
+
+    /// The sum of all recorded durations.
+    pub fn total(&self) -> Duration {
+        self.total
+    }
+
+    /// The shortest recorded duration, or `None` if nothing was recorded.
+    pub fn min(&self) -> Option<Duration> {
+        (self.count > 0).then_some(self.min)
+    }
+
+    /// The mean of the recorded durations, or `None` if nothing was recorded.
+    pub fn mean(&self) -> Option<Duration> {
+        // The mean never exceeds `max`, so converting back from nanoseconds can only truncate for
+        // durations of centuries.
+        let nanos = self.total.as_nanos().checked_div(self.count as u128)?;
+        Some(Duration::from_nanos(nanos as u64))
+    }
+
+    /// The longest recorded duration, or `None` if nothing was recorded.
+    pub fn max(&self) -> Option<Duration> {
+        (self.count > 0).then_some(self.max)
+    }
+}
+
+/// Per-poll timings of a stream, reported in its [`DropContext`](crate::DropContext) when
+/// enabled.
+///
+/// Long polls only come from CPU-bound or blocking work inside the inner stream's `poll_next`. An
+/// async producer that is slow, such as one waiting on the network, returns `Pending` quickly, so
+/// like a slow consumer it shows up as long gaps between items with short polls.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct Latency {
+    poll: LatencySummary,
+    gap: LatencySummary,
+    last_item_at: Option<Instant>,
+}
+
+impl Latency {
+    pub(crate) fn record_poll(&mut self, started: Instant, yielded_item: bool) {
+        let now = Instant::now();
+        self.poll.record(now - started);
+
+        if yielded_item {
+            if let Some(last_item_at) = self.last_item_at {
+                self.gap.record(now - last_item_at);
+            }
+            self.last_item_at = Some(now);
+        }
+    }
+
+    /// The time spent inside the inner stream's `poll_next`.
+    pub fn poll(&self) -> &LatencySummary {
+        &self.poll
+    }
+
+    /// The time between consecutive items.
+    pub fn item_gaps(&self) -> &LatencySummary {
+        &self.gap
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::LatencySummary;
+
+    #[test]
+    fn summary_tracks_min_mean_max() {
+        let mut summary = LatencySummary::default();
+        assert_eq!(summary.mean(), None);
+
+        for millis in [30, 10, 20] {
+            summary.record(Duration::from_millis(millis));
+        }
+
+        assert_eq!(summary.count(), 3);
+        assert_eq!(summary.min(), Some(Duration::from_millis(10)));
+        assert_eq!(summary.mean(), Some(Duration::from_millis(20)));
+        assert_eq!(summary.max(), Some(Duration::from_millis(30)));
+    }
+}
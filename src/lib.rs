@@ -1,10 +1,215 @@
-use futures_core::Stream;
-use pin_project::{pin_project, pinned_drop};
+#![forbid(unsafe_code)]
+
+use futures_core::{stream::FusedStream, Future, Stream};
+use pin_project::pin_project;
 use std::{
     pin::Pin,
     task::{Context, Poll},
 };
 
+mod abortable;
+mod aggregate;
+mod anomaly;
+mod async_ext;
+#[cfg(feature = "http")]
+mod body;
+mod builder;
+mod byte_count;
+mod chain;
+mod channel;
+mod checkpoint;
+#[cfg(feature = "sink")]
+mod close;
+mod context;
+mod context_stream;
+mod control;
+mod dead_letter;
+#[cfg(feature = "tokio-util")]
+mod delay_queue;
+mod download;
+mod drain;
+mod dropper;
+mod encode;
+mod event;
+mod fallible;
+#[cfg(feature = "gloo-net")]
+mod fetch;
+#[cfg(feature = "io")]
+mod finish;
+mod fold;
+mod forward;
+mod future;
+mod gauge;
+#[cfg(feature = "tokio")]
+mod grace;
+mod guard;
+#[cfg(feature = "tokio")]
+mod heartbeat;
+mod idempotent;
+#[cfg(feature = "tokio")]
+mod idle;
+mod instrumented;
+#[cfg(feature = "io")]
+mod io;
+#[cfg(feature = "tokio")]
+mod keep_alive;
+mod last_item;
+mod latency;
+mod limit;
+mod map;
+mod membership;
+mod merge;
+#[cfg(feature = "http")]
+mod middleware;
+mod multipart;
+#[cfg(feature = "async-net")]
+mod net;
+mod observer;
+#[cfg(feature = "async-signal")]
+mod os_signal;
+mod outbound;
+mod panic;
+mod part;
+#[cfg(all(feature = "named-pipe", windows))]
+mod pipe;
+#[cfg(feature = "http")]
+mod proxy;
+mod queue;
+mod queue_depth;
+mod range;
+#[cfg(feature = "wasm-streams")]
+mod readable;
+#[cfg(feature = "tokio")]
+mod reaper;
+mod reason;
+mod remote;
+mod sample;
+mod scope;
+mod settle;
+mod signal;
+#[cfg(feature = "sink")]
+mod sink;
+mod spawn;
+mod stacked;
+mod static_drop;
+mod stop;
+mod take_until;
+#[cfg(feature = "tokio")]
+mod teardown;
+#[cfg(any(test, feature = "test-util"))]
+pub mod test_util;
+mod transaction;
+mod try_stream;
+#[cfg(all(feature = "async-net", unix))]
+mod uds;
+#[cfg(feature = "notify")]
+mod watch;
+
+pub use abortable::{abortable_on_drop, AbortHandle, AbortableDropStream};
+pub use aggregate::{DropAggregator, LabelSummary};
+pub use anomaly::{report_anomaly, set_anomaly_handler};
+pub use async_ext::OnDropAsyncExt;
+#[cfg(feature = "http")]
+pub use body::DropBody;
+pub use builder::{DropStreamBuilder, HookedStream};
+pub use byte_count::{ByteCountStream, ByteLenFn};
+pub use chain::ChainOnEnd;
+pub use channel::{drop_channel, Attached, DropEndpoint, PeerDropped};
+pub use checkpoint::{Checkpointed, Checkpointer};
+#[cfg(feature = "sink")]
+pub use close::CloseWith;
+pub use context::{ContextDropFn, DropContext, IdempotencyKey, OnReason};
+pub use context_stream::{ContextDropStream, MeasureFn};
+pub use control::{ControlledDropStream, DropControl};
+pub use dead_letter::{DeadLetterStream, Delivery};
+#[cfg(feature = "tokio-util")]
+pub use delay_queue::{DropDelayQueue, DropInterval, Ticks};
+pub use download::Download;
+pub use drain::{DrainOnDrop, DrainReport};
+pub use encode::Encoded;
+#[cfg(feature = "log")]
+pub use event::LogHandler;
+#[cfg(feature = "metrics")]
+pub use event::MetricsHandler;
+#[cfg(feature = "tracing")]
+pub use event::TracingHandler;
+pub use event::{drop_events, register_drop_handler, DropEvent, DropEventHandler, DropEvents};
+pub use fallible::{report_drop_error, set_drop_error_handler, FallibleDropStream};
+#[cfg(feature = "gloo-net")]
+pub use fetch::{fetch_body, NotifyServer};
+#[cfg(feature = "io")]
+pub use finish::{FinishOnDrop, FinishOutcome};
+pub use fold::FoldOnDrop;
+pub use forward::{ForwardOnDrop, TrySend};
+pub use future::{DropFuture, DropFutureExt, DropTryFuture, OnCancel, OnOutput};
+pub use gauge::{LiveGauge, LiveGuard};
+#[cfg(feature = "tokio")]
+pub use grace::{DelayedDrop, GraceHandle};
+pub use guard::DropGuard;
+#[cfg(feature = "tokio")]
+pub use heartbeat::Heartbeat;
+pub use idempotent::Idempotent;
+#[cfg(feature = "tokio")]
+pub use idle::IdleTimeout;
+pub use instrumented::Instrumented;
+#[cfg(feature = "io")]
+pub use io::DropAsyncBufRead;
+#[cfg(feature = "tokio")]
+pub use keep_alive::KeepAlive;
+pub use last_item::LastItemStream;
+pub use latency::{Latency, LatencySummary};
+pub use limit::{LimitExceeded, LimitTracker, Limited};
+pub use map::{DropMap, Tracked, WaitEmpty};
+pub use membership::{memberships, Departures, Member, Membership};
+pub use merge::{merge_on_drop, zip_on_drop, MergeOnDrop, ZipOnDrop};
+#[cfg(feature = "http")]
+pub use middleware::{CancelToken, Disconnect, DisconnectFuture, DisconnectLayer, Disconnected};
+#[cfg(feature = "aws-s3")]
+pub use multipart::S3MultipartUpload;
+pub use multipart::{AbortMultipart, AbortOnDrop};
+#[cfg(feature = "async-net")]
+pub use net::{tcp_chunks, tcp_lines, Chunks, ShutdownOnDrop, Socket};
+pub use observer::{Observed, StreamObserver};
+#[cfg(feature = "async-signal")]
+pub use os_signal::{watch_signals, SignalStream};
+pub use outbound::{watch_outbound, Outbound, OutboundHandle};
+pub use panic::{take_deferred_panic, DropPanic, PanicPolicy};
+pub use part::PartStream;
+#[cfg(all(feature = "named-pipe", windows))]
+pub use pipe::PipeIncoming;
+#[cfg(feature = "http")]
+pub use proxy::{link_upstream, CancelUpstream, UpstreamLink};
+pub use queue::{DropQueue, Flusher};
+pub use queue_depth::{QueueDepth, QueueDepthStream};
+pub use range::HeldRange;
+#[cfg(feature = "wasm-streams")]
+pub use readable::{from_readable_stream, into_readable_stream};
+#[cfg(feature = "tokio")]
+pub use reaper::{reap, reaper_pending};
+pub use reason::DropReason;
+pub use remote::{remote_drop, remote_drop_with, RemoteDropStream, RemoteHandle};
+pub use sample::{Sampled, SampledHandler, Sampler};
+pub use scope::{DropScope, Scoped};
+pub use settle::{Acknowledge, Outstanding, SettleOnDrop, Settlement};
+pub use signal::{DropSignal, OnceDropped};
+#[cfg(feature = "sink")]
+pub use sink::{DropSplitSink, DropSplitStream, Reunited};
+pub use spawn::{BoxFuture, Spawn};
+pub use stacked::{StackOrder, StackedDropStream};
+pub use static_drop::StaticDropStream;
+pub use stop::{StopOnDrop, StopSending};
+pub use take_until::TakeUntilDropped;
+#[cfg(feature = "tokio")]
+pub use teardown::AsyncTeardown;
+pub use transaction::{Transaction, TransactionStream};
+pub use try_stream::{CloneFn, DropTryStream, DropTryStreamExt, IgnoreError, LastErrorStream};
+#[cfg(all(feature = "async-net", unix))]
+pub use uds::{unix_chunks, unix_lines, UnixIncoming};
+#[cfg(feature = "notify")]
+pub use watch::{watch_paths, WatchStream};
+
+use dropper::Dropper;
+
 /// A stream that wraps another stream with a closure that is called once it is dropped.
 /// Very useful for libraries that use streams for data transfer and you need to connect
 /// when the opposite site drops the connection, thus dropping the stream.
@@ -16,12 +221,10 @@ use std::{
 ///
 /// let test_stream = futures::stream::repeat(true);
 /// {
-///     let wrapped_stream = DropStream::new(test_stream, move || {
+///     let mut wrapped_stream = DropStream::pin_new(test_stream, move || {
 ///         println!("Stream has been dropped!");
 ///     });
 ///
-///     let mut wrapped_stream = Box::pin(wrapped_stream);
-///
 ///     let waker = futures::task::noop_waker();
 ///     let mut context = futures::task::Context::from_waker(&waker);
 ///     assert_eq!(
@@ -30,19 +233,86 @@ use std::{
 ///     );
 /// }
 /// ```
-#[pin_project(PinnedDrop)]
+#[pin_project]
 pub struct DropStream<S: Stream<Item = T>, T, U: FnOnce()> {
+    // Declared before the stream so the closure runs before the inner stream is dropped.
+    dropper: Dropper<U>,
     #[pin]
     stream: S,
-    // Option used to wrap FnOnce since ownership of FnOnce needs to be gained in the Drop::drop() method.
-    dropper: Option<U>,
 }
 
 impl<S: Stream<Item = T>, T, U: FnOnce()> DropStream<S, T, U> {
     pub fn new(stream: S, dropper: U) -> Self {
         Self {
+            dropper: Dropper::new(dropper),
+            stream,
+        }
+    }
+
+    /// Creates the wrapper pinned in a box, ready to be polled, for the common case of wrapping a
+    /// stream that is not `Unpin`.
+    pub fn pin_new(stream: S, dropper: U) -> Pin<Box<Self>> {
+        Box::pin(Self::new(stream, dropper))
+    }
+
+    /// Transforms the inner stream while keeping the closure, so adapters such as `map` or
+    /// `filter` can be applied without burying the wrapper or changing where the closure lives.
+    ///
+    /// ex:
+    /// ```rust
+    /// use futures::{executor::block_on_stream, stream, StreamExt};
+    /// use drop_stream::DropStreamExt;
+    ///
+    /// let mut has_run = false;
+    /// let has_run_ref = &mut has_run;
+    /// let drop_stream = stream::iter([1, 2, 3])
+    ///     .on_drop(move || *has_run_ref = true)
+    ///     .wrap_inner(|inner| inner.map(|x| x * 2));
+    ///
+    /// assert_eq!(block_on_stream(drop_stream).collect::<Vec<_>>(), vec![2, 4, 6]);
+    /// assert!(has_run);
+    /// ```
+    pub fn wrap_inner<S2: Stream<Item = T2>, T2>(
+        self,
+        wrap: impl FnOnce(S) -> S2,
+    ) -> DropStream<S2, T2, U> {
+        let DropStream { dropper, stream } = self;
+
+        DropStream {
+            dropper,
+            stream: wrap(stream),
+        }
+    }
+
+    /// Replaces the closure with `map(closure)`, so a layer can decorate a pending closure (e.g.
+    /// with timing or error handling) without access to where it was defined.
+    ///
+    /// ex:
+    /// ```rust
+    /// use std::time::Instant;
+    /// use drop_stream::DropStreamExt;
+    ///
+    /// let mut took = None;
+    /// let took_ref = &mut took;
+    /// let drop_stream = futures::stream::repeat(true)
+    ///     .on_drop(|| println!("Stream has been dropped!"))
+    ///     .map_dropper(|dropper| {
+    ///         move || {
+    ///             let start = Instant::now();
+    ///             dropper();
+    ///             *took_ref = Some(start.elapsed());
+    ///         }
+    ///     });
+    ///
+    /// drop(drop_stream);
+    /// assert!(took.is_some());
+    /// ```
+    pub fn map_dropper<U2: FnOnce()>(self, map: impl FnOnce(U) -> U2) -> DropStream<S, T, U2> {
+        let DropStream { dropper, stream } = self;
+
+        DropStream {
+            dropper: dropper.map(map),
             stream,
-            dropper: Some(dropper),
         }
     }
 }
@@ -54,21 +324,40 @@ impl<S: Stream<Item = T>, T, U: FnOnce()> Stream for DropStream<S, T, U> {
         let stream = self.project().stream;
         stream.poll_next(cx)
     }
-}
 
-#[pinned_drop]
-impl<S: Stream<Item = T>, T, U: FnOnce()> PinnedDrop for DropStream<S, T, U> {
-    fn drop(self: Pin<&mut Self>) {
-        let Some(dropper) = self.project().dropper.take() else {
-            // Only taken in the "drop"-method, and always set in the constructor. Thus it cannot be None here.
-            unreachable!()
-        };
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.stream.size_hint()
+    }
+}
 
-        dropper()
+/// Forwarded from the inner stream, so a wrapped fused stream can be used in `select!` without an
+/// extra `.fuse()` layer hiding the wrapper.
+impl<S: FusedStream<Item = T>, T, U: FnOnce()> FusedStream for DropStream<S, T, U> {
+    fn is_terminated(&self) -> bool {
+        self.stream.is_terminated()
     }
 }
 
-pub trait DropStreamExt<U: FnOnce()>: Stream + Sized {
+// The layout guarantee is one word of overhead: a wrapper is at most the stream, the closure and
+// one alignment unit of the stream, which is a `usize` for the usual boxed or pointer-sized streams. Calling the closure by value from `Drop::drop()` without unsafe code needs a flag for
+// whether it was already called. A closure that captures a reference has a niche to hold the flag
+// in, so it costs nothing. A closure that captures nothing has no spare bits, so it costs exactly
+// that word. `size_of::<DropStream<S, F>>() == size_of::<S>()` for such closures would take unsafe
+// code, which `#![forbid(unsafe_code)]` rules out.
+const _: () = {
+    /// Returns how many bytes wrapping an `S` with the closure `U` adds on top of the closure
+    /// itself.
+    const fn overhead<S: Stream<Item = T>, T, U: FnOnce()>(_: &U) -> usize {
+        size_of::<DropStream<S, T, U>>() - size_of::<S>() - size_of::<U>()
+    }
+
+    fn noop() {}
+    let flag = &mut false;
+    assert!(overhead::<Pin<Box<dyn Stream<Item = u64>>>, u64, _>(&noop) == align_of::<usize>());
+    assert!(overhead::<Pin<Box<dyn Stream<Item = u64>>>, u64, _>(&|| *flag = true) == 0);
+};
+
+pub trait DropStreamExt: Stream + Sized {
     /// Wraps the stream with a closure that is called once it is dropped.
     /// ex:
     /// ```rust
@@ -92,16 +381,571 @@ pub trait DropStreamExt<U: FnOnce()>: Stream + Sized {
     /// drop(drop_stream); // Runs the closure
     /// assert!(has_run);
     /// ```
-    fn on_drop(self, dropper: U) -> DropStream<Self, Self::Item, U>;
+    fn on_drop<U: FnOnce()>(self, dropper: U) -> DropStream<Self, Self::Item, U>;
+
+    /// Wraps the stream with a closure that is called once it is dropped, pinned in a box. See
+    /// [`DropStream::pin_new`].
+    fn pinned_on_drop<U: FnOnce()>(self, dropper: U) -> Pin<Box<DropStream<Self, Self::Item, U>>>;
+
+    /// Wraps the stream with a plain function that is called with `context` once it is dropped.
+    /// See [`StaticDropStream`].
+    fn on_drop_fn<C>(self, dropper: fn(C), context: C) -> StaticDropStream<Self, C>;
+
+    /// Wraps the stream with a closure that is called once it is dropped, in a wrapper that
+    /// further closures can be added to without nesting. See [`StackedDropStream`].
+    fn on_drop_stacked<'a, U: FnOnce() + Send + 'a>(
+        self,
+        dropper: U,
+    ) -> StackedDropStream<'a, Self>;
+
+    /// Wraps the stream with a closure that is called with the target of `weak` once the stream is
+    /// dropped, but only if the target still exists at that point.
+    ///
+    /// Unlike capturing an `Arc` in an [`on_drop`](DropStreamExt::on_drop) closure, this doesn't
+    /// keep the target alive for as long as the stream is.
+    ///
+    /// ex:
+    /// ```rust
+    /// use std::sync::{Arc, Mutex};
+    /// use drop_stream::DropStreamExt;
+    ///
+    /// let registry = Arc::new(Mutex::new(vec!["client"]));
+    /// let stream = futures::stream::repeat(true)
+    ///     .on_drop_weak(Arc::downgrade(&registry), |registry| registry.lock().unwrap().clear());
+    ///
+    /// drop(stream);
+    /// assert!(registry.lock().unwrap().is_empty());
+    /// ```
+    fn on_drop_weak<W, U: FnOnce(std::sync::Arc<W>)>(
+        self,
+        weak: std::sync::Weak<W>,
+        dropper: U,
+    ) -> DropStream<Self, Self::Item, impl FnOnce()>;
+
+    /// Wraps the stream so that the returned [`DropSignal`], and every clone of it, resolves once
+    /// the stream is dropped.
+    fn drop_signal(self) -> (DropStream<Self, Self::Item, impl FnOnce()>, DropSignal);
+
+    /// Wraps the stream so that the returned [`OnceDropped`], and every clone of it, resolves with
+    /// the stream's [`DropContext`] once it is dropped.
+    #[track_caller]
+    fn once_dropped(
+        self,
+    ) -> (
+        ContextDropStream<Self, impl FnOnce(DropContext)>,
+        OnceDropped,
+    );
+
+    /// Wraps the stream so that, once dropped, the closure is moved into `queue` instead of being
+    /// called. It runs on the next [`DropQueue::flush`] or by the queue's [`Flusher`].
+    fn on_drop_deferred<U: FnOnce() + Send + 'static>(
+        self,
+        queue: &DropQueue,
+        dropper: U,
+    ) -> DropStream<Self, Self::Item, impl FnOnce()>;
+
+    /// Wraps the stream with a closure that the returned [`DropControl`] can disarm or run early.
+    /// See [`ControlledDropStream`].
+    fn on_drop_controlled<U: FnOnce() + Send + 'static>(
+        self,
+        dropper: U,
+    ) -> (ControlledDropStream<Self>, DropControl);
+
+    /// Ends the stream once `signal` completes, calling the closure with the reason the stream
+    /// ended. See [`TakeUntilDropped`].
+    fn take_until_dropped<F: Future, U: FnOnce(DropReason)>(
+        self,
+        signal: F,
+        dropper: U,
+    ) -> TakeUntilDropped<Self, F, U>;
+
+    /// Wraps the stream with a fallible closure that is called once it is dropped, reporting its
+    /// error to the crate-level handler. See [`FallibleDropStream`].
+    fn on_drop_fallible<E: std::error::Error + 'static, U: FnOnce() -> Result<(), E>>(
+        self,
+        dropper: U,
+    ) -> FallibleDropStream<Self, E, U, fn(E)>;
+
+    /// Wraps the stream with a closure that is called with a [`DropContext`] once it is dropped.
+    /// See [`ContextDropStream`].
+    fn on_drop_ctx<U: FnOnce(DropContext)>(self, dropper: U) -> ContextDropStream<Self, U>;
+
+    /// Hands a clone of the last yielded item to the closure once the stream is dropped. See
+    /// [`LastItemStream`].
+    fn on_drop_with_last<U: FnOnce(DropContext, Option<Self::Item>)>(
+        self,
+        dropper: U,
+    ) -> LastItemStream<Self, CloneFn<Self::Item>, Self::Item, U>
+    where
+        Self::Item: Clone;
+
+    /// Hands `project(&item)` of the last yielded item to the closure once the stream is dropped.
+    /// See [`LastItemStream`].
+    fn on_drop_with_last_by<P, K, U>(self, project: P, dropper: U) -> LastItemStream<Self, P, K, U>
+    where
+        P: FnMut(&Self::Item) -> K,
+        U: FnOnce(DropContext, Option<K>);
+
+    /// Hands the position projected from the last yielded item to `checkpointer` once the stream is
+    /// dropped. See [`Checkpointed`].
+    fn checkpoint_on_drop<F, P, C>(
+        self,
+        position: F,
+        checkpointer: C,
+    ) -> Checkpointed<Self, F, P, C>
+    where
+        F: FnMut(&Self::Item) -> P,
+        C: Checkpointer<P>;
+
+    /// Probes how full the channel the stream receives from is once it is dropped, reporting it in
+    /// the [`DropContext`]. See [`QueueDepthStream`].
+    fn on_drop_with_queue_depth<P, U>(self, probe: P, dropper: U) -> QueueDepthStream<Self, P, U>
+    where
+        P: FnMut(&Self) -> QueueDepth,
+        U: FnOnce(DropContext);
+
+    /// Folds every yielded item into `init` with `fold`, and hands the accumulator to the closure
+    /// once the stream is dropped. See [`FoldOnDrop`].
+    fn fold_on_drop<A, F, U>(self, init: A, fold: F, dropper: U) -> FoldOnDrop<Self, A, F, U>
+    where
+        F: FnMut(&mut A, &Self::Item),
+        U: FnOnce(DropContext, A);
+
+    /// Encodes every yielded row through `writer` with `encode`, and hands the writer to
+    /// `finalize` once the stream is dropped. See [`Encoded`].
+    fn encode_into<W, E, O, F>(
+        self,
+        writer: W,
+        encode: E,
+        finalize: F,
+    ) -> Encoded<Self, W, E, O, F>
+    where
+        E: FnMut(&mut W, Self::Item) -> O,
+        F: FnOnce(DropContext, W);
+
+    /// Counts the bytes of every yielded item, reporting the total in the [`DropContext`] once the
+    /// stream is dropped. See [`ByteCountStream`].
+    fn on_drop_with_bytes<U: FnOnce(DropContext)>(
+        self,
+        dropper: U,
+    ) -> ByteCountStream<Self, ByteLenFn<Self::Item>, U>
+    where
+        Self::Item: AsRef<[u8]>;
+
+    /// Counts `len(&item)` bytes for every yielded item, reporting the total in the
+    /// [`DropContext`] once the stream is dropped. See [`ByteCountStream`].
+    fn on_drop_with_bytes_by<L, U>(self, len: L, dropper: U) -> ByteCountStream<Self, L, U>
+    where
+        L: FnMut(&Self::Item) -> usize,
+        U: FnOnce(DropContext);
+
+    /// Reports every event of the stream to `observer`. See [`StreamObserver`].
+    fn observe<O: StreamObserver<Self::Item>>(self, observer: O) -> Observed<Self, O>;
+
+    /// Yields the items returned by `finalizer` once the inner stream finishes, calling the
+    /// closure with the reason the stream ended when dropped. See [`ChainOnEnd`].
+    fn chain_on_end<F, I, U>(self, finalizer: F, dropper: U) -> ChainOnEnd<Self, F, I, U>
+    where
+        F: FnOnce() -> I,
+        I: IntoIterator<Item = Self::Item>,
+        U: FnOnce(DropReason);
+
+    /// Hands the inner stream to a background task on drop, which polls up to `budget` more items
+    /// from it before calling the closure. See [`DrainOnDrop`].
+    fn drain_on_drop<Sp: Spawn, U: FnOnce(DrainReport) + Send + 'static>(
+        self,
+        spawner: Sp,
+        budget: usize,
+        dropper: U,
+    ) -> DrainOnDrop<Self, Sp, U>
+    where
+        Self: Unpin + Send + 'static;
+
+    /// Forwards the yielded items that were not acknowledged to `sender` once the stream is
+    /// dropped. See [`DeadLetterStream`].
+    fn dead_letter_on_drop<Tx: TrySend<Self::Item>>(self, sender: Tx) -> DeadLetterStream<Self, Tx>
+    where
+        Self::Item: Clone;
+
+    /// Settles the deliveries that are still outstanding once the stream is dropped, through the
+    /// handles `acker` extracts from them. See [`SettleOnDrop`].
+    fn settle_on_drop<F, A>(self, acker: F) -> SettleOnDrop<Self, F, A>
+    where
+        F: FnMut(&Self::Item) -> A,
+        A: Acknowledge;
+
+    /// Aborts the multipart upload the stream feeds through the spawner if the stream is dropped
+    /// before it finished. See [`AbortOnDrop`].
+    fn abort_multipart_on_drop<A: AbortMultipart, Sp: Spawn>(
+        self,
+        upload: A,
+        spawner: Sp,
+    ) -> AbortOnDrop<Self, A, Sp>;
+
+    /// Owns `transaction` for as long as the stream is alive, committing it through the spawner
+    /// once the stream is dropped if it completed, or rolling it back if it was cancelled. See
+    /// [`TransactionStream`].
+    fn in_transaction<Tx: Transaction, Sp: Spawn>(
+        self,
+        transaction: Tx,
+        spawner: Sp,
+    ) -> TransactionStream<Self, Tx, Sp>;
+
+    /// Moves the items the inner stream can still yield immediately into `sender` when dropped.
+    /// See [`ForwardOnDrop`].
+    fn forward_on_drop<Tx: TrySend<Self::Item>>(self, sender: Tx) -> ForwardOnDrop<Self, Tx>;
+
+    /// Runs `hook` whenever the stream has not been polled for longer than `threshold`. See
+    /// [`IdleTimeout`].
+    #[cfg(feature = "tokio")]
+    fn idle_timeout<H: FnMut() + Send + 'static>(
+        self,
+        threshold: std::time::Duration,
+        hook: H,
+    ) -> IdleTimeout<Self>;
+
+    /// Runs the closure once the stream has been dropped for longer than `delay`, unless a new
+    /// stream is subscribed through the returned handle first. See [`DelayedDrop`].
+    #[cfg(feature = "tokio")]
+    fn on_drop_delayed<U: FnOnce() + Send + 'static>(
+        self,
+        delay: std::time::Duration,
+        dropper: U,
+    ) -> (DelayedDrop<Self>, GraceHandle);
+
+    /// Calls `hook` every `interval` while the stream is alive. See [`Heartbeat`].
+    #[cfg(feature = "tokio")]
+    fn heartbeat<H: FnMut() + Send + 'static>(
+        self,
+        interval: std::time::Duration,
+        hook: H,
+    ) -> Heartbeat<Self, H>;
+
+    /// Yields the item created by `keep_alive` whenever the stream has been quiet for `interval`,
+    /// and calls the closure with the [`DropReason`] once it is dropped. See [`KeepAlive`].
+    #[cfg(feature = "tokio")]
+    fn keep_alive<K, U>(
+        self,
+        interval: std::time::Duration,
+        keep_alive: K,
+        dropper: U,
+    ) -> KeepAlive<Self, K, U>
+    where
+        K: FnMut() -> Self::Item,
+        U: FnOnce(DropReason);
+
+    /// Spawns the future returned by the closure once the stream is dropped, abandoning it after
+    /// `budget`. See [`AsyncTeardown`].
+    #[cfg(feature = "tokio")]
+    fn on_drop_teardown<U, F>(
+        self,
+        budget: std::time::Duration,
+        dropper: U,
+    ) -> AsyncTeardown<Self, U, F>
+    where
+        U: FnOnce() -> F,
+        F: Future<Output = ()> + Send + 'static;
+
+    /// Hands the future returned by the closure to the current runtime's reaper once the stream is
+    /// dropped, which drives it to completion and reports its error. See [`reap`].
+    ///
+    /// # Panics
+    ///
+    /// Panics if called outside of a tokio runtime.
+    #[cfg(feature = "tokio")]
+    fn on_drop_reaped<U, F, E>(self, dropper: U) -> DropStream<Self, Self::Item, impl FnOnce()>
+    where
+        U: FnOnce() -> F,
+        F: Future<Output = Result<(), E>> + Send + 'static,
+        E: std::error::Error + Send + 'static;
 }
 
-impl<T, U: FnOnce()> DropStreamExt<U> for T
+impl<T> DropStreamExt for T
 where
     T: Stream + Sized,
 {
-    fn on_drop(self, dropper: U) -> DropStream<T, T::Item, U> {
+    fn on_drop<U: FnOnce()>(self, dropper: U) -> DropStream<T, T::Item, U> {
         DropStream::new(self, dropper)
     }
+
+    fn pinned_on_drop<U: FnOnce()>(self, dropper: U) -> Pin<Box<DropStream<T, T::Item, U>>> {
+        DropStream::pin_new(self, dropper)
+    }
+
+    fn on_drop_fn<C>(self, dropper: fn(C), context: C) -> StaticDropStream<T, C> {
+        StaticDropStream::new(self, dropper, context)
+    }
+
+    fn on_drop_stacked<'a, U: FnOnce() + Send + 'a>(self, dropper: U) -> StackedDropStream<'a, T> {
+        StackedDropStream::new(self).on_drop(dropper)
+    }
+
+    fn on_drop_weak<W, U: FnOnce(std::sync::Arc<W>)>(
+        self,
+        weak: std::sync::Weak<W>,
+        dropper: U,
+    ) -> DropStream<T, T::Item, impl FnOnce()> {
+        DropStream::new(self, move || {
+            if let Some(target) = weak.upgrade() {
+                dropper(target)
+            }
+        })
+    }
+
+    fn drop_signal(self) -> (DropStream<T, T::Item, impl FnOnce()>, DropSignal) {
+        let signal = DropSignal::default();
+        let fire = signal.clone();
+
+        (DropStream::new(self, move || fire.fire()), signal)
+    }
+
+    #[track_caller]
+    fn once_dropped(self) -> (ContextDropStream<T, impl FnOnce(DropContext)>, OnceDropped) {
+        let dropped = OnceDropped::default();
+        let fire = dropped.clone();
+
+        (
+            ContextDropStream::new(self, move |context| fire.fire(context)),
+            dropped,
+        )
+    }
+
+    fn on_drop_deferred<U: FnOnce() + Send + 'static>(
+        self,
+        queue: &DropQueue,
+        dropper: U,
+    ) -> DropStream<T, T::Item, impl FnOnce()> {
+        let queue = queue.clone();
+        DropStream::new(self, move || queue.push(dropper))
+    }
+
+    fn on_drop_controlled<U: FnOnce() + Send + 'static>(
+        self,
+        dropper: U,
+    ) -> (ControlledDropStream<T>, DropControl) {
+        ControlledDropStream::new(self, dropper)
+    }
+
+    fn take_until_dropped<F: Future, U: FnOnce(DropReason)>(
+        self,
+        signal: F,
+        dropper: U,
+    ) -> TakeUntilDropped<T, F, U> {
+        TakeUntilDropped::new(self, signal, dropper)
+    }
+
+    fn on_drop_fallible<E: std::error::Error + 'static, U: FnOnce() -> Result<(), E>>(
+        self,
+        dropper: U,
+    ) -> FallibleDropStream<T, E, U, fn(E)> {
+        FallibleDropStream::new(self, dropper)
+    }
+
+    #[track_caller]
+    fn on_drop_ctx<U: FnOnce(DropContext)>(self, dropper: U) -> ContextDropStream<T, U> {
+        ContextDropStream::new(self, dropper)
+    }
+
+    #[track_caller]
+    fn on_drop_with_last<U: FnOnce(DropContext, Option<T::Item>)>(
+        self,
+        dropper: U,
+    ) -> LastItemStream<T, CloneFn<T::Item>, T::Item, U>
+    where
+        T::Item: Clone,
+    {
+        LastItemStream::new(self, Clone::clone, dropper)
+    }
+
+    #[track_caller]
+    fn on_drop_with_last_by<P, K, U>(self, project: P, dropper: U) -> LastItemStream<T, P, K, U>
+    where
+        P: FnMut(&T::Item) -> K,
+        U: FnOnce(DropContext, Option<K>),
+    {
+        LastItemStream::new(self, project, dropper)
+    }
+
+    fn checkpoint_on_drop<F, P, C>(self, position: F, checkpointer: C) -> Checkpointed<T, F, P, C>
+    where
+        F: FnMut(&T::Item) -> P,
+        C: Checkpointer<P>,
+    {
+        Checkpointed::new(self, position, checkpointer)
+    }
+
+    #[track_caller]
+    fn on_drop_with_queue_depth<P, U>(self, probe: P, dropper: U) -> QueueDepthStream<T, P, U>
+    where
+        P: FnMut(&T) -> QueueDepth,
+        U: FnOnce(DropContext),
+    {
+        QueueDepthStream::new(self, probe, dropper)
+    }
+
+    #[track_caller]
+    fn fold_on_drop<A, F, U>(self, init: A, fold: F, dropper: U) -> FoldOnDrop<T, A, F, U>
+    where
+        F: FnMut(&mut A, &T::Item),
+        U: FnOnce(DropContext, A),
+    {
+        FoldOnDrop::new(self, init, fold, dropper)
+    }
+
+    #[track_caller]
+    fn encode_into<W, E, O, F>(self, writer: W, encode: E, finalize: F) -> Encoded<T, W, E, O, F>
+    where
+        E: FnMut(&mut W, T::Item) -> O,
+        F: FnOnce(DropContext, W),
+    {
+        Encoded::new(self, writer, encode, finalize)
+    }
+
+    #[track_caller]
+    fn on_drop_with_bytes<U: FnOnce(DropContext)>(
+        self,
+        dropper: U,
+    ) -> ByteCountStream<T, ByteLenFn<T::Item>, U>
+    where
+        T::Item: AsRef<[u8]>,
+    {
+        ByteCountStream::new(self, |item| item.as_ref().len(), dropper)
+    }
+
+    #[track_caller]
+    fn on_drop_with_bytes_by<L, U>(self, len: L, dropper: U) -> ByteCountStream<T, L, U>
+    where
+        L: FnMut(&T::Item) -> usize,
+        U: FnOnce(DropContext),
+    {
+        ByteCountStream::new(self, len, dropper)
+    }
+
+    #[track_caller]
+    fn observe<O: StreamObserver<T::Item>>(self, observer: O) -> Observed<T, O> {
+        Observed::new(self, observer)
+    }
+
+    fn chain_on_end<F, I, U>(self, finalizer: F, dropper: U) -> ChainOnEnd<T, F, I, U>
+    where
+        F: FnOnce() -> I,
+        I: IntoIterator<Item = T::Item>,
+        U: FnOnce(DropReason),
+    {
+        ChainOnEnd::new(self, finalizer, dropper)
+    }
+
+    fn drain_on_drop<Sp: Spawn, U: FnOnce(DrainReport) + Send + 'static>(
+        self,
+        spawner: Sp,
+        budget: usize,
+        dropper: U,
+    ) -> DrainOnDrop<T, Sp, U>
+    where
+        T: Unpin + Send + 'static,
+    {
+        DrainOnDrop::new(self, spawner, budget, dropper)
+    }
+
+    fn dead_letter_on_drop<Tx: TrySend<T::Item>>(self, sender: Tx) -> DeadLetterStream<T, Tx>
+    where
+        T::Item: Clone,
+    {
+        DeadLetterStream::new(self, sender)
+    }
+
+    fn settle_on_drop<F, A>(self, acker: F) -> SettleOnDrop<T, F, A>
+    where
+        F: FnMut(&T::Item) -> A,
+        A: Acknowledge,
+    {
+        SettleOnDrop::new(self, acker)
+    }
+
+    fn abort_multipart_on_drop<A: AbortMultipart, Sp: Spawn>(
+        self,
+        upload: A,
+        spawner: Sp,
+    ) -> AbortOnDrop<T, A, Sp> {
+        AbortOnDrop::new(self, upload, spawner)
+    }
+
+    fn in_transaction<Tx: Transaction, Sp: Spawn>(
+        self,
+        transaction: Tx,
+        spawner: Sp,
+    ) -> TransactionStream<T, Tx, Sp> {
+        TransactionStream::new(self, transaction, spawner)
+    }
+
+    fn forward_on_drop<Tx: TrySend<T::Item>>(self, sender: Tx) -> ForwardOnDrop<T, Tx> {
+        ForwardOnDrop::new(self, sender)
+    }
+
+    #[cfg(feature = "tokio")]
+    fn idle_timeout<H: FnMut() + Send + 'static>(
+        self,
+        threshold: std::time::Duration,
+        hook: H,
+    ) -> IdleTimeout<T> {
+        IdleTimeout::new(self, threshold, hook)
+    }
+
+    #[cfg(feature = "tokio")]
+    fn on_drop_delayed<U: FnOnce() + Send + 'static>(
+        self,
+        delay: std::time::Duration,
+        dropper: U,
+    ) -> (DelayedDrop<T>, GraceHandle) {
+        DelayedDrop::new(self, delay, dropper)
+    }
+
+    #[cfg(feature = "tokio")]
+    fn heartbeat<H: FnMut() + Send + 'static>(
+        self,
+        interval: std::time::Duration,
+        hook: H,
+    ) -> Heartbeat<T, H> {
+        Heartbeat::new(self, interval, hook)
+    }
+
+    #[cfg(feature = "tokio")]
+    fn keep_alive<K, U>(
+        self,
+        interval: std::time::Duration,
+        keep_alive: K,
+        dropper: U,
+    ) -> KeepAlive<T, K, U>
+    where
+        K: FnMut() -> T::Item,
+        U: FnOnce(DropReason),
+    {
+        KeepAlive::new(self, interval, keep_alive, dropper)
+    }
+
+    #[cfg(feature = "tokio")]
+    fn on_drop_teardown<U, F>(
+        self,
+        budget: std::time::Duration,
+        dropper: U,
+    ) -> AsyncTeardown<T, U, F>
+    where
+        U: FnOnce() -> F,
+        F: Future<Output = ()> + Send + 'static,
+    {
+        AsyncTeardown::new(self, budget, dropper)
+    }
+
+    #[cfg(feature = "tokio")]
+    fn on_drop_reaped<U, F, E>(self, dropper: U) -> DropStream<T, T::Item, impl FnOnce()>
+    where
+        U: FnOnce() -> F,
+        F: Future<Output = Result<(), E>> + Send + 'static,
+        E: std::error::Error + Send + 'static,
+    {
+        let runtime = tokio::runtime::Handle::current();
+        DropStream::new(self, move || reaper::reap_on(&runtime, dropper()))
+    }
 }
 
 #[cfg(test)]
@@ -109,7 +953,7 @@ mod tests {
     use std::task::Poll;
 
     use crate::{DropStream, DropStreamExt};
-    use futures::{stream::repeat, Stream};
+    use futures::{stream::repeat, Stream, StreamExt};
 
     #[test]
     fn dropper_runs_on_drop() {
@@ -143,6 +987,64 @@ mod tests {
         );
     }
 
+    #[test]
+    fn wrappers_forward_unwind_safety() {
+        use crate::{
+            ContextDropStream, DropContext, DropFuture, DropGuard, DropSignal, Instrumented,
+            OnceDropped, StaticDropStream,
+        };
+        use std::{
+            future::Ready,
+            panic::{catch_unwind, RefUnwindSafe, UnwindSafe},
+            sync::Mutex,
+        };
+
+        fn assert_unwind_safe<T: UnwindSafe + RefUnwindSafe>() {}
+        type Inner = futures::stream::Iter<std::vec::IntoIter<u32>>;
+        assert_unwind_safe::<DropStream<Inner, u32, fn()>>();
+        assert_unwind_safe::<ContextDropStream<Inner, fn(DropContext)>>();
+        assert_unwind_safe::<StaticDropStream<Inner, u32>>();
+        assert_unwind_safe::<DropFuture<Ready<u32>, fn()>>();
+        assert_unwind_safe::<Instrumented<Ready<u32>, fn(DropContext)>>();
+        assert_unwind_safe::<DropGuard<u32, fn(u32)>>();
+        assert_unwind_safe::<DropSignal>();
+        assert_unwind_safe::<OnceDropped>();
+
+        // A wrapper whose closure shares state through a poisoning lock crosses `catch_unwind`
+        // without `AssertUnwindSafe`.
+        let reasons = Mutex::new(Vec::new());
+        let stream = futures::stream::iter(vec![1, 2])
+            .on_drop_ctx(|c| reasons.lock().unwrap().push(c.reason()));
+        let result = catch_unwind(|| {
+            for item in futures::executor::block_on_stream(stream) {
+                assert_ne!(item, 2, "handler failed");
+            }
+        });
+
+        assert!(result.is_err());
+        assert_eq!(*reasons.lock().unwrap(), [crate::DropReason::Cancelled]);
+    }
+
+    #[test]
+    fn pinned_on_drop_polls_unpin_less_stream() {
+        let mut has_run = false;
+
+        {
+            let has_run_ref = &mut has_run;
+            let stream = futures::stream::unfold(0, |n| async move { Some((n, n + 1)) });
+            let mut drop_stream = stream.pinned_on_drop(move || *has_run_ref = true);
+
+            let waker = futures::task::noop_waker();
+            let mut context = futures::task::Context::from_waker(&waker);
+            assert_eq!(
+                drop_stream.as_mut().poll_next(&mut context),
+                Poll::Ready(Some(0))
+            );
+        }
+
+        assert!(has_run)
+    }
+
     #[test]
     fn dropper_runs_on_drop_after_passing_result() {
         let test_stream = repeat(true);
@@ -192,4 +1094,146 @@ mod tests {
 
         assert!(has_run)
     }
+
+    #[test]
+    fn wrap_inner_keeps_dropper() {
+        let mut has_run = false;
+
+        {
+            let has_run_ref = &mut has_run;
+            let drop_stream = repeat(1)
+                .on_drop(move || {
+                    *has_run_ref = true;
+                })
+                .wrap_inner(|inner| inner.map(|x| x + 1));
+
+            let mut drop_stream = Box::pin(drop_stream);
+
+            let waker = futures::task::noop_waker();
+            let mut context = futures::task::Context::from_waker(&waker);
+            assert_eq!(
+                drop_stream.as_mut().poll_next(&mut context),
+                Poll::Ready(Some(2))
+            );
+        }
+
+        assert!(has_run)
+    }
+
+    #[test]
+    fn outer_dropper_runs_before_inner() {
+        let order = std::cell::RefCell::new(Vec::new());
+
+        {
+            let _drop_stream = repeat(true)
+                .on_drop(|| order.borrow_mut().push("inner"))
+                .on_drop(|| order.borrow_mut().push("outer"));
+        }
+
+        assert_eq!(*order.borrow(), vec!["outer", "inner"]);
+    }
+
+    #[test]
+    fn fused_stream_is_forwarded() {
+        use futures::stream::FusedStream;
+
+        let drop_stream = futures::stream::iter([1]).fuse().on_drop(|| {});
+
+        let mut drop_stream = Box::pin(drop_stream);
+        assert!(!drop_stream.is_terminated());
+
+        let waker = futures::task::noop_waker();
+        let mut context = futures::task::Context::from_waker(&waker);
+        assert_eq!(
+            drop_stream.as_mut().poll_next(&mut context),
+            Poll::Ready(Some(1))
+        );
+        assert_eq!(
+            drop_stream.as_mut().poll_next(&mut context),
+            Poll::Ready(None)
+        );
+        assert!(drop_stream.is_terminated());
+    }
+
+    #[test]
+    fn usable_in_select() {
+        let mut has_run = false;
+
+        {
+            let has_run_ref = &mut has_run;
+            let mut drop_stream = futures::stream::iter([1, 2])
+                .fuse()
+                .on_drop(move || *has_run_ref = true);
+
+            let sum = futures::executor::block_on(async {
+                let mut sum = 0;
+                loop {
+                    futures::select! {
+                        item = drop_stream.next() => match item {
+                            Some(item) => sum += item,
+                            None => break,
+                        },
+                        complete => break,
+                    }
+                }
+                sum
+            });
+            assert_eq!(sum, 3);
+        }
+
+        assert!(has_run)
+    }
+
+    #[test]
+    fn size_hint_is_forwarded() {
+        let drop_stream = futures::stream::iter([1, 2, 3]).on_drop(|| {});
+
+        assert_eq!(drop_stream.size_hint(), (3, Some(3)));
+    }
+
+    #[test]
+    fn weak_dropper_skips_gone_target() {
+        let target = std::sync::Arc::new(());
+        let weak = std::sync::Arc::downgrade(&target);
+
+        let mut has_run = false;
+        {
+            let has_run_ref = &mut has_run;
+            let _drop_stream = repeat(true).on_drop_weak(weak, move |_| *has_run_ref = true);
+
+            drop(target);
+        }
+
+        assert!(!has_run);
+    }
+
+    #[test]
+    fn map_dropper_decorates_closure() {
+        let runs = std::sync::Mutex::new(Vec::new());
+
+        {
+            let runs = &runs;
+            let drop_stream = repeat(true)
+                .on_drop(move || runs.lock().unwrap().push("original"))
+                .map_dropper(|dropper| {
+                    move || {
+                        dropper();
+                        runs.lock().unwrap().push("decorator");
+                    }
+                });
+
+            let mut drop_stream = Box::pin(drop_stream);
+
+            let waker = futures::task::noop_waker();
+            let mut context = futures::task::Context::from_waker(&waker);
+            assert_eq!(
+                drop_stream.as_mut().poll_next(&mut context),
+                Poll::Ready(Some(true))
+            );
+            // Mapping doesn't call the closure.
+            assert!(runs.lock().unwrap().is_empty());
+        }
+
+        assert_eq!(*runs.lock().unwrap(), ["original", "decorator"]);
+    }
 }
@@ -0,0 +1,297 @@
+use futures_core::Stream;
+use pin_project::pin_project;
+use std::{
+    collections::HashMap,
+    fmt,
+    hash::Hash,
+    pin::Pin,
+    sync::{Arc, Mutex, MutexGuard},
+    task::{Context, Poll},
+};
+
+struct State<K> {
+    counts: HashMap<K, usize>,
+    limits: HashMap<K, usize>,
+    default_limit: usize,
+}
+
+impl<K: Eq + Hash> State<K> {
+    fn limit(&self, key: &K) -> usize {
+        self.limits.get(key).copied().unwrap_or(self.default_limit)
+    }
+}
+
+/// Counts the live streams per key, such as the open connections of every tenant, and enforces a
+/// cap on them.
+///
+/// Wrapping a stream with [`track`](LimitTracker::track) or [`try_track`](LimitTracker::try_track)
+/// increments the count of its key, and dropping the wrapper decrements it exactly once, even if
+/// the drop happens while unwinding. `try_track` refuses the stream if its key is already at the
+/// limit, which is the same for every key unless overridden with
+/// [`set_limit`](LimitTracker::set_limit). The tracker is cheap to clone, and every clone counts
+/// the same streams.
+///
+/// Example
+/// ```
+/// use drop_stream::LimitTracker;
+///
+/// let connections = LimitTracker::new(2);
+/// let first = connections.try_track("acme", futures::stream::repeat(true)).unwrap();
+/// let second = connections.try_track("acme", futures::stream::repeat(true)).unwrap();
+///
+/// let refused = connections.try_track("acme", futures::stream::repeat(true));
+/// assert!(refused.is_err());
+///
+/// drop(first);
+/// assert_eq!(connections.count(&"acme"), 1);
+/// assert!(connections.try_track("acme", futures::stream::repeat(true)).is_ok());
+/// # drop(second);
+/// ```
+pub struct LimitTracker<K> {
+    state: Arc<Mutex<State<K>>>,
+}
+
+impl<K: Eq + Hash + Clone> LimitTracker<K> {
+    /// Creates a tracker that allows `limit` live streams per key.
+    pub fn new(limit: usize) -> Self {
+        Self {
+            state: Arc::new(Mutex::new(State {
+                counts: HashMap::new(),
+                limits: HashMap::new(),
+                default_limit: limit,
+            })),
+        }
+    }
+
+    /// Overrides the limit of `key`. Streams that are already live are not affected.
+    pub fn set_limit(&self, key: K, limit: usize) {
+        lock(&self.state).limits.insert(key, limit);
+    }
+
+    /// Returns the limit of `key`.
+    pub fn limit(&self, key: &K) -> usize {
+        lock(&self.state).limit(key)
+    }
+
+    /// Counts `stream` under `key` until the returned wrapper is dropped, regardless of the limit.
+    pub fn track<S: Stream>(&self, key: K, stream: S) -> Limited<S, K> {
+        *lock(&self.state).counts.entry(key.clone()).or_default() += 1;
+        self.limited(key, stream)
+    }
+
+    /// Counts `stream` under `key` until the returned wrapper is dropped, unless `key` is already
+    /// at its limit, in which case the stream is handed back in the error.
+    pub fn try_track<S: Stream>(
+        &self,
+        key: K,
+        stream: S,
+    ) -> Result<Limited<S, K>, LimitExceeded<S>> {
+        {
+            let mut state = lock(&self.state);
+            let limit = state.limit(&key);
+            let count = state.counts.entry(key.clone()).or_default();
+            if *count >= limit {
+                if *count == 0 {
+                    state.counts.remove(&key);
+                }
+                return Err(LimitExceeded { stream, limit });
+            }
+            *count += 1;
+        }
+
+        Ok(self.limited(key, stream))
+    }
+
+    fn limited<S: Stream>(&self, key: K, stream: S) -> Limited<S, K> {
+        Limited {
+            slot: Slot {
+                key: Some(key),
+                state: self.state.clone(),
+            },
+            stream,
+        }
+    }
+
+    /// Returns the number of live streams under `key`.
+    pub fn count(&self, key: &K) -> usize {
+        lock(&self.state).counts.get(key).copied().unwrap_or(0)
+    }
+
+    /// Returns the number of live streams across every key.
+    pub fn total(&self) -> usize {
+        lock(&self.state).counts.values().sum()
+    }
+
+    /// Returns true if `key` is at or above its limit.
+    pub fn is_full(&self, key: &K) -> bool {
+        let state = lock(&self.state);
+        state.counts.get(key).copied().unwrap_or(0) >= state.limit(key)
+    }
+}
+
+fn lock<K>(state: &Mutex<State<K>>) -> MutexGuard<'_, State<K>> {
+    state.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+impl<K> Clone for LimitTracker<K> {
+    fn clone(&self) -> Self {
+        Self {
+            state: self.state.clone(),
+        }
+    }
+}
+
+impl<K> fmt::Debug for LimitTracker<K> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let state = lock(&self.state);
+        f.debug_struct("LimitTracker")
+            .field("default_limit", &state.default_limit)
+            .field("total", &state.counts.values().sum::<usize>())
+            .finish()
+    }
+}
+
+struct Slot<K: Eq + Hash> {
+    // Taken on release, so the count is decremented at most once.
+    key: Option<K>,
+    state: Arc<Mutex<State<K>>>,
+}
+
+impl<K: Eq + Hash> Drop for Slot<K> {
+    fn drop(&mut self) {
+        let Some(key) = self.key.take() else {
+            return;
+        };
+
+        let mut state = lock(&self.state);
+        if let Some(count) = state.counts.get_mut(&key) {
+            *count -= 1;
+            if *count == 0 {
+                state.counts.remove(&key);
+            }
+        }
+    }
+}
+
+/// A stream counted by a [`LimitTracker`]. Created by [`LimitTracker::track`] and
+/// [`LimitTracker::try_track`].
+#[pin_project]
+pub struct Limited<S, K: Eq + Hash> {
+    // Declared before the stream so the count is decremented before the inner stream is dropped.
+    slot: Slot<K>,
+    #[pin]
+    stream: S,
+}
+
+impl<S, K: Eq + Hash> Limited<S, K> {
+    /// Returns the key the stream is counted under.
+    pub fn key(&self) -> &K {
+        // Only taken in the drop method.
+        self.slot.key.as_ref().expect("key taken before drop")
+    }
+}
+
+impl<S: Stream, K: Eq + Hash> Stream for Limited<S, K> {
+    type Item = S::Item;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        self.project().stream.poll_next(cx)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.stream.size_hint()
+    }
+}
+
+impl<S, K: Eq + Hash + fmt::Debug> fmt::Debug for Limited<S, K> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Limited")
+            .field("key", self.key())
+            .finish_non_exhaustive()
+    }
+}
+
+/// The error of [`LimitTracker::try_track`] once the key of a stream is at its limit, holding the
+/// refused stream.
+pub struct LimitExceeded<S> {
+    stream: S,
+    limit: usize,
+}
+
+impl<S> LimitExceeded<S> {
+    /// Returns the limit that was reached.
+    pub fn limit(&self) -> usize {
+        self.limit
+    }
+
+    /// Returns the refused stream.
+    pub fn into_inner(self) -> S {
+        self.stream
+    }
+}
+
+impl<S> fmt::Debug for LimitExceeded<S> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("LimitExceeded")
+            .field("limit", &self.limit)
+            .finish_non_exhaustive()
+    }
+}
+
+impl<S> fmt::Display for LimitExceeded<S> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "limit of {} live streams reached", self.limit)
+    }
+}
+
+impl<S> std::error::Error for LimitExceeded<S> {}
+
+#[cfg(test)]
+mod tests {
+    use std::panic::{catch_unwind, AssertUnwindSafe};
+
+    use crate::LimitTracker;
+    use futures::stream::repeat;
+
+    #[test]
+    fn per_key_limits_are_enforced() {
+        let tracker = LimitTracker::new(1);
+        tracker.set_limit("big", 2);
+
+        let small = tracker.try_track("small", repeat(1)).unwrap();
+        let refused = tracker.try_track("small", repeat(2)).unwrap_err();
+        assert_eq!(refused.limit(), 1);
+
+        let _big = [
+            tracker.try_track("big", repeat(1)).unwrap(),
+            tracker.try_track("big", repeat(1)).unwrap(),
+        ];
+        assert!(tracker.is_full(&"big"));
+        assert_eq!(tracker.total(), 3);
+
+        // Tracking without enforcement goes over the limit.
+        let _over = tracker.track("small", refused.into_inner());
+        assert_eq!(tracker.count(&"small"), 2);
+        drop(small);
+        assert_eq!(tracker.count(&"small"), 1);
+    }
+
+    #[test]
+    fn drop_while_unwinding_decrements_once() {
+        let tracker = LimitTracker::new(0);
+        assert!(tracker.try_track(1, repeat(1)).is_err());
+        assert_eq!(tracker.count(&1), 0);
+
+        let other = tracker.track(1, repeat(1));
+        let stream = tracker.track(1, repeat(1));
+        let result = catch_unwind(AssertUnwindSafe(move || {
+            let _stream = stream;
+            panic!("handler failed");
+        }));
+
+        assert!(result.is_err());
+        assert_eq!(tracker.count(&1), 1);
+        drop(other);
+        assert_eq!(tracker.total(), 0);
+    }
+}
@@ -0,0 +1,260 @@
+use futures_core::{Future, Stream};
+use pin_project::pin_project;
+use std::{
+    collections::HashMap,
+    fmt,
+    hash::Hash,
+    pin::Pin,
+    sync::{Arc, Mutex, MutexGuard},
+    task::{Context, Poll, Waker},
+};
+
+struct State<K> {
+    // The number of live streams per key, as several may share one.
+    live: HashMap<K, usize>,
+    // Tasks waiting for the map to become empty.
+    waiters: Vec<Waker>,
+}
+
+/// Tracks live streams by key: wrapping a stream with [`track`](DropMap::track) registers it,
+/// and dropping the wrapper deregisters it.
+///
+/// This replaces the `Arc<Mutex<HashMap>>` bookkeeping of session servers that need to know which
+/// sessions are still connected. The map is cheap to clone, and every clone refers to the same
+/// streams. Several streams may be tracked under the same key, which then stays alive until the
+/// last of them is dropped.
+///
+/// Example
+/// ```
+/// use drop_stream::DropMap;
+///
+/// let sessions = DropMap::new();
+/// let alice = sessions.track("alice", futures::stream::repeat(true));
+/// let bob = sessions.track("bob", futures::stream::repeat(true));
+/// assert_eq!(sessions.len(), 2);
+///
+/// drop(alice);
+/// assert!(!sessions.is_alive(&"alice"));
+/// assert_eq!(sessions.keys(), ["bob"]);
+///
+/// drop(bob);
+/// futures::executor::block_on(sessions.wait_empty());
+/// ```
+pub struct DropMap<K> {
+    state: Arc<Mutex<State<K>>>,
+}
+
+impl<K: Eq + Hash + Clone> DropMap<K> {
+    pub fn new() -> Self {
+        Self {
+            state: Arc::new(Mutex::new(State {
+                live: HashMap::new(),
+                waiters: Vec::new(),
+            })),
+        }
+    }
+
+    /// Registers `stream` under `key` until the returned wrapper is dropped.
+    pub fn track<S: Stream>(&self, key: K, stream: S) -> Tracked<S, K> {
+        *lock(&self.state).live.entry(key.clone()).or_default() += 1;
+
+        Tracked {
+            entry: Entry {
+                key,
+                state: self.state.clone(),
+            },
+            stream,
+        }
+    }
+
+    /// Returns true if a stream tracked under `key` is still alive.
+    pub fn is_alive(&self, key: &K) -> bool {
+        lock(&self.state).live.contains_key(key)
+    }
+
+    /// Returns the number of keys with a live stream.
+    pub fn len(&self) -> usize {
+        lock(&self.state).live.len()
+    }
+
+    /// Returns true if no tracked stream is alive.
+    pub fn is_empty(&self) -> bool {
+        lock(&self.state).live.is_empty()
+    }
+
+    /// Returns the keys with a live stream, in no particular order.
+    pub fn keys(&self) -> Vec<K> {
+        lock(&self.state).live.keys().cloned().collect()
+    }
+
+    /// Returns a future that resolves once no tracked stream is alive, such as for a graceful
+    /// shutdown that waits for every session to end.
+    pub fn wait_empty(&self) -> WaitEmpty<K> {
+        WaitEmpty {
+            state: self.state.clone(),
+        }
+    }
+}
+
+fn lock<K>(state: &Mutex<State<K>>) -> MutexGuard<'_, State<K>> {
+    state.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+impl<K: Eq + Hash + Clone> Default for DropMap<K> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<K> Clone for DropMap<K> {
+    fn clone(&self) -> Self {
+        Self {
+            state: self.state.clone(),
+        }
+    }
+}
+
+impl<K> fmt::Debug for DropMap<K> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("DropMap")
+            .field("len", &lock(&self.state).live.len())
+            .finish()
+    }
+}
+
+struct Entry<K: Eq + Hash> {
+    key: K,
+    state: Arc<Mutex<State<K>>>,
+}
+
+impl<K: Eq + Hash> Drop for Entry<K> {
+    fn drop(&mut self) {
+        let waiters = {
+            let mut state = lock(&self.state);
+            if let Some(live) = state.live.get_mut(&self.key) {
+                *live -= 1;
+                if *live == 0 {
+                    state.live.remove(&self.key);
+                }
+            }
+
+            if !state.live.is_empty() {
+                return;
+            }
+            std::mem::take(&mut state.waiters)
+        };
+
+        for waiter in waiters {
+            waiter.wake();
+        }
+    }
+}
+
+/// A stream tracked by a [`DropMap`]. Created by [`DropMap::track`].
+#[pin_project]
+pub struct Tracked<S, K: Eq + Hash> {
+    // Declared before the stream so the key is deregistered before the inner stream is dropped.
+    entry: Entry<K>,
+    #[pin]
+    stream: S,
+}
+
+impl<S, K: Eq + Hash> Tracked<S, K> {
+    /// Returns the key the stream is tracked under.
+    pub fn key(&self) -> &K {
+        &self.entry.key
+    }
+}
+
+impl<S: Stream, K: Eq + Hash> Stream for Tracked<S, K> {
+    type Item = S::Item;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        self.project().stream.poll_next(cx)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.stream.size_hint()
+    }
+}
+
+impl<S, K: Eq + Hash + fmt::Debug> fmt::Debug for Tracked<S, K> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Tracked")
+            .field("key", &self.entry.key)
+            .finish_non_exhaustive()
+    }
+}
+
+/// A future that resolves once no stream tracked by a [`DropMap`] is alive. Created by
+/// [`DropMap::wait_empty`].
+pub struct WaitEmpty<K> {
+    state: Arc<Mutex<State<K>>>,
+}
+
+impl<K> Future for WaitEmpty<K> {
+    type Output = ();
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<()> {
+        let mut state = lock(&self.state);
+        if state.live.is_empty() {
+            return Poll::Ready(());
+        }
+
+        if !state
+            .waiters
+            .iter()
+            .any(|waker| waker.will_wake(cx.waker()))
+        {
+            state.waiters.push(cx.waker().clone());
+        }
+        Poll::Pending
+    }
+}
+
+impl<K> fmt::Debug for WaitEmpty<K> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("WaitEmpty").finish_non_exhaustive()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::task::Poll;
+
+    use crate::DropMap;
+    use futures::{future::Future, stream::repeat};
+
+    #[test]
+    fn shared_key_stays_alive_until_last_stream_drops() {
+        let map = DropMap::new();
+
+        let first = map.track(1, repeat(true));
+        let second = map.clone().track(1, repeat(true));
+        assert_eq!(map.len(), 1);
+
+        drop(first);
+        assert!(map.is_alive(&1));
+        drop(second);
+        assert!(!map.is_alive(&1));
+        assert!(map.is_empty());
+    }
+
+    #[test]
+    fn wait_empty_wakes_once_last_stream_drops() {
+        let map = DropMap::new();
+        let a = map.track("a", repeat(true));
+        let b = map.track("b", repeat(true));
+
+        let mut wait = Box::pin(map.wait_empty());
+        let (waker, count) = futures_test::task::new_count_waker();
+        let mut cx = futures::task::Context::from_waker(&waker);
+        assert_eq!(wait.as_mut().poll(&mut cx), Poll::Pending);
+
+        drop(a);
+        assert_eq!(count, 0);
+        drop(b);
+        assert_eq!(count, 1);
+        assert_eq!(wait.as_mut().poll(&mut cx), Poll::Ready(()));
+    }
+}
@@ -0,0 +1,269 @@
+use futures_core::Stream;
+use pin_project::pin_project;
+use std::{
+    collections::VecDeque,
+    fmt,
+    pin::Pin,
+    sync::{Arc, Mutex, MutexGuard},
+    task::{Context, Poll, Waker},
+};
+
+use crate::DropReason;
+
+struct State<K> {
+    departures: VecDeque<(K, DropReason)>,
+    waker: Option<Waker>,
+    // Membership handles and members that can still send a departure.
+    senders: usize,
+}
+
+struct Shared<K>(Mutex<State<K>>);
+
+impl<K> Shared<K> {
+    fn lock(&self) -> MutexGuard<'_, State<K>> {
+        self.0.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    fn add_sender(self: &Arc<Self>) -> Arc<Self> {
+        self.lock().senders += 1;
+        self.clone()
+    }
+
+    /// Queues `departure` if there is one, and wakes the receiver.
+    fn remove_sender(&self, departure: Option<(K, DropReason)>) {
+        let waker = {
+            let mut state = self.lock();
+            state.senders -= 1;
+            state.departures.extend(departure);
+            state.waker.take()
+        };
+
+        if let Some(waker) = waker {
+            waker.wake();
+        }
+    }
+}
+
+/// Creates a [`Membership`] that tags streams with a key before they are inserted into a
+/// multiplexer such as `SelectAll` or `tokio_stream::StreamMap`, and the [`Departures`] stream
+/// that yields the key of every member that leaves it.
+///
+/// A member leaves when it is dropped: with [`DropReason::Completed`] if it finished, which makes
+/// `SelectAll` drop it, and with [`DropReason::Cancelled`] if it was removed or the multiplexer
+/// was dropped first. The owning task can then clean up after each member without tracking them
+/// itself. The departures stream ends once the membership and every member have been dropped.
+///
+/// Example
+/// ```
+/// use futures::{executor::block_on_stream, stream::{iter, select_all}};
+/// use drop_stream::{memberships, DropReason};
+///
+/// let (membership, departures) = memberships();
+/// let members = select_all([
+///     membership.attach("a", iter(vec![1, 2])),
+///     membership.attach("b", iter(vec![3])),
+/// ]);
+/// drop(membership);
+///
+/// assert_eq!(block_on_stream(members).count(), 3);
+/// let mut departed: Vec<_> = block_on_stream(departures).collect();
+/// departed.sort_by_key(|(key, _)| *key);
+/// assert_eq!(departed, [("a", DropReason::Completed), ("b", DropReason::Completed)]);
+/// ```
+pub fn memberships<K>() -> (Membership<K>, Departures<K>) {
+    let shared = Arc::new(Shared(Mutex::new(State {
+        departures: VecDeque::new(),
+        waker: None,
+        senders: 1,
+    })));
+
+    (
+        Membership {
+            shared: shared.clone(),
+        },
+        Departures { shared },
+    )
+}
+
+/// Tags streams with the key they are reported under once they leave. Created by
+/// [`memberships`].
+pub struct Membership<K> {
+    shared: Arc<Shared<K>>,
+}
+
+impl<K> Membership<K> {
+    /// Wraps `stream` so that `key` is reported to the [`Departures`] stream once it is dropped.
+    pub fn attach<S: Stream>(&self, key: K, stream: S) -> Member<S, K> {
+        Member {
+            departure: MemberDeparture {
+                key: Some(key),
+                reason: DropReason::Cancelled,
+                shared: self.shared.add_sender(),
+            },
+            stream,
+        }
+    }
+}
+
+impl<K> Clone for Membership<K> {
+    fn clone(&self) -> Self {
+        Self {
+            shared: self.shared.add_sender(),
+        }
+    }
+}
+
+impl<K> Drop for Membership<K> {
+    fn drop(&mut self) {
+        self.shared.remove_sender(None);
+    }
+}
+
+impl<K> fmt::Debug for Membership<K> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Membership").finish_non_exhaustive()
+    }
+}
+
+struct MemberDeparture<K> {
+    // Only taken in the drop method.
+    key: Option<K>,
+    reason: DropReason,
+    shared: Arc<Shared<K>>,
+}
+
+impl<K> Drop for MemberDeparture<K> {
+    fn drop(&mut self) {
+        let departure = self.key.take().map(|key| (key, self.reason));
+        self.shared.remove_sender(departure);
+    }
+}
+
+/// A stream tagged with a key by a [`Membership`].
+#[pin_project]
+pub struct Member<S, K> {
+    // Declared before the stream so the departure is reported before the inner stream is dropped.
+    departure: MemberDeparture<K>,
+    #[pin]
+    stream: S,
+}
+
+impl<S, K> Member<S, K> {
+    /// Returns the key the stream is reported under.
+    pub fn key(&self) -> &K {
+        // Only taken in the drop method.
+        self.departure.key.as_ref().expect("key taken before drop")
+    }
+}
+
+impl<S: Stream, K> Stream for Member<S, K> {
+    type Item = S::Item;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let this = self.project();
+
+        let poll = this.stream.poll_next(cx);
+        if let Poll::Ready(None) = poll {
+            this.departure.reason = DropReason::Completed;
+        }
+
+        poll
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.stream.size_hint()
+    }
+}
+
+impl<S, K: fmt::Debug> fmt::Debug for Member<S, K> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Member")
+            .field("key", self.key())
+            .finish_non_exhaustive()
+    }
+}
+
+/// A stream of the keys of the members that left, along with the reason they left. Created by
+/// [`memberships`].
+#[must_use = "streams do nothing unless polled"]
+pub struct Departures<K> {
+    shared: Arc<Shared<K>>,
+}
+
+impl<K> Stream for Departures<K> {
+    type Item = (K, DropReason);
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let mut state = self.shared.lock();
+        if let Some(departure) = state.departures.pop_front() {
+            return Poll::Ready(Some(departure));
+        }
+        if state.senders == 0 {
+            return Poll::Ready(None);
+        }
+
+        state.waker = Some(cx.waker().clone());
+        Poll::Pending
+    }
+}
+
+impl<K> fmt::Debug for Departures<K> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Departures")
+            .field("queued", &self.shared.lock().departures.len())
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::task::Poll;
+
+    use crate::{memberships, DropReason};
+    use futures::{
+        stream::{iter, pending, SelectAll},
+        Stream, StreamExt,
+    };
+
+    #[test]
+    fn finished_and_removed_members_depart() {
+        let (membership, departures) = memberships();
+        let mut departures = Box::pin(departures);
+
+        let (waker, count) = futures_test::task::new_count_waker();
+        let mut cx = futures::task::Context::from_waker(&waker);
+
+        let mut members = SelectAll::new();
+        members.push(membership.attach("idle", pending::<bool>()).boxed());
+        members.push(membership.attach("once", iter([true])).boxed());
+        assert_eq!(departures.as_mut().poll_next(&mut cx), Poll::Pending);
+
+        assert_eq!(members.poll_next_unpin(&mut cx), Poll::Ready(Some(true)));
+        // The finished member is dropped by `SelectAll` on the next poll.
+        assert_eq!(members.poll_next_unpin(&mut cx), Poll::Pending);
+        assert!(count.get() >= 1);
+        assert_eq!(
+            departures.as_mut().poll_next(&mut cx),
+            Poll::Ready(Some(("once", DropReason::Completed)))
+        );
+
+        members.clear();
+        assert_eq!(
+            departures.as_mut().poll_next(&mut cx),
+            Poll::Ready(Some(("idle", DropReason::Cancelled)))
+        );
+        assert_eq!(departures.as_mut().poll_next(&mut cx), Poll::Pending);
+    }
+
+    #[test]
+    fn departures_end_once_every_sender_is_gone() {
+        let (membership, departures) = memberships::<u8>();
+        let member = membership.clone().attach(1, iter([1]));
+        drop(membership);
+
+        let mut departures = futures::executor::block_on_stream(departures);
+        drop(member);
+        assert_eq!(departures.next(), Some((1, DropReason::Cancelled)));
+        assert_eq!(departures.next(), None);
+    }
+}
@@ -0,0 +1,243 @@
+use futures_core::Stream;
+use pin_project::pin_project;
+use std::{
+    pin::Pin,
+    task::{Context, Poll},
+};
+
+/// Merges two streams into one that yields items from both as they become ready, keeping both
+/// alive until the merged stream is dropped.
+///
+/// Meant for wrapped streams: neither side is dropped when it finishes, so both drop closures run
+/// when the merged stream is dropped, `a`'s first. The sides are polled in turn, so neither can
+/// starve the other.
+///
+/// Example
+/// ```
+/// use futures::{executor::block_on_stream, stream};
+/// use drop_stream::{merge_on_drop, DropStreamExt};
+///
+/// let dropped = std::cell::RefCell::new(Vec::new());
+/// let a = stream::iter([1, 3]).on_drop(|| dropped.borrow_mut().push("a"));
+/// let b = stream::iter([2]).on_drop(|| dropped.borrow_mut().push("b"));
+///
+/// let items: Vec<_> = block_on_stream(merge_on_drop(a, b)).collect();
+/// assert_eq!(items, [1, 2, 3]);
+/// assert_eq!(*dropped.borrow(), ["a", "b"]);
+/// ```
+pub fn merge_on_drop<A, B>(a: A, b: B) -> MergeOnDrop<A, B>
+where
+    A: Stream,
+    B: Stream<Item = A::Item>,
+{
+    MergeOnDrop {
+        a,
+        b,
+        a_done: false,
+        b_done: false,
+        b_first: false,
+    }
+}
+
+/// A stream yielding the items of two streams as they become ready. Created by
+/// [`merge_on_drop`].
+#[pin_project]
+pub struct MergeOnDrop<A, B> {
+    #[pin]
+    a: A,
+    #[pin]
+    b: B,
+    a_done: bool,
+    b_done: bool,
+    // Which side is polled first next time, flipped on every poll.
+    b_first: bool,
+}
+
+impl<A, B> Stream for MergeOnDrop<A, B>
+where
+    A: Stream,
+    B: Stream<Item = A::Item>,
+{
+    type Item = A::Item;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let mut this = self.project();
+
+        let b_first = *this.b_first;
+        *this.b_first = !b_first;
+
+        for poll_b in [b_first, !b_first] {
+            let (done, poll) = if poll_b {
+                if *this.b_done {
+                    continue;
+                }
+                (&mut *this.b_done, this.b.as_mut().poll_next(cx))
+            } else {
+                if *this.a_done {
+                    continue;
+                }
+                (&mut *this.a_done, this.a.as_mut().poll_next(cx))
+            };
+
+            match poll {
+                Poll::Ready(Some(item)) => return Poll::Ready(Some(item)),
+                Poll::Ready(None) => *done = true,
+                Poll::Pending => {}
+            }
+        }
+
+        if *this.a_done && *this.b_done {
+            Poll::Ready(None)
+        } else {
+            Poll::Pending
+        }
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let hint = |done: bool, hint: (usize, Option<usize>)| {
+            if done {
+                (0, Some(0))
+            } else {
+                hint
+            }
+        };
+        let (a_lower, a_upper) = hint(self.a_done, self.a.size_hint());
+        let (b_lower, b_upper) = hint(self.b_done, self.b.size_hint());
+
+        let upper = a_upper.zip(b_upper).and_then(|(a, b)| a.checked_add(b));
+        (a_lower.saturating_add(b_lower), upper)
+    }
+}
+
+/// Zips two streams into one that yields pairs of their items, keeping both alive until the
+/// zipped stream is dropped.
+///
+/// Like [`merge_on_drop`], neither side is dropped when the zipped stream ends, so both drop
+/// closures run when it is dropped, `a`'s first. The zipped stream ends as soon as either side
+/// does.
+pub fn zip_on_drop<A: Stream, B: Stream>(a: A, b: B) -> ZipOnDrop<A, B> {
+    ZipOnDrop {
+        a,
+        b,
+        a_item: None,
+        b_item: None,
+        done: false,
+    }
+}
+
+/// A stream yielding pairs of the items of two streams. Created by [`zip_on_drop`].
+#[pin_project]
+pub struct ZipOnDrop<A: Stream, B: Stream> {
+    #[pin]
+    a: A,
+    #[pin]
+    b: B,
+    // Items that are ready while the other side isn't yet.
+    a_item: Option<A::Item>,
+    b_item: Option<B::Item>,
+    done: bool,
+}
+
+impl<A: Stream, B: Stream> Stream for ZipOnDrop<A, B> {
+    type Item = (A::Item, B::Item);
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let this = self.project();
+
+        if *this.done {
+            return Poll::Ready(None);
+        }
+
+        if this.a_item.is_none() {
+            match this.a.poll_next(cx) {
+                Poll::Ready(Some(item)) => *this.a_item = Some(item),
+                Poll::Ready(None) => *this.done = true,
+                Poll::Pending => {}
+            }
+        }
+        if this.b_item.is_none() && !*this.done {
+            match this.b.poll_next(cx) {
+                Poll::Ready(Some(item)) => *this.b_item = Some(item),
+                Poll::Ready(None) => *this.done = true,
+                Poll::Pending => {}
+            }
+        }
+
+        if *this.done {
+            return Poll::Ready(None);
+        }
+
+        match (this.a_item.take(), this.b_item.take()) {
+            (Some(a), Some(b)) => Poll::Ready(Some((a, b))),
+            (a, b) => {
+                *this.a_item = a;
+                *this.b_item = b;
+                Poll::Pending
+            }
+        }
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        if self.done {
+            return (0, Some(0));
+        }
+
+        let buffered = |item: bool, (lower, upper): (usize, Option<usize>)| {
+            let item = usize::from(item);
+            (
+                lower.saturating_add(item),
+                upper.and_then(|upper| upper.checked_add(item)),
+            )
+        };
+        let (a_lower, a_upper) = buffered(self.a_item.is_some(), self.a.size_hint());
+        let (b_lower, b_upper) = buffered(self.b_item.is_some(), self.b.size_hint());
+
+        let upper = match (a_upper, b_upper) {
+            (Some(a), Some(b)) => Some(a.min(b)),
+            (upper, None) | (None, upper) => upper,
+        };
+        (a_lower.min(b_lower), upper)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{merge_on_drop, test_util::DropTracker, zip_on_drop, DropStreamExt};
+    use futures::{
+        executor::block_on_stream,
+        stream::{iter, repeat},
+    };
+
+    #[test]
+    fn merge_keeps_finished_side_until_dropped() {
+        let tracker = DropTracker::new();
+
+        let a = iter([1]).on_drop(tracker.callback("a"));
+        let b = iter([2, 3, 4]).on_drop(tracker.callback("b"));
+        let mut merged = block_on_stream(merge_on_drop(a, b));
+
+        assert_eq!(merged.next(), Some(1));
+        assert_eq!(merged.next(), Some(2));
+        assert_eq!(merged.next(), Some(3));
+        tracker.assert_not_dropped("a");
+
+        assert_eq!(merged.by_ref().count(), 1);
+        drop(merged);
+        tracker.assert_order(&["a", "b"]);
+    }
+
+    #[test]
+    fn zip_ends_with_shorter_side_and_drops_both() {
+        let tracker = DropTracker::new();
+
+        let a = iter([1, 2]).on_drop(tracker.callback("a"));
+        let b = repeat(true).on_drop(tracker.callback("b"));
+        let mut zipped = block_on_stream(zip_on_drop(a, b));
+
+        assert_eq!(zipped.by_ref().collect::<Vec<_>>(), [(1, true), (2, true)]);
+        tracker.assert_not_dropped("b");
+
+        drop(zipped);
+        tracker.assert_order(&["a", "b"]);
+    }
+}
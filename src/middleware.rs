@@ -0,0 +1,471 @@
+use futures_core::Future;
+use http::{Request, Response};
+use pin_project::pin_project;
+use std::{
+    fmt,
+    pin::Pin,
+    sync::Arc,
+    task::{Context, Poll},
+};
+use tower_layer::Layer;
+use tower_service::Service;
+
+use crate::{signal::Signal, ContextDropFn, DropBody, DropContext};
+
+type DisconnectHook = Arc<dyn Fn(&DropContext) + Send + Sync>;
+
+/// A tower layer, usable with axum and other tower-based servers, that detects clients
+/// disconnecting before they received the whole response, and lets the request's handler react.
+///
+/// Every response body is wrapped in a [`DropBody`] labelled with the request's `method`, `path`,
+/// the response's `status`, and, if the request has an `x-request-id` header, its `request_id`.
+/// Every request gets a [`CancelToken`] in its extensions, which is cancelled if the response body
+/// is dropped before it was sent in full, or if the response future is dropped before it produced
+/// a response, so handlers and the tasks they spawned can stop working for a client that left.
+/// With the `tokio-util` feature, the request also gets a `tokio_util` `CancellationToken`, which is
+/// cancelled along with the [`CancelToken`], for handlers built around those. Cancelling can be
+/// turned off with [`cancel_on_disconnect`](Self::cancel_on_disconnect), for handlers that only
+/// want to know about disconnects.
+///
+/// On such a disconnect the hook set with [`on_disconnect`](Self::on_disconnect) is called with
+/// the body's context, and with the `tracing` feature enabled an event is emitted at info level
+/// under the `drop_stream` target, carrying the labels above.
+///
+/// Example
+/// ```
+/// use std::{convert::Infallible, future::Ready};
+/// use drop_stream::{CancelToken, DisconnectLayer};
+/// use http::{Request, Response};
+/// use tower_layer::Layer;
+///
+/// #[derive(Clone)]
+/// struct Handler;
+///
+/// impl tower_service::Service<Request<()>> for Handler {
+///     type Response = Response<http_body_util::Full<bytes::Bytes>>;
+///     type Error = Infallible;
+///     type Future = Ready<Result<Self::Response, Infallible>>;
+///
+///     fn poll_ready(&mut self, _: &mut std::task::Context<'_>) -> std::task::Poll<Result<(), Infallible>> {
+///         std::task::Poll::Ready(Ok(()))
+///     }
+///
+///     fn call(&mut self, request: Request<()>) -> Self::Future {
+///         let token = request.extensions().get::<CancelToken>().unwrap().clone();
+///         // Hand the token to the work producing the response...
+///         # drop(token);
+///         std::future::ready(Ok(Response::new("hello".into())))
+///     }
+/// }
+///
+/// let service = DisconnectLayer::new()
+///     .on_disconnect(|context| println!("{:?} left early", context.label("request_id")))
+///     .layer(Handler);
+/// # drop(service);
+/// ```
+#[derive(Clone, Default)]
+pub struct DisconnectLayer {
+    hook: Option<DisconnectHook>,
+    // Leaves the tokens of a request alone when its client disconnects.
+    skip_cancel: bool,
+}
+
+impl DisconnectLayer {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Calls `hook` with the response body's context whenever a client disconnects early,
+    /// replacing any previous hook.
+    pub fn on_disconnect(mut self, hook: impl Fn(&DropContext) + Send + Sync + 'static) -> Self {
+        self.hook = Some(Arc::new(hook));
+        self
+    }
+
+    /// Sets whether the tokens of a request are cancelled once its client disconnects, which is
+    /// the default. Turned off, the tokens are still handed to the request, and the hook is still
+    /// called.
+    pub fn cancel_on_disconnect(mut self, cancel: bool) -> Self {
+        self.skip_cancel = !cancel;
+        self
+    }
+}
+
+impl<S> Layer<S> for DisconnectLayer {
+    type Service = Disconnect<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        Disconnect {
+            inner,
+            hook: self.hook.clone(),
+            skip_cancel: self.skip_cancel,
+        }
+    }
+}
+
+impl fmt::Debug for DisconnectLayer {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("DisconnectLayer")
+            .field("hook", &self.hook.is_some())
+            .field("cancel_on_disconnect", &!self.skip_cancel)
+            .finish()
+    }
+}
+
+/// The service created by [`DisconnectLayer`].
+#[derive(Clone)]
+pub struct Disconnect<S> {
+    inner: S,
+    hook: Option<DisconnectHook>,
+    skip_cancel: bool,
+}
+
+impl<S, ReqBody, ResBody> Service<Request<ReqBody>> for Disconnect<S>
+where
+    S: Service<Request<ReqBody>, Response = Response<ResBody>>,
+    ResBody: http_body::Body,
+{
+    type Response = Response<DropBody<ResBody, Disconnected>>;
+    type Error = S::Error;
+    type Future = DisconnectFuture<S::Future>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, mut request: Request<ReqBody>) -> Self::Future {
+        let token = CancelToken::default();
+        request.extensions_mut().insert(token.clone());
+        #[cfg(feature = "tokio-util")]
+        request
+            .extensions_mut()
+            .insert(token.cancellation_token.clone());
+
+        let request_id = request
+            .headers()
+            .get("x-request-id")
+            .and_then(|id| id.to_str().ok())
+            .map(str::to_owned);
+        let labels = RequestLabels {
+            method: request.method().to_string(),
+            path: request.uri().path().to_owned(),
+            request_id,
+        };
+
+        DisconnectFuture {
+            guard: CancelGuard((!self.skip_cancel).then_some(token)),
+            labels: Some(labels),
+            hook: self.hook.clone(),
+            inner: self.inner.call(request),
+        }
+    }
+}
+
+impl<S: fmt::Debug> fmt::Debug for Disconnect<S> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Disconnect")
+            .field("inner", &self.inner)
+            .finish_non_exhaustive()
+    }
+}
+
+struct RequestLabels {
+    method: String,
+    path: String,
+    request_id: Option<String>,
+}
+
+/// Cancels the token if the response future is dropped before it produced a response. Holds no
+/// token if cancelling is turned off.
+struct CancelGuard(Option<CancelToken>);
+
+impl Drop for CancelGuard {
+    fn drop(&mut self) {
+        if let Some(token) = self.0.take() {
+            token.cancel();
+        }
+    }
+}
+
+/// The response future of [`Disconnect`].
+#[pin_project]
+pub struct DisconnectFuture<F> {
+    guard: CancelGuard,
+    labels: Option<RequestLabels>,
+    hook: Option<DisconnectHook>,
+    #[pin]
+    inner: F,
+}
+
+impl<F, B, E> Future for DisconnectFuture<F>
+where
+    F: Future<Output = Result<Response<B>, E>>,
+    B: http_body::Body,
+{
+    type Output = Result<Response<DropBody<B, Disconnected>>, E>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let this = self.project();
+
+        let result = match this.inner.poll(cx) {
+            Poll::Ready(result) => result,
+            Poll::Pending => return Poll::Pending,
+        };
+        // Only taken once the inner future is ready.
+        let Some(labels) = this.labels.take() else {
+            panic!("`DisconnectFuture` polled after completion");
+        };
+        let token = this.guard.0.take();
+
+        Poll::Ready(result.map(|response| {
+            let status = response.status();
+            let disconnected = Disconnected {
+                token,
+                hook: this.hook.take(),
+            };
+
+            response.map(|body| {
+                let body = DropBody::new(body, disconnected)
+                    .label("method", labels.method)
+                    .label("path", labels.path)
+                    .label("status", status.as_str());
+                match labels.request_id {
+                    Some(request_id) => body.label("request_id", request_id),
+                    None => body,
+                }
+            })
+        }))
+    }
+}
+
+impl<F> fmt::Debug for DisconnectFuture<F> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("DisconnectFuture").finish_non_exhaustive()
+    }
+}
+
+/// The dropper of the response bodies of [`Disconnect`], which reacts to early drops.
+pub struct Disconnected {
+    token: Option<CancelToken>,
+    hook: Option<DisconnectHook>,
+}
+
+impl ContextDropFn for Disconnected {
+    fn call(self, context: DropContext) {
+        if !context.reason().is_cancelled() {
+            return;
+        }
+
+        if let Some(token) = self.token {
+            token.cancel();
+        }
+        #[cfg(feature = "tracing")]
+        tracing::info!(
+            target: "drop_stream",
+            method = context.label("method"),
+            path = context.label("path"),
+            status = context.label("status"),
+            request_id = context.label("request_id"),
+            bytes = context.bytes(),
+            "response body dropped before it was sent in full"
+        );
+        if let Some(hook) = self.hook {
+            hook(&context);
+        }
+    }
+}
+
+impl fmt::Debug for Disconnected {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Disconnected").finish_non_exhaustive()
+    }
+}
+
+/// Cancelled once the client of a request handled behind a [`DisconnectLayer`] disconnects, and
+/// found in the request's extensions.
+///
+/// Awaiting it resolves once the request is cancelled. It is cheap to clone, so it can be handed
+/// to every task working on the request.
+#[derive(Debug, Clone, Default)]
+pub struct CancelToken {
+    signal: Arc<Signal>,
+    #[cfg(feature = "tokio-util")]
+    cancellation_token: tokio_util::sync::CancellationToken,
+}
+
+impl CancelToken {
+    fn cancel(&self) {
+        self.signal.fire();
+        #[cfg(feature = "tokio-util")]
+        self.cancellation_token.cancel();
+    }
+
+    /// Returns the `tokio_util` token that is cancelled along with this one, which is also found
+    /// in the request's extensions.
+    #[cfg(feature = "tokio-util")]
+    pub fn cancellation_token(&self) -> tokio_util::sync::CancellationToken {
+        self.cancellation_token.clone()
+    }
+
+    /// Returns true if the client disconnected.
+    pub fn is_cancelled(&self) -> bool {
+        self.signal.is_fired()
+    }
+}
+
+impl Future for CancelToken {
+    type Output = ();
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<()> {
+        self.signal.poll_fired(cx)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        convert::Infallible,
+        future::Future,
+        pin::Pin,
+        sync::{Arc, Mutex},
+        task::{Context, Poll},
+    };
+
+    use crate::{CancelToken, DisconnectLayer};
+    use bytes::Bytes;
+    use http::{Request, Response};
+    use http_body_util::{BodyExt, Full};
+    use tower_layer::Layer;
+    use tower_service::Service;
+
+    type ResponseFuture = Pin<Box<dyn Future<Output = Result<Response<Full<Bytes>>, Infallible>>>>;
+
+    /// Keeps the token of the last request, and responds only if `respond` is set.
+    #[derive(Clone, Default)]
+    struct Handler {
+        token: Arc<Mutex<Option<CancelToken>>>,
+        respond: bool,
+    }
+
+    impl Service<Request<()>> for Handler {
+        type Response = Response<Full<Bytes>>;
+        type Error = Infallible;
+        type Future = ResponseFuture;
+
+        fn poll_ready(&mut self, _: &mut Context<'_>) -> Poll<Result<(), Infallible>> {
+            Poll::Ready(Ok(()))
+        }
+
+        fn call(&mut self, request: Request<()>) -> Self::Future {
+            *self.token.lock().unwrap() = request.extensions().get::<CancelToken>().cloned();
+            if self.respond {
+                Box::pin(std::future::ready(Ok(Response::new(Full::from("hello")))))
+            } else {
+                Box::pin(std::future::pending())
+            }
+        }
+    }
+
+    fn request() -> Request<()> {
+        Request::post("/upload")
+            .header("x-request-id", "req-1")
+            .body(())
+            .unwrap()
+    }
+
+    #[test]
+    fn early_body_drop_cancels_and_reports_labels() {
+        let reported = Arc::new(Mutex::new(Vec::new()));
+        let reported_ref = reported.clone();
+        let handler = Handler {
+            respond: true,
+            ..Handler::default()
+        };
+
+        let mut service = DisconnectLayer::new()
+            .on_disconnect(move |context| {
+                let labels = ["method", "path", "status", "request_id"]
+                    .map(|key| context.label(key).unwrap().to_owned());
+                reported_ref.lock().unwrap().push(labels);
+            })
+            .layer(handler.clone());
+
+        let response = futures::executor::block_on(service.call(request())).unwrap();
+        let token = handler.token.lock().unwrap().clone().unwrap();
+        assert!(!token.is_cancelled());
+        drop(response);
+
+        assert!(token.is_cancelled());
+        assert_eq!(
+            *reported.lock().unwrap(),
+            [["POST", "/upload", "200", "req-1"].map(str::to_owned)]
+        );
+
+        // A body that is sent in full is not a disconnect.
+        let response = futures::executor::block_on(service.call(request())).unwrap();
+        futures::executor::block_on(response.into_body().collect()).unwrap();
+        assert!(!handler
+            .token
+            .lock()
+            .unwrap()
+            .as_ref()
+            .unwrap()
+            .is_cancelled());
+        assert_eq!(reported.lock().unwrap().len(), 1);
+    }
+
+    #[test]
+    fn dropping_response_future_cancels() {
+        let handler = Handler::default();
+        let mut service = DisconnectLayer::new().layer(handler.clone());
+
+        let future = service.call(request());
+        let token = handler.token.lock().unwrap().clone().unwrap();
+        assert!(!token.is_cancelled());
+
+        drop(future);
+        assert!(token.is_cancelled());
+    }
+
+    #[test]
+    fn cancelling_can_be_turned_off() {
+        let reported = Arc::new(Mutex::new(0));
+        let reported_ref = reported.clone();
+        let handler = Handler {
+            respond: true,
+            ..Handler::default()
+        };
+        let mut service = DisconnectLayer::new()
+            .cancel_on_disconnect(false)
+            .on_disconnect(move |_| *reported_ref.lock().unwrap() += 1)
+            .layer(handler.clone());
+
+        let response = futures::executor::block_on(service.call(request())).unwrap();
+        drop(response);
+        drop(service.call(request()));
+
+        assert!(!handler
+            .token
+            .lock()
+            .unwrap()
+            .as_ref()
+            .unwrap()
+            .is_cancelled());
+        assert_eq!(*reported.lock().unwrap(), 1);
+    }
+
+    #[cfg(feature = "tokio-util")]
+    #[test]
+    fn tokio_util_token_is_cancelled_along() {
+        let handler = Handler::default();
+        let mut service = DisconnectLayer::new().layer(handler.clone());
+
+        let future = service.call(request());
+        let token = handler.token.lock().unwrap().clone().unwrap();
+        let cancellation_token = token.cancellation_token();
+        assert!(!cancellation_token.is_cancelled());
+
+        drop(future);
+        assert!(cancellation_token.is_cancelled());
+    }
+}
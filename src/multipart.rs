@@ -0,0 +1,256 @@
+use futures_core::{Future, Stream};
+use pin_project::{pin_project, pinned_drop};
+use std::{
+    error::Error,
+    fmt,
+    pin::Pin,
+    task::{Context, Poll},
+};
+
+use crate::{report_drop_error, Spawn};
+
+/// Aborts a multipart upload, such as an S3 multipart upload, so the parts uploaded so far are
+/// discarded instead of being billed for. See [`AbortOnDrop`].
+///
+/// With the `aws-s3` feature this is implemented by [`S3MultipartUpload`].
+pub trait AbortMultipart {
+    type Error: Error + 'static;
+
+    fn abort_multipart(self) -> impl Future<Output = Result<(), Self::Error>> + Send + 'static;
+}
+
+/// A stream feeding the parts of a multipart upload that aborts the upload in the background if
+/// it is dropped before it finished.
+///
+/// A stream that is dropped early, because the client uploading the file went away or a part
+/// failed, leaves an upload behind whose parts are stored, and billed, until it is aborted. Once
+/// the inner stream has finished the upload is left alone, so it can be completed. Otherwise the
+/// abort is handed to the spawner on drop, and its error is reported through the crate-level
+/// handler, see [`set_drop_error_handler`](crate::set_drop_error_handler).
+///
+/// Example
+/// ```
+/// use std::{convert::Infallible, sync::{Arc, Mutex}};
+/// use drop_stream::{AbortMultipart, BoxFuture, DropStreamExt};
+///
+/// struct Upload(Arc<Mutex<bool>>);
+///
+/// impl AbortMultipart for Upload {
+///     type Error = Infallible;
+///
+///     fn abort_multipart(self) -> impl std::future::Future<Output = Result<(), Infallible>> + Send + 'static {
+///         // `client.abort_multipart_upload()...send().await`
+///         *self.0.lock().unwrap() = true;
+///         std::future::ready(Ok(()))
+///     }
+/// }
+///
+/// let tasks = Arc::new(Mutex::new(Vec::new()));
+/// let spawner = {
+///     let tasks = tasks.clone();
+///     move |future: BoxFuture| tasks.lock().unwrap().push(future)
+/// };
+///
+/// let aborted = Arc::new(Mutex::new(false));
+/// let parts = futures::stream::iter([vec![0u8; 1024], vec![0u8; 1024]])
+///     .abort_multipart_on_drop(Upload(aborted.clone()), spawner);
+///
+/// drop(parts);
+/// for task in tasks.lock().unwrap().drain(..) {
+///     futures::executor::block_on(task);
+/// }
+/// assert!(*aborted.lock().unwrap());
+/// ```
+#[pin_project(PinnedDrop)]
+pub struct AbortOnDrop<S, A, Sp>
+where
+    S: Stream,
+    A: AbortMultipart,
+    Sp: Spawn,
+{
+    #[pin]
+    stream: S,
+    // Taken once the inner stream has finished, or in the drop method.
+    upload: Option<A>,
+    spawner: Sp,
+}
+
+impl<S, A, Sp> AbortOnDrop<S, A, Sp>
+where
+    S: Stream,
+    A: AbortMultipart,
+    Sp: Spawn,
+{
+    pub fn new(stream: S, upload: A, spawner: Sp) -> Self {
+        Self {
+            stream,
+            upload: Some(upload),
+            spawner,
+        }
+    }
+
+    /// Leaves the upload alone once this is dropped, such as because it is completed by other
+    /// means, and returns it.
+    pub fn disarm(self: Pin<&mut Self>) -> Option<A> {
+        self.project().upload.take()
+    }
+
+    /// Returns true if the upload is aborted when this is dropped.
+    pub fn is_armed(&self) -> bool {
+        self.upload.is_some()
+    }
+}
+
+impl<S, A, Sp> Stream for AbortOnDrop<S, A, Sp>
+where
+    S: Stream,
+    A: AbortMultipart,
+    Sp: Spawn,
+{
+    type Item = S::Item;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let this = self.project();
+
+        let poll = this.stream.poll_next(cx);
+        if let Poll::Ready(None) = poll {
+            *this.upload = None;
+        }
+
+        poll
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.stream.size_hint()
+    }
+}
+
+#[pinned_drop]
+impl<S, A, Sp> PinnedDrop for AbortOnDrop<S, A, Sp>
+where
+    S: Stream,
+    A: AbortMultipart,
+    Sp: Spawn,
+{
+    fn drop(self: Pin<&mut Self>) {
+        let this = self.project();
+
+        if let Some(upload) = this.upload.take() {
+            let abort = upload.abort_multipart();
+            this.spawner.spawn(Box::pin(async move {
+                if let Err(error) = abort.await {
+                    report_drop_error(error);
+                }
+            }));
+        }
+    }
+}
+
+impl<S, A, Sp> fmt::Debug for AbortOnDrop<S, A, Sp>
+where
+    S: Stream,
+    A: AbortMultipart,
+    Sp: Spawn,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("AbortOnDrop")
+            .field("armed", &self.is_armed())
+            .finish_non_exhaustive()
+    }
+}
+
+/// An S3 multipart upload that is aborted through the SDK client. Created with the bucket, key
+/// and upload ID that `CreateMultipartUpload` returned.
+#[cfg(feature = "aws-s3")]
+#[derive(Debug, Clone)]
+pub struct S3MultipartUpload {
+    client: aws_sdk_s3::Client,
+    bucket: String,
+    key: String,
+    upload_id: String,
+}
+
+#[cfg(feature = "aws-s3")]
+impl S3MultipartUpload {
+    pub fn new(
+        client: aws_sdk_s3::Client,
+        bucket: impl Into<String>,
+        key: impl Into<String>,
+        upload_id: impl Into<String>,
+    ) -> Self {
+        Self {
+            client,
+            bucket: bucket.into(),
+            key: key.into(),
+            upload_id: upload_id.into(),
+        }
+    }
+}
+
+#[cfg(feature = "aws-s3")]
+impl AbortMultipart for S3MultipartUpload {
+    type Error = aws_sdk_s3::error::SdkError<
+        aws_sdk_s3::operation::abort_multipart_upload::AbortMultipartUploadError,
+    >;
+
+    fn abort_multipart(self) -> impl Future<Output = Result<(), Self::Error>> + Send + 'static {
+        let request = self
+            .client
+            .abort_multipart_upload()
+            .bucket(self.bucket)
+            .key(self.key)
+            .upload_id(self.upload_id);
+
+        async move { request.send().await.map(drop) }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        convert::Infallible,
+        future::{ready, Future},
+        sync::{Arc, Mutex},
+    };
+
+    use crate::{AbortMultipart, BoxFuture, DropStreamExt};
+    use futures::{executor::block_on_stream, stream::iter};
+
+    struct Upload(Arc<Mutex<usize>>);
+
+    impl AbortMultipart for Upload {
+        type Error = Infallible;
+
+        fn abort_multipart(self) -> impl Future<Output = Result<(), Infallible>> + Send + 'static {
+            *self.0.lock().unwrap() += 1;
+            ready(Ok(()))
+        }
+    }
+
+    #[test]
+    fn finished_upload_is_not_aborted() {
+        let aborts = Arc::new(Mutex::new(0));
+        let spawned = Arc::new(Mutex::new(0));
+
+        let spawned_ref = spawned.clone();
+        let parts = iter([1, 2])
+            .abort_multipart_on_drop(Upload(aborts.clone()), move |_: BoxFuture| {
+                *spawned_ref.lock().unwrap() += 1
+            });
+        assert_eq!(block_on_stream(parts).count(), 2);
+
+        assert_eq!((*aborts.lock().unwrap(), *spawned.lock().unwrap()), (0, 0));
+    }
+
+    #[test]
+    fn disarmed_upload_is_handed_back() {
+        let aborts = Arc::new(Mutex::new(0));
+
+        let parts = iter([1, 2]).abort_multipart_on_drop(Upload(aborts.clone()), |_: BoxFuture| {
+            panic!("nothing to abort")
+        });
+        let mut parts = Box::pin(parts);
+        assert!(parts.as_mut().disarm().is_some());
+        assert!(!parts.is_armed());
+    }
+}
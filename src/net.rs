@@ -0,0 +1,262 @@
+use async_net::{Shutdown, TcpStream};
+use futures_core::Stream;
+use futures_io::AsyncRead;
+use futures_lite::io::{AsyncBufReadExt, BufReader, Lines};
+use pin_project::pin_project;
+use std::{
+    fmt, io,
+    pin::Pin,
+    task::{ready, Context, Poll},
+};
+
+use crate::{ContextDropFn, ContextDropStream};
+
+/// The most bytes a [`Chunks`] stream yields at once.
+const CHUNK_SIZE: usize = 8 * 1024;
+
+/// Wraps the lines read from `socket` with a closure that is called with a
+/// [`DropContext`](crate::DropContext) once the stream is dropped, and shuts the socket down
+/// afterwards.
+///
+/// Shutting the socket down, rather than just dropping it, closes the connection even if clones
+/// of it are still held elsewhere, such as by the task writing to it, so the peer sees the end of
+/// the connection right away. The context is labeled with the address of the peer under
+/// `"peer"`.
+///
+/// Example
+/// ```no_run
+/// # fn main() -> std::io::Result<()> {
+/// # futures::executor::block_on(async {
+/// use async_net::TcpListener;
+/// use drop_stream::{tcp_lines, DropContext};
+/// use futures::StreamExt;
+///
+/// let listener = TcpListener::bind("127.0.0.1:7000").await?;
+/// let (socket, _) = listener.accept().await?;
+/// let mut lines = tcp_lines(socket, |context: DropContext| {
+///     println!("{:?} left after {} lines", context.label("peer"), context.items());
+/// });
+/// while let Some(line) = lines.next().await {
+///     if line? == "quit" {
+///         break;
+///     }
+/// }
+/// # Ok(())
+/// # })
+/// # }
+/// ```
+#[track_caller]
+pub fn tcp_lines<U: ContextDropFn>(
+    socket: TcpStream,
+    dropper: U,
+) -> ContextDropStream<ShutdownOnDrop<Lines<BufReader<TcpStream>>>, U> {
+    let lines = BufReader::new(socket.clone()).lines();
+
+    with_peer(socket.clone(), ShutdownOnDrop::new(socket, lines), dropper)
+}
+
+/// Wraps the bytes read from `socket` with a closure that is called with a
+/// [`DropContext`](crate::DropContext) once the stream is dropped, and shuts the socket down
+/// afterwards, as in [`tcp_lines`].
+///
+/// The stream yields the bytes in chunks of at most 8 KiB, as they arrive.
+#[track_caller]
+pub fn tcp_chunks<U: ContextDropFn>(
+    socket: TcpStream,
+    dropper: U,
+) -> ContextDropStream<ShutdownOnDrop<Chunks<TcpStream>>, U> {
+    let chunks = Chunks::new(socket.clone());
+
+    with_peer(socket.clone(), ShutdownOnDrop::new(socket, chunks), dropper)
+}
+
+#[track_caller]
+fn with_peer<S: Stream, U: ContextDropFn>(
+    socket: TcpStream,
+    stream: S,
+    dropper: U,
+) -> ContextDropStream<S, U> {
+    let stream = ContextDropStream::new(stream, dropper);
+    match socket.peer_addr() {
+        Ok(peer) => stream.label("peer", peer.to_string()),
+        Err(_) => stream,
+    }
+}
+
+/// A socket that [`ShutdownOnDrop`] can shut down.
+pub trait Socket {
+    /// Shuts down both halves of the connection.
+    fn shutdown(&self) -> io::Result<()>;
+}
+
+impl Socket for TcpStream {
+    fn shutdown(&self) -> io::Result<()> {
+        TcpStream::shutdown(self, Shutdown::Both)
+    }
+}
+
+#[cfg(unix)]
+impl Socket for async_net::unix::UnixStream {
+    fn shutdown(&self) -> io::Result<()> {
+        async_net::unix::UnixStream::shutdown(self, Shutdown::Both)
+    }
+}
+
+/// A stream read from a socket that shuts the socket down once it is dropped. Created by
+/// [`tcp_lines`] and [`tcp_chunks`], or [`unix_lines`](crate::unix_lines) and
+/// [`unix_chunks`](crate::unix_chunks) on Unix.
+#[pin_project]
+pub struct ShutdownOnDrop<S, T: Socket = TcpStream> {
+    // Declared before the stream so the socket is shut down before the inner stream is dropped.
+    guard: ShutdownGuard<T>,
+    #[pin]
+    stream: S,
+}
+
+impl<S, T: Socket> ShutdownOnDrop<S, T> {
+    pub fn new(socket: T, stream: S) -> Self {
+        Self {
+            guard: ShutdownGuard(socket),
+            stream,
+        }
+    }
+
+    /// Returns the socket that is shut down once the stream is dropped.
+    pub fn socket(&self) -> &T {
+        &self.guard.0
+    }
+}
+
+impl<S: Stream, T: Socket> Stream for ShutdownOnDrop<S, T> {
+    type Item = S::Item;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        self.project().stream.poll_next(cx)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.stream.size_hint()
+    }
+}
+
+impl<S, T: Socket + fmt::Debug> fmt::Debug for ShutdownOnDrop<S, T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ShutdownOnDrop")
+            .field("socket", &self.guard.0)
+            .finish_non_exhaustive()
+    }
+}
+
+struct ShutdownGuard<T: Socket>(T);
+
+impl<T: Socket> Drop for ShutdownGuard<T> {
+    fn drop(&mut self) {
+        // Fails if the peer already closed the connection, which is fine.
+        let _ = self.0.shutdown();
+    }
+}
+
+/// A stream of the bytes read from a reader, in chunks of at most 8 KiB. Created by
+/// [`tcp_chunks`] and [`unix_chunks`](crate::unix_chunks).
+#[pin_project]
+pub struct Chunks<R> {
+    #[pin]
+    reader: R,
+    buf: Box<[u8]>,
+}
+
+impl<R: AsyncRead> Chunks<R> {
+    pub fn new(reader: R) -> Self {
+        Self {
+            reader,
+            buf: vec![0; CHUNK_SIZE].into_boxed_slice(),
+        }
+    }
+}
+
+impl<R: AsyncRead> Stream for Chunks<R> {
+    type Item = io::Result<Vec<u8>>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let this = self.project();
+
+        match ready!(this.reader.poll_read(cx, this.buf)) {
+            Ok(0) => Poll::Ready(None),
+            Ok(read) => Poll::Ready(Some(Ok(this.buf[..read].to_vec()))),
+            Err(error) => Poll::Ready(Some(Err(error))),
+        }
+    }
+}
+
+impl<R> fmt::Debug for Chunks<R> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Chunks").finish_non_exhaustive()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{Arc, Mutex};
+
+    use crate::{tcp_chunks, tcp_lines, DropContext, DropReason};
+    use async_net::{TcpListener, TcpStream};
+    use futures::{executor::block_on, AsyncReadExt, AsyncWriteExt, StreamExt};
+
+    /// Returns both ends of a loopback connection, the accepted end first.
+    async fn connect() -> (TcpStream, TcpStream) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let client = TcpStream::connect(listener.local_addr().unwrap())
+            .await
+            .unwrap();
+        let (server, _) = listener.accept().await.unwrap();
+        (server, client)
+    }
+
+    #[test]
+    fn dropped_lines_shut_down_shared_socket() {
+        block_on(async {
+            let (server, mut client) = connect().await;
+            let context = Arc::new(Mutex::new(None::<DropContext>));
+
+            let context_ref = context.clone();
+            // A clone kept for writing, which keeps the socket open after a plain drop.
+            let _writer = server.clone();
+            let mut lines = tcp_lines(server, move |c: DropContext| {
+                *context_ref.lock().unwrap() = Some(c)
+            });
+
+            client.write_all(b"hello\nworld\n").await.unwrap();
+            assert_eq!(lines.next().await.unwrap().unwrap(), "hello");
+            drop(lines);
+
+            let mut rest = Vec::new();
+            client.read_to_end(&mut rest).await.unwrap();
+            assert!(rest.is_empty());
+
+            let context = context.lock().unwrap().take().unwrap();
+            assert_eq!(context.reason(), DropReason::Cancelled);
+            assert_eq!(context.items(), 1);
+            assert!(context.label("peer").is_some());
+        });
+    }
+
+    #[test]
+    fn chunks_complete_once_peer_closes() {
+        block_on(async {
+            let (server, mut client) = connect().await;
+            let reason = Arc::new(Mutex::new(None));
+
+            let reason_ref = reason.clone();
+            let chunks = tcp_chunks(server, move |c: DropContext| {
+                *reason_ref.lock().unwrap() = Some(c.reason())
+            });
+
+            client.write_all(b"abc").await.unwrap();
+            client.close().await.unwrap();
+            let bytes: Vec<u8> = chunks.map(Result::unwrap).concat().await;
+
+            assert_eq!(bytes, b"abc");
+            assert_eq!(*reason.lock().unwrap(), Some(DropReason::Completed));
+        });
+    }
+}
@@ -0,0 +1,249 @@
+use futures_core::Stream;
+use pin_project::pin_project;
+use std::{
+    pin::Pin,
+    task::{Context, Poll},
+};
+
+use crate::{context::Stats, DropContext};
+
+/// Reusable instrumentation for a stream, such as metrics, audit logging or debugging, driven by
+/// an [`Observed`] wrapper.
+///
+/// Every method does nothing by default, so an observer only implements the events it cares
+/// about. A pair of observers is itself an observer, calling the first before the second.
+///
+/// Example
+/// ```
+/// use futures::{executor::block_on_stream, stream};
+/// use drop_stream::{DropStreamExt, StreamObserver};
+///
+/// #[derive(Default)]
+/// struct ItemCounter(usize);
+///
+/// impl<T> StreamObserver<T> for &mut ItemCounter {
+///     fn on_item(&mut self, _item: &T) {
+///         self.0 += 1;
+///     }
+/// }
+///
+/// let mut counter = ItemCounter::default();
+/// let stream = stream::iter([1, 2, 3]).observe(&mut counter);
+///
+/// assert_eq!(block_on_stream(stream).count(), 3);
+/// assert_eq!(counter.0, 3);
+/// ```
+pub trait StreamObserver<T> {
+    /// Called every time the stream is polled, before the inner stream is.
+    fn on_poll(&mut self) {}
+
+    /// Called with every item the stream yields.
+    fn on_item(&mut self, _item: &T) {}
+
+    /// Called every time the inner stream returns `Pending`.
+    fn on_pending(&mut self) {}
+
+    /// Called once the inner stream finishes.
+    fn on_end(&mut self) {}
+
+    /// Called once the stream is dropped.
+    fn on_drop(&mut self, _context: &DropContext) {}
+}
+
+/// An observer that ignores every event.
+impl<T> StreamObserver<T> for () {}
+
+impl<T, A: StreamObserver<T>, B: StreamObserver<T>> StreamObserver<T> for (A, B) {
+    fn on_poll(&mut self) {
+        self.0.on_poll();
+        self.1.on_poll();
+    }
+
+    fn on_item(&mut self, item: &T) {
+        self.0.on_item(item);
+        self.1.on_item(item);
+    }
+
+    fn on_pending(&mut self) {
+        self.0.on_pending();
+        self.1.on_pending();
+    }
+
+    fn on_end(&mut self) {
+        self.0.on_end();
+        self.1.on_end();
+    }
+
+    fn on_drop(&mut self, context: &DropContext) {
+        self.0.on_drop(context);
+        self.1.on_drop(context);
+    }
+}
+
+/// Holds an observer along with the [`Stats`] for its [`DropContext`], and notifies it once the
+/// holder is dropped.
+struct ObserverGuard<T, O: StreamObserver<T>> {
+    stats: Stats,
+    observer: O,
+    _item: std::marker::PhantomData<fn(&T)>,
+}
+
+impl<T, O: StreamObserver<T>> Drop for ObserverGuard<T, O> {
+    fn drop(&mut self) {
+        self.observer.on_drop(&self.stats.context())
+    }
+}
+
+/// A stream that reports every event of its inner stream to a [`StreamObserver`].
+#[pin_project]
+pub struct Observed<S: Stream, O: StreamObserver<S::Item>> {
+    // Declared before the stream so the observer is notified before the inner stream is dropped.
+    guard: ObserverGuard<S::Item, O>,
+    #[pin]
+    stream: S,
+}
+
+impl<S: Stream, O: StreamObserver<S::Item>> Observed<S, O> {
+    #[track_caller]
+    pub fn new(stream: S, observer: O) -> Self {
+        Self {
+            guard: ObserverGuard {
+                stats: Stats::new(),
+                observer,
+                _item: std::marker::PhantomData,
+            },
+            stream,
+        }
+    }
+
+    /// Returns the observer.
+    pub fn observer(&self) -> &O {
+        &self.guard.observer
+    }
+
+    /// Returns the ID of the stream, reported as [`DropContext::id`].
+    pub fn id(&self) -> u64 {
+        self.guard.stats.id()
+    }
+
+    /// Names the stream in the context the observer is notified with, replacing any previous
+    /// name.
+    pub fn name(mut self, name: impl Into<String>) -> Self {
+        self.guard.stats.set_name(name.into());
+        self
+    }
+
+    /// Adds a label to the context the observer is notified with.
+    pub fn label(mut self, key: &'static str, value: impl Into<String>) -> Self {
+        self.guard.stats.add_label(key, value.into());
+        self
+    }
+}
+
+impl<S: Stream, O: StreamObserver<S::Item>> Stream for Observed<S, O> {
+    type Item = S::Item;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let this = self.project();
+        let guard = this.guard;
+
+        guard.observer.on_poll();
+
+        let poll = this.stream.poll_next(cx);
+        match &poll {
+            Poll::Ready(Some(item)) => {
+                guard.stats.record_item();
+                guard.observer.on_item(item);
+            }
+            Poll::Ready(None) => {
+                guard.stats.record_end();
+                guard.observer.on_end();
+            }
+            Poll::Pending => {
+                guard.stats.record_pending();
+                guard.observer.on_pending()
+            }
+        }
+
+        poll
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.stream.size_hint()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::task::Poll;
+
+    use crate::{DropContext, DropReason, DropStreamExt, StreamObserver};
+    use futures::{
+        stream::{iter, pending},
+        Stream, StreamExt,
+    };
+
+    #[derive(Default)]
+    struct Recorder {
+        events: Vec<&'static str>,
+        reason: Option<DropReason>,
+    }
+
+    impl<T> StreamObserver<T> for &mut Recorder {
+        fn on_poll(&mut self) {
+            self.events.push("poll");
+        }
+
+        fn on_item(&mut self, _item: &T) {
+            self.events.push("item");
+        }
+
+        fn on_pending(&mut self) {
+            self.events.push("pending");
+        }
+
+        fn on_end(&mut self) {
+            self.events.push("end");
+        }
+
+        fn on_drop(&mut self, context: &DropContext) {
+            self.events.push("drop");
+            self.reason = Some(context.reason());
+        }
+    }
+
+    #[test]
+    fn observer_sees_every_event() {
+        let mut recorder = Recorder::default();
+
+        {
+            let drop_stream = iter([1]).chain(pending()).observe(&mut recorder);
+
+            let mut drop_stream = Box::pin(drop_stream);
+
+            let waker = futures::task::noop_waker();
+            let mut context = futures::task::Context::from_waker(&waker);
+            assert_eq!(
+                drop_stream.as_mut().poll_next(&mut context),
+                Poll::Ready(Some(1))
+            );
+            assert_eq!(drop_stream.as_mut().poll_next(&mut context), Poll::Pending);
+        }
+
+        assert_eq!(recorder.events, ["poll", "item", "poll", "pending", "drop"]);
+        assert_eq!(recorder.reason, Some(DropReason::Cancelled));
+    }
+
+    #[test]
+    fn paired_observers_are_both_notified() {
+        let mut first = Recorder::default();
+        let mut second = Recorder::default();
+
+        let drop_stream = iter([1]).observe((&mut first, &mut second));
+        assert_eq!(futures::executor::block_on_stream(drop_stream).count(), 1);
+
+        assert_eq!(first.events, ["poll", "item", "poll", "end", "drop"]);
+        assert_eq!(first.events, second.events);
+        assert_eq!(second.reason, Some(DropReason::Completed));
+    }
+}
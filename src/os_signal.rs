@@ -0,0 +1,140 @@
+use async_signal::{Signal, Signals};
+use futures_core::Stream;
+use std::{
+    fmt, io,
+    pin::Pin,
+    task::{Context, Poll},
+};
+
+use crate::{ContextDropFn, ContextDropStream};
+
+/// Listens for `signals` and wraps the stream of the ones received with a closure that is called
+/// with a [`DropContext`](crate::DropContext) once it is dropped.
+///
+/// This is a shorthand for [`SignalStream::new`].
+///
+/// # Errors
+///
+/// Returns the error from registering any of the signals.
+#[track_caller]
+pub fn watch_signals<U: ContextDropFn>(
+    signals: impl IntoIterator<Item = Signal>,
+    dropper: U,
+) -> io::Result<ContextDropStream<SignalStream, U>> {
+    Ok(ContextDropStream::new(SignalStream::new(signals)?, dropper))
+}
+
+/// A stream of the signals received by the process that deregisters its handlers once it is
+/// dropped.
+///
+/// Plugins that listen for signals for as long as they are loaded would otherwise leave their
+/// handlers behind when they are unloaded. Tokio's signal streams can't be used for this, as tokio
+/// keeps its handlers registered for the rest of the process.
+///
+/// While a signal is registered, its default action, such as terminating the process on
+/// `SIGTERM`, doesn't happen, and deregistering the last handler doesn't bring it back: the
+/// handler `async-signal` installs stays in place, and restoring the disposition the signal had
+/// before would take unsafe code. A plugin listening for such signals should leave handling them
+/// to whoever loaded it once it is unloaded.
+///
+/// Example
+/// ```
+/// # fn main() -> std::io::Result<()> {
+/// # futures::executor::block_on(async {
+/// use async_signal::Signal;
+/// use drop_stream::{watch_signals, DropContext};
+/// use futures::StreamExt;
+///
+/// let mut reloads = watch_signals([Signal::Hup], |context: DropContext| {
+///     println!("stopped after {} reloads", context.items());
+/// })?;
+/// signal_hook::low_level::raise(Signal::Hup as i32)?;
+/// assert_eq!(reloads.next().await.unwrap()?, Signal::Hup);
+///
+/// // Unloading the plugin deregisters the handler.
+/// drop(reloads);
+/// # Ok(())
+/// # })
+/// # }
+/// ```
+pub struct SignalStream {
+    signals: Signals,
+    registered: Vec<Signal>,
+}
+
+impl SignalStream {
+    /// Registers handlers for `signals`.
+    ///
+    /// # Errors
+    ///
+    /// Returns the error from registering any of the signals.
+    pub fn new(signals: impl IntoIterator<Item = Signal>) -> io::Result<Self> {
+        let mut registered: Vec<Signal> = signals.into_iter().collect();
+        registered.sort();
+        registered.dedup();
+
+        Ok(Self {
+            signals: Signals::new(&registered)?,
+            registered,
+        })
+    }
+
+    /// Returns the signals the stream listens for, in ascending order.
+    pub fn signals(&self) -> &[Signal] {
+        &self.registered
+    }
+}
+
+impl Stream for SignalStream {
+    type Item = io::Result<Signal>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        Pin::new(&mut self.signals).poll_next(cx)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.signals.size_hint()
+    }
+}
+
+impl fmt::Debug for SignalStream {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("SignalStream")
+            .field("signals", &self.registered)
+            .finish()
+    }
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use std::sync::{Arc, Mutex};
+
+    use crate::{watch_signals, DropContext, DropReason, SignalStream};
+    use async_signal::Signal;
+    use futures::{executor::block_on, StreamExt};
+
+    #[test]
+    fn received_signals_are_counted_until_dropped() {
+        let context = Arc::new(Mutex::new(None::<DropContext>));
+
+        let context_ref = context.clone();
+        let mut signals = watch_signals([Signal::Usr1], move |c| {
+            *context_ref.lock().unwrap() = Some(c)
+        })
+        .unwrap();
+
+        signal_hook::low_level::raise(Signal::Usr1 as i32).unwrap();
+        assert_eq!(block_on(signals.next()).unwrap().unwrap(), Signal::Usr1);
+        drop(signals);
+
+        let context = context.lock().unwrap().take().unwrap();
+        assert_eq!(context.reason(), DropReason::Cancelled);
+        assert_eq!(context.items(), 1);
+    }
+
+    #[test]
+    fn signals_are_deduplicated() {
+        let signals = SignalStream::new([Signal::Winch, Signal::Winch]).unwrap();
+        assert_eq!(signals.signals(), [Signal::Winch]);
+    }
+}
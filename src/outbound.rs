@@ -0,0 +1,173 @@
+use futures_core::{Future, Stream};
+use pin_project::pin_project;
+use std::{
+    fmt,
+    pin::Pin,
+    sync::{Arc, Mutex},
+    task::{Context, Poll},
+};
+
+use crate::{dropper::ReasonDropper, signal::Signal, DropReason};
+
+#[derive(Default)]
+struct Shared {
+    signal: Signal,
+    reason: Mutex<Option<DropReason>>,
+}
+
+impl Shared {
+    fn reason(&self) -> Option<DropReason> {
+        *self.reason.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+// Boxed so that the stream type can be named, e.g. in the signature of a tonic call.
+type Notify = Box<dyn FnOnce(DropReason) + Send>;
+
+fn notify(shared: Arc<Shared>) -> Notify {
+    Box::new(move |reason| {
+        *shared.reason.lock().unwrap_or_else(|e| e.into_inner()) = Some(reason);
+        shared.signal.fire();
+    })
+}
+
+/// Wraps an outbound message stream handed to a transport, such as the request stream of a tonic
+/// client-streaming or bidirectional call, so the producer learns when the transport lets go of
+/// it through the returned [`OutboundHandle`].
+///
+/// The transport drops the stream once it sent every message, or early when the server cancelled
+/// the call or the connection died. The handle tells the two apart, so producers can stop
+/// generating messages the moment nobody will send them.
+///
+/// Example
+/// ```
+/// use drop_stream::{watch_outbound, DropReason};
+///
+/// let (requests, handle) = watch_outbound(futures::stream::repeat("ping"));
+/// // `client.stream_pings(requests).await` drops the stream once the call ends...
+/// drop(requests);
+///
+/// assert_eq!(futures::executor::block_on(handle), DropReason::Cancelled);
+/// ```
+pub fn watch_outbound<S: Stream>(stream: S) -> (Outbound<S>, OutboundHandle) {
+    let shared = Arc::new(Shared::default());
+
+    let stream = Outbound {
+        dropper: ReasonDropper::new(notify(shared.clone())),
+        stream,
+    };
+
+    (stream, OutboundHandle { shared })
+}
+
+/// An outbound stream watched by an [`OutboundHandle`]. Created by [`watch_outbound`].
+#[pin_project]
+pub struct Outbound<S> {
+    // Declared before the stream so the handle is notified before the inner stream is dropped.
+    dropper: ReasonDropper<Notify>,
+    #[pin]
+    stream: S,
+}
+
+impl<S: Stream> Stream for Outbound<S> {
+    type Item = S::Item;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let this = self.project();
+
+        let poll = this.stream.poll_next(cx);
+        if let Poll::Ready(None) = poll {
+            this.dropper.reason = DropReason::Completed;
+        }
+
+        poll
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.stream.size_hint()
+    }
+}
+
+impl<S> fmt::Debug for Outbound<S> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Outbound").finish_non_exhaustive()
+    }
+}
+
+/// Resolves with the reason once the transport dropped the [`Outbound`] stream it was created
+/// with. It can be cloned, to be checked from every task producing messages.
+#[derive(Clone)]
+pub struct OutboundHandle {
+    shared: Arc<Shared>,
+}
+
+impl OutboundHandle {
+    /// Returns why the stream was dropped, or `None` if it is still alive.
+    pub fn reason(&self) -> Option<DropReason> {
+        self.shared.reason()
+    }
+
+    /// Returns true if the stream was dropped before it finished, meaning messages produced from
+    /// now on will never be sent.
+    pub fn is_cancelled(&self) -> bool {
+        self.reason().is_some_and(DropReason::is_cancelled)
+    }
+}
+
+impl Future for OutboundHandle {
+    type Output = DropReason;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<DropReason> {
+        match self.shared.signal.poll_fired(cx) {
+            // Set before the signal is fired.
+            Poll::Ready(()) => Poll::Ready(self.reason().unwrap_or(DropReason::Cancelled)),
+            Poll::Pending => Poll::Pending,
+        }
+    }
+}
+
+impl fmt::Debug for OutboundHandle {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("OutboundHandle")
+            .field("reason", &self.reason())
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::task::Poll;
+
+    use crate::{watch_outbound, DropReason};
+    use futures::{executor::block_on_stream, future::Future, stream::iter, StreamExt};
+
+    #[test]
+    fn sent_in_full_is_completed() {
+        let (requests, handle) = watch_outbound(iter([1, 2]));
+        assert_eq!(handle.reason(), None);
+
+        assert_eq!(block_on_stream(requests).count(), 2);
+        assert_eq!(handle.reason(), Some(DropReason::Completed));
+        assert!(!handle.is_cancelled());
+    }
+
+    #[test]
+    fn transport_dropping_early_wakes_producer() {
+        let (mut requests, handle) = watch_outbound(iter([1, 2]));
+        let mut waiting = Box::pin(handle.clone());
+
+        let (waker, count) = futures_test::task::new_count_waker();
+        let mut cx = futures::task::Context::from_waker(&waker);
+        assert_eq!(waiting.as_mut().poll(&mut cx), Poll::Pending);
+
+        assert_eq!(requests.poll_next_unpin(&mut cx), Poll::Ready(Some(1)));
+        drop(requests);
+
+        assert_eq!(count, 1);
+        assert_eq!(
+            waiting.as_mut().poll(&mut cx),
+            Poll::Ready(DropReason::Cancelled)
+        );
+        assert!(handle.is_cancelled());
+    }
+}
@@ -0,0 +1,150 @@
+use std::{
+    any::Any,
+    cell::RefCell,
+    fmt,
+    panic::{catch_unwind, AssertUnwindSafe},
+};
+
+use crate::report_drop_error;
+
+thread_local! {
+    static DEFERRED: RefCell<Option<Box<dyn Any + Send>>> = const { RefCell::new(None) };
+}
+
+/// What a drop hook panicking while the thread is already unwinding from another panic leads to,
+/// set with [`DropStreamBuilder::panic_policy`](crate::DropStreamBuilder::panic_policy).
+///
+/// A panic in a drop hook while the thread is not unwinding always propagates as usual.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum PanicPolicy {
+    /// Lets the panic propagate, which aborts the process, as for any double panic.
+    #[default]
+    Abort,
+    /// Catches the panic and reports it as a [`DropPanic`] to the handler set with
+    /// [`set_drop_error_handler`](crate::set_drop_error_handler).
+    Report,
+    /// Catches the panic and keeps its payload for the thread, to be taken with
+    /// [`take_deferred_panic`] and re-raised with `std::panic::resume_unwind` once the first
+    /// panic has been caught. Only the first deferred payload is kept.
+    Defer,
+}
+
+/// A panic of a drop hook that was caught while the thread was unwinding, reported under
+/// [`PanicPolicy::Report`].
+#[derive(Debug)]
+pub struct DropPanic {
+    message: String,
+}
+
+impl DropPanic {
+    fn new(payload: &(dyn Any + Send)) -> Self {
+        let message = match (
+            payload.downcast_ref::<&str>(),
+            payload.downcast_ref::<String>(),
+        ) {
+            (Some(message), _) => message.to_string(),
+            (_, Some(message)) => message.clone(),
+            _ => String::from("Box<dyn Any>"),
+        };
+
+        Self { message }
+    }
+
+    /// Returns the message the hook panicked with.
+    pub fn message(&self) -> &str {
+        &self.message
+    }
+}
+
+impl fmt::Display for DropPanic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "drop hook panicked while unwinding: {}", self.message)
+    }
+}
+
+impl std::error::Error for DropPanic {}
+
+/// Takes the payload of the panic a drop hook deferred on this thread under
+/// [`PanicPolicy::Defer`], if any.
+///
+/// Example
+/// ```
+/// use std::panic::catch_unwind;
+/// use drop_stream::{take_deferred_panic, DropStream, PanicPolicy};
+///
+/// let outer = catch_unwind(|| {
+///     let _stream = DropStream::builder(futures::stream::repeat(true))
+///         .on_drop(|| panic!("hook failed"))
+///         .panic_policy(PanicPolicy::Defer)
+///         .build();
+///     panic!("request failed");
+/// });
+///
+/// assert!(outer.is_err());
+/// let deferred = take_deferred_panic().unwrap();
+/// assert_eq!(deferred.downcast_ref::<&str>(), Some(&"hook failed"));
+/// // The hook's panic can now be re-raised with `resume_unwind(deferred)`.
+/// ```
+pub fn take_deferred_panic() -> Option<Box<dyn Any + Send>> {
+    DEFERRED.with(|deferred| deferred.borrow_mut().take())
+}
+
+/// Calls `hook`, applying `policy` to a panic if the thread is already unwinding.
+pub(crate) fn run_hook(policy: PanicPolicy, hook: impl FnOnce()) {
+    if policy == PanicPolicy::Abort || !std::thread::panicking() {
+        return hook();
+    }
+
+    let Err(payload) = catch_unwind(AssertUnwindSafe(hook)) else {
+        return;
+    };
+    match policy {
+        PanicPolicy::Report => report_drop_error(DropPanic::new(&*payload)),
+        _ => DEFERRED.with(|deferred| {
+            deferred.borrow_mut().get_or_insert(payload);
+        }),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::panic::catch_unwind;
+
+    use crate::{take_deferred_panic, DropStream, PanicPolicy};
+    use futures::stream::repeat;
+
+    #[test]
+    fn deferred_panic_keeps_the_first_payload() {
+        let outer = catch_unwind(|| {
+            let _first = DropStream::builder(repeat(1))
+                .on_drop(|| panic!("second"))
+                .panic_policy(PanicPolicy::Defer)
+                .build();
+            let _second = DropStream::builder(repeat(1))
+                .on_cancel(|| panic!("first"))
+                .panic_policy(PanicPolicy::Defer)
+                .build();
+            panic!("outer");
+        });
+
+        assert_eq!(outer.unwrap_err().downcast_ref::<&str>(), Some(&"outer"));
+        let deferred = take_deferred_panic().unwrap();
+        assert_eq!(deferred.downcast_ref::<&str>(), Some(&"first"));
+        assert!(take_deferred_panic().is_none());
+    }
+
+    #[test]
+    fn policy_does_not_apply_outside_of_unwinding() {
+        let result = catch_unwind(|| {
+            drop(
+                DropStream::builder(repeat(1))
+                    .on_drop(|| panic!("hook"))
+                    .panic_policy(PanicPolicy::Defer)
+                    .build(),
+            )
+        });
+
+        assert_eq!(result.unwrap_err().downcast_ref::<&str>(), Some(&"hook"));
+        assert!(take_deferred_panic().is_none());
+    }
+}
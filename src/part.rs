@@ -0,0 +1,140 @@
+use futures_core::{Stream, TryStream};
+use pin_project::pin_project;
+use std::{
+    pin::Pin,
+    task::{Context, Poll},
+};
+
+use crate::context::{ContextDropFn, ContextDropper};
+
+/// A multipart/form-data part, such as a `multer` or axum multipart field, that calls a closure
+/// with a [`DropContext`](crate::DropContext) once it is dropped, named after the part's field.
+///
+/// Every chunk counts as an item and its length as bytes, so the closure knows how much of the
+/// part was read. Use [`on_part_abandoned`](crate::DropTryStreamExt::on_part_abandoned) to only
+/// run the closure if the part was abandoned mid-read, to clean up whatever was buffered or
+/// written to disk for it, while a part that was read in full is left to its handler.
+///
+/// Example
+/// ```
+/// use drop_stream::DropTryStreamExt;
+///
+/// let chunks = futures::stream::iter([Ok::<_, std::io::Error>(b"GIF89a".to_vec()), Ok(vec![0; 64])]);
+///
+/// let mut removed = None;
+/// let removed_ref = &mut removed;
+/// let part = chunks.on_part_abandoned("avatar", move |context| {
+///     // Remove the partially written upload...
+///     *removed_ref = Some((context.name().unwrap().to_owned(), context.bytes()));
+/// });
+///
+/// let mut part = futures::executor::block_on_stream(part);
+/// assert!(part.next().is_some());
+/// drop(part);
+/// assert_eq!(removed, Some(("avatar".to_owned(), 6)));
+/// ```
+#[pin_project]
+pub struct PartStream<S, U: ContextDropFn> {
+    // Declared before the stream so the closure runs before the inner stream is dropped.
+    dropper: ContextDropper<U>,
+    #[pin]
+    stream: S,
+}
+
+impl<S, U> PartStream<S, U>
+where
+    S: TryStream,
+    S::Ok: AsRef<[u8]>,
+    U: ContextDropFn,
+{
+    #[track_caller]
+    pub fn new(stream: S, field_name: impl Into<String>, dropper: U) -> Self {
+        let mut dropper = ContextDropper::new(dropper);
+        dropper.stats.set_name(field_name.into());
+
+        Self { dropper, stream }
+    }
+
+    /// Returns the number of bytes read from the part so far.
+    pub fn bytes(&self) -> u64 {
+        self.dropper.stats.bytes()
+    }
+
+    /// Adds a label to the part's context, such as its file name.
+    pub fn label(mut self, key: &'static str, value: impl Into<String>) -> Self {
+        self.dropper.stats.add_label(key, value.into());
+        self
+    }
+}
+
+impl<S, U> Stream for PartStream<S, U>
+where
+    S: TryStream,
+    S::Ok: AsRef<[u8]>,
+    U: ContextDropFn,
+{
+    type Item = Result<S::Ok, S::Error>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let this = self.project();
+
+        let poll = this.stream.try_poll_next(cx);
+        match &poll {
+            Poll::Ready(Some(Ok(chunk))) => {
+                this.dropper.stats.record_item();
+                this.dropper.stats.record_bytes(chunk.as_ref().len());
+            }
+            Poll::Ready(Some(Err(_))) => this.dropper.stats.record_error(),
+            Poll::Ready(None) => this.dropper.stats.record_end(),
+            Poll::Pending => this.dropper.stats.record_pending(),
+        }
+
+        poll
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.stream.size_hint()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{DropContext, DropTryStreamExt};
+    use futures::{executor::block_on_stream, stream::iter};
+
+    #[test]
+    fn part_read_in_full_is_not_cleaned_up() {
+        let mut cleaned_up = false;
+
+        {
+            let cleaned_up_ref = &mut cleaned_up;
+            let chunks = iter([Ok::<_, ()>("ab"), Ok("cd")]);
+            let part = chunks.on_part_abandoned("file", move |_| *cleaned_up_ref = true);
+            assert_eq!(block_on_stream(part).count(), 2);
+        }
+
+        assert!(!cleaned_up);
+    }
+
+    #[test]
+    fn abandoned_part_reports_field_and_bytes() {
+        let mut context = None::<DropContext>;
+
+        {
+            let context_ref = &mut context;
+            let chunks = iter([Ok::<_, ()>("ab"), Err(()), Ok("cd")]);
+            let part = chunks
+                .on_part_abandoned("upload", move |c| *context_ref = Some(c))
+                .label("filename", "report.pdf");
+
+            let mut part = block_on_stream(part);
+            assert_eq!(part.next(), Some(Ok("ab")));
+            assert_eq!(part.next(), Some(Err(())));
+        }
+
+        let context = context.unwrap();
+        assert_eq!(context.name(), Some("upload"));
+        assert_eq!(context.label("filename"), Some("report.pdf"));
+        assert_eq!((context.bytes(), context.errors()), (2, 1));
+    }
+}
@@ -0,0 +1,178 @@
+use futures_core::{Future, Stream};
+use pin_project::pin_project;
+use std::{
+    ffi::{OsStr, OsString},
+    fmt, io,
+    pin::Pin,
+    task::{Context, Poll},
+};
+use tokio::net::windows::named_pipe::{NamedPipeServer, ServerOptions};
+
+use crate::context::{ContextDropFn, ContextDropper};
+
+type Accept = Pin<
+    Box<dyn Future<Output = (io::Result<NamedPipeServer>, io::Result<NamedPipeServer>)> + Send>,
+>;
+
+/// The connections accepted on a Windows named pipe, with a closure that is called with a
+/// [`DropContext`](crate::DropContext) once the stream is dropped.
+///
+/// This is the named-pipe counterpart of [`UnixIncoming`](crate::UnixIncoming). Every connected
+/// client counts as an item, and every failed accept as an error. The next instance of the pipe is
+/// created as soon as a client connects, so clients never find the pipe missing between accepts,
+/// and dropping the stream closes the instance still waiting for a client, which frees the pipe
+/// name once the accepted connections are closed too. There's no file to remove, unlike for a
+/// Unix domain socket. The stream never ends, so the context always has
+/// [`DropReason::Cancelled`](crate::DropReason::Cancelled).
+///
+/// The accepted connections use tokio's I/O traits, so to read from them wrap them in the stream
+/// of your codec and then in a [`ContextDropStream`](crate::ContextDropStream), as
+/// [`unix_lines`](crate::unix_lines) does for Unix domain sockets.
+///
+/// Only available on Windows, and must be created within a tokio runtime.
+///
+/// Example
+/// ```no_run
+/// # #[tokio::main(flavor = "current_thread")]
+/// # async fn main() -> std::io::Result<()> {
+/// use drop_stream::{DropContext, PipeIncoming};
+/// use futures::StreamExt;
+///
+/// let mut incoming = PipeIncoming::bind(r"\\.\pipe\drop-stream", |context: DropContext| {
+///     println!("accepted {} clients", context.items());
+/// })?;
+/// while let Some(client) = incoming.next().await {
+///     tokio::spawn(serve(client?));
+/// }
+/// # Ok(())
+/// # }
+/// # async fn serve(_: tokio::net::windows::named_pipe::NamedPipeServer) {}
+/// ```
+#[pin_project]
+pub struct PipeIncoming<U: ContextDropFn> {
+    // Declared before the pending instance so the closure runs before it is closed.
+    dropper: ContextDropper<U>,
+    // Owns the instance waiting for a client, or nothing if creating it failed.
+    accept: Option<Accept>,
+    name: OsString,
+}
+
+impl<U: ContextDropFn> PipeIncoming<U> {
+    /// Creates the first instance of the pipe called `name`, such as `\\.\pipe\name`.
+    ///
+    /// # Errors
+    ///
+    /// Returns the error from creating the pipe, such as if another process already owns the name.
+    #[track_caller]
+    pub fn bind(name: impl AsRef<OsStr>, dropper: U) -> io::Result<Self> {
+        let name = name.as_ref().to_os_string();
+        let server = ServerOptions::new()
+            .first_pipe_instance(true)
+            .create(&name)?;
+
+        Ok(Self {
+            dropper: ContextDropper::new(dropper),
+            accept: Some(accept(server, name.clone())),
+            name,
+        })
+    }
+
+    /// Returns the name of the pipe.
+    pub fn pipe_name(&self) -> &OsStr {
+        &self.name
+    }
+
+    /// Returns the ID of the wrapper, see [`ContextDropStream::id`](crate::ContextDropStream::id).
+    pub fn id(&self) -> u64 {
+        self.dropper.stats.id()
+    }
+
+    /// Names the wrapper in its context, replacing any previous name.
+    pub fn name(mut self, name: impl Into<String>) -> Self {
+        self.dropper.stats.set_name(name.into());
+        self
+    }
+
+    /// Adds a label to the wrapper's context.
+    pub fn label(mut self, key: &'static str, value: impl Into<String>) -> Self {
+        self.dropper.stats.add_label(key, value.into());
+        self
+    }
+}
+
+/// Waits for a client on `server`, then creates the instance for the next one.
+fn accept(server: NamedPipeServer, name: OsString) -> Accept {
+    Box::pin(async move {
+        let connected = server.connect().await.map(|()| server);
+        (connected, ServerOptions::new().create(&name))
+    })
+}
+
+impl<U: ContextDropFn> Stream for PipeIncoming<U> {
+    type Item = io::Result<NamedPipeServer>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let this = self.project();
+
+        let pending = match this.accept {
+            Some(pending) => pending,
+            None => match ServerOptions::new().create(&*this.name) {
+                Ok(server) => this.accept.insert(accept(server, this.name.clone())),
+                Err(error) => {
+                    this.dropper.stats.record_error();
+                    return Poll::Ready(Some(Err(error)));
+                }
+            },
+        };
+
+        let Poll::Ready((connected, next)) = pending.as_mut().poll(cx) else {
+            this.dropper.stats.record_pending();
+            return Poll::Pending;
+        };
+        // A failed instance is created again on the next poll.
+        *this.accept = next.ok().map(|server| accept(server, this.name.clone()));
+
+        match &connected {
+            Ok(_) => this.dropper.stats.record_item(),
+            Err(_) => this.dropper.stats.record_error(),
+        }
+        Poll::Ready(Some(connected))
+    }
+}
+
+impl<U: ContextDropFn> fmt::Debug for PipeIncoming<U> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("PipeIncoming")
+            .field("name", &self.name)
+            .finish_non_exhaustive()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{Arc, Mutex};
+
+    use crate::{DropContext, DropReason, PipeIncoming};
+    use futures::StreamExt;
+    use tokio::net::windows::named_pipe::ClientOptions;
+
+    #[tokio::test]
+    async fn accepted_clients_are_counted_until_dropped() {
+        let name = format!(r"\\.\pipe\drop-stream-test-{}", std::process::id());
+        let context = Arc::new(Mutex::new(None::<DropContext>));
+
+        let context_ref = context.clone();
+        let mut incoming =
+            PipeIncoming::bind(&name, move |c| *context_ref.lock().unwrap() = Some(c)).unwrap();
+
+        let _first = ClientOptions::new().open(&name).unwrap();
+        let _server = incoming.next().await.unwrap().unwrap();
+        // The next instance already exists, so a second client can connect right away.
+        let _second = ClientOptions::new().open(&name).unwrap();
+        drop(incoming);
+
+        let context = context.lock().unwrap().take().unwrap();
+        assert_eq!(context.reason(), DropReason::Cancelled);
+        assert_eq!(context.items(), 1);
+    }
+}
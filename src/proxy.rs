@@ -0,0 +1,130 @@
+use http_body::Body;
+use std::fmt;
+
+use crate::{AbortHandle, ContextDropFn, DropBody, DropContext};
+
+/// A handle to upstream work, such as an in-flight request or call, that can be cancelled. See
+/// [`link_upstream`].
+///
+/// Implemented for closures, for [`AbortHandle`], and with the `tokio` feature for the join and
+/// abort handles of the task driving the upstream request.
+pub trait CancelUpstream {
+    fn cancel_upstream(self);
+}
+
+impl<F: FnOnce()> CancelUpstream for F {
+    fn cancel_upstream(self) {
+        self()
+    }
+}
+
+impl CancelUpstream for AbortHandle {
+    fn cancel_upstream(self) {
+        self.abort();
+    }
+}
+
+#[cfg(feature = "tokio")]
+impl<T> CancelUpstream for tokio::task::JoinHandle<T> {
+    fn cancel_upstream(self) {
+        self.abort();
+    }
+}
+
+#[cfg(feature = "tokio")]
+impl CancelUpstream for tokio::task::AbortHandle {
+    fn cancel_upstream(self) {
+        self.abort();
+    }
+}
+
+/// Links the response body sent downstream by a reverse proxy to the upstream request it is
+/// relayed from, cancelling `upstream` if the body is dropped before it was sent in full.
+///
+/// A client that goes away drops the downstream body, and with it the upstream response body,
+/// but not the task or call producing it, which would otherwise keep the upstream busy until it
+/// finishes on its own. A body that was sent in full leaves `upstream` untouched. The body is a
+/// [`DropBody`], so it can be named and labelled as usual.
+///
+/// Example
+/// ```
+/// use std::sync::atomic::{AtomicBool, Ordering};
+/// use drop_stream::link_upstream;
+///
+/// let cancelled = AtomicBool::new(false);
+/// // The body of the response from `client.request(upstream_request).await`...
+/// let upstream_body = http_body_util::Full::new(bytes::Bytes::from("hello"));
+/// let body = link_upstream(upstream_body, || cancelled.store(true, Ordering::SeqCst));
+///
+/// // The client disconnects before the body was sent...
+/// drop(body);
+/// assert!(cancelled.load(Ordering::SeqCst));
+/// ```
+#[track_caller]
+pub fn link_upstream<B: Body, H: CancelUpstream>(
+    body: B,
+    upstream: H,
+) -> DropBody<B, UpstreamLink<H>> {
+    DropBody::new(body, UpstreamLink { upstream })
+}
+
+/// The dropper of the bodies returned by [`link_upstream`], which cancels the upstream on early
+/// drops.
+pub struct UpstreamLink<H: CancelUpstream> {
+    upstream: H,
+}
+
+impl<H: CancelUpstream> ContextDropFn for UpstreamLink<H> {
+    fn call(self, context: DropContext) {
+        if context.reason().is_cancelled() {
+            self.upstream.cancel_upstream();
+        }
+    }
+}
+
+impl<H: CancelUpstream> fmt::Debug for UpstreamLink<H> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("UpstreamLink").finish_non_exhaustive()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicBool, Ordering};
+
+    use crate::{abortable_on_drop, link_upstream};
+    use bytes::Bytes;
+    use http_body_util::{BodyExt, Full};
+
+    #[test]
+    fn body_sent_in_full_leaves_upstream_alone() {
+        let cancelled = AtomicBool::new(false);
+
+        let body = link_upstream(Full::new(Bytes::from("hello")), || {
+            cancelled.store(true, Ordering::SeqCst)
+        });
+        futures::executor::block_on(body.collect()).unwrap();
+
+        assert!(!cancelled.load(Ordering::SeqCst));
+    }
+
+    #[test]
+    fn early_drop_aborts_upstream() {
+        let (upstream, handle) = abortable_on_drop(futures::stream::repeat(true), |_| {});
+
+        drop(link_upstream(Full::new(Bytes::from("hello")), handle));
+
+        assert_eq!(futures::executor::block_on_stream(upstream).count(), 0);
+    }
+
+    #[cfg(feature = "tokio")]
+    #[tokio::test]
+    async fn early_drop_aborts_upstream_task() {
+        let task = tokio::spawn(futures::future::pending::<()>());
+        let abort = task.abort_handle();
+
+        drop(link_upstream(Full::new(Bytes::from("hello")), abort));
+
+        assert!(task.await.unwrap_err().is_cancelled());
+    }
+}
@@ -0,0 +1,231 @@
+use futures_core::Future;
+use std::{
+    fmt,
+    pin::Pin,
+    sync::{Arc, Mutex, MutexGuard, Weak},
+    task::{Context, Poll, Waker},
+};
+
+type Callback = Box<dyn FnOnce() + Send>;
+
+#[derive(Default)]
+struct State {
+    callbacks: Vec<Callback>,
+    waker: Option<Waker>,
+}
+
+#[derive(Default)]
+struct Shared {
+    state: Mutex<State>,
+}
+
+impl Shared {
+    fn lock(&self) -> MutexGuard<'_, State> {
+        self.state.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+impl Drop for Shared {
+    fn drop(&mut self) {
+        let state = self.state.get_mut().unwrap_or_else(|e| e.into_inner());
+        // Nothing can flush the queue anymore, so whatever is left runs now.
+        for callback in std::mem::take(&mut state.callbacks) {
+            callback();
+        }
+
+        if let Some(waker) = state.waker.take() {
+            waker.wake();
+        }
+    }
+}
+
+/// A queue that drop callbacks are moved into instead of being run inline, to be run later by an
+/// explicit [`flush`](DropQueue::flush) or a background [`Flusher`].
+///
+/// Useful when many streams can be dropped at once, such as closing every connection on shutdown,
+/// and the callbacks shouldn't run on that path. Streams are added with
+/// [`on_drop_deferred`](crate::DropStreamExt::on_drop_deferred). Callbacks still queued when the
+/// last handle to the queue is dropped run at that point.
+///
+/// Example
+/// ```
+/// use std::sync::{atomic::{AtomicUsize, Ordering}, Arc};
+/// use drop_stream::{DropQueue, DropStreamExt};
+///
+/// let queue = DropQueue::new();
+/// let closed = Arc::new(AtomicUsize::new(0));
+///
+/// let streams: Vec<_> = (0..3)
+///     .map(|_| {
+///         let closed = closed.clone();
+///         futures::stream::repeat(true).on_drop_deferred(&queue, move || {
+///             closed.fetch_add(1, Ordering::SeqCst);
+///         })
+///     })
+///     .collect();
+///
+/// drop(streams);
+/// assert_eq!(closed.load(Ordering::SeqCst), 0);
+///
+/// assert_eq!(queue.flush(), 3);
+/// assert_eq!(closed.load(Ordering::SeqCst), 3);
+/// ```
+#[derive(Clone, Default)]
+pub struct DropQueue {
+    shared: Arc<Shared>,
+}
+
+impl DropQueue {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds a callback to the queue, waking the [`Flusher`] if there is one.
+    pub fn push(&self, callback: impl FnOnce() + Send + 'static) {
+        let waker = {
+            let mut state = self.shared.lock();
+            state.callbacks.push(Box::new(callback));
+            state.waker.take()
+        };
+
+        if let Some(waker) = waker {
+            waker.wake();
+        }
+    }
+
+    /// Runs every queued callback, returning how many ran. Callbacks queued while flushing are
+    /// left for the next flush.
+    pub fn flush(&self) -> usize {
+        // Taken before running, so callbacks can drop further streams into the queue.
+        let callbacks = std::mem::take(&mut self.shared.lock().callbacks);
+        let count = callbacks.len();
+        for callback in callbacks {
+            callback();
+        }
+
+        count
+    }
+
+    /// Returns the number of callbacks waiting to be run.
+    pub fn len(&self) -> usize {
+        self.shared.lock().callbacks.len()
+    }
+
+    /// Returns true if no callbacks are waiting to be run.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Returns a future that flushes the queue whenever callbacks are added to it, to be spawned
+    /// onto a runtime. See [`Flusher`].
+    pub fn flusher(&self) -> Flusher {
+        Flusher {
+            shared: Arc::downgrade(&self.shared),
+        }
+    }
+}
+
+impl fmt::Debug for DropQueue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("DropQueue")
+            .field("len", &self.len())
+            .finish()
+    }
+}
+
+/// A future that runs the callbacks of a [`DropQueue`] as they are added, so they run on whichever
+/// task it is spawned onto instead of where the streams were dropped. Created by
+/// [`DropQueue::flusher`].
+///
+/// It completes once every handle to the queue, including those held by streams that haven't been
+/// dropped yet, is gone.
+#[derive(Debug)]
+#[must_use = "futures do nothing unless polled"]
+pub struct Flusher {
+    shared: Weak<Shared>,
+}
+
+impl Future for Flusher {
+    type Output = ();
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<()> {
+        let Some(shared) = self.shared.upgrade() else {
+            return Poll::Ready(());
+        };
+
+        loop {
+            let callbacks = {
+                let mut state = shared.lock();
+                if state.callbacks.is_empty() {
+                    state.waker = Some(cx.waker().clone());
+                    return Poll::Pending;
+                }
+                std::mem::take(&mut state.callbacks)
+            };
+
+            for callback in callbacks {
+                callback();
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::task::Poll;
+
+    use crate::{test_util::DropTracker, DropQueue, DropStreamExt};
+    use futures::{stream::repeat, Future};
+
+    #[test]
+    fn callbacks_wait_for_flush() {
+        let tracker = DropTracker::new();
+        let queue = DropQueue::new();
+
+        let first = repeat(true).on_drop_deferred(&queue, tracker.callback("first"));
+        let second = repeat(true).on_drop_deferred(&queue, tracker.callback("second"));
+
+        drop(first);
+        drop(second);
+        tracker.assert_not_dropped("first");
+        assert_eq!(queue.len(), 2);
+
+        assert_eq!(queue.flush(), 2);
+        tracker.assert_order(&["first", "second"]);
+        assert!(queue.is_empty());
+    }
+
+    #[test]
+    fn flusher_runs_pushed_callbacks_and_ends_with_queue() {
+        let tracker = DropTracker::new();
+        let queue = DropQueue::new();
+        let mut flusher = Box::pin(queue.flusher());
+
+        let (waker, count) = futures_test::task::new_count_waker();
+        let mut context = futures::task::Context::from_waker(&waker);
+        assert_eq!(flusher.as_mut().poll(&mut context), Poll::Pending);
+
+        drop(repeat(true).on_drop_deferred(&queue, tracker.callback("stream")));
+        assert_eq!(count, 1);
+        tracker.assert_not_dropped("stream");
+
+        assert_eq!(flusher.as_mut().poll(&mut context), Poll::Pending);
+        tracker.assert_dropped("stream");
+
+        drop(queue);
+        assert_eq!(count, 2);
+        assert_eq!(flusher.as_mut().poll(&mut context), Poll::Ready(()));
+    }
+
+    #[test]
+    fn dropping_last_handle_runs_leftovers() {
+        let tracker = DropTracker::new();
+        let queue = DropQueue::new();
+
+        drop(repeat(true).on_drop_deferred(&queue, tracker.callback("stream")));
+        tracker.assert_not_dropped("stream");
+
+        drop(queue);
+        tracker.assert_dropped("stream");
+    }
+}
@@ -0,0 +1,210 @@
+use futures_core::Stream;
+use pin_project::{pin_project, pinned_drop};
+use std::{
+    pin::Pin,
+    task::{Context, Poll},
+};
+
+use crate::context::{ContextDropFn, ContextDropper};
+
+/// A snapshot of how full a channel was, reported as
+/// [`DropContext::queue_depth`](crate::DropContext::queue_depth).
+///
+/// With the `tokio` feature, snapshots can be taken from tokio's mpsc receivers with `From`, such
+/// as with `|stream| stream.as_ref().into()` from the receiver inside a
+/// `tokio_stream::wrappers::ReceiverStream`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct QueueDepth {
+    /// The number of items waiting in the channel.
+    pub len: usize,
+    /// The number of items the channel can hold, or `None` if it is unbounded.
+    pub capacity: Option<usize>,
+}
+
+impl QueueDepth {
+    /// Returns true if the channel was bounded and full, meaning the consumer wasn't keeping up.
+    pub fn is_full(&self) -> bool {
+        self.capacity.is_some_and(|capacity| self.len >= capacity)
+    }
+}
+
+#[cfg(feature = "tokio")]
+impl<T> From<&tokio::sync::mpsc::Receiver<T>> for QueueDepth {
+    fn from(receiver: &tokio::sync::mpsc::Receiver<T>) -> Self {
+        Self {
+            len: receiver.len(),
+            capacity: Some(receiver.max_capacity()),
+        }
+    }
+}
+
+#[cfg(feature = "tokio")]
+impl<T> From<&tokio::sync::mpsc::UnboundedReceiver<T>> for QueueDepth {
+    fn from(receiver: &tokio::sync::mpsc::UnboundedReceiver<T>) -> Self {
+        Self {
+            len: receiver.len(),
+            capacity: None,
+        }
+    }
+}
+
+/// A stream receiving from a channel that probes how full the channel is once it is dropped, and
+/// reports it as [`DropContext::queue_depth`](crate::DropContext::queue_depth) to a closure called
+/// with the [`DropContext`](crate::DropContext).
+///
+/// The probe is called with the inner stream, right before it is dropped. A cancelled consumer
+/// that left an empty channel behind was keeping up, while one that left a full channel was
+/// drowning.
+///
+/// Example
+/// ```
+/// use futures::{executor::block_on_stream, Stream};
+/// use drop_stream::{DropStreamExt, QueueDepth};
+///
+/// let mut depth = None;
+/// let depth_ref = &mut depth;
+/// let stream = futures::stream::iter([1, 2, 3]).on_drop_with_queue_depth(
+///     |stream| QueueDepth { len: stream.size_hint().0, capacity: Some(3) },
+///     move |context| *depth_ref = context.queue_depth(),
+/// );
+///
+/// let mut stream = block_on_stream(stream);
+/// stream.next();
+/// drop(stream);
+/// assert_eq!(depth, Some(QueueDepth { len: 2, capacity: Some(3) }));
+/// ```
+#[pin_project(PinnedDrop)]
+pub struct QueueDepthStream<S, P, U>
+where
+    S: Stream,
+    P: FnMut(&S) -> QueueDepth,
+    U: ContextDropFn,
+{
+    // Declared before the stream so the closure runs before the inner stream is dropped.
+    dropper: ContextDropper<U>,
+    #[pin]
+    stream: S,
+    probe: P,
+}
+
+impl<S, P, U> QueueDepthStream<S, P, U>
+where
+    S: Stream,
+    P: FnMut(&S) -> QueueDepth,
+    U: ContextDropFn,
+{
+    #[track_caller]
+    pub fn new(stream: S, probe: P, dropper: U) -> Self {
+        Self {
+            dropper: ContextDropper::new(dropper),
+            stream,
+            probe,
+        }
+    }
+
+    /// Returns the ID of the stream, reported as [`DropContext::id`](crate::DropContext::id).
+    pub fn id(&self) -> u64 {
+        self.dropper.stats.id()
+    }
+}
+
+impl<S, P, U> Stream for QueueDepthStream<S, P, U>
+where
+    S: Stream,
+    P: FnMut(&S) -> QueueDepth,
+    U: ContextDropFn,
+{
+    type Item = S::Item;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let this = self.project();
+
+        let poll = this.stream.poll_next(cx);
+        match &poll {
+            Poll::Ready(Some(_)) => this.dropper.stats.record_item(),
+            Poll::Ready(None) => this.dropper.stats.record_end(),
+            Poll::Pending => this.dropper.stats.record_pending(),
+        }
+
+        poll
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.stream.size_hint()
+    }
+}
+
+#[pinned_drop]
+impl<S, P, U> PinnedDrop for QueueDepthStream<S, P, U>
+where
+    S: Stream,
+    P: FnMut(&S) -> QueueDepth,
+    U: ContextDropFn,
+{
+    fn drop(self: Pin<&mut Self>) {
+        // Runs before the `ContextDropper` field is dropped, which calls the closure.
+        let this = self.project();
+        let depth = (this.probe)(&this.stream);
+        this.dropper.stats.set_queue_depth(depth);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::QueueDepth;
+
+    #[test]
+    fn full_only_when_bounded_and_at_capacity() {
+        let depth = |len, capacity| QueueDepth { len, capacity };
+
+        assert!(depth(8, Some(8)).is_full());
+        assert!(!depth(7, Some(8)).is_full());
+        assert!(!depth(1_000, None).is_full());
+    }
+
+    #[cfg(feature = "tokio")]
+    #[tokio::test]
+    async fn cancelled_consumer_reports_backlog() {
+        use std::{
+            pin::Pin,
+            task::{Context, Poll},
+        };
+
+        use crate::{DropContext, DropStreamExt};
+        use futures::{Stream, StreamExt};
+
+        struct Receiver(tokio::sync::mpsc::Receiver<u32>);
+
+        impl Stream for Receiver {
+            type Item = u32;
+
+            fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<u32>> {
+                self.0.poll_recv(cx)
+            }
+        }
+
+        let (sender, receiver) = tokio::sync::mpsc::channel(4);
+        for i in 0..4 {
+            sender.send(i).await.unwrap();
+        }
+
+        let mut context = None::<DropContext>;
+        {
+            let context_ref = &mut context;
+            let mut stream = Receiver(receiver).on_drop_with_queue_depth(
+                |receiver| (&receiver.0).into(),
+                move |c| *context_ref = Some(c),
+            );
+            assert_eq!(stream.next().await, Some(0));
+        }
+
+        let depth = context.unwrap().queue_depth().unwrap();
+        assert_eq!(
+            depth,
+            QueueDepth {
+                len: 3,
+                capacity: Some(4)
+            }
+        );
+    }
+}
@@ -0,0 +1,181 @@
+use futures_core::{Stream, TryStream};
+use pin_project::{pin_project, pinned_drop};
+use std::{
+    fmt,
+    ops::Range,
+    pin::Pin,
+    task::{Context, Poll},
+};
+
+use crate::{context::Stats, dropper::Once, DropContext};
+
+/// A stream of the bytes of a range response that holds a file lock or open handle for as long as
+/// it is alive, and hands it to a closure together with a [`DropContext`] once it is dropped.
+///
+/// The closure runs whether the range was served in full or the client went away, right when the
+/// response drops the stream, so the lock is released deterministically instead of whenever the
+/// last clone of a handle happens to go. The context is labelled with the range under `range`, as
+/// `start..end`, and counts the bytes served as [`DropContext::bytes`].
+///
+/// Example
+/// ```
+/// use futures::executor::block_on_stream;
+/// use drop_stream::DropTryStreamExt;
+///
+/// # let path = std::env::temp_dir().join("drop-stream-range-doctest");
+/// # std::fs::write(&path, b"0123456789").unwrap();
+/// let file = std::fs::File::open(&path).unwrap();
+/// file.lock_shared().unwrap();
+///
+/// let mut served = None;
+/// let served_ref = &mut served;
+/// let chunks = futures::stream::iter([Ok::<_, std::io::Error>(b"2345".to_vec())]);
+/// let body = chunks.hold_for_range(2..6, file, move |context, file| {
+///     file.unlock().unwrap();
+///     *served_ref = Some((context.label("range").unwrap().to_owned(), context.bytes()));
+/// });
+///
+/// assert_eq!(block_on_stream(body).count(), 1);
+/// assert_eq!(served, Some(("2..6".to_owned(), 4)));
+/// ```
+#[pin_project(PinnedDrop)]
+pub struct HeldRange<S, H, U>
+where
+    S: TryStream,
+    S::Ok: AsRef<[u8]>,
+    U: FnOnce(DropContext, H),
+{
+    #[pin]
+    stream: S,
+    stats: Stats,
+    range: Range<u64>,
+    // Only taken in the drop method.
+    handle: Option<H>,
+    dropper: Once<U>,
+}
+
+impl<S, H, U> HeldRange<S, H, U>
+where
+    S: TryStream,
+    S::Ok: AsRef<[u8]>,
+    U: FnOnce(DropContext, H),
+{
+    #[track_caller]
+    pub fn new(stream: S, range: Range<u64>, handle: H, dropper: U) -> Self {
+        let mut stats = Stats::new();
+        stats.add_label("range", format!("{}..{}", range.start, range.end));
+
+        Self {
+            stream,
+            stats,
+            range,
+            handle: Some(handle),
+            dropper: Once::new(dropper),
+        }
+    }
+
+    /// Returns the range being served.
+    pub fn range(&self) -> Range<u64> {
+        self.range.clone()
+    }
+
+    /// Returns the handle held for the range.
+    pub fn handle(&self) -> &H {
+        // Only taken in the drop method.
+        self.handle.as_ref().expect("handle taken before drop")
+    }
+
+    /// Returns the number of bytes served so far.
+    pub fn bytes(&self) -> u64 {
+        self.stats.bytes()
+    }
+}
+
+impl<S, H, U> Stream for HeldRange<S, H, U>
+where
+    S: TryStream,
+    S::Ok: AsRef<[u8]>,
+    U: FnOnce(DropContext, H),
+{
+    type Item = Result<S::Ok, S::Error>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let this = self.project();
+
+        let poll = this.stream.try_poll_next(cx);
+        match &poll {
+            Poll::Ready(Some(Ok(chunk))) => {
+                this.stats.record_item();
+                this.stats.record_bytes(chunk.as_ref().len());
+            }
+            Poll::Ready(Some(Err(_))) => this.stats.record_error(),
+            Poll::Ready(None) => this.stats.record_end(),
+            Poll::Pending => this.stats.record_pending(),
+        }
+
+        poll
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.stream.size_hint()
+    }
+}
+
+#[pinned_drop]
+impl<S, H, U> PinnedDrop for HeldRange<S, H, U>
+where
+    S: TryStream,
+    S::Ok: AsRef<[u8]>,
+    U: FnOnce(DropContext, H),
+{
+    fn drop(self: Pin<&mut Self>) {
+        let this = self.project();
+
+        if let (Some(dropper), Some(handle)) = (this.dropper.take(), this.handle.take()) {
+            dropper(this.stats.context(), handle)
+        }
+    }
+}
+
+impl<S, H, U> fmt::Debug for HeldRange<S, H, U>
+where
+    S: TryStream,
+    S::Ok: AsRef<[u8]>,
+    U: FnOnce(DropContext, H),
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("HeldRange")
+            .field("range", &self.range)
+            .field("bytes", &self.stats.bytes())
+            .finish_non_exhaustive()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{Arc, Mutex};
+
+    use crate::{DropReason, DropTryStreamExt};
+    use futures::{executor::block_on_stream, stream::iter};
+
+    #[test]
+    fn handle_is_released_on_cancel() {
+        let lock = Arc::new(Mutex::new(()));
+        let mut report = None;
+
+        {
+            let report_ref = &mut report;
+            let handle = lock.clone();
+            let chunks = iter([Ok::<_, ()>("abc"), Ok("def")]);
+            let body = chunks.hold_for_range(100..106, handle, move |c, handle| {
+                *report_ref = Some((c.reason(), c.bytes(), Arc::strong_count(&handle)));
+            });
+
+            let mut body = block_on_stream(body);
+            assert!(body.next().is_some());
+        }
+
+        assert_eq!(report, Some((DropReason::Cancelled, 3, 2)));
+        assert_eq!(Arc::strong_count(&lock), 1);
+    }
+}
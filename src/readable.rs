@@ -0,0 +1,154 @@
+use futures_core::Stream;
+use js_sys::{Object, Promise};
+use std::{cell::RefCell, future::poll_fn, pin::Pin, rc::Rc, task::Poll};
+use wasm_bindgen::{prelude::wasm_bindgen, JsCast, JsValue};
+use wasm_bindgen_futures::future_to_promise;
+use wasm_streams::readable::IntoStream;
+use web_sys::{ReadableStream, ReadableStreamDefaultController};
+
+use crate::{ContextDropFn, ContextDropStream, DropContext};
+
+type ChunkStream = Pin<Box<dyn Stream<Item = Result<JsValue, JsValue>>>>;
+
+/// Wraps a JS `ReadableStream` with a closure that is called with a [`DropContext`] once it is
+/// dropped.
+///
+/// Dropping the stream before it ended cancels the JS stream, so the producer on the JS side
+/// stops as well, and the closure sees [`DropReason::Cancelled`](crate::DropReason::Cancelled).
+///
+/// Example
+/// ```no_run
+/// use drop_stream::{from_readable_stream, DropContext};
+///
+/// # fn run(raw: web_sys::ReadableStream) {
+/// let mut items = 0;
+/// let chunks = from_readable_stream(raw, |context: DropContext| items = context.items());
+/// // Read some of the chunks, then cancel the JS stream by dropping it.
+/// drop(chunks);
+/// # }
+/// ```
+#[track_caller]
+pub fn from_readable_stream<U: ContextDropFn>(
+    raw: ReadableStream,
+    dropper: U,
+) -> ContextDropStream<IntoStream<'static>, U> {
+    let stream = wasm_streams::ReadableStream::from_raw(raw).into_stream();
+
+    ContextDropStream::new(stream, dropper)
+}
+
+/// Turns a stream of JS chunks into a JS `ReadableStream`, calling `dropper` with a
+/// [`DropContext`] once the stream is dropped.
+///
+/// The stream is polled whenever the JS side pulls from it. If the JS side cancels the
+/// `ReadableStream`, the stream is dropped right away with
+/// [`DropReason::Cancelled`](crate::DropReason::Cancelled), and the reason given to `cancel` is
+/// labeled under `"cancel_reason"`. A stream that ends closes the `ReadableStream`, and an error
+/// item errors it.
+///
+/// # Errors
+///
+/// Returns the exception thrown by the `ReadableStream` constructor, if any.
+///
+/// Example
+/// ```no_run
+/// use std::{cell::RefCell, rc::Rc};
+/// use drop_stream::{into_readable_stream, DropContext};
+/// use wasm_bindgen::JsValue;
+///
+/// # fn run() -> Result<(), JsValue> {
+/// let chunks = futures::stream::iter(["a", "b"].map(|chunk| Ok(JsValue::from(chunk))));
+/// let reason = Rc::new(RefCell::new(None));
+/// let reason_ref = reason.clone();
+/// let raw = into_readable_stream(chunks, move |context: DropContext| {
+///     *reason_ref.borrow_mut() = context.label("cancel_reason").map(str::to_owned);
+/// })?;
+/// // Hand `raw` to a JS consumer, such as a `Response` body.
+/// # Ok(())
+/// # }
+/// ```
+#[track_caller]
+pub fn into_readable_stream<S, U>(stream: S, dropper: U) -> Result<ReadableStream, JsValue>
+where
+    S: Stream<Item = Result<JsValue, JsValue>> + 'static,
+    U: ContextDropFn + 'static,
+{
+    let reason = Rc::new(RefCell::new(None));
+    let stream = ContextDropStream::new(
+        stream,
+        LabelCancelReason {
+            reason: reason.clone(),
+            dropper,
+        },
+    );
+
+    let source = Source {
+        stream: Rc::new(RefCell::new(Some(Box::pin(stream)))),
+        reason,
+    };
+    ReadableStream::new_with_underlying_source(&JsValue::from(source).unchecked_into::<Object>())
+}
+
+/// The underlying source of a `ReadableStream` created by [`into_readable_stream`].
+///
+/// Public for `wasm_bindgen` to export its methods, but not reachable outside of the crate.
+#[wasm_bindgen]
+pub struct Source {
+    // Shared with the pulls in flight, and taken once the stream ends or is cancelled.
+    stream: Rc<RefCell<Option<ChunkStream>>>,
+    reason: Rc<RefCell<Option<String>>>,
+}
+
+#[wasm_bindgen]
+impl Source {
+    pub fn pull(&self, controller: ReadableStreamDefaultController) -> Promise {
+        let stream = self.stream.clone();
+
+        future_to_promise(async move {
+            // Only borrowed while polling, so a cancel in between can take the stream.
+            let next = poll_fn(|cx| match stream.borrow_mut().as_mut() {
+                Some(stream) => stream.as_mut().poll_next(cx),
+                None => Poll::Ready(None),
+            })
+            .await;
+
+            match next {
+                Some(Ok(chunk)) => controller.enqueue_with_chunk(&chunk)?,
+                Some(Err(error)) => {
+                    drop(stream.take());
+                    return Err(error);
+                }
+                None => {
+                    drop(stream.take());
+                    // Fails if the stream was cancelled meanwhile, which is fine.
+                    let _ = controller.close();
+                }
+            }
+
+            Ok(JsValue::UNDEFINED)
+        })
+    }
+
+    pub fn cancel(&self, reason: JsValue) {
+        let reason = reason.as_string().unwrap_or_else(|| format!("{reason:?}"));
+        *self.reason.borrow_mut() = Some(reason);
+
+        drop(self.stream.take());
+    }
+}
+
+/// Labels the context with the reason the JS side cancelled the stream with, if it did.
+struct LabelCancelReason<U: ContextDropFn> {
+    reason: Rc<RefCell<Option<String>>>,
+    dropper: U,
+}
+
+impl<U: ContextDropFn> ContextDropFn for LabelCancelReason<U> {
+    fn call(self, mut context: DropContext) {
+        if let Some(reason) = self.reason.take() {
+            context.add_label("cancel_reason", reason);
+        }
+
+        self.dropper.call(context)
+    }
+}
@@ -0,0 +1,216 @@
+use futures_core::Future;
+use std::{
+    error::Error,
+    future::poll_fn,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Mutex,
+    },
+    task::Poll,
+};
+use tokio::{
+    runtime::{self, Handle},
+    sync::mpsc::{unbounded_channel, UnboundedReceiver, UnboundedSender},
+    task::JoinSet,
+};
+
+use crate::{report_drop_error, BoxFuture};
+
+// One reaper per runtime. Reapers of runtimes that shut down are removed once noticed.
+static REAPERS: Mutex<Vec<(runtime::Id, UnboundedSender<BoxFuture>)>> = Mutex::new(Vec::new());
+static PENDING: AtomicUsize = AtomicUsize::new(0);
+
+/// Hands `cleanup` to the reaper of the current tokio runtime, which drives it to completion and
+/// reports its error, or its panic, to the handler set with
+/// [`set_drop_error_handler`](crate::set_drop_error_handler).
+///
+/// This gives drop closures reliable async cleanup without every call site wiring up a spawner.
+/// The reaper is a task that is spawned the first time a runtime needs one, and runs every
+/// cleanup future as a task of its own. See also
+/// [`on_drop_reaped`](crate::DropStreamExt::on_drop_reaped).
+///
+/// # Panics
+///
+/// Panics if called outside of a tokio runtime.
+///
+/// Example
+/// ```
+/// # #[tokio::main(flavor = "current_thread")]
+/// # async fn main() {
+/// use drop_stream::{reap, reaper_pending};
+///
+/// reap(async {
+///     // Close a remote session...
+///     Ok::<_, std::io::Error>(())
+/// });
+///
+/// while reaper_pending() > 0 {
+///     tokio::task::yield_now().await;
+/// }
+/// # }
+/// ```
+pub fn reap<F, E>(cleanup: F)
+where
+    F: Future<Output = Result<(), E>> + Send + 'static,
+    E: Error + Send + 'static,
+{
+    reap_on(&Handle::current(), cleanup)
+}
+
+/// Like [`reap`], on the reaper of `runtime`.
+pub(crate) fn reap_on<F, E>(runtime: &Handle, cleanup: F)
+where
+    F: Future<Output = Result<(), E>> + Send + 'static,
+    E: Error + Send + 'static,
+{
+    let pending = PendingGuard::new();
+    let mut cleanup: BoxFuture = Box::pin(async move {
+        let _pending = pending;
+        if let Err(error) = cleanup.await {
+            report_drop_error(error);
+        }
+    });
+
+    let id = runtime.id();
+    let mut reapers = REAPERS.lock().unwrap_or_else(|e| e.into_inner());
+    if let Some(index) = reapers.iter().position(|(reaper, _)| *reaper == id) {
+        match reapers[index].1.send(cleanup) {
+            Ok(()) => return,
+            // The reaper was dropped along with its runtime's tasks, so a new one is spawned.
+            Err(error) => {
+                cleanup = error.0;
+                reapers.swap_remove(index);
+            }
+        }
+    }
+    reapers.retain(|(_, sender)| !sender.is_closed());
+
+    let (sender, receiver) = unbounded_channel();
+    drop(runtime.spawn(run(receiver)));
+    if sender.send(cleanup).is_err() {
+        // The runtime is shutting down, so the cleanup can't run.
+        return;
+    }
+    reapers.push((id, sender));
+}
+
+/// Returns the number of cleanup futures handed to [`reap`] that haven't finished yet, across
+/// every runtime, for shutdown paths that wait for cleanups to finish.
+pub fn reaper_pending() -> usize {
+    PENDING.load(Ordering::Relaxed)
+}
+
+/// Counts a cleanup in [`reaper_pending`] until it is dropped, which happens once it finished, or
+/// once it was dropped without finishing because its runtime shut down.
+struct PendingGuard;
+
+impl PendingGuard {
+    fn new() -> Self {
+        PENDING.fetch_add(1, Ordering::Relaxed);
+        Self
+    }
+}
+
+impl Drop for PendingGuard {
+    fn drop(&mut self) {
+        PENDING.fetch_sub(1, Ordering::Relaxed);
+    }
+}
+
+async fn run(mut receiver: UnboundedReceiver<BoxFuture>) {
+    let mut cleanups = JoinSet::new();
+    let mut open = true;
+
+    poll_fn(|cx| {
+        while open {
+            match receiver.poll_recv(cx) {
+                Poll::Ready(Some(cleanup)) => drop(cleanups.spawn(cleanup)),
+                Poll::Ready(None) => open = false,
+                Poll::Pending => break,
+            }
+        }
+
+        while let Poll::Ready(Some(result)) = cleanups.poll_join_next(cx) {
+            if let Err(error) = result {
+                report_drop_error(error);
+            }
+        }
+
+        if open || !cleanups.is_empty() {
+            Poll::Pending
+        } else {
+            Poll::Ready(())
+        }
+    })
+    .await
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        io,
+        sync::{Arc, Mutex},
+        time::{Duration, Instant},
+    };
+
+    use crate::DropStreamExt;
+    use futures::stream::repeat;
+
+    #[tokio::test]
+    async fn dropped_stream_cleanup_is_reaped() {
+        let (sender, receiver) = tokio::sync::oneshot::channel();
+
+        let stream = repeat(true).on_drop_reaped(move || async move {
+            tokio::task::yield_now().await;
+            sender.send(()).unwrap();
+            Ok::<_, io::Error>(())
+        });
+        drop(stream);
+
+        receiver.await.unwrap();
+    }
+
+    #[test]
+    fn each_runtime_gets_its_own_reaper() {
+        let done = Arc::new(Mutex::new(0));
+
+        for _ in 0..2 {
+            let runtime = tokio::runtime::Builder::new_current_thread()
+                .build()
+                .unwrap();
+            let done = done.clone();
+
+            runtime.block_on(async move {
+                let (sender, receiver) = tokio::sync::oneshot::channel();
+                crate::reap(async move {
+                    *done.lock().unwrap() += 1;
+                    sender.send(()).unwrap();
+                    Ok::<_, io::Error>(())
+                });
+                receiver.await.unwrap();
+            });
+        }
+
+        assert_eq!(*done.lock().unwrap(), 2);
+    }
+
+    #[test]
+    fn cleanups_dropped_with_their_runtime_are_not_pending() {
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .build()
+            .unwrap();
+        runtime.block_on(async {
+            crate::reap(futures::future::pending::<Result<(), io::Error>>());
+            tokio::task::yield_now().await;
+            assert!(crate::reaper_pending() >= 1);
+        });
+        drop(runtime);
+
+        // Other tests' cleanups may still be running, but they all finish.
+        let deadline = Instant::now() + Duration::from_secs(5);
+        while crate::reaper_pending() > 0 {
+            assert!(Instant::now() < deadline, "cleanup still counted");
+            std::thread::yield_now();
+        }
+    }
+}
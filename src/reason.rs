@@ -0,0 +1,33 @@
+/// The reason a drop callback is being run.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub enum DropReason {
+    /// The stream was dropped before it finished.
+    Cancelled,
+    /// The inner stream ran to completion (yielded `None`) before the wrapper was dropped.
+    Completed,
+    /// The stream was ended early by an external signal, such as a shutdown future.
+    Stopped,
+    /// Like [`DropReason::Completed`], but the stream yielded at least one `Err` item.
+    CompletedWithError,
+    /// Like [`DropReason::Cancelled`], but the stream yielded at least one `Err` item before it
+    /// was dropped.
+    CancelledAfterError,
+}
+
+impl DropReason {
+    /// Returns true if the stream was dropped before it finished, with or without errors.
+    pub fn is_cancelled(self) -> bool {
+        matches!(self, Self::Cancelled | Self::CancelledAfterError)
+    }
+
+    /// Returns true if the stream ran to completion, with or without errors.
+    pub fn is_completed(self) -> bool {
+        matches!(self, Self::Completed | Self::CompletedWithError)
+    }
+
+    /// Returns true if the stream yielded at least one `Err` item.
+    pub fn is_error(self) -> bool {
+        matches!(self, Self::CompletedWithError | Self::CancelledAfterError)
+    }
+}
@@ -0,0 +1,191 @@
+use futures_core::Stream;
+use pin_project::pin_project;
+use std::{
+    pin::Pin,
+    sync::Arc,
+    task::{Context, Poll},
+};
+
+use crate::signal::Signal;
+
+/// Wraps a stream so that it ends once the returned [`RemoteHandle`] is dropped.
+///
+/// This is the reverse of [`DropStream`](crate::DropStream): instead of the producer being
+/// notified when the consumer drops the stream, the producer can revoke the consumer by dropping
+/// the handle. The stream then yields `None` on its next poll, and any task waiting on it is woken.
+///
+/// Example
+/// ```
+/// use futures::Stream;
+/// use drop_stream::remote_drop;
+///
+/// let (stream, handle) = remote_drop(futures::stream::repeat(true));
+/// let mut stream = Box::pin(stream);
+///
+/// let waker = futures::task::noop_waker();
+/// let mut context = futures::task::Context::from_waker(&waker);
+/// assert_eq!(
+///     stream.as_mut().poll_next(&mut context),
+///     std::task::Poll::Ready(Some(true))
+/// );
+///
+/// drop(handle);
+/// assert_eq!(
+///     stream.as_mut().poll_next(&mut context),
+///     std::task::Poll::Ready(None)
+/// );
+/// ```
+pub fn remote_drop<S: Stream>(stream: S) -> (RemoteDropStream<S, fn()>, RemoteHandle) {
+    RemoteDropStream::new(stream, None)
+}
+
+/// Like [`remote_drop`], but runs `callback` once the stream observes that the handle was dropped.
+pub fn remote_drop_with<S: Stream, U: FnOnce()>(
+    stream: S,
+    callback: U,
+) -> (RemoteDropStream<S, U>, RemoteHandle) {
+    RemoteDropStream::new(stream, Some(callback))
+}
+
+/// A stream that ends once its paired [`RemoteHandle`] is dropped. Created by [`remote_drop`] and
+/// [`remote_drop_with`].
+#[pin_project]
+pub struct RemoteDropStream<S: Stream, U: FnOnce()> {
+    #[pin]
+    stream: S,
+    signal: Arc<Signal>,
+    // Option used to wrap FnOnce since ownership of FnOnce needs to be gained when it is called.
+    callback: Option<U>,
+}
+
+impl<S: Stream, U: FnOnce()> RemoteDropStream<S, U> {
+    fn new(stream: S, callback: Option<U>) -> (Self, RemoteHandle) {
+        let signal = Arc::new(Signal::default());
+
+        let stream = Self {
+            stream,
+            signal: signal.clone(),
+            callback,
+        };
+
+        (stream, RemoteHandle { signal })
+    }
+
+    /// Returns true if the paired handle has been dropped.
+    pub fn is_revoked(&self) -> bool {
+        self.signal.is_fired()
+    }
+}
+
+impl<S: Stream, U: FnOnce()> Stream for RemoteDropStream<S, U> {
+    type Item = S::Item;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let this = self.project();
+
+        if this.signal.poll_fired(cx).is_ready() {
+            if let Some(callback) = this.callback.take() {
+                callback();
+            }
+
+            return Poll::Ready(None);
+        }
+
+        this.stream.poll_next(cx)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        if self.is_revoked() {
+            return (0, Some(0));
+        }
+
+        // The handle may be dropped at any point.
+        (0, self.stream.size_hint().1)
+    }
+}
+
+/// The handle paired with a [`RemoteDropStream`]. Dropping it ends the stream.
+#[derive(Debug)]
+pub struct RemoteHandle {
+    signal: Arc<Signal>,
+}
+
+impl Drop for RemoteHandle {
+    fn drop(&mut self) {
+        self.signal.fire();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::task::Poll;
+
+    use crate::{remote_drop, remote_drop_with};
+    use futures::{stream::repeat, Stream};
+
+    #[test]
+    fn dropping_handle_ends_stream() {
+        let (drop_stream, handle) = remote_drop(repeat(true));
+
+        let mut drop_stream = Box::pin(drop_stream);
+
+        let waker = futures::task::noop_waker();
+        let mut context = futures::task::Context::from_waker(&waker);
+        assert_eq!(
+            drop_stream.as_mut().poll_next(&mut context),
+            Poll::Ready(Some(true))
+        );
+        assert!(!drop_stream.is_revoked());
+
+        drop(handle);
+
+        assert!(drop_stream.is_revoked());
+        assert_eq!(
+            drop_stream.as_mut().poll_next(&mut context),
+            Poll::Ready(None)
+        );
+    }
+
+    #[test]
+    fn callback_runs_once_when_revoked() {
+        let mut runs = 0;
+
+        {
+            let runs_ref = &mut runs;
+            let (drop_stream, handle) = remote_drop_with(repeat(true), move || {
+                *runs_ref += 1;
+            });
+
+            let mut drop_stream = Box::pin(drop_stream);
+            drop(handle);
+
+            let waker = futures::task::noop_waker();
+            let mut context = futures::task::Context::from_waker(&waker);
+            assert_eq!(
+                drop_stream.as_mut().poll_next(&mut context),
+                Poll::Ready(None)
+            );
+            assert_eq!(
+                drop_stream.as_mut().poll_next(&mut context),
+                Poll::Ready(None)
+            );
+        }
+
+        assert_eq!(runs, 1);
+    }
+
+    #[test]
+    fn dropping_handle_wakes_waiting_task() {
+        let (drop_stream, handle) = remote_drop(futures::stream::pending::<()>());
+
+        let mut drop_stream = Box::pin(drop_stream);
+
+        let (waker, count) = futures_test::task::new_count_waker();
+        let mut context = futures::task::Context::from_waker(&waker);
+        assert_eq!(drop_stream.as_mut().poll_next(&mut context), Poll::Pending);
+
+        drop(handle);
+
+        assert_eq!(count, 1);
+    }
+}
@@ -0,0 +1,239 @@
+use std::{
+    collections::HashMap,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
+};
+
+use crate::{DropContext, DropEvent, DropEventHandler, StreamObserver};
+
+#[derive(Debug)]
+struct SamplerState {
+    rate: f64,
+    labels: HashMap<String, f64>,
+    counter: AtomicU64,
+}
+
+impl Clone for SamplerState {
+    fn clone(&self) -> Self {
+        Self {
+            rate: self.rate,
+            labels: self.labels.clone(),
+            counter: AtomicU64::new(self.counter.load(Ordering::Relaxed)),
+        }
+    }
+}
+
+/// Decides which streams get instrumented, for streams created too often to report every one.
+///
+/// Each stream is sampled once, when it is wrapped, so a sampled stream reports every one of its
+/// events and an unsampled one reports none. Only the observer is sampled: drop closures still run
+/// for every stream. Rates are between 0 (never) and 1 (always), and can be overridden per label.
+/// Drop event handlers, such as the `log`, `tracing` and `metrics` ones, are sampled by wrapping
+/// them with [`handler`](Self::handler).
+///
+/// Example
+/// ```
+/// use drop_stream::{DropStreamExt, Sampler};
+///
+/// let sampler = Sampler::new(1.0).label_rate("/health", 0.0);
+///
+/// let stream = futures::stream::repeat(true).observe(sampler.sample("/health", ()));
+/// assert!(!stream.observer().is_sampled());
+/// ```
+#[derive(Debug, Clone)]
+pub struct Sampler {
+    state: Arc<SamplerState>,
+}
+
+impl Sampler {
+    /// Creates a sampler that samples streams with `rate`, unless their label has a rate of its
+    /// own.
+    pub fn new(rate: f64) -> Self {
+        Self {
+            state: Arc::new(SamplerState {
+                rate,
+                labels: HashMap::new(),
+                counter: AtomicU64::new(0),
+            }),
+        }
+    }
+
+    /// Samples streams with `label` at `rate` instead.
+    pub fn label_rate(mut self, label: impl Into<String>, rate: f64) -> Self {
+        Arc::make_mut(&mut self.state)
+            .labels
+            .insert(label.into(), rate);
+        self
+    }
+
+    /// Returns whether the next stream with `label` should be instrumented.
+    pub fn should_sample(&self, label: &str) -> bool {
+        let rate = self
+            .state
+            .labels
+            .get(label)
+            .copied()
+            .unwrap_or(self.state.rate);
+        if rate >= 1.0 {
+            return true;
+        }
+        if rate <= 0.0 {
+            return false;
+        }
+
+        let n = self.state.counter.fetch_add(1, Ordering::Relaxed);
+        // Spreads consecutive counts over the whole range, so every rate is hit evenly.
+        (splitmix64(n) as f64) < rate * u64::MAX as f64
+    }
+
+    /// Wraps `observer` so it only sees the events of the stream if it is sampled.
+    pub fn sample<O>(&self, label: &str, observer: O) -> Sampled<O> {
+        Sampled {
+            observer: self.should_sample(label).then_some(observer),
+        }
+    }
+
+    /// Wraps `handler` so it only sees the drop events of sampled streams, using the value of the
+    /// stream's label `key` as the label, or `""` if it has none.
+    ///
+    /// Example
+    /// ```
+    /// use std::sync::{Arc, Mutex};
+    /// use drop_stream::{register_drop_handler, DropEvent, DropStreamExt, Sampler};
+    ///
+    /// let names = Arc::new(Mutex::new(Vec::new()));
+    /// let names_ref = names.clone();
+    /// let sampler = Sampler::new(1.0).label_rate("/health", 0.0);
+    /// register_drop_handler(sampler.handler("route", move |event: DropEvent| {
+    ///     names_ref.lock().unwrap().push(event.context().name().map(str::to_owned));
+    /// }));
+    ///
+    /// let stream = futures::stream::repeat(true).on_drop_ctx(|_| {});
+    /// drop(stream.name("probe").label("route", "/health"));
+    /// let stream = futures::stream::repeat(true).on_drop_ctx(|_| {});
+    /// drop(stream.name("invoice").label("route", "/billing"));
+    /// assert_eq!(*names.lock().unwrap(), [Some("invoice".to_owned())]);
+    /// ```
+    pub fn handler<H: DropEventHandler>(&self, key: &'static str, handler: H) -> SampledHandler<H> {
+        SampledHandler {
+            sampler: self.clone(),
+            key,
+            handler,
+        }
+    }
+}
+
+fn splitmix64(n: u64) -> u64 {
+    let mut z = n.wrapping_add(0x9e37_79b9_7f4a_7c15);
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    z ^ (z >> 31)
+}
+
+/// An observer that only forwards events if its stream was sampled. Created by
+/// [`Sampler::sample`].
+#[derive(Debug)]
+pub struct Sampled<O> {
+    observer: Option<O>,
+}
+
+impl<O> Sampled<O> {
+    /// Returns true if the stream was sampled.
+    pub fn is_sampled(&self) -> bool {
+        self.observer.is_some()
+    }
+
+    /// Returns the observer, if the stream was sampled.
+    pub fn observer(&self) -> Option<&O> {
+        self.observer.as_ref()
+    }
+}
+
+impl<T, O: StreamObserver<T>> StreamObserver<T> for Sampled<O> {
+    fn on_poll(&mut self) {
+        if let Some(observer) = self.observer.as_mut() {
+            observer.on_poll()
+        }
+    }
+
+    fn on_item(&mut self, item: &T) {
+        if let Some(observer) = self.observer.as_mut() {
+            observer.on_item(item)
+        }
+    }
+
+    fn on_pending(&mut self) {
+        if let Some(observer) = self.observer.as_mut() {
+            observer.on_pending()
+        }
+    }
+
+    fn on_end(&mut self) {
+        if let Some(observer) = self.observer.as_mut() {
+            observer.on_end()
+        }
+    }
+
+    fn on_drop(&mut self, context: &DropContext) {
+        if let Some(observer) = self.observer.as_mut() {
+            observer.on_drop(context)
+        }
+    }
+}
+
+/// A drop event handler that only forwards the events of sampled streams. Created by
+/// [`Sampler::handler`].
+#[derive(Debug, Clone)]
+pub struct SampledHandler<H> {
+    sampler: Sampler,
+    key: &'static str,
+    handler: H,
+}
+
+impl<H: DropEventHandler> DropEventHandler for SampledHandler<H> {
+    fn handle(&self, event: DropEvent) {
+        if self
+            .sampler
+            .should_sample(event.context().label(self.key).unwrap_or(""))
+        {
+            self.handler.handle(event)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{DropStreamExt, Sampler};
+    use futures::stream::repeat;
+
+    #[test]
+    fn rate_is_roughly_kept() {
+        let sampler = Sampler::new(0.1);
+
+        let sampled = (0..10_000).filter(|_| sampler.should_sample("")).count();
+        assert!((900..1100).contains(&sampled), "sampled {sampled}");
+    }
+
+    #[test]
+    fn label_rate_overrides_default_and_dropper_still_runs() {
+        let sampler = Sampler::new(0.0).label_rate("/billing", 1.0);
+        let mut drops = 0;
+
+        {
+            let drops_ref = &mut drops;
+            let observed = repeat(true).observe(sampler.sample("/billing", ()));
+            assert!(observed.observer().is_sampled());
+            let _drop_stream = observed.on_drop(move || *drops_ref += 1);
+        }
+        {
+            let drops_ref = &mut drops;
+            let observed = repeat(true).observe(sampler.sample("/health", ()));
+            assert!(!observed.observer().is_sampled());
+            let _drop_stream = observed.on_drop(move || *drops_ref += 1);
+        }
+
+        assert_eq!(drops, 2);
+    }
+}
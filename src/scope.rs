@@ -0,0 +1,242 @@
+use futures_core::Future;
+use pin_project::pin_project;
+use std::{
+    cell::RefCell,
+    fmt,
+    panic::{RefUnwindSafe, UnwindSafe},
+    pin::Pin,
+    sync::Arc,
+    task::{Context, Poll},
+};
+
+use crate::DropContext;
+
+type Hook = Arc<dyn Fn(&DropContext) + Send + Sync>;
+
+#[derive(Clone, Default)]
+pub(crate) struct ScopeState {
+    labels: Vec<(&'static str, String)>,
+    hooks: Vec<Hook>,
+}
+
+// The state is never changed once the scope is created, and the hooks are shared `Fn`s, so a
+// panicking hook can't leave it broken for the context wrappers that hold on to it.
+impl UnwindSafe for ScopeState {}
+impl RefUnwindSafe for ScopeState {}
+
+impl ScopeState {
+    pub(crate) fn labels(&self) -> &[(&'static str, String)] {
+        &self.labels
+    }
+
+    pub(crate) fn report(&self, context: &DropContext) {
+        for hook in &self.hooks {
+            hook(context)
+        }
+    }
+}
+
+thread_local! {
+    static CURRENT: RefCell<Option<Arc<ScopeState>>> = const { RefCell::new(None) };
+}
+
+/// Returns the scope entered on this thread, if any.
+pub(crate) fn current() -> Option<Arc<ScopeState>> {
+    CURRENT.with(|current| current.borrow().clone())
+}
+
+/// A set of labels and drop hooks that every wrapper building a [`DropContext`] picks up when it
+/// is created within the scope, such as [`on_drop_ctx`](crate::DropStreamExt::on_drop_ctx) and
+/// [`ContextDropStream`](crate::ContextDropStream).
+///
+/// Lets instrumentation be enforced in one place instead of at every call site. The scope's labels
+/// come before the wrapper's own, and its hooks are called with the context before the wrapper's
+/// closure is. Scopes nest: a scope entered within another adds to its labels and hooks.
+///
+/// Scopes are entered on the current thread with [`enter`](DropScope::enter), or for every poll
+/// of a future with [`scope`](DropScope::scope), which follows the future across threads.
+///
+/// Example
+/// ```
+/// use std::sync::{Arc, Mutex};
+/// use drop_stream::{DropScope, DropStreamExt};
+///
+/// let tenants = Arc::new(Mutex::new(Vec::new()));
+/// let tenants_ref = tenants.clone();
+/// let scope = DropScope::new()
+///     .label("tenant", "acme")
+///     .on_drop(move |context| {
+///         let tenant = context.label("tenant").unwrap().to_owned();
+///         tenants_ref.lock().unwrap().push(tenant);
+///     });
+///
+/// let stream = scope.enter(|| futures::stream::repeat(true).on_drop_ctx(|_| {}));
+///
+/// drop(stream);
+/// assert_eq!(*tenants.lock().unwrap(), vec!["acme"]);
+/// ```
+#[derive(Clone, Default)]
+pub struct DropScope {
+    state: Arc<ScopeState>,
+}
+
+impl DropScope {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    fn state_mut(&mut self) -> &mut ScopeState {
+        Arc::make_mut(&mut self.state)
+    }
+
+    /// Adds a label to every wrapper created within the scope.
+    pub fn label(mut self, key: &'static str, value: impl Into<String>) -> Self {
+        self.state_mut().labels.push((key, value.into()));
+        self
+    }
+
+    /// Adds a hook called with the [`DropContext`] of every wrapper created within the scope.
+    pub fn on_drop(mut self, hook: impl Fn(&DropContext) + Send + Sync + 'static) -> Self {
+        self.state_mut().hooks.push(Arc::new(hook));
+        self
+    }
+
+    /// Runs `f` with the scope entered on the current thread.
+    pub fn enter<R>(&self, f: impl FnOnce() -> R) -> R {
+        let state = match current() {
+            Some(outer) => Arc::new(ScopeState {
+                labels: [outer.labels.as_slice(), &self.state.labels].concat(),
+                hooks: [outer.hooks.as_slice(), &self.state.hooks].concat(),
+            }),
+            None => self.state.clone(),
+        };
+
+        let _guard = EnterGuard {
+            previous: CURRENT.with(|current| current.replace(Some(state))),
+        };
+        f()
+    }
+
+    /// Wraps `future` so the scope is entered whenever it is polled.
+    pub fn scope<F: Future>(&self, future: F) -> Scoped<F> {
+        Scoped {
+            future,
+            scope: self.clone(),
+        }
+    }
+}
+
+impl fmt::Debug for ScopeState {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("DropScope")
+            .field("labels", &self.labels)
+            .field("hooks", &self.hooks.len())
+            .finish()
+    }
+}
+
+impl fmt::Debug for DropScope {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.state.fmt(f)
+    }
+}
+
+/// Restores the previously entered scope, even if the closure panics.
+struct EnterGuard {
+    previous: Option<Arc<ScopeState>>,
+}
+
+impl Drop for EnterGuard {
+    fn drop(&mut self) {
+        let previous = self.previous.take();
+        CURRENT.with(|current| *current.borrow_mut() = previous);
+    }
+}
+
+/// A future that enters a [`DropScope`] whenever it is polled. Created by [`DropScope::scope`].
+#[pin_project]
+#[derive(Debug)]
+#[must_use = "futures do nothing unless polled"]
+pub struct Scoped<F> {
+    #[pin]
+    future: F,
+    scope: DropScope,
+}
+
+impl<F: Future> Future for Scoped<F> {
+    type Output = F::Output;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<F::Output> {
+        let this = self.project();
+        this.scope.enter(|| this.future.poll(cx))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{Arc, Mutex};
+
+    use crate::{DropContext, DropScope, DropStreamExt};
+    use futures::stream::repeat;
+
+    #[test]
+    fn nested_scopes_combine_labels_and_hooks() {
+        let seen = Arc::new(Mutex::new(Vec::new()));
+        let contexts = Arc::new(Mutex::new(Vec::<DropContext>::new()));
+
+        let seen_ref = seen.clone();
+        let outer = DropScope::new()
+            .label("team", "platform")
+            .on_drop(move |_| seen_ref.lock().unwrap().push("outer"));
+        let seen_ref = seen.clone();
+        let inner = DropScope::new()
+            .label("route", "/events")
+            .on_drop(move |_| seen_ref.lock().unwrap().push("inner"));
+
+        let contexts_ref = contexts.clone();
+        let drop_stream = outer.enter(|| {
+            inner.enter(|| {
+                repeat(true).on_drop_ctx(move |context| contexts_ref.lock().unwrap().push(context))
+            })
+        });
+
+        drop(drop_stream);
+        assert_eq!(*seen.lock().unwrap(), vec!["outer", "inner"]);
+
+        let contexts = contexts.lock().unwrap();
+        assert_eq!(
+            contexts[0].labels(),
+            [
+                ("team", "platform".to_owned()),
+                ("route", "/events".to_owned())
+            ]
+        );
+    }
+
+    #[test]
+    fn scope_ends_with_enter() {
+        let contexts = Arc::new(Mutex::new(Vec::<DropContext>::new()));
+        let scope = DropScope::new().label("tenant", "acme");
+
+        scope.enter(|| {});
+        let contexts_ref = contexts.clone();
+        drop(repeat(true).on_drop_ctx(move |context| contexts_ref.lock().unwrap().push(context)));
+
+        assert!(contexts.lock().unwrap()[0].labels().is_empty());
+    }
+
+    #[test]
+    fn scoped_future_enters_on_poll() {
+        let contexts = Arc::new(Mutex::new(Vec::<DropContext>::new()));
+        let scope = DropScope::new().label("tenant", "acme");
+
+        let contexts_ref = contexts.clone();
+        futures::executor::block_on(scope.scope(async move {
+            drop(
+                repeat(true).on_drop_ctx(move |context| contexts_ref.lock().unwrap().push(context)),
+            );
+        }));
+
+        assert_eq!(contexts.lock().unwrap()[0].label("tenant"), Some("acme"));
+    }
+}
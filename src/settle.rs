@@ -0,0 +1,299 @@
+use futures_core::Stream;
+use pin_project::{pin_project, pinned_drop};
+use std::{
+    collections::BTreeMap,
+    fmt,
+    ops::Deref,
+    pin::Pin,
+    sync::{Arc, Mutex, MutexGuard},
+    task::{Context, Poll},
+};
+
+/// Confirms or rejects a message-queue delivery, such as the acker of an AMQP delivery, the offset
+/// of a Kafka message to commit, or a JetStream message. See [`SettleOnDrop`].
+///
+/// Both methods take the handle by value, as a delivery is settled once. Brokers whose clients
+/// acknowledge asynchronously are expected to spawn the acknowledgement.
+///
+/// The crate doesn't implement it for any broker client, such as `lapin`, `rdkafka` or
+/// `async-nats`, as that would tie its releases to theirs. Implement it for a newtype around the
+/// client's handle instead, e.g. one holding a `lapin` `Acker` that spawns `ack` and `nack`.
+pub trait Acknowledge {
+    /// Confirms the delivery, so the broker doesn't redeliver it.
+    fn ack(self);
+
+    /// Rejects the delivery, so the broker redelivers it, possibly to another consumer.
+    fn nack(self);
+}
+
+/// What [`SettleOnDrop`] does with the deliveries that are still outstanding when it is dropped.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Settlement {
+    /// Acknowledge them, for consumers that hand deliveries off to work that finishes on its own.
+    Ack,
+    /// Reject them, so they are redelivered.
+    Nack,
+}
+
+struct State<A> {
+    // The handles of the deliveries that were yielded but not settled yet, by delivery order.
+    outstanding: BTreeMap<u64, A>,
+    next_id: u64,
+}
+
+struct Shared<A>(Mutex<State<A>>);
+
+impl<A> Shared<A> {
+    fn lock(&self) -> MutexGuard<'_, State<A>> {
+        self.0.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+/// A stream of message-queue deliveries that settles every delivery still outstanding once it is
+/// dropped, so what happens to unconfirmed messages when the consumer disappears is decided in one
+/// place.
+///
+/// Deliveries are yielded as an [`Outstanding`], along with the [`Acknowledge`] handle of each
+/// that the closure passed to [`settle_on_drop`](crate::DropStreamExt::settle_on_drop) extracts.
+/// They are settled through [`Outstanding::ack`] and [`Outstanding::nack`]. Once the stream is
+/// dropped, every delivery that wasn't settled yet is nacked in the order it was yielded, or
+/// acked with [`on_drop`](Self::on_drop) set to [`Settlement::Ack`]. Settling a delivery after
+/// that does nothing.
+///
+/// Example
+/// ```
+/// use std::sync::mpsc::Sender;
+/// use futures::{executor::block_on_stream, stream};
+/// use drop_stream::{Acknowledge, DropStreamExt};
+///
+/// struct Acker(u64, Sender<String>);
+///
+/// impl Acknowledge for Acker {
+///     fn ack(self) {
+///         self.1.send(format!("ack {}", self.0)).unwrap();
+///     }
+///
+///     fn nack(self) {
+///         self.1.send(format!("nack {}", self.0)).unwrap();
+///     }
+/// }
+///
+/// let (sender, log) = std::sync::mpsc::channel();
+/// let deliveries = stream::iter([1, 2, 3])
+///     .settle_on_drop(move |tag: &u64| Acker(*tag, sender.clone()));
+///
+/// let mut deliveries = block_on_stream(deliveries);
+/// deliveries.next().unwrap().ack();
+/// let _in_progress = deliveries.next().unwrap();
+/// drop(deliveries);
+///
+/// assert_eq!(log.try_iter().collect::<Vec<_>>(), ["ack 1", "nack 2"]);
+/// ```
+#[pin_project(PinnedDrop)]
+pub struct SettleOnDrop<S, F, A>
+where
+    S: Stream,
+    F: FnMut(&S::Item) -> A,
+    A: Acknowledge,
+{
+    #[pin]
+    stream: S,
+    acker: F,
+    shared: Arc<Shared<A>>,
+    settlement: Settlement,
+}
+
+impl<S, F, A> SettleOnDrop<S, F, A>
+where
+    S: Stream,
+    F: FnMut(&S::Item) -> A,
+    A: Acknowledge,
+{
+    pub fn new(stream: S, acker: F) -> Self {
+        Self {
+            stream,
+            acker,
+            shared: Arc::new(Shared(Mutex::new(State {
+                outstanding: BTreeMap::new(),
+                next_id: 0,
+            }))),
+            settlement: Settlement::Nack,
+        }
+    }
+
+    /// Sets what is done with the outstanding deliveries on drop, [`Settlement::Nack`] by default.
+    pub fn on_drop(mut self, settlement: Settlement) -> Self {
+        self.settlement = settlement;
+        self
+    }
+
+    /// Returns the number of yielded deliveries that were not settled yet.
+    pub fn outstanding(&self) -> usize {
+        self.shared.lock().outstanding.len()
+    }
+}
+
+impl<S, F, A> Stream for SettleOnDrop<S, F, A>
+where
+    S: Stream,
+    F: FnMut(&S::Item) -> A,
+    A: Acknowledge,
+{
+    type Item = Outstanding<S::Item, A>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let this = self.project();
+
+        let Poll::Ready(item) = this.stream.poll_next(cx) else {
+            return Poll::Pending;
+        };
+
+        Poll::Ready(item.map(|item| {
+            let acker = (this.acker)(&item);
+            let mut state = this.shared.lock();
+            let id = state.next_id;
+            state.next_id += 1;
+            state.outstanding.insert(id, acker);
+
+            Outstanding {
+                item,
+                id,
+                shared: this.shared.clone(),
+            }
+        }))
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.stream.size_hint()
+    }
+}
+
+#[pinned_drop]
+impl<S, F, A> PinnedDrop for SettleOnDrop<S, F, A>
+where
+    S: Stream,
+    F: FnMut(&S::Item) -> A,
+    A: Acknowledge,
+{
+    fn drop(self: Pin<&mut Self>) {
+        let outstanding = std::mem::take(&mut self.shared.lock().outstanding);
+
+        for acker in outstanding.into_values() {
+            match self.settlement {
+                Settlement::Ack => acker.ack(),
+                Settlement::Nack => acker.nack(),
+            }
+        }
+    }
+}
+
+impl<S, F, A> fmt::Debug for SettleOnDrop<S, F, A>
+where
+    S: Stream,
+    F: FnMut(&S::Item) -> A,
+    A: Acknowledge,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("SettleOnDrop")
+            .field("outstanding", &self.outstanding())
+            .field("settlement", &self.settlement)
+            .finish_non_exhaustive()
+    }
+}
+
+/// A delivery yielded by a [`SettleOnDrop`] stream, which is settled on the stream's drop unless
+/// it is settled before.
+///
+/// Dropping it without settling leaves the delivery outstanding.
+pub struct Outstanding<T, A> {
+    item: T,
+    id: u64,
+    shared: Arc<Shared<A>>,
+}
+
+impl<T, A: Acknowledge> Outstanding<T, A> {
+    /// Acknowledges the delivery and returns it.
+    pub fn ack(self) -> T {
+        if let Some(acker) = self.take() {
+            acker.ack();
+        }
+        self.item
+    }
+
+    /// Rejects the delivery and returns it.
+    pub fn nack(self) -> T {
+        if let Some(acker) = self.take() {
+            acker.nack();
+        }
+        self.item
+    }
+
+    fn take(&self) -> Option<A> {
+        self.shared.lock().outstanding.remove(&self.id)
+    }
+
+    /// Returns the delivery without settling it.
+    pub fn into_inner(self) -> T {
+        self.item
+    }
+}
+
+impl<T, A> Deref for Outstanding<T, A> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        &self.item
+    }
+}
+
+impl<T: fmt::Debug, A> fmt::Debug for Outstanding<T, A> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Outstanding")
+            .field("item", &self.item)
+            .field("id", &self.id)
+            .finish_non_exhaustive()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{Arc, Mutex};
+
+    use crate::{Acknowledge, DropStreamExt, Settlement};
+    use futures::{executor::block_on_stream, stream::iter};
+
+    type Log = Arc<Mutex<Vec<(u32, bool)>>>;
+
+    struct Acker(u32, Log);
+
+    impl Acknowledge for Acker {
+        fn ack(self) {
+            self.1.lock().unwrap().push((self.0, true));
+        }
+
+        fn nack(self) {
+            self.1.lock().unwrap().push((self.0, false));
+        }
+    }
+
+    #[test]
+    fn outstanding_deliveries_are_acked_when_configured() {
+        let log = Log::default();
+
+        let log_ref = log.clone();
+        let deliveries = iter([1, 2, 3])
+            .settle_on_drop(move |tag| Acker(*tag, log_ref.clone()))
+            .on_drop(Settlement::Ack);
+        let mut deliveries = block_on_stream(deliveries);
+
+        assert_eq!(deliveries.next().unwrap().nack(), 1);
+        let second = deliveries.next().unwrap();
+        let third = deliveries.next().unwrap();
+        drop(second);
+        drop(deliveries);
+
+        // Settling after the stream was dropped does nothing.
+        assert_eq!(third.nack(), 3);
+        assert_eq!(*log.lock().unwrap(), [(1, false), (2, true), (3, true)]);
+    }
+}
@@ -0,0 +1,203 @@
+use futures_core::Future;
+use std::{
+    pin::Pin,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex, OnceLock,
+    },
+    task::{Context, Poll, Waker},
+};
+
+use crate::DropContext;
+
+/// A one-shot notification shared between the parts of a wrapper, such as a stream and its
+/// remote handle. Once fired it stays fired, and every task that polled it is woken.
+#[derive(Debug, Default)]
+pub(crate) struct Signal {
+    fired: AtomicBool,
+    wakers: Mutex<Vec<Waker>>,
+}
+
+impl Signal {
+    pub(crate) fn fire(&self) {
+        if self.fired.swap(true, Ordering::AcqRel) {
+            return;
+        }
+
+        let wakers = std::mem::take(&mut *self.wakers.lock().unwrap_or_else(|e| e.into_inner()));
+        for waker in wakers {
+            waker.wake();
+        }
+    }
+
+    pub(crate) fn is_fired(&self) -> bool {
+        self.fired.load(Ordering::Acquire)
+    }
+
+    pub(crate) fn poll_fired(&self, cx: &mut Context<'_>) -> Poll<()> {
+        if self.is_fired() {
+            return Poll::Ready(());
+        }
+
+        let mut wakers = self.wakers.lock().unwrap_or_else(|e| e.into_inner());
+        // Checked again while holding the lock, since `fire` drains the wakers under it.
+        if self.is_fired() {
+            return Poll::Ready(());
+        }
+
+        if !wakers.iter().any(|waker| waker.will_wake(cx.waker())) {
+            wakers.push(cx.waker().clone());
+        }
+
+        Poll::Pending
+    }
+}
+
+/// A future that resolves once a stream wrapped with
+/// [`drop_signal`](crate::DropStreamExt::drop_signal) is dropped.
+///
+/// Unlike a oneshot receiver it can be cloned, so any number of independent subsystems can each
+/// await the same stream's drop without coordinating who owns the notification. A clone created
+/// after the drop resolves immediately.
+///
+/// Example
+/// ```
+/// use drop_stream::DropStreamExt;
+///
+/// let (stream, signal) = futures::stream::repeat(true).drop_signal();
+/// let (metrics, sessions) = (signal.clone(), signal);
+///
+/// drop(stream);
+/// futures::executor::block_on(async {
+///     metrics.await;
+///     sessions.await;
+/// });
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct DropSignal {
+    signal: Arc<Signal>,
+}
+
+impl DropSignal {
+    pub(crate) fn fire(&self) {
+        self.signal.fire();
+    }
+
+    /// Returns true if the stream has been dropped.
+    pub fn is_dropped(&self) -> bool {
+        self.signal.is_fired()
+    }
+}
+
+impl Future for DropSignal {
+    type Output = ();
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<()> {
+        self.signal.poll_fired(cx)
+    }
+}
+
+#[derive(Debug, Default)]
+struct Dropped {
+    context: OnceLock<DropContext>,
+    signal: Signal,
+}
+
+/// A future that resolves with the [`DropContext`] of a stream wrapped with
+/// [`once_dropped`](crate::DropStreamExt::once_dropped) once it is dropped.
+///
+/// A supervising task can both wait for the stream to go away and inspect why it did, how many
+/// items it yielded and the labels it was given, without the stream's owner passing that along.
+/// Like [`DropSignal`] it can be cloned, and every clone resolves with the same context.
+///
+/// Example
+/// ```
+/// use drop_stream::{DropReason, DropStreamExt};
+///
+/// let (stream, dropped) = futures::stream::iter([1, 2, 3]).once_dropped();
+/// let stream = stream.label("session", "42");
+///
+/// assert_eq!(futures::executor::block_on_stream(stream).count(), 3);
+/// let context = futures::executor::block_on(dropped);
+/// assert_eq!(context.reason(), DropReason::Completed);
+/// assert_eq!((context.items(), context.label("session")), (3, Some("42")));
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct OnceDropped {
+    dropped: Arc<Dropped>,
+}
+
+impl OnceDropped {
+    pub(crate) fn fire(&self, context: DropContext) {
+        let _ = self.dropped.context.set(context);
+        self.dropped.signal.fire();
+    }
+
+    /// Returns the context of the stream if it has been dropped.
+    pub fn context(&self) -> Option<&DropContext> {
+        self.dropped.context.get()
+    }
+}
+
+impl Future for OnceDropped {
+    type Output = DropContext;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<DropContext> {
+        match self.dropped.signal.poll_fired(cx) {
+            Poll::Ready(()) => Poll::Ready(
+                self.dropped
+                    .context
+                    .get()
+                    .cloned()
+                    .expect("context set before the signal fires"),
+            ),
+            Poll::Pending => Poll::Pending,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::task::Poll;
+
+    use crate::{DropReason, DropStreamExt};
+    use futures::{future::Future, stream::repeat};
+
+    #[test]
+    fn every_clone_is_woken_by_the_drop() {
+        let (stream, signal) = repeat(true).drop_signal();
+        let mut listeners: Vec<_> = (0..3).map(|_| Box::pin(signal.clone())).collect();
+
+        let (waker, count) = futures_test::task::new_count_waker();
+        let mut cx = futures::task::Context::from_waker(&waker);
+        for listener in &mut listeners {
+            assert_eq!(listener.as_mut().poll(&mut cx), Poll::Pending);
+        }
+
+        drop(stream);
+        assert!(signal.is_dropped());
+        assert_eq!(count, 1);
+        for listener in &mut listeners {
+            assert_eq!(listener.as_mut().poll(&mut cx), Poll::Ready(()));
+        }
+    }
+
+    #[test]
+    fn once_dropped_wakes_with_the_context() {
+        let (stream, dropped) = repeat(true).once_dropped();
+        let mut waiter = Box::pin(dropped.clone());
+
+        let (waker, count) = futures_test::task::new_count_waker();
+        let mut cx = futures::task::Context::from_waker(&waker);
+        assert_eq!(waiter.as_mut().poll(&mut cx), Poll::Pending);
+        assert!(dropped.context().is_none());
+
+        drop(stream.name("feed"));
+        assert_eq!(count, 1);
+        let Poll::Ready(context) = waiter.as_mut().poll(&mut cx) else {
+            panic!("not woken with the context");
+        };
+        assert_eq!(context.reason(), DropReason::Cancelled);
+        assert_eq!(dropped.context().and_then(|c| c.name()), Some("feed"));
+    }
+}
@@ -0,0 +1,391 @@
+use futures_core::Stream;
+use futures_sink::Sink;
+use std::{
+    fmt,
+    pin::Pin,
+    sync::{Arc, Mutex, MutexGuard, TryLockError},
+    task::{Context, Poll, Waker},
+};
+
+use crate::{CloseWith, DropStream, Dropper, Spawn};
+
+/// Forwarded from the inner stream, so wrapping a duplex such as a framed transport with
+/// [`on_drop`](crate::DropStreamExt::on_drop) keeps its write half.
+impl<S: Stream<Item = T> + Sink<I>, T, U: FnOnce(), I> Sink<I> for DropStream<S, T, U> {
+    type Error = S::Error;
+
+    fn poll_ready(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.project().stream.poll_ready(cx)
+    }
+
+    fn start_send(self: Pin<&mut Self>, item: I) -> Result<(), Self::Error> {
+        self.project().stream.start_send(item)
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.project().stream.poll_flush(cx)
+    }
+
+    fn poll_close(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.project().stream.poll_close(cx)
+    }
+}
+
+impl<S: Stream<Item = T>, T, U: FnOnce()> DropStream<S, T, U> {
+    /// Splits a wrapped duplex, such as a framed transport, into a stream half and a sink half
+    /// that share the closure, which runs once both halves have been dropped.
+    ///
+    /// The halves take turns at the inner stream through a lock. As with `StreamExt::split`, a
+    /// half that finds the lock taken by the other one registers its waker and returns `Pending`
+    /// rather than blocking, and is woken once the other half is done. Only `start_send` and
+    /// `size_hint`, which can't return `Pending`, wait for the other half's call in progress to
+    /// return, which doesn't take longer than one poll of the inner stream.
+    pub fn split(self) -> (DropSplitStream<S, U>, DropSplitSink<S, U>) {
+        let DropStream { dropper, stream } = self;
+
+        let shared = Arc::new(SplitShared {
+            dropper,
+            stream: Mutex::new(Box::pin(stream)),
+            waiter: Mutex::new(None),
+        });
+
+        (
+            DropSplitStream {
+                shared: shared.clone(),
+            },
+            DropSplitSink { shared },
+        )
+    }
+
+    /// Sends `message` into a wrapped duplex before it is closed, or from a background task
+    /// spawned with `spawner` if it is dropped without being closed. The closure runs once the
+    /// duplex has been closed. See [`CloseWith`].
+    pub fn close_with<I, Sp>(self, message: I, spawner: Sp) -> CloseWith<Self, I, Sp>
+    where
+        Self: Sink<I> + Unpin + Send + 'static,
+        I: Send + 'static,
+        Sp: Spawn,
+    {
+        CloseWith::new(self, message, spawner)
+    }
+}
+
+/// A [`DropStream`] put back together from its halves, see [`DropSplitStream::try_unwrap`].
+pub type Reunited<S, U> = DropStream<Pin<Box<S>>, <S as Stream>::Item, U>;
+
+struct SplitShared<S, U: FnOnce()> {
+    // Declared before the stream so the closure runs before the inner stream is dropped.
+    dropper: Dropper<U>,
+    stream: Mutex<Pin<Box<S>>>,
+    // The half waiting for the other one to release the stream.
+    waiter: Mutex<Option<Waker>>,
+}
+
+impl<S, U: FnOnce()> SplitShared<S, U> {
+    /// Calls `f` with the stream, waiting for it if the other half is using it.
+    fn with<R>(&self, f: impl FnOnce(Pin<&mut S>) -> R) -> R {
+        let result = f(self
+            .stream
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .as_mut());
+        self.wake_waiter();
+        result
+    }
+
+    /// Calls `f` with the stream, or returns `Pending` and wakes the task once the other half
+    /// released the stream if it is using it.
+    fn poll_with<R>(
+        &self,
+        cx: &mut Context<'_>,
+        f: impl FnOnce(Pin<&mut S>, &mut Context<'_>) -> Poll<R>,
+    ) -> Poll<R> {
+        let mut stream = match self.try_lock() {
+            Some(stream) => stream,
+            None => {
+                *self.waiter.lock().unwrap_or_else(|e| e.into_inner()) = Some(cx.waker().clone());
+                // Retried in case the other half released the stream before the waker was set.
+                match self.try_lock() {
+                    Some(stream) => stream,
+                    None => return Poll::Pending,
+                }
+            }
+        };
+
+        let poll = f(stream.as_mut(), cx);
+        drop(stream);
+        self.wake_waiter();
+        poll
+    }
+
+    fn try_lock(&self) -> Option<MutexGuard<'_, Pin<Box<S>>>> {
+        match self.stream.try_lock() {
+            Ok(stream) => Some(stream),
+            Err(TryLockError::Poisoned(e)) => Some(e.into_inner()),
+            Err(TryLockError::WouldBlock) => None,
+        }
+    }
+
+    fn wake_waiter(&self) {
+        let waiter = self.waiter.lock().unwrap_or_else(|e| e.into_inner()).take();
+        if let Some(waiter) = waiter {
+            waiter.wake();
+        }
+    }
+}
+
+impl<S: Stream, U: FnOnce()> SplitShared<S, U> {
+    /// Puts the wrapper back together if `shared` is the last handle to it.
+    fn try_unwrap(shared: Arc<Self>) -> Result<Reunited<S, U>, Arc<Self>> {
+        let SplitShared {
+            dropper, stream, ..
+        } = Arc::try_unwrap(shared)?;
+
+        Ok(DropStream {
+            dropper,
+            stream: stream.into_inner().unwrap_or_else(|e| e.into_inner()),
+        })
+    }
+}
+
+/// The stream half of a [`DropStream`] split with [`DropStream::split`].
+pub struct DropSplitStream<S, U: FnOnce()> {
+    shared: Arc<SplitShared<S, U>>,
+}
+
+impl<S: Stream, U: FnOnce()> DropSplitStream<S, U> {
+    /// Returns the inner stream along with the closure, without running it, if the sink half has
+    /// been dropped, so a single consumer can go back to polling it without the lock. Otherwise
+    /// returns the stream half back.
+    pub fn try_unwrap(self) -> Result<Reunited<S, U>, Self> {
+        SplitShared::try_unwrap(self.shared).map_err(|shared| Self { shared })
+    }
+}
+
+impl<S: Stream, U: FnOnce()> Stream for DropSplitStream<S, U> {
+    type Item = S::Item;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        self.shared.poll_with(cx, |stream, cx| stream.poll_next(cx))
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.shared.with(|stream| stream.size_hint())
+    }
+}
+
+impl<S, U: FnOnce()> fmt::Debug for DropSplitStream<S, U> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("DropSplitStream").finish_non_exhaustive()
+    }
+}
+
+/// The sink half of a [`DropStream`] split with [`DropStream::split`].
+pub struct DropSplitSink<S, U: FnOnce()> {
+    shared: Arc<SplitShared<S, U>>,
+}
+
+impl<S: Stream, U: FnOnce()> DropSplitSink<S, U> {
+    /// Returns the inner duplex along with the closure, without running it, if the stream half
+    /// has been dropped. Otherwise returns the sink half back. See
+    /// [`DropSplitStream::try_unwrap`].
+    pub fn try_unwrap(self) -> Result<Reunited<S, U>, Self> {
+        SplitShared::try_unwrap(self.shared).map_err(|shared| Self { shared })
+    }
+}
+
+impl<S: Sink<I>, U: FnOnce(), I> Sink<I> for DropSplitSink<S, U> {
+    type Error = S::Error;
+
+    fn poll_ready(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.shared
+            .poll_with(cx, |stream, cx| stream.poll_ready(cx))
+    }
+
+    fn start_send(self: Pin<&mut Self>, item: I) -> Result<(), Self::Error> {
+        self.shared.with(|stream| stream.start_send(item))
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.shared
+            .poll_with(cx, |stream, cx| stream.poll_flush(cx))
+    }
+
+    fn poll_close(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.shared
+            .poll_with(cx, |stream, cx| stream.poll_close(cx))
+    }
+}
+
+impl<S, U: FnOnce()> fmt::Debug for DropSplitSink<S, U> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("DropSplitSink").finish_non_exhaustive()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        collections::VecDeque,
+        convert::Infallible,
+        pin::Pin,
+        task::{Context, Poll},
+    };
+
+    use crate::DropStreamExt;
+    use futures::{executor::block_on, Sink, SinkExt, Stream, StreamExt};
+
+    /// A loopback duplex: items sent into it are yielded back out.
+    #[derive(Default)]
+    struct Loopback(VecDeque<u32>);
+
+    impl Stream for Loopback {
+        type Item = u32;
+
+        fn poll_next(mut self: Pin<&mut Self>, _: &mut Context<'_>) -> Poll<Option<u32>> {
+            Poll::Ready(self.0.pop_front())
+        }
+    }
+
+    impl Sink<u32> for Loopback {
+        type Error = Infallible;
+
+        fn poll_ready(self: Pin<&mut Self>, _: &mut Context<'_>) -> Poll<Result<(), Infallible>> {
+            Poll::Ready(Ok(()))
+        }
+
+        fn start_send(mut self: Pin<&mut Self>, item: u32) -> Result<(), Infallible> {
+            self.0.push_back(item);
+            Ok(())
+        }
+
+        fn poll_flush(self: Pin<&mut Self>, _: &mut Context<'_>) -> Poll<Result<(), Infallible>> {
+            Poll::Ready(Ok(()))
+        }
+
+        fn poll_close(self: Pin<&mut Self>, _: &mut Context<'_>) -> Poll<Result<(), Infallible>> {
+            Poll::Ready(Ok(()))
+        }
+    }
+
+    #[test]
+    fn sink_is_forwarded() {
+        let mut has_run = false;
+
+        {
+            let has_run_ref = &mut has_run;
+            let mut duplex = Loopback::default().on_drop(move || *has_run_ref = true);
+
+            block_on(async {
+                duplex.send(1).await.unwrap();
+                duplex.send(2).await.unwrap();
+                assert_eq!(duplex.next().await, Some(1));
+                assert_eq!(duplex.next().await, Some(2));
+            });
+        }
+
+        assert!(has_run);
+    }
+
+    #[test]
+    fn split_halves_share_one_dropper() {
+        let mut runs = 0;
+
+        {
+            let runs_ref = &mut runs;
+            let duplex = Loopback::default().on_drop(move || *runs_ref += 1);
+            let (mut reader, mut writer) = duplex.split();
+
+            block_on(async {
+                writer.send(1).await.unwrap();
+                drop(writer);
+                assert_eq!(reader.next().await, Some(1));
+            });
+        }
+
+        assert_eq!(runs, 1);
+    }
+
+    #[test]
+    fn last_half_unwraps_without_running_dropper() {
+        let mut runs = 0;
+
+        {
+            let runs_ref = &mut runs;
+            let duplex = Loopback::default().on_drop(move || *runs_ref += 1);
+            let (reader, mut writer) = duplex.split();
+
+            block_on(writer.send(1)).unwrap();
+            let Err(reader) = reader.try_unwrap() else {
+                panic!("unwrapped while the sink half is alive");
+            };
+            drop(writer);
+
+            let Ok(mut duplex) = reader.try_unwrap() else {
+                panic!("not unwrapped once the sink half is gone");
+            };
+            block_on(async {
+                duplex.send(2).await.unwrap();
+                assert_eq!(duplex.next().await, Some(1));
+                assert_eq!(duplex.next().await, Some(2));
+            });
+        }
+
+        assert_eq!(runs, 1);
+    }
+
+    /// A duplex whose stream half blocks inside `poll_next` until it is let through.
+    struct Gate {
+        entered: std::sync::mpsc::Sender<()>,
+        release: std::sync::mpsc::Receiver<()>,
+    }
+
+    impl Stream for Gate {
+        type Item = ();
+
+        fn poll_next(self: Pin<&mut Self>, _: &mut Context<'_>) -> Poll<Option<()>> {
+            self.entered.send(()).unwrap();
+            self.release.recv().unwrap();
+            Poll::Ready(None)
+        }
+    }
+
+    impl Sink<()> for Gate {
+        type Error = Infallible;
+
+        fn poll_ready(self: Pin<&mut Self>, _: &mut Context<'_>) -> Poll<Result<(), Infallible>> {
+            Poll::Ready(Ok(()))
+        }
+
+        fn start_send(self: Pin<&mut Self>, _: ()) -> Result<(), Infallible> {
+            Ok(())
+        }
+
+        fn poll_flush(self: Pin<&mut Self>, _: &mut Context<'_>) -> Poll<Result<(), Infallible>> {
+            Poll::Ready(Ok(()))
+        }
+
+        fn poll_close(self: Pin<&mut Self>, _: &mut Context<'_>) -> Poll<Result<(), Infallible>> {
+            Poll::Ready(Ok(()))
+        }
+    }
+
+    #[test]
+    fn contended_half_waits_for_a_wakeup_instead_of_blocking() {
+        let (entered, entered_rx) = std::sync::mpsc::channel();
+        let (release_tx, release) = std::sync::mpsc::channel();
+        let (mut reader, mut writer) = Gate { entered, release }.on_drop(|| {}).split();
+
+        let reading = std::thread::spawn(move || block_on(reader.next()));
+        entered_rx.recv().unwrap();
+
+        let (waker, wakes) = futures_test::task::new_count_waker();
+        let mut context = Context::from_waker(&waker);
+        assert!(Pin::new(&mut writer).poll_ready(&mut context).is_pending());
+
+        release_tx.send(()).unwrap();
+        assert_eq!(reading.join().unwrap(), None);
+        assert_eq!(wakes.get(), 1);
+        assert!(Pin::new(&mut writer).poll_ready(&mut context).is_ready());
+    }
+}
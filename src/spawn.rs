@@ -0,0 +1,46 @@
+use futures_core::Future;
+use std::pin::Pin;
+
+/// A boxed future handed to a [`Spawn`] implementation.
+pub type BoxFuture = Pin<Box<dyn Future<Output = ()> + Send + 'static>>;
+
+/// Spawns background work, such as draining a stream after it has been dropped.
+///
+/// This crate does not depend on any runtime, so wrappers that need to run work after the drop
+/// take a spawner instead. Any `Fn(BoxFuture)` closure is a spawner, and with the `tokio` feature
+/// enabled so is a [`tokio::runtime::Handle`].
+pub trait Spawn {
+    fn spawn(&self, future: BoxFuture);
+}
+
+impl<F: Fn(BoxFuture)> Spawn for F {
+    fn spawn(&self, future: BoxFuture) {
+        self(future)
+    }
+}
+
+#[cfg(feature = "tokio")]
+impl Spawn for tokio::runtime::Handle {
+    fn spawn(&self, future: BoxFuture) {
+        drop(tokio::runtime::Handle::spawn(self, future));
+    }
+}
+
+#[cfg(all(test, feature = "tokio"))]
+mod tests {
+    use crate::Spawn;
+
+    #[tokio::test]
+    async fn tokio_handle_spawns() {
+        let (sender, receiver) = tokio::sync::oneshot::channel();
+
+        Spawn::spawn(
+            &tokio::runtime::Handle::current(),
+            Box::pin(async move {
+                sender.send(()).unwrap();
+            }),
+        );
+
+        receiver.await.unwrap();
+    }
+}
@@ -0,0 +1,191 @@
+use futures_core::Stream;
+use pin_project::pin_project;
+use std::{
+    pin::Pin,
+    task::{Context, Poll},
+};
+
+use std::cmp::Reverse;
+
+type BoxDropper<'a> = Box<dyn FnOnce() + Send + 'a>;
+
+/// The order the closures of a [`StackedDropStream`] with the same priority run in.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum StackOrder {
+    /// Last registered runs first, the same order nested wrappers would run in.
+    #[default]
+    Lifo,
+    /// First registered runs first.
+    Fifo,
+}
+
+/// Holds the closures of a [`StackedDropStream`] and calls them once the holder is dropped.
+struct DropStack<'a> {
+    droppers: Vec<(i32, BoxDropper<'a>)>,
+    order: StackOrder,
+}
+
+impl Drop for DropStack<'_> {
+    fn drop(&mut self) {
+        let mut droppers = std::mem::take(&mut self.droppers);
+        if self.order == StackOrder::Lifo {
+            droppers.reverse();
+        }
+        // Stable, so closures with the same priority keep the order above.
+        droppers.sort_by_key(|(priority, _)| Reverse(*priority));
+
+        for (_, dropper) in droppers {
+            dropper()
+        }
+    }
+}
+
+/// A stream that wraps another stream with any number of closures that are called once it is
+/// dropped, without nesting a wrapper type per closure.
+///
+/// Calling [`on_drop`](StackedDropStream::on_drop) on it adds another closure instead of wrapping
+/// it again, so middleware layers can each register their cleanup and the type stays the same.
+/// The closures run last registered first, as nested wrappers would, unless the
+/// [`order`](StackedDropStream::order) is changed. Teardown steps that depend on each other can
+/// also be given a [priority](StackedDropStream::on_drop_with_priority). The closures are boxed,
+/// so they must be `Send` for the stream to stay `Send`, and the stream is not `UnwindSafe`; wrap
+/// it in `AssertUnwindSafe` to poll it within `catch_unwind`.
+///
+/// Example
+/// ```
+/// use std::sync::Mutex;
+/// use drop_stream::{DropStreamExt, StackedDropStream};
+///
+/// let order = Mutex::new(Vec::new());
+///
+/// fn layer<'a>(
+///     stream: StackedDropStream<'a, futures::stream::Repeat<bool>>,
+///     name: &'static str,
+///     order: &'a Mutex<Vec<&'static str>>,
+/// ) -> StackedDropStream<'a, futures::stream::Repeat<bool>> {
+///     stream.on_drop(move || order.lock().unwrap().push(name))
+/// }
+///
+/// let stream = futures::stream::repeat(true).on_drop_stacked(|| {});
+/// let stream = layer(layer(stream, "router", &order), "metrics", &order);
+///
+/// drop(stream);
+/// assert_eq!(*order.lock().unwrap(), ["metrics", "router"]);
+/// ```
+#[pin_project]
+pub struct StackedDropStream<'a, S: Stream> {
+    // Declared before the stream so the closures run before the inner stream is dropped.
+    droppers: DropStack<'a>,
+    #[pin]
+    stream: S,
+}
+
+impl<'a, S: Stream> StackedDropStream<'a, S> {
+    pub fn new(stream: S) -> Self {
+        Self {
+            droppers: DropStack {
+                droppers: Vec::new(),
+                order: StackOrder::Lifo,
+            },
+            stream,
+        }
+    }
+
+    /// Adds another closure to call once the stream is dropped, ahead of the ones already added.
+    pub fn on_drop(mut self, dropper: impl FnOnce() + Send + 'a) -> Self {
+        self.push(dropper);
+        self
+    }
+
+    /// Adds a closure that runs before every closure with a lower priority, regardless of the
+    /// order they were added in. Closures added with [`on_drop`](Self::on_drop) have priority 0.
+    pub fn on_drop_with_priority(
+        mut self,
+        priority: i32,
+        dropper: impl FnOnce() + Send + 'a,
+    ) -> Self {
+        self.push_with_priority(priority, dropper);
+        self
+    }
+
+    /// Sets the order closures with the same priority run in. Defaults to [`StackOrder::Lifo`].
+    pub fn order(mut self, order: StackOrder) -> Self {
+        self.droppers.order = order;
+        self
+    }
+
+    /// Like [`on_drop`](Self::on_drop), for layers that only have a mutable reference.
+    pub fn push(&mut self, dropper: impl FnOnce() + Send + 'a) {
+        self.push_with_priority(0, dropper);
+    }
+
+    /// Like [`on_drop_with_priority`](Self::on_drop_with_priority), for layers that only have a
+    /// mutable reference.
+    pub fn push_with_priority(&mut self, priority: i32, dropper: impl FnOnce() + Send + 'a) {
+        self.droppers.droppers.push((priority, Box::new(dropper)));
+    }
+
+    /// Returns the number of closures registered.
+    pub fn len(&self) -> usize {
+        self.droppers.droppers.len()
+    }
+
+    /// Returns true if no closures are registered.
+    pub fn is_empty(&self) -> bool {
+        self.droppers.droppers.is_empty()
+    }
+}
+
+impl<S: Stream> Stream for StackedDropStream<'_, S> {
+    type Item = S::Item;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        self.project().stream.poll_next(cx)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.stream.size_hint()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{test_util::DropTracker, DropStreamExt, StackOrder, StackedDropStream};
+    use futures::stream::repeat;
+
+    #[test]
+    fn stacked_droppers_run_last_registered_first() {
+        let tracker = DropTracker::new();
+
+        let mut drop_stream = tracker
+            .source::<()>("source")
+            .on_drop_stacked(tracker.callback("first"))
+            .on_drop(tracker.callback("second"));
+        drop_stream.push(tracker.callback("third"));
+        assert_eq!(drop_stream.len(), 3);
+
+        drop(drop_stream);
+        tracker.assert_order(&["third", "second", "first", "source"]);
+    }
+
+    #[test]
+    fn empty_stack_drops_cleanly() {
+        let drop_stream = StackedDropStream::new(repeat(true));
+        assert!(drop_stream.is_empty());
+    }
+
+    #[test]
+    fn fifo_order_and_priorities() {
+        let tracker = DropTracker::new();
+
+        let drop_stream = repeat(true)
+            .on_drop_stacked(tracker.callback("close socket"))
+            .on_drop(tracker.callback("flush"))
+            .on_drop_with_priority(10, tracker.callback("deregister"))
+            .on_drop_with_priority(-1, tracker.callback("log"))
+            .order(StackOrder::Fifo);
+
+        drop(drop_stream);
+        tracker.assert_order(&["deregister", "close socket", "flush", "log"]);
+    }
+}
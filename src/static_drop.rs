@@ -0,0 +1,131 @@
+use futures_core::Stream;
+use pin_project::pin_project;
+use std::{
+    fmt,
+    pin::Pin,
+    task::{Context, Poll},
+};
+
+use crate::dropper::Once;
+
+/// A stream that wraps another stream with a plain function that is called with a context value
+/// once it is dropped.
+///
+/// This is the closure-free counterpart of [`DropStream`](crate::DropStream), for streams created
+/// per packet or on targets where the wrapper's footprint matters: nothing is allocated, the
+/// wrapper can be named without generics for the closure, and it adds exactly the function pointer
+/// and the context on top of the stream, which is checked at compile time.
+///
+/// Example
+/// ```
+/// use std::sync::atomic::{AtomicUsize, Ordering};
+/// use drop_stream::DropStreamExt;
+///
+/// static CLOSED: AtomicUsize = AtomicUsize::new(0);
+///
+/// fn close(port: u16) {
+///     CLOSED.fetch_add(port.into(), Ordering::Relaxed);
+/// }
+///
+/// drop(futures::stream::repeat(true).on_drop_fn(close, 8080));
+/// assert_eq!(CLOSED.load(Ordering::Relaxed), 8080);
+/// ```
+#[pin_project]
+pub struct StaticDropStream<S, C> {
+    // Declared before the stream so the function runs before the inner stream is dropped.
+    dropper: StaticDropper<C>,
+    #[pin]
+    stream: S,
+}
+
+impl<S: Stream, C> StaticDropStream<S, C> {
+    pub fn new(stream: S, dropper: fn(C), context: C) -> Self {
+        Self {
+            dropper: StaticDropper(Once::new((dropper, context))),
+            stream,
+        }
+    }
+
+    /// Returns the context the function is called with.
+    pub fn context(&self) -> &C {
+        // Only taken in the drop method.
+        let (_, context) = self.dropper.0.get().expect("context taken before drop");
+        context
+    }
+}
+
+impl<S: Stream, C> Stream for StaticDropStream<S, C> {
+    type Item = S::Item;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        self.project().stream.poll_next(cx)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.stream.size_hint()
+    }
+}
+
+impl<S, C: fmt::Debug> fmt::Debug for StaticDropStream<S, C> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("StaticDropStream")
+            .field("context", &self.dropper.0.get().map(|(_, context)| context))
+            .finish_non_exhaustive()
+    }
+}
+
+struct StaticDropper<C>(Once<(fn(C), C)>);
+
+impl<C> Drop for StaticDropper<C> {
+    fn drop(&mut self) {
+        if let Some((dropper, context)) = self.0.take() {
+            dropper(context)
+        }
+    }
+}
+
+// Whether the function was called is stored in the niche of the function pointer.
+const _: () = {
+    type Inner = Pin<Box<dyn Stream<Item = u64>>>;
+    assert!(
+        size_of::<StaticDropStream<Inner, u64>>()
+            == size_of::<Inner>() + size_of::<fn(u64)>() + size_of::<u64>()
+    );
+};
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        sync::atomic::{AtomicU32, Ordering},
+        task::Poll,
+    };
+
+    use crate::{DropStreamExt, StaticDropStream};
+    use futures::{stream::iter, Stream};
+
+    #[test]
+    fn function_gets_the_context_on_drop() {
+        static DROPPED: AtomicU32 = AtomicU32::new(0);
+
+        {
+            let stream = StaticDropStream::new(
+                iter([1, 2]),
+                |id| {
+                    DROPPED.store(id, Ordering::SeqCst);
+                },
+                7,
+            );
+            let mut stream = Box::pin(stream);
+            assert_eq!(*stream.context(), 7);
+
+            let waker = futures::task::noop_waker();
+            let mut cx = futures::task::Context::from_waker(&waker);
+            assert_eq!(stream.as_mut().poll_next(&mut cx), Poll::Ready(Some(1)));
+            assert_eq!(DROPPED.load(Ordering::SeqCst), 0);
+        }
+
+        assert_eq!(DROPPED.load(Ordering::SeqCst), 7);
+        drop(iter([1]).on_drop_fn(|id| DROPPED.store(id, Ordering::SeqCst), 9));
+        assert_eq!(DROPPED.load(Ordering::SeqCst), 9);
+    }
+}
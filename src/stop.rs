@@ -0,0 +1,173 @@
+use futures_core::Stream;
+use pin_project::{pin_project, pinned_drop};
+use std::{
+    pin::Pin,
+    task::{Context, Poll},
+};
+
+use crate::{dropper::ReasonDropper, DropReason};
+
+/// A receive stream that can tell its peer to stop sending, such as an HTTP/3 or WebTransport
+/// receive stream, or a QUIC stream in general.
+///
+/// Implement it for the receive stream of the transport in use, e.g. by calling `stop_sending` on
+/// an `h3` stream or `stop` on a `quinn` one, to wrap it in a [`StopOnDrop`]. The crate doesn't
+/// implement it for those itself, as that would tie its releases to theirs, so implement it for a
+/// newtype around their receive stream that also yields its items as a [`Stream`].
+pub trait StopSending {
+    /// Tells the peer to stop sending, with the application error `code`.
+    fn stop_sending(self: Pin<&mut Self>, code: u64);
+}
+
+/// A receive stream that tells its peer to stop sending when it is dropped before it finished,
+/// and then calls the closure with the reason it ended.
+///
+/// Sessions multiplexed over one connection only release a stream's flow control and buffers on
+/// the peer once it is told to stop, so abandoning a stream without doing so leaks them for as
+/// long as the session lives. A stream that finished is not stopped. For datagram streams, which
+/// have no stop signal, [`on_drop`](crate::DropStreamExt::on_drop) is all that is needed.
+///
+/// Example
+/// ```
+/// use std::pin::Pin;
+/// use drop_stream::{DropReason, StopOnDrop, StopSending};
+///
+/// struct RecvStream {
+///     stopped: Option<u64>,
+/// #   data: futures::stream::Repeat<u8>,
+/// }
+/// # impl futures::Stream for RecvStream {
+/// #     type Item = u8;
+/// #     fn poll_next(mut self: Pin<&mut Self>, cx: &mut std::task::Context<'_>) -> std::task::Poll<Option<u8>> {
+/// #         Pin::new(&mut self.data).poll_next(cx)
+/// #     }
+/// # }
+///
+/// impl StopSending for RecvStream {
+///     fn stop_sending(mut self: Pin<&mut Self>, code: u64) {
+///         self.stopped = Some(code);
+///     }
+/// }
+///
+/// let mut reason = None;
+/// let reason_ref = &mut reason;
+/// let stream = RecvStream { stopped: None, data: futures::stream::repeat(0) };
+/// drop(StopOnDrop::new(stream, 0x10c, move |r| *reason_ref = Some(r)));
+/// assert_eq!(reason, Some(DropReason::Cancelled));
+/// ```
+#[pin_project(PinnedDrop)]
+pub struct StopOnDrop<S: StopSending, U: FnOnce(DropReason)> {
+    #[pin]
+    stream: S,
+    code: u64,
+    dropper: ReasonDropper<U>,
+}
+
+impl<S: Stream + StopSending, U: FnOnce(DropReason)> StopOnDrop<S, U> {
+    pub fn new(stream: S, code: u64, dropper: U) -> Self {
+        Self {
+            stream,
+            code,
+            dropper: ReasonDropper::new(dropper),
+        }
+    }
+
+    /// Returns the error code the peer is told to stop sending with.
+    pub fn code(&self) -> u64 {
+        self.code
+    }
+}
+
+impl<S: Stream + StopSending, U: FnOnce(DropReason)> Stream for StopOnDrop<S, U> {
+    type Item = S::Item;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let this = self.project();
+
+        let poll = this.stream.poll_next(cx);
+        if let Poll::Ready(None) = poll {
+            this.dropper.reason = DropReason::Completed;
+        }
+
+        poll
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.stream.size_hint()
+    }
+}
+
+#[pinned_drop]
+impl<S: StopSending, U: FnOnce(DropReason)> PinnedDrop for StopOnDrop<S, U> {
+    fn drop(self: Pin<&mut Self>) {
+        let this = self.project();
+
+        let reason = this.dropper.reason;
+        if reason.is_cancelled() {
+            this.stream.stop_sending(*this.code);
+        }
+        this.dropper.fire(reason);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        pin::Pin,
+        sync::{Arc, Mutex},
+        task::{Context, Poll},
+    };
+
+    use crate::{DropReason, StopOnDrop, StopSending};
+    use futures::{executor::block_on_stream, stream::Iter, Stream};
+
+    /// A receive stream that logs when it is stopped.
+    struct Recv {
+        data: Iter<std::vec::IntoIter<u8>>,
+        log: Arc<Mutex<Vec<String>>>,
+    }
+
+    impl Stream for Recv {
+        type Item = u8;
+
+        fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<u8>> {
+            Pin::new(&mut self.data).poll_next(cx)
+        }
+    }
+
+    impl StopSending for Recv {
+        fn stop_sending(self: Pin<&mut Self>, code: u64) {
+            self.log.lock().unwrap().push(format!("stopped {code}"));
+        }
+    }
+
+    fn wrapped(log: &Arc<Mutex<Vec<String>>>) -> StopOnDrop<Recv, impl FnOnce(DropReason)> {
+        let recv = Recv {
+            data: futures::stream::iter(vec![1, 2]),
+            log: log.clone(),
+        };
+        let log = log.clone();
+        StopOnDrop::new(recv, 7, move |reason| {
+            log.lock().unwrap().push(format!("{reason:?}"))
+        })
+    }
+
+    #[test]
+    fn abandoned_stream_is_stopped_before_dropper() {
+        let log = Arc::new(Mutex::new(Vec::new()));
+
+        let mut stream = block_on_stream(wrapped(&log));
+        assert_eq!(stream.next(), Some(1));
+        drop(stream);
+
+        assert_eq!(*log.lock().unwrap(), ["stopped 7", "Cancelled"]);
+    }
+
+    #[test]
+    fn finished_stream_is_not_stopped() {
+        let log = Arc::new(Mutex::new(Vec::new()));
+
+        assert_eq!(block_on_stream(wrapped(&log)).count(), 2);
+        assert_eq!(*log.lock().unwrap(), ["Completed"]);
+    }
+}
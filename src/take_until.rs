@@ -0,0 +1,183 @@
+use futures_core::{Future, Stream};
+use pin_project::pin_project;
+use std::{
+    pin::Pin,
+    task::{Context, Poll},
+};
+
+use crate::{dropper::ReasonDropper, DropReason};
+
+/// A stream that ends once an external signal future completes, running a closure with the
+/// [`DropReason`] for why it ended.
+///
+/// The closure runs exactly once: with [`DropReason::Stopped`] as soon as the signal completes,
+/// or otherwise with [`DropReason::Completed`] or [`DropReason::Cancelled`] when the wrapper is
+/// dropped, depending on whether the inner stream had finished.
+///
+/// Example
+/// ```
+/// use futures::{future, stream, Stream};
+/// use drop_stream::{DropReason, DropStreamExt};
+///
+/// let mut reason = None;
+/// let reason_ref = &mut reason;
+/// let stream = stream::repeat(true).take_until_dropped(future::ready(()), move |r| {
+///     *reason_ref = Some(r);
+/// });
+///
+/// let mut stream = Box::pin(stream);
+///
+/// let waker = futures::task::noop_waker();
+/// let mut context = futures::task::Context::from_waker(&waker);
+/// assert_eq!(
+///     stream.as_mut().poll_next(&mut context),
+///     std::task::Poll::Ready(None)
+/// );
+///
+/// drop(stream);
+/// assert_eq!(reason, Some(DropReason::Stopped));
+/// ```
+#[pin_project]
+pub struct TakeUntilDropped<S: Stream, F: Future, U: FnOnce(DropReason)> {
+    // Declared before the stream so the closure runs before the inner stream is dropped.
+    dropper: ReasonDropper<U>,
+    #[pin]
+    stream: S,
+    // Set to None once the signal has completed, so it is never polled again.
+    #[pin]
+    signal: Option<F>,
+}
+
+impl<S: Stream, F: Future, U: FnOnce(DropReason)> TakeUntilDropped<S, F, U> {
+    pub fn new(stream: S, signal: F, dropper: U) -> Self {
+        Self {
+            dropper: ReasonDropper::new(dropper),
+            stream,
+            signal: Some(signal),
+        }
+    }
+}
+
+impl<S: Stream, F: Future, U: FnOnce(DropReason)> Stream for TakeUntilDropped<S, F, U> {
+    type Item = S::Item;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let mut this = self.project();
+
+        let Some(signal) = this.signal.as_mut().as_pin_mut() else {
+            return Poll::Ready(None);
+        };
+
+        if signal.poll(cx).is_ready() {
+            this.signal.set(None);
+            this.dropper.fire(DropReason::Stopped);
+
+            return Poll::Ready(None);
+        }
+
+        let poll = this.stream.poll_next(cx);
+        if let Poll::Ready(None) = poll {
+            this.dropper.reason = DropReason::Completed;
+        }
+
+        poll
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        if self.signal.is_none() {
+            return (0, Some(0));
+        }
+
+        // The signal may end the stream at any point.
+        (0, self.stream.size_hint().1)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::task::Poll;
+
+    use crate::{DropReason, DropStreamExt};
+    use futures::{
+        future::{pending, ready},
+        stream::{iter, repeat},
+        Stream,
+    };
+
+    #[test]
+    fn signal_stops_stream_and_runs_dropper() {
+        let mut reasons = Vec::new();
+
+        {
+            let reasons_ref = &mut reasons;
+            let drop_stream = repeat(true).take_until_dropped(ready(()), move |reason| {
+                reasons_ref.push(reason);
+            });
+
+            let mut drop_stream = Box::pin(drop_stream);
+
+            let waker = futures::task::noop_waker();
+            let mut context = futures::task::Context::from_waker(&waker);
+            assert_eq!(
+                drop_stream.as_mut().poll_next(&mut context),
+                Poll::Ready(None)
+            );
+            assert_eq!(
+                drop_stream.as_mut().poll_next(&mut context),
+                Poll::Ready(None)
+            );
+        }
+
+        assert_eq!(reasons, vec![DropReason::Stopped]);
+    }
+
+    #[test]
+    fn dropper_reports_cancelled_when_dropped_early() {
+        let mut reason = None;
+
+        {
+            let reason_ref = &mut reason;
+            let drop_stream = repeat(true).take_until_dropped(pending::<()>(), move |r| {
+                *reason_ref = Some(r);
+            });
+
+            let mut drop_stream = Box::pin(drop_stream);
+
+            let waker = futures::task::noop_waker();
+            let mut context = futures::task::Context::from_waker(&waker);
+            assert_eq!(
+                drop_stream.as_mut().poll_next(&mut context),
+                Poll::Ready(Some(true))
+            );
+        }
+
+        assert_eq!(reason, Some(DropReason::Cancelled));
+    }
+
+    #[test]
+    fn dropper_reports_completed_when_inner_finished() {
+        let mut reason = None;
+
+        {
+            let reason_ref = &mut reason;
+            let drop_stream = iter([1]).take_until_dropped(pending::<()>(), move |r| {
+                *reason_ref = Some(r);
+            });
+
+            let mut drop_stream = Box::pin(drop_stream);
+
+            let waker = futures::task::noop_waker();
+            let mut context = futures::task::Context::from_waker(&waker);
+            assert_eq!(
+                drop_stream.as_mut().poll_next(&mut context),
+                Poll::Ready(Some(1))
+            );
+            assert_eq!(
+                drop_stream.as_mut().poll_next(&mut context),
+                Poll::Ready(None)
+            );
+        }
+
+        assert_eq!(reason, Some(DropReason::Completed));
+    }
+}
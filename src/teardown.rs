@@ -0,0 +1,209 @@
+use futures_core::{Future, Stream};
+use pin_project::pin_project;
+use std::{
+    fmt,
+    pin::Pin,
+    task::{Context, Poll},
+    time::Duration,
+};
+use tokio::runtime::Handle;
+
+use crate::dropper::Once;
+
+type TimeoutHook = Box<dyn FnOnce(Duration) + Send>;
+
+/// A stream whose drop closure returns a cleanup future, which is run on the tokio runtime for at
+/// most a fixed budget.
+///
+/// Cleanup such as closing a remote session needs to await, which a sync `Drop` can't. The
+/// returned future is spawned on the runtime the stream was created in, and abandoned once it
+/// has run for longer than the budget, so a hung network call can't hold shutdown hostage. In
+/// that case the hook set with [`on_timeout`](Self::on_timeout) is called with the budget.
+///
+/// # Panics
+///
+/// Panics if created outside of a tokio runtime.
+///
+/// Example
+/// ```
+/// # #[tokio::main(flavor = "current_thread", start_paused = true)]
+/// # async fn main() {
+/// use std::{sync::{atomic::{AtomicBool, Ordering}, Arc}, time::Duration};
+/// use drop_stream::DropStreamExt;
+///
+/// let timed_out = Arc::new(AtomicBool::new(false));
+/// let timed_out_ref = timed_out.clone();
+/// let stream = futures::stream::repeat(true)
+///     .on_drop_teardown(Duration::from_secs(2), || async {
+///         // A goodbye call to a peer that never answers.
+///         futures::future::pending::<()>().await
+///     })
+///     .on_timeout(move |_| timed_out_ref.store(true, Ordering::SeqCst));
+///
+/// drop(stream);
+/// tokio::time::sleep(Duration::from_secs(3)).await;
+/// assert!(timed_out.load(Ordering::SeqCst));
+/// # }
+/// ```
+#[pin_project]
+pub struct AsyncTeardown<S, U, F>
+where
+    U: FnOnce() -> F,
+    F: Future<Output = ()> + Send + 'static,
+{
+    // Declared before the stream so the cleanup future is created before the stream is dropped.
+    dropper: TeardownDropper<U, F>,
+    #[pin]
+    stream: S,
+}
+
+impl<S, U, F> AsyncTeardown<S, U, F>
+where
+    S: Stream,
+    U: FnOnce() -> F,
+    F: Future<Output = ()> + Send + 'static,
+{
+    pub fn new(stream: S, budget: Duration, dropper: U) -> Self {
+        Self {
+            dropper: TeardownDropper {
+                dropper: Once::new(dropper),
+                budget,
+                on_timeout: None,
+                runtime: Handle::current(),
+            },
+            stream,
+        }
+    }
+
+    /// Calls `hook` with the budget if the cleanup future is abandoned, replacing any previous
+    /// hook.
+    pub fn on_timeout<H: FnOnce(Duration) + Send + 'static>(mut self, hook: H) -> Self {
+        self.dropper.on_timeout = Some(Box::new(hook));
+        self
+    }
+
+    /// Returns how long the cleanup future may run for.
+    pub fn budget(&self) -> Duration {
+        self.dropper.budget
+    }
+}
+
+impl<S, U, F> Stream for AsyncTeardown<S, U, F>
+where
+    S: Stream,
+    U: FnOnce() -> F,
+    F: Future<Output = ()> + Send + 'static,
+{
+    type Item = S::Item;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        self.project().stream.poll_next(cx)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.stream.size_hint()
+    }
+}
+
+impl<S, U, F> fmt::Debug for AsyncTeardown<S, U, F>
+where
+    U: FnOnce() -> F,
+    F: Future<Output = ()> + Send + 'static,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("AsyncTeardown")
+            .field("budget", &self.dropper.budget)
+            .finish_non_exhaustive()
+    }
+}
+
+struct TeardownDropper<U, F>
+where
+    U: FnOnce() -> F,
+    F: Future<Output = ()> + Send + 'static,
+{
+    dropper: Once<U>,
+    budget: Duration,
+    on_timeout: Option<TimeoutHook>,
+    runtime: Handle,
+}
+
+impl<U, F> Drop for TeardownDropper<U, F>
+where
+    U: FnOnce() -> F,
+    F: Future<Output = ()> + Send + 'static,
+{
+    fn drop(&mut self) {
+        let Some(dropper) = self.dropper.take() else {
+            return;
+        };
+
+        let cleanup = dropper();
+        let budget = self.budget;
+        let on_timeout = self.on_timeout.take();
+        drop(self.runtime.spawn(async move {
+            if tokio::time::timeout(budget, cleanup).await.is_err() {
+                if let Some(on_timeout) = on_timeout {
+                    on_timeout(budget);
+                }
+            }
+        }));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        sync::{
+            atomic::{AtomicBool, Ordering},
+            Arc,
+        },
+        time::Duration,
+    };
+
+    use crate::DropStreamExt;
+    use futures::stream::repeat;
+
+    #[tokio::test(start_paused = true)]
+    async fn cleanup_within_budget_does_not_time_out() {
+        let cleaned_up = Arc::new(AtomicBool::new(false));
+        let timed_out = Arc::new(AtomicBool::new(false));
+
+        let cleaned_up_ref = cleaned_up.clone();
+        let timed_out_ref = timed_out.clone();
+        let stream = repeat(true)
+            .on_drop_teardown(Duration::from_secs(2), move || async move {
+                tokio::time::sleep(Duration::from_secs(1)).await;
+                cleaned_up_ref.store(true, Ordering::SeqCst);
+            })
+            .on_timeout(move |_| timed_out_ref.store(true, Ordering::SeqCst));
+
+        drop(stream);
+        tokio::time::sleep(Duration::from_secs(5)).await;
+        assert!(cleaned_up.load(Ordering::SeqCst));
+        assert!(!timed_out.load(Ordering::SeqCst));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn hung_cleanup_is_abandoned_at_budget() {
+        let cleaned_up = Arc::new(AtomicBool::new(false));
+        let timeout = Arc::new(std::sync::Mutex::new(None));
+
+        let cleaned_up_ref = cleaned_up.clone();
+        let timeout_ref = timeout.clone();
+        let stream = repeat(true)
+            .on_drop_teardown(Duration::from_secs(2), move || async move {
+                tokio::time::sleep(Duration::from_secs(60)).await;
+                cleaned_up_ref.store(true, Ordering::SeqCst);
+            })
+            .on_timeout(move |budget| *timeout_ref.lock().unwrap() = Some(budget));
+
+        drop(stream);
+        tokio::time::sleep(Duration::from_secs(1)).await;
+        assert_eq!(*timeout.lock().unwrap(), None);
+
+        tokio::time::sleep(Duration::from_secs(120)).await;
+        assert_eq!(*timeout.lock().unwrap(), Some(Duration::from_secs(2)));
+        assert!(!cleaned_up.load(Ordering::SeqCst));
+    }
+}
@@ -0,0 +1,612 @@
+//! Helpers for testing drop behavior, enabled with the `test-util` feature.
+//!
+//! Instead of capturing a `has_run` boolean in each drop closure, hand out callbacks or tokens
+//! from a [`DropTracker`] and assert on what it recorded. To test how code handles cancellation,
+//! feed it a [`MockStream`] and assert on its [`MockHandle`]. The [`poll_then_drop!`] and
+//! [`assert_drops!`] macros cover the usual poll-a-few-times-then-cancel test without building a
+//! waker and context by hand. With the `tokio-test` feature, [`spawn_then_drop`] does the same
+//! through `tokio_test::task`, whose waker records whether the stream was woken.
+//!
+//! [`poll_then_drop!`]: crate::poll_then_drop
+//! [`assert_drops!`]: crate::assert_drops
+
+use futures_core::Stream;
+use std::{
+    collections::VecDeque,
+    pin::Pin,
+    sync::{Arc, Condvar, Mutex},
+    task::{Context, Poll},
+    time::{Duration, Instant},
+};
+
+/// A single drop recorded by a [`DropTracker`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DropRecord {
+    name: String,
+    at: Instant,
+}
+
+impl DropRecord {
+    /// The name the callback or token was created with.
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// When the drop was recorded.
+    pub fn at(&self) -> Instant {
+        self.at
+    }
+}
+
+/// Records which of its callbacks and tokens were dropped, in what order and when.
+///
+/// Clones share their records, so the tracker can be moved into the code under test while the
+/// test keeps a clone to assert on.
+///
+/// Example
+/// ```
+/// use drop_stream::{test_util::DropTracker, DropStreamExt};
+///
+/// let tracker = DropTracker::new();
+/// let first = futures::stream::repeat(true).on_drop(tracker.callback("first"));
+/// let second = futures::stream::repeat(true).on_drop(tracker.callback("second"));
+///
+/// drop(second);
+/// tracker.assert_not_dropped("first");
+///
+/// drop(first);
+/// tracker.assert_order(&["second", "first"]);
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct DropTracker {
+    records: Arc<Mutex<Vec<DropRecord>>>,
+}
+
+impl DropTracker {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Returns a closure that records `name` when it is called, for use as a drop closure.
+    pub fn callback(&self, name: impl Into<String>) -> impl FnOnce() + Send + 'static {
+        let token = self.token(name);
+        move || drop(token)
+    }
+
+    /// Returns a token that records `name` when it is dropped, for moving into a stream, closure
+    /// or task whose drop should be observed.
+    pub fn token(&self, name: impl Into<String>) -> DropToken {
+        DropToken {
+            name: Some(name.into()),
+            records: self.records.clone(),
+        }
+    }
+
+    /// Returns a [`MockStream`] whose drop is recorded as `name`, as the source of a pipeline
+    /// whose teardown order is under test.
+    ///
+    /// Example
+    /// ```
+    /// use futures::StreamExt;
+    /// use drop_stream::{test_util::DropTracker, DropStreamExt};
+    ///
+    /// let tracker = DropTracker::new();
+    /// let pipeline = tracker
+    ///     .source("source")
+    ///     .items([1, 2, 3])
+    ///     .on_drop(tracker.callback("inner"))
+    ///     .map(|x| x * 2)
+    ///     .on_drop(tracker.callback("outer"));
+    ///
+    /// drop(pipeline);
+    /// tracker.assert_order(&["outer", "inner", "source"]);
+    /// ```
+    pub fn source<T>(&self, name: impl Into<String>) -> MockStream<T> {
+        MockStream::new().track(self.token(name))
+    }
+
+    /// Returns every recorded drop, in the order they happened.
+    pub fn records(&self) -> Vec<DropRecord> {
+        self.lock().clone()
+    }
+
+    /// Returns the names of the recorded drops, in the order they happened.
+    pub fn dropped(&self) -> Vec<String> {
+        self.lock().iter().map(|r| r.name.clone()).collect()
+    }
+
+    /// Returns true if a drop of `name` was recorded.
+    pub fn is_dropped(&self, name: &str) -> bool {
+        self.dropped_at(name).is_some()
+    }
+
+    /// Returns when `name` was first dropped, if it was.
+    pub fn dropped_at(&self, name: &str) -> Option<Instant> {
+        self.lock().iter().find(|r| r.name == name).map(|r| r.at)
+    }
+
+    /// Panics unless a drop of `name` was recorded.
+    #[track_caller]
+    pub fn assert_dropped(&self, name: &str) {
+        assert!(
+            self.is_dropped(name),
+            "expected `{name}` to be dropped, dropped: {:?}",
+            self.dropped()
+        );
+    }
+
+    /// Panics if a drop of `name` was recorded.
+    #[track_caller]
+    pub fn assert_not_dropped(&self, name: &str) {
+        assert!(
+            !self.is_dropped(name),
+            "expected `{name}` not to be dropped, dropped: {:?}",
+            self.dropped()
+        );
+    }
+
+    /// Panics unless both `first` and `second` were dropped, `first` before `second`.
+    #[track_caller]
+    pub fn assert_before(&self, first: &str, second: &str) {
+        let dropped = self.dropped();
+        let position = |name| dropped.iter().position(|dropped| dropped == name);
+
+        match (position(first), position(second)) {
+            (Some(first_at), Some(second_at)) if first_at < second_at => {}
+            _ => panic!("expected `{first}` to be dropped before `{second}`, dropped: {dropped:?}"),
+        }
+    }
+
+    /// Panics unless exactly `names` were dropped, in that order.
+    #[track_caller]
+    pub fn assert_order(&self, names: &[&str]) {
+        assert_eq!(self.dropped(), names, "unexpected drop order");
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, Vec<DropRecord>> {
+        self.records.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+/// Records its name with the [`DropTracker`] that created it once it is dropped.
+#[derive(Debug)]
+pub struct DropToken {
+    // Option used so the name can be moved into the record in the Drop::drop() method.
+    name: Option<String>,
+    records: Arc<Mutex<Vec<DropRecord>>>,
+}
+
+impl Drop for DropToken {
+    fn drop(&mut self) {
+        if let Some(name) = self.name.take() {
+            let record = DropRecord {
+                name,
+                at: Instant::now(),
+            };
+            self.records
+                .lock()
+                .unwrap_or_else(|e| e.into_inner())
+                .push(record);
+        }
+    }
+}
+
+enum Step<T> {
+    Item(T),
+    Pending,
+    Hang,
+}
+
+#[derive(Debug, Default)]
+struct MockState {
+    polls: usize,
+    dropped_at: Option<Instant>,
+}
+
+#[derive(Debug, Default)]
+struct MockShared {
+    state: Mutex<MockState>,
+    dropped: Condvar,
+}
+
+impl MockShared {
+    fn lock(&self) -> std::sync::MutexGuard<'_, MockState> {
+        self.state.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+/// A stream that yields a scripted sequence of items and pending periods, and records when it is
+/// dropped, for testing cancellation handling against deterministic fixtures.
+///
+/// The stream ends once the script runs out, unless it ends with [`hang`](MockStream::hang).
+///
+/// Example
+/// ```
+/// use futures::executor::block_on_stream;
+/// use drop_stream::test_util::MockStream;
+///
+/// let stream = MockStream::new().item(1).pending().item(2).hang();
+/// let handle = stream.handle();
+///
+/// let mut items = block_on_stream(stream);
+/// assert_eq!(items.next(), Some(1));
+/// assert_eq!(items.next(), Some(2));
+/// handle.assert_not_dropped();
+///
+/// drop(items);
+/// handle.assert_dropped();
+/// ```
+pub struct MockStream<T> {
+    script: VecDeque<Step<T>>,
+    shared: Arc<MockShared>,
+    token: Option<DropToken>,
+}
+
+impl<T> MockStream<T> {
+    pub fn new() -> Self {
+        Self {
+            script: VecDeque::new(),
+            shared: Arc::default(),
+            token: None,
+        }
+    }
+
+    /// Drops `token` along with the stream, so the stream's drop is recorded by the
+    /// [`DropTracker`] that created the token, in order with the drop closures wrapping it.
+    pub fn track(mut self, token: DropToken) -> Self {
+        self.token = Some(token);
+        self
+    }
+
+    /// Yields `item`.
+    pub fn item(mut self, item: T) -> Self {
+        self.script.push_back(Step::Item(item));
+        self
+    }
+
+    /// Yields every item of `items`.
+    pub fn items(mut self, items: impl IntoIterator<Item = T>) -> Self {
+        self.script.extend(items.into_iter().map(Step::Item));
+        self
+    }
+
+    /// Returns `Pending` once, waking the task right away so it polls again.
+    pub fn pending(mut self) -> Self {
+        self.script.push_back(Step::Pending);
+        self
+    }
+
+    /// Returns `Pending` forever from this point, without waking the task.
+    pub fn hang(mut self) -> Self {
+        self.script.push_back(Step::Hang);
+        self
+    }
+
+    /// Returns a handle for asserting on the stream after it has been handed off.
+    pub fn handle(&self) -> MockHandle {
+        MockHandle {
+            shared: self.shared.clone(),
+        }
+    }
+}
+
+impl<T> Default for MockStream<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T> Unpin for MockStream<T> {}
+
+impl<T> Stream for MockStream<T> {
+    type Item = T;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<T>> {
+        self.shared.lock().polls += 1;
+
+        match self.script.pop_front() {
+            Some(Step::Item(item)) => Poll::Ready(Some(item)),
+            Some(Step::Pending) => {
+                cx.waker().wake_by_ref();
+                Poll::Pending
+            }
+            Some(Step::Hang) => {
+                self.script.push_front(Step::Hang);
+                Poll::Pending
+            }
+            None => Poll::Ready(None),
+        }
+    }
+}
+
+impl<T> Drop for MockStream<T> {
+    fn drop(&mut self) {
+        self.shared.lock().dropped_at = Some(Instant::now());
+        self.shared.dropped.notify_all();
+    }
+}
+
+/// The handle of a [`MockStream`], for asserting on it once it has been handed off.
+#[derive(Debug, Clone)]
+pub struct MockHandle {
+    shared: Arc<MockShared>,
+}
+
+impl MockHandle {
+    /// The number of times the stream was polled.
+    pub fn polls(&self) -> usize {
+        self.shared.lock().polls
+    }
+
+    /// When the stream was dropped, if it was.
+    pub fn dropped_at(&self) -> Option<Instant> {
+        self.shared.lock().dropped_at
+    }
+
+    /// Returns true if the stream was dropped.
+    pub fn is_dropped(&self) -> bool {
+        self.dropped_at().is_some()
+    }
+
+    /// Panics unless the stream was dropped.
+    #[track_caller]
+    pub fn assert_dropped(&self) {
+        assert!(self.is_dropped(), "expected the mock stream to be dropped");
+    }
+
+    /// Panics if the stream was dropped.
+    #[track_caller]
+    pub fn assert_not_dropped(&self) {
+        assert!(
+            !self.is_dropped(),
+            "expected the mock stream not to be dropped"
+        );
+    }
+
+    /// Waits up to `duration` for the stream to be dropped, and panics if it isn't.
+    ///
+    /// This blocks the current thread, so the stream must be dropped by another thread, e.g. a
+    /// task on a multi-threaded runtime.
+    #[track_caller]
+    pub fn assert_dropped_within(&self, duration: Duration) {
+        let state = self.shared.lock();
+        let (state, _) = self
+            .shared
+            .dropped
+            .wait_timeout_while(state, duration, |state| state.dropped_at.is_none())
+            .unwrap_or_else(|e| e.into_inner());
+
+        assert!(
+            state.dropped_at.is_some(),
+            "expected the mock stream to be dropped within {duration:?}"
+        );
+    }
+}
+
+/// Polls `stream` once with a waker that does nothing, so poll-level tests don't need to build a
+/// waker and context by hand. Used by [`poll_then_drop!`](crate::poll_then_drop).
+pub fn poll_once<S: Stream + ?Sized>(stream: Pin<&mut S>) -> Poll<Option<S::Item>> {
+    stream.poll_next(&mut Context::from_waker(std::task::Waker::noop()))
+}
+
+/// Polls `stream` `polls` times as a `tokio_test` task and then drops it, returning the results of
+/// the polls and whether the stream was woken after the last of them, e.g. by a producer it
+/// registered with. Enabled with the `tokio-test` feature.
+///
+/// Example
+/// ```
+/// use std::task::Poll;
+/// use drop_stream::test_util::{spawn_then_drop, MockStream};
+///
+/// let stream = MockStream::new().item(1).hang();
+/// let handle = stream.handle();
+///
+/// let (polls, woken) = spawn_then_drop(stream, 2);
+/// assert_eq!(polls, [Poll::Ready(Some(1)), Poll::Pending]);
+/// assert!(!woken);
+/// handle.assert_dropped();
+/// ```
+#[cfg(feature = "tokio-test")]
+pub fn spawn_then_drop<S: Stream>(stream: S, polls: usize) -> (Vec<Poll<Option<S::Item>>>, bool) {
+    let mut task = tokio_test::task::spawn(stream);
+    let polls = (0..polls).map(|_| task.poll_next()).collect();
+
+    (polls, task.is_woken())
+}
+
+/// Polls a stream a number of times and then drops it, evaluating to the results of the polls.
+///
+/// The stream is pinned on the heap and polled with a waker that does nothing, as a cancelled
+/// consumer would; works the same in a plain `#[test]` and in `#[tokio::test]`.
+///
+/// Example
+/// ```
+/// use std::task::Poll;
+/// use drop_stream::{poll_then_drop, test_util::MockStream};
+///
+/// let stream = MockStream::new().item(1).hang();
+/// let handle = stream.handle();
+///
+/// assert_eq!(poll_then_drop!(stream, 2), [Poll::Ready(Some(1)), Poll::Pending]);
+/// handle.assert_dropped();
+/// ```
+#[macro_export]
+macro_rules! poll_then_drop {
+    ($stream:expr, $polls:expr) => {{
+        let mut stream = ::std::boxed::Box::pin($stream);
+        let polls: ::std::vec::Vec<_> = (0..$polls)
+            .map(|_| $crate::test_util::poll_once(stream.as_mut()))
+            .collect();
+        ::std::mem::drop(stream);
+        polls
+    }};
+}
+
+/// Asserts that something is not dropped before `$body` runs, and is dropped once it has.
+///
+/// Takes either a [`MockHandle`], or a [`DropTracker`] together with the name of one of its
+/// callbacks or tokens. Evaluates to the value of `$body`.
+///
+/// Example
+/// ```
+/// use drop_stream::{assert_drops, test_util::DropTracker, DropStreamExt};
+///
+/// let tracker = DropTracker::new();
+/// let stream = futures::stream::repeat(true).on_drop(tracker.callback("stream"));
+///
+/// assert_drops!(tracker, "stream", drop(stream));
+/// ```
+#[macro_export]
+macro_rules! assert_drops {
+    ($tracker:expr, $name:expr, $body:expr) => {{
+        let name = $name;
+        $tracker.assert_not_dropped(name);
+        let value = $body;
+        $tracker.assert_dropped(name);
+        value
+    }};
+    ($handle:expr, $body:expr) => {{
+        $handle.assert_not_dropped();
+        let value = $body;
+        $handle.assert_dropped();
+        value
+    }};
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{task::Poll, time::Duration};
+
+    use super::{DropTracker, MockStream};
+    use crate::DropStreamExt;
+    use futures::{stream::repeat, Stream, StreamExt};
+
+    #[test]
+    fn records_drops_in_order() {
+        let tracker = DropTracker::new();
+
+        let token = tracker.token("token");
+        let drop_stream = repeat(true).on_drop(tracker.callback("stream"));
+        tracker.assert_order(&[]);
+
+        drop(drop_stream);
+        drop(token);
+
+        tracker.assert_order(&["stream", "token"]);
+        let records = tracker.records();
+        assert!(records[0].at() <= records[1].at());
+    }
+
+    #[test]
+    #[should_panic(expected = "expected `stream` to be dropped")]
+    fn assert_dropped_panics_when_alive() {
+        let tracker = DropTracker::new();
+        let _drop_stream = repeat(true).on_drop(tracker.callback("stream"));
+
+        tracker.assert_dropped("stream");
+    }
+
+    #[test]
+    fn mock_stream_follows_script() {
+        let drop_stream = MockStream::new().item(1).pending().items([2, 3]).hang();
+        let handle = drop_stream.handle();
+
+        let mut drop_stream = Box::pin(drop_stream);
+
+        let (waker, wakes) = futures_test::task::new_count_waker();
+        let mut context = futures::task::Context::from_waker(&waker);
+        assert_eq!(
+            drop_stream.as_mut().poll_next(&mut context),
+            Poll::Ready(Some(1))
+        );
+        assert_eq!(drop_stream.as_mut().poll_next(&mut context), Poll::Pending);
+        assert_eq!(wakes, 1);
+        assert_eq!(
+            drop_stream.as_mut().poll_next(&mut context),
+            Poll::Ready(Some(2))
+        );
+        assert_eq!(
+            drop_stream.as_mut().poll_next(&mut context),
+            Poll::Ready(Some(3))
+        );
+        assert_eq!(drop_stream.as_mut().poll_next(&mut context), Poll::Pending);
+        assert_eq!(drop_stream.as_mut().poll_next(&mut context), Poll::Pending);
+        assert_eq!(wakes, 1);
+        assert_eq!(handle.polls(), 6);
+        handle.assert_not_dropped();
+    }
+
+    #[test]
+    fn dropped_within_waits_for_other_thread() {
+        let drop_stream = MockStream::<()>::new().hang();
+        let handle = drop_stream.handle();
+
+        let thread = std::thread::spawn(move || {
+            std::thread::sleep(Duration::from_millis(10));
+            drop(drop_stream);
+        });
+
+        handle.assert_dropped_within(Duration::from_secs(5));
+        thread.join().unwrap();
+    }
+
+    #[tokio::test]
+    async fn macros_work_in_tokio_tests() {
+        let drop_stream = MockStream::new().item(1).pending().item(2);
+        let handle = drop_stream.handle();
+
+        let polls = crate::assert_drops!(handle, crate::poll_then_drop!(drop_stream, 2));
+        assert_eq!(polls, [Poll::Ready(Some(1)), Poll::Pending]);
+        assert_eq!(handle.polls(), 2);
+    }
+
+    #[test]
+    fn pipeline_tears_down_outside_in() {
+        let tracker = DropTracker::new();
+
+        let first = tracker
+            .source::<u32>("first source")
+            .on_drop(tracker.callback("first"));
+        let second = tracker
+            .source::<u32>("second source")
+            .on_drop(tracker.callback("second"));
+        let merged = futures::stream::select(first, second)
+            .on_drop(tracker.callback("merged"))
+            .wrap_inner(|inner| inner.map(|x| x + 1));
+
+        assert_eq!(crate::poll_then_drop!(merged, 1), [Poll::Ready(None)]);
+
+        tracker.assert_before("merged", "first");
+        tracker.assert_before("first", "first source");
+        tracker.assert_order(&["merged", "first", "first source", "second", "second source"]);
+    }
+
+    #[test]
+    #[should_panic(expected = "expected `inner` to be dropped before `outer`")]
+    fn assert_before_panics_on_wrong_order() {
+        let tracker = DropTracker::new();
+        drop(
+            tracker
+                .source::<()>("inner")
+                .on_drop(tracker.callback("outer")),
+        );
+
+        tracker.assert_before("inner", "outer");
+    }
+
+    #[cfg(feature = "tokio-test")]
+    #[test]
+    fn spawned_polls_record_wakeups() {
+        let tracker = DropTracker::new();
+        let stream = futures::stream::poll_fn(|cx| {
+            cx.waker().wake_by_ref();
+            Poll::<Option<()>>::Pending
+        })
+        .on_drop(tracker.callback("stream"));
+
+        let (polls, woken) = super::spawn_then_drop(stream, 1);
+        assert_eq!(polls, [Poll::Pending]);
+        assert!(woken);
+        tracker.assert_dropped("stream");
+    }
+}
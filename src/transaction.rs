@@ -0,0 +1,254 @@
+use futures_core::{Future, Stream};
+use pin_project::{pin_project, pinned_drop};
+use std::{
+    error::Error,
+    fmt,
+    pin::Pin,
+    task::{Context, Poll},
+};
+
+use crate::{report_drop_error, DropReason, Spawn};
+
+/// A database transaction that can be committed or rolled back asynchronously. See
+/// [`TransactionStream`].
+pub trait Transaction {
+    type Error: Error + 'static;
+
+    fn commit(self) -> impl Future<Output = Result<(), Self::Error>> + Send + 'static;
+
+    fn rollback(self) -> impl Future<Output = Result<(), Self::Error>> + Send + 'static;
+}
+
+/// A stream of rows read within a transaction that owns the transaction, and commits it in the
+/// background once it is dropped if the stream completed, or rolls it back if it was cancelled.
+///
+/// This solves the cursor-in-transaction problem of a handler streaming rows to a client: the
+/// transaction must outlive the stream, and whether to commit depends on whether the client read
+/// every row. The commit or rollback is handed to the spawner on drop, and its error is reported
+/// through the crate-level handler, see [`set_drop_error_handler`](crate::set_drop_error_handler).
+/// The hook set with [`on_settled`](Self::on_settled) is called with [`DropReason::Completed`]
+/// after a commit and [`DropReason::Cancelled`] after a rollback, once it succeeded.
+///
+/// Example
+/// ```
+/// use std::{convert::Infallible, future::{ready, Future}, sync::{Arc, Mutex}};
+/// use drop_stream::{BoxFuture, DropStreamExt, Transaction};
+///
+/// struct Tx(Arc<Mutex<&'static str>>);
+///
+/// impl Transaction for Tx {
+///     type Error = Infallible;
+///
+///     fn commit(self) -> impl Future<Output = Result<(), Infallible>> + Send + 'static {
+///         *self.0.lock().unwrap() = "committed";
+///         ready(Ok(()))
+///     }
+///
+///     fn rollback(self) -> impl Future<Output = Result<(), Infallible>> + Send + 'static {
+///         *self.0.lock().unwrap() = "rolled back";
+///         ready(Ok(()))
+///     }
+/// }
+///
+/// let tasks = Arc::new(Mutex::new(Vec::new()));
+/// let spawner = {
+///     let tasks = tasks.clone();
+///     move |future: BoxFuture| tasks.lock().unwrap().push(future)
+/// };
+///
+/// let state = Arc::new(Mutex::new("open"));
+/// let rows = futures::stream::iter([1, 2, 3]).in_transaction(Tx(state.clone()), spawner);
+///
+/// // The client disconnects before reading every row...
+/// drop(rows);
+/// for task in tasks.lock().unwrap().drain(..) {
+///     futures::executor::block_on(task);
+/// }
+/// assert_eq!(*state.lock().unwrap(), "rolled back");
+/// ```
+#[pin_project(PinnedDrop)]
+pub struct TransactionStream<S, T, Sp>
+where
+    S: Stream,
+    T: Transaction,
+    Sp: Spawn,
+{
+    #[pin]
+    stream: S,
+    // Only taken in the drop method.
+    transaction: Option<T>,
+    spawner: Sp,
+    on_settled: Option<Box<dyn FnOnce(DropReason) + Send>>,
+    completed: bool,
+}
+
+impl<S, T, Sp> TransactionStream<S, T, Sp>
+where
+    S: Stream,
+    T: Transaction,
+    Sp: Spawn,
+{
+    pub fn new(stream: S, transaction: T, spawner: Sp) -> Self {
+        Self {
+            stream,
+            transaction: Some(transaction),
+            spawner,
+            on_settled: None,
+            completed: false,
+        }
+    }
+
+    /// Calls `hook` once the transaction was committed or rolled back, replacing any previous
+    /// hook.
+    pub fn on_settled<H: FnOnce(DropReason) + Send + 'static>(mut self, hook: H) -> Self {
+        self.on_settled = Some(Box::new(hook));
+        self
+    }
+
+    /// Returns the transaction.
+    pub fn transaction(&self) -> &T {
+        // Only taken in the drop method.
+        self.transaction
+            .as_ref()
+            .expect("transaction taken before drop")
+    }
+}
+
+impl<S, T, Sp> Stream for TransactionStream<S, T, Sp>
+where
+    S: Stream,
+    T: Transaction,
+    Sp: Spawn,
+{
+    type Item = S::Item;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let this = self.project();
+
+        let poll = this.stream.poll_next(cx);
+        if let Poll::Ready(None) = poll {
+            *this.completed = true;
+        }
+
+        poll
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.stream.size_hint()
+    }
+}
+
+#[pinned_drop]
+impl<S, T, Sp> PinnedDrop for TransactionStream<S, T, Sp>
+where
+    S: Stream,
+    T: Transaction,
+    Sp: Spawn,
+{
+    fn drop(self: Pin<&mut Self>) {
+        let this = self.project();
+
+        let Some(transaction) = this.transaction.take() else {
+            return;
+        };
+
+        let (reason, settle): (_, Pin<Box<dyn Future<Output = _> + Send>>) = match this.completed {
+            true => (DropReason::Completed, Box::pin(transaction.commit())),
+            false => (DropReason::Cancelled, Box::pin(transaction.rollback())),
+        };
+        let on_settled = this.on_settled.take();
+        this.spawner.spawn(Box::pin(async move {
+            match settle.await {
+                Ok(()) => {
+                    if let Some(on_settled) = on_settled {
+                        on_settled(reason);
+                    }
+                }
+                Err(error) => report_drop_error(error),
+            }
+        }));
+    }
+}
+
+impl<S, T, Sp> fmt::Debug for TransactionStream<S, T, Sp>
+where
+    S: Stream,
+    T: Transaction,
+    Sp: Spawn,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("TransactionStream")
+            .field("completed", &self.completed)
+            .finish_non_exhaustive()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        future::{ready, Future},
+        sync::{Arc, Mutex},
+    };
+
+    use crate::{BoxFuture, DropReason, DropStreamExt, Transaction};
+    use futures::{executor::block_on, executor::block_on_stream, stream::iter};
+
+    #[derive(Debug)]
+    struct Failed;
+
+    impl std::fmt::Display for Failed {
+        fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+            f.write_str("commit failed")
+        }
+    }
+
+    impl std::error::Error for Failed {}
+
+    struct Tx(bool);
+
+    impl Transaction for Tx {
+        type Error = Failed;
+
+        fn commit(self) -> impl Future<Output = Result<(), Failed>> + Send + 'static {
+            ready(if self.0 { Ok(()) } else { Err(Failed) })
+        }
+
+        fn rollback(self) -> impl Future<Output = Result<(), Failed>> + Send + 'static {
+            ready(Ok(()))
+        }
+    }
+
+    fn run(tx: Tx, read_all: bool) -> Option<DropReason> {
+        let tasks = Arc::new(Mutex::new(Vec::<BoxFuture>::new()));
+        let settled = Arc::new(Mutex::new(None));
+
+        let tasks_ref = tasks.clone();
+        let settled_ref = settled.clone();
+        let rows = iter([1, 2])
+            .in_transaction(tx, move |future| tasks_ref.lock().unwrap().push(future))
+            .on_settled(move |reason| *settled_ref.lock().unwrap() = Some(reason));
+        let mut rows = block_on_stream(rows);
+        rows.next();
+        if read_all {
+            assert_eq!(rows.by_ref().count(), 1);
+        }
+        drop(rows);
+
+        for task in tasks.lock().unwrap().drain(..) {
+            block_on(task);
+        }
+        let settled = *settled.lock().unwrap();
+        settled
+    }
+
+    #[test]
+    fn completed_stream_commits_and_cancelled_rolls_back() {
+        assert_eq!(run(Tx(true), true), Some(DropReason::Completed));
+        assert_eq!(run(Tx(true), false), Some(DropReason::Cancelled));
+    }
+
+    #[test]
+    fn failed_commit_is_not_reported_as_settled() {
+        assert_eq!(run(Tx(false), true), None);
+    }
+}
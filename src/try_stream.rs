@@ -0,0 +1,573 @@
+use futures_core::{Stream, TryStream};
+use pin_project::{pin_project, pinned_drop};
+use std::{
+    pin::Pin,
+    task::{Context, Poll},
+};
+
+use crate::{
+    context::{ContextDropper, Stats},
+    dropper::Once,
+    ContextDropFn, Download, DropContext, DropReason, HeldRange, OnReason, PartStream,
+};
+
+fn ignore_error<E>(_: &E) {}
+
+/// The error hook of a [`DropTryStream`] that hasn't been given one with
+/// [`DropTryStream::on_error`].
+pub type IgnoreError<E> = fn(&E);
+
+/// The projection used by wrappers that keep a clone of an item or error, i.e. [`Clone::clone`].
+pub type CloneFn<T> = fn(&T) -> T;
+
+/// A [`TryStream`] wrapper that calls a closure with a [`DropContext`] once it is dropped, and
+/// optionally a hook for every `Err` item it yields.
+///
+/// The context counts the errors that were yielded, so a fallible pipeline gets cancellation
+/// detection and error accounting from one wrapper.
+///
+/// Example
+/// ```
+/// use futures::{executor::block_on_stream, stream};
+/// use drop_stream::DropTryStreamExt;
+///
+/// let mut errors = 0;
+/// let errors_ref = &mut errors;
+/// let mut logged = Vec::new();
+/// let logged_ref = &mut logged;
+///
+/// let stream = stream::iter([Ok(1), Err("bad"), Ok(2)])
+///     .on_try_drop(move |context| *errors_ref = context.errors())
+///     .on_error(move |error: &&str| logged_ref.push(error.to_string()));
+///
+/// assert_eq!(block_on_stream(stream).count(), 3);
+/// assert_eq!(errors, 1);
+/// assert_eq!(logged, vec!["bad"]);
+/// ```
+#[pin_project]
+pub struct DropTryStream<S, H, U>
+where
+    S: TryStream,
+    H: FnMut(&S::Error),
+    U: ContextDropFn,
+{
+    // Declared before the stream so the closure runs before the inner stream is dropped.
+    dropper: ContextDropper<U>,
+    on_error: H,
+    #[pin]
+    stream: S,
+}
+
+impl<S, U> DropTryStream<S, IgnoreError<S::Error>, U>
+where
+    S: TryStream,
+    U: ContextDropFn,
+{
+    #[track_caller]
+    pub fn new(stream: S, dropper: U) -> Self {
+        Self {
+            dropper: ContextDropper::new(dropper),
+            on_error: ignore_error,
+            stream,
+        }
+    }
+}
+
+impl<S, H, U> DropTryStream<S, H, U>
+where
+    S: TryStream,
+    H: FnMut(&S::Error),
+    U: ContextDropFn,
+{
+    /// Calls `hook` with every `Err` item yielded by the stream, replacing any previous hook.
+    pub fn on_error<H2: FnMut(&S::Error)>(self, hook: H2) -> DropTryStream<S, H2, U> {
+        let DropTryStream {
+            dropper, stream, ..
+        } = self;
+
+        DropTryStream {
+            dropper,
+            on_error: hook,
+            stream,
+        }
+    }
+}
+
+impl<S, H, U> Stream for DropTryStream<S, H, U>
+where
+    S: TryStream,
+    H: FnMut(&S::Error),
+    U: ContextDropFn,
+{
+    type Item = Result<S::Ok, S::Error>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let this = self.project();
+
+        let poll = this.stream.try_poll_next(cx);
+        match &poll {
+            Poll::Ready(Some(Ok(_))) => this.dropper.stats.record_item(),
+            Poll::Ready(Some(Err(error))) => {
+                this.dropper.stats.record_error();
+                (this.on_error)(error);
+            }
+            Poll::Ready(None) => this.dropper.stats.record_end(),
+            Poll::Pending => this.dropper.stats.record_pending(),
+        }
+
+        poll
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.stream.size_hint()
+    }
+}
+
+/// A [`TryStream`] wrapper that keeps the most recent error it yielded and hands it by value to a
+/// closure once it is dropped, together with a [`DropContext`].
+///
+/// This lets cleanup code include the failure cause in its logging without a parallel side
+/// channel. Since errors are passed on to the consumer, what is kept is a projection of each
+/// error: a clone with [`on_try_drop_with_error`](DropTryStreamExt::on_try_drop_with_error), or
+/// anything else with [`on_try_drop_with_error_by`](DropTryStreamExt::on_try_drop_with_error_by).
+///
+/// Example
+/// ```
+/// use futures::{executor::block_on_stream, stream};
+/// use drop_stream::DropTryStreamExt;
+///
+/// let mut cause = None;
+/// let cause_ref = &mut cause;
+/// let stream = stream::iter([Ok(1), Err("timed out"), Ok(2)])
+///     .on_try_drop_with_error(move |_context, error| *cause_ref = error);
+///
+/// assert_eq!(block_on_stream(stream).count(), 3);
+/// assert_eq!(cause, Some("timed out"));
+/// ```
+#[pin_project(PinnedDrop)]
+pub struct LastErrorStream<S, P, K, U>
+where
+    S: TryStream,
+    P: FnMut(&S::Error) -> K,
+    U: FnOnce(DropContext, Option<K>),
+{
+    #[pin]
+    stream: S,
+    project: P,
+    stats: Stats,
+    last_error: Option<K>,
+    dropper: Once<U>,
+}
+
+impl<S, P, K, U> LastErrorStream<S, P, K, U>
+where
+    S: TryStream,
+    P: FnMut(&S::Error) -> K,
+    U: FnOnce(DropContext, Option<K>),
+{
+    #[track_caller]
+    pub fn new(stream: S, project: P, dropper: U) -> Self {
+        Self {
+            stream,
+            project,
+            stats: Stats::new(),
+            last_error: None,
+            dropper: Once::new(dropper),
+        }
+    }
+}
+
+impl<S, P, K, U> Stream for LastErrorStream<S, P, K, U>
+where
+    S: TryStream,
+    P: FnMut(&S::Error) -> K,
+    U: FnOnce(DropContext, Option<K>),
+{
+    type Item = Result<S::Ok, S::Error>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let this = self.project();
+
+        let poll = this.stream.try_poll_next(cx);
+        match &poll {
+            Poll::Ready(Some(Ok(_))) => this.stats.record_item(),
+            Poll::Ready(Some(Err(error))) => {
+                this.stats.record_error();
+                *this.last_error = Some((this.project)(error));
+            }
+            Poll::Ready(None) => this.stats.record_end(),
+            Poll::Pending => this.stats.record_pending(),
+        }
+
+        poll
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.stream.size_hint()
+    }
+}
+
+#[pinned_drop]
+impl<S, P, K, U> PinnedDrop for LastErrorStream<S, P, K, U>
+where
+    S: TryStream,
+    P: FnMut(&S::Error) -> K,
+    U: FnOnce(DropContext, Option<K>),
+{
+    fn drop(self: Pin<&mut Self>) {
+        let this = self.project();
+
+        if let Some(dropper) = this.dropper.take() {
+            dropper(this.stats.context(), this.last_error.take())
+        }
+    }
+}
+
+pub trait DropTryStreamExt: TryStream + Sized {
+    /// Wraps the stream with a closure that is called with a [`DropContext`] once it is dropped.
+    /// See [`DropTryStream`].
+    fn on_try_drop<U: FnOnce(DropContext)>(
+        self,
+        dropper: U,
+    ) -> DropTryStream<Self, IgnoreError<Self::Error>, U>;
+
+    /// Calls the closure once the stream is dropped, if it ran to completion without yielding an
+    /// `Err` item.
+    fn on_ok_drop<F: FnOnce(DropContext)>(
+        self,
+        dropper: F,
+    ) -> DropTryStream<Self, IgnoreError<Self::Error>, OnReason<F>>;
+
+    /// Calls the closure once the stream is dropped, if it yielded an `Err` item, whether or not
+    /// it ran to completion.
+    fn on_err_drop<F: FnOnce(DropContext)>(
+        self,
+        dropper: F,
+    ) -> DropTryStream<Self, IgnoreError<Self::Error>, OnReason<F>>;
+
+    /// Calls the closure once the stream is dropped, if it was cancelled before finishing without
+    /// having yielded an `Err` item.
+    fn on_cancel_drop<F: FnOnce(DropContext)>(
+        self,
+        dropper: F,
+    ) -> DropTryStream<Self, IgnoreError<Self::Error>, OnReason<F>>;
+
+    /// Wraps a download response with a closure that is called with a [`DropContext`] and the
+    /// offset reached in the file, starting at `start`, once it is dropped. See [`Download`].
+    fn on_download_drop<U: FnOnce(DropContext, u64)>(
+        self,
+        start: u64,
+        dropper: U,
+    ) -> Download<Self, U>
+    where
+        Self::Ok: AsRef<[u8]>;
+
+    /// Holds `handle`, such as a file lock, while the bytes of `range` are served, and hands it to
+    /// a closure together with a [`DropContext`] once the stream is dropped. See [`HeldRange`].
+    fn hold_for_range<H, U: FnOnce(DropContext, H)>(
+        self,
+        range: std::ops::Range<u64>,
+        handle: H,
+        dropper: U,
+    ) -> HeldRange<Self, H, U>
+    where
+        Self::Ok: AsRef<[u8]>;
+
+    /// Wraps a multipart part with a closure that is called with a [`DropContext`] named after
+    /// `field_name` once it is dropped, if it was abandoned before being read in full. See
+    /// [`PartStream`].
+    fn on_part_abandoned<F: FnOnce(DropContext)>(
+        self,
+        field_name: impl Into<String>,
+        cleanup: F,
+    ) -> PartStream<Self, OnReason<F>>
+    where
+        Self::Ok: AsRef<[u8]>;
+
+    /// Wraps the stream with a closure that is called with a [`DropContext`] and a clone of the
+    /// most recent error once it is dropped. See [`LastErrorStream`].
+    fn on_try_drop_with_error<U: FnOnce(DropContext, Option<Self::Error>)>(
+        self,
+        dropper: U,
+    ) -> LastErrorStream<Self, CloneFn<Self::Error>, Self::Error, U>
+    where
+        Self::Error: Clone;
+
+    /// Like [`on_try_drop_with_error`](DropTryStreamExt::on_try_drop_with_error), but keeps
+    /// `project(&error)` instead of a clone of the error.
+    fn on_try_drop_with_error_by<P, K, U>(
+        self,
+        project: P,
+        dropper: U,
+    ) -> LastErrorStream<Self, P, K, U>
+    where
+        P: FnMut(&Self::Error) -> K,
+        U: FnOnce(DropContext, Option<K>);
+}
+
+impl<T> DropTryStreamExt for T
+where
+    T: TryStream + Sized,
+{
+    #[track_caller]
+    fn on_try_drop<U: FnOnce(DropContext)>(
+        self,
+        dropper: U,
+    ) -> DropTryStream<T, IgnoreError<T::Error>, U> {
+        DropTryStream::new(self, dropper)
+    }
+
+    #[track_caller]
+    fn on_ok_drop<F: FnOnce(DropContext)>(
+        self,
+        dropper: F,
+    ) -> DropTryStream<T, IgnoreError<T::Error>, OnReason<F>> {
+        DropTryStream::new(self, OnReason::new(|r| r == DropReason::Completed, dropper))
+    }
+
+    #[track_caller]
+    fn on_err_drop<F: FnOnce(DropContext)>(
+        self,
+        dropper: F,
+    ) -> DropTryStream<T, IgnoreError<T::Error>, OnReason<F>> {
+        DropTryStream::new(self, OnReason::new(DropReason::is_error, dropper))
+    }
+
+    #[track_caller]
+    fn on_cancel_drop<F: FnOnce(DropContext)>(
+        self,
+        dropper: F,
+    ) -> DropTryStream<T, IgnoreError<T::Error>, OnReason<F>> {
+        DropTryStream::new(self, OnReason::new(|r| r == DropReason::Cancelled, dropper))
+    }
+
+    #[track_caller]
+    fn on_download_drop<U: FnOnce(DropContext, u64)>(self, start: u64, dropper: U) -> Download<T, U>
+    where
+        T::Ok: AsRef<[u8]>,
+    {
+        Download::new(self, start, dropper)
+    }
+
+    #[track_caller]
+    fn hold_for_range<H, U: FnOnce(DropContext, H)>(
+        self,
+        range: std::ops::Range<u64>,
+        handle: H,
+        dropper: U,
+    ) -> HeldRange<T, H, U>
+    where
+        T::Ok: AsRef<[u8]>,
+    {
+        HeldRange::new(self, range, handle, dropper)
+    }
+
+    #[track_caller]
+    fn on_part_abandoned<F: FnOnce(DropContext)>(
+        self,
+        field_name: impl Into<String>,
+        cleanup: F,
+    ) -> PartStream<T, OnReason<F>>
+    where
+        T::Ok: AsRef<[u8]>,
+    {
+        PartStream::new(
+            self,
+            field_name,
+            OnReason::new(DropReason::is_cancelled, cleanup),
+        )
+    }
+
+    #[track_caller]
+    fn on_try_drop_with_error<U: FnOnce(DropContext, Option<T::Error>)>(
+        self,
+        dropper: U,
+    ) -> LastErrorStream<T, CloneFn<T::Error>, T::Error, U>
+    where
+        T::Error: Clone,
+    {
+        LastErrorStream::new(self, Clone::clone, dropper)
+    }
+
+    #[track_caller]
+    fn on_try_drop_with_error_by<P, K, U>(
+        self,
+        project: P,
+        dropper: U,
+    ) -> LastErrorStream<T, P, K, U>
+    where
+        P: FnMut(&T::Error) -> K,
+        U: FnOnce(DropContext, Option<K>),
+    {
+        LastErrorStream::new(self, project, dropper)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::task::Poll;
+
+    use crate::{DropReason, DropTryStreamExt};
+    use futures::{
+        stream::{iter, repeat},
+        Stream,
+    };
+
+    #[test]
+    fn size_hint_is_forwarded() {
+        let items = [Ok::<_, ()>(1), Ok(2)];
+
+        assert_eq!(iter(items).on_try_drop(|_| {}).size_hint(), (2, Some(2)));
+        assert_eq!(
+            iter(items).on_try_drop_with_error(|_, _| {}).size_hint(),
+            (2, Some(2))
+        );
+    }
+
+    #[test]
+    fn context_counts_errors() {
+        let mut context = None;
+
+        {
+            let context_ref = &mut context;
+            let drop_stream = iter([Ok::<_, &str>(1), Err("first"), Err("second")])
+                .on_try_drop(move |c| *context_ref = Some(c));
+
+            let mut drop_stream = Box::pin(drop_stream);
+
+            let waker = futures::task::noop_waker();
+            let mut cx = futures::task::Context::from_waker(&waker);
+            while let Poll::Ready(Some(_)) = drop_stream.as_mut().poll_next(&mut cx) {}
+        }
+
+        let context = context.unwrap();
+        assert_eq!(context.reason(), DropReason::CompletedWithError);
+        assert_eq!(context.items(), 3);
+        assert_eq!(context.errors(), 2);
+    }
+
+    #[test]
+    fn reason_without_errors() {
+        let reasons = std::cell::RefCell::new(Vec::new());
+
+        {
+            let _cancelled =
+                repeat(Ok::<_, ()>(1)).on_try_drop(|c| reasons.borrow_mut().push(c.reason()));
+
+            let drop_stream =
+                iter([Ok::<_, ()>(1)]).on_try_drop(|c| reasons.borrow_mut().push(c.reason()));
+            let mut drop_stream = Box::pin(drop_stream);
+
+            let waker = futures::task::noop_waker();
+            let mut cx = futures::task::Context::from_waker(&waker);
+            while let Poll::Ready(Some(_)) = drop_stream.as_mut().poll_next(&mut cx) {}
+        }
+
+        assert_eq!(
+            *reasons.borrow(),
+            vec![DropReason::Completed, DropReason::Cancelled]
+        );
+    }
+
+    #[test]
+    fn on_error_hook_sees_each_error() {
+        let mut seen = Vec::new();
+        let mut context = None;
+
+        {
+            let seen_ref = &mut seen;
+            let context_ref = &mut context;
+            let drop_stream = repeat(Err::<(), _>(7))
+                .on_try_drop(move |c| *context_ref = Some(c))
+                .on_error(move |error| seen_ref.push(*error));
+
+            let mut drop_stream = Box::pin(drop_stream);
+
+            let waker = futures::task::noop_waker();
+            let mut cx = futures::task::Context::from_waker(&waker);
+            assert_eq!(
+                drop_stream.as_mut().poll_next(&mut cx),
+                Poll::Ready(Some(Err(7)))
+            );
+            assert_eq!(
+                drop_stream.as_mut().poll_next(&mut cx),
+                Poll::Ready(Some(Err(7)))
+            );
+        }
+
+        assert_eq!(seen, vec![7, 7]);
+        assert_eq!(context.unwrap().reason(), DropReason::CancelledAfterError);
+    }
+
+    #[test]
+    fn last_error_is_handed_to_dropper() {
+        let mut last = None;
+
+        {
+            let last_ref = &mut last;
+            let drop_stream = iter([Err("first"), Ok(1), Err("second"), Ok(2)])
+                .on_try_drop_with_error(move |c, error| *last_ref = Some((c.errors(), error)));
+
+            let mut drop_stream = Box::pin(drop_stream);
+
+            let waker = futures::task::noop_waker();
+            let mut cx = futures::task::Context::from_waker(&waker);
+            while let Poll::Ready(Some(_)) = drop_stream.as_mut().poll_next(&mut cx) {}
+        }
+
+        assert_eq!(last, Some((2, Some("second"))));
+    }
+
+    #[test]
+    fn last_error_by_projection() {
+        let mut last = None;
+
+        {
+            let last_ref = &mut last;
+            let drop_stream = iter([Ok::<_, std::io::Error>(1)]).on_try_drop_with_error_by(
+                |error| error.kind(),
+                move |c, error| *last_ref = Some((c.reason(), error)),
+            );
+
+            let mut drop_stream = Box::pin(drop_stream);
+
+            let waker = futures::task::noop_waker();
+            let mut cx = futures::task::Context::from_waker(&waker);
+            while let Poll::Ready(Some(_)) = drop_stream.as_mut().poll_next(&mut cx) {}
+        }
+
+        assert_eq!(last, Some((DropReason::Completed, None)));
+    }
+
+    #[test]
+    fn outcome_combinators_fire_for_their_outcome() {
+        let fired = std::cell::RefCell::new(Vec::new());
+
+        let run = |items: Vec<Result<i32, i32>>, take: usize| {
+            let drop_stream = iter(items)
+                .on_ok_drop(|_| fired.borrow_mut().push("ok"))
+                .on_err_drop(|_| fired.borrow_mut().push("err"))
+                .on_cancel_drop(|_| fired.borrow_mut().push("cancel"));
+
+            let mut drop_stream = Box::pin(drop_stream);
+
+            let waker = futures::task::noop_waker();
+            let mut cx = futures::task::Context::from_waker(&waker);
+            for _ in 0..take {
+                let _ = drop_stream.as_mut().poll_next(&mut cx);
+            }
+        };
+
+        run(vec![Ok(1)], 2);
+        assert_eq!(fired.take(), vec!["ok"]);
+
+        run(vec![Ok(1), Err(2)], 3);
+        assert_eq!(fired.take(), vec!["err"]);
+
+        run(vec![Err(1), Ok(2)], 1);
+        assert_eq!(fired.take(), vec!["err"]);
+
+        run(vec![Ok(1), Ok(2)], 1);
+        assert_eq!(fired.take(), vec!["cancel"]);
+    }
+}
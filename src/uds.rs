@@ -0,0 +1,241 @@
+use async_net::unix::{UnixListener, UnixStream};
+use futures_core::{Future, Stream};
+use futures_lite::io::{AsyncBufReadExt, BufReader, Lines};
+use pin_project::pin_project;
+use std::{
+    fmt, io,
+    path::{Path, PathBuf},
+    pin::Pin,
+    task::{Context, Poll},
+};
+
+use crate::{
+    context::{ContextDropFn, ContextDropper},
+    Chunks, ContextDropStream, ShutdownOnDrop,
+};
+
+type Accept = Pin<Box<dyn Future<Output = (UnixListener, io::Result<UnixStream>)> + Send>>;
+
+/// Wraps the lines read from a Unix domain socket, as [`tcp_lines`](crate::tcp_lines) does for
+/// TCP.
+#[track_caller]
+pub fn unix_lines<U: ContextDropFn>(
+    socket: UnixStream,
+    dropper: U,
+) -> ContextDropStream<ShutdownOnDrop<Lines<BufReader<UnixStream>>, UnixStream>, U> {
+    let lines = BufReader::new(socket.clone()).lines();
+
+    ContextDropStream::new(ShutdownOnDrop::new(socket, lines), dropper)
+}
+
+/// Wraps the bytes read from a Unix domain socket, as [`tcp_chunks`](crate::tcp_chunks) does for
+/// TCP.
+#[track_caller]
+pub fn unix_chunks<U: ContextDropFn>(
+    socket: UnixStream,
+    dropper: U,
+) -> ContextDropStream<ShutdownOnDrop<Chunks<UnixStream>, UnixStream>, U> {
+    let chunks = Chunks::new(socket.clone());
+
+    ContextDropStream::new(ShutdownOnDrop::new(socket, chunks), dropper)
+}
+
+/// The connections accepted by a Unix domain socket listener, with a closure that is called with
+/// a [`DropContext`](crate::DropContext) once the stream is dropped.
+///
+/// Every accepted connection counts as an item, and every failed accept as an error. The stream
+/// never ends, so the context always has [`DropReason::Cancelled`](crate::DropReason::Cancelled).
+/// With [`unlink_on_drop`](Self::unlink_on_drop), which [`bind`](Self::bind) sets, the socket file
+/// is also removed once the listener is closed, so the next bind to the same path doesn't fail
+/// with `AddrInUse`. Names, labels and IDs work as for other context wrappers.
+///
+/// Only available on Unix. On Windows, `PipeIncoming` accepts the connections of a named pipe
+/// instead, behind the `named-pipe` feature.
+///
+/// Example
+/// ```
+/// # fn main() -> std::io::Result<()> {
+/// # futures::executor::block_on(async {
+/// use async_net::unix::UnixStream;
+/// use drop_stream::{DropContext, UnixIncoming};
+/// use futures::StreamExt;
+///
+/// let path = std::env::temp_dir().join(format!("drop-stream-doc-{}.sock", std::process::id()));
+/// let mut accepted = 0;
+/// let mut incoming = UnixIncoming::bind(&path, |context: DropContext| accepted = context.items())?;
+///
+/// let _client = UnixStream::connect(&path).await?;
+/// let _server = incoming.next().await.unwrap()?;
+/// drop(incoming);
+///
+/// assert_eq!(accepted, 1);
+/// assert!(!path.exists());
+/// # Ok(())
+/// # })
+/// # }
+/// ```
+#[pin_project]
+pub struct UnixIncoming<U: ContextDropFn> {
+    // Declared before the listener so the closure runs before the listener is closed.
+    dropper: ContextDropper<U>,
+    // Owns the listener between accepts.
+    accept: Accept,
+    // Declared after the listener so the file is only removed once the listener is closed.
+    path: SocketPath,
+}
+
+impl<U: ContextDropFn> UnixIncoming<U> {
+    #[track_caller]
+    pub fn new(listener: UnixListener, dropper: U) -> Self {
+        let path = listener
+            .local_addr()
+            .ok()
+            .and_then(|addr| addr.as_pathname().map(Path::to_path_buf));
+
+        Self {
+            dropper: ContextDropper::new(dropper),
+            accept: accept(listener),
+            path: SocketPath {
+                path,
+                unlink: false,
+            },
+        }
+    }
+
+    /// Binds a listener to `path` and removes the socket file once the stream is dropped.
+    ///
+    /// # Errors
+    ///
+    /// Returns the error from binding the listener, such as if `path` already exists.
+    #[track_caller]
+    pub fn bind(path: impl AsRef<Path>, dropper: U) -> io::Result<Self> {
+        Ok(Self::new(UnixListener::bind(path)?, dropper).unlink_on_drop())
+    }
+
+    /// Removes the socket file once the stream is dropped. Does nothing for an unnamed listener.
+    pub fn unlink_on_drop(mut self) -> Self {
+        self.path.unlink = true;
+        self
+    }
+
+    /// Returns the path the listener is bound to, if it has one.
+    pub fn path(&self) -> Option<&Path> {
+        self.path.path.as_deref()
+    }
+
+    /// Returns the ID of the wrapper, see [`ContextDropStream::id`].
+    pub fn id(&self) -> u64 {
+        self.dropper.stats.id()
+    }
+
+    /// Names the wrapper in its context, replacing any previous name.
+    pub fn name(mut self, name: impl Into<String>) -> Self {
+        self.dropper.stats.set_name(name.into());
+        self
+    }
+
+    /// Adds a label to the wrapper's context.
+    pub fn label(mut self, key: &'static str, value: impl Into<String>) -> Self {
+        self.dropper.stats.add_label(key, value.into());
+        self
+    }
+}
+
+fn accept(listener: UnixListener) -> Accept {
+    Box::pin(async move {
+        let accepted = listener.accept().await.map(|(socket, _)| socket);
+        (listener, accepted)
+    })
+}
+
+impl<U: ContextDropFn> Stream for UnixIncoming<U> {
+    type Item = io::Result<UnixStream>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let this = self.project();
+
+        let Poll::Ready((listener, accepted)) = this.accept.as_mut().poll(cx) else {
+            this.dropper.stats.record_pending();
+            return Poll::Pending;
+        };
+        *this.accept = accept(listener);
+
+        match &accepted {
+            Ok(_) => this.dropper.stats.record_item(),
+            Err(_) => this.dropper.stats.record_error(),
+        }
+        Poll::Ready(Some(accepted))
+    }
+}
+
+impl<U: ContextDropFn> fmt::Debug for UnixIncoming<U> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("UnixIncoming")
+            .field("path", &self.path.path)
+            .field("unlink", &self.path.unlink)
+            .finish_non_exhaustive()
+    }
+}
+
+struct SocketPath {
+    path: Option<PathBuf>,
+    unlink: bool,
+}
+
+impl Drop for SocketPath {
+    fn drop(&mut self) {
+        if let (true, Some(path)) = (self.unlink, &self.path) {
+            // Fails if the file was already removed, which is fine.
+            let _ = std::fs::remove_file(path);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{Arc, Mutex};
+
+    use crate::{unix_lines, DropContext, DropReason, UnixIncoming};
+    use async_net::unix::{UnixListener, UnixStream};
+    use futures::{executor::block_on, AsyncReadExt, AsyncWriteExt, StreamExt};
+
+    fn socket_path(name: &str) -> std::path::PathBuf {
+        std::env::temp_dir().join(format!("drop-stream-{name}-{}.sock", std::process::id()))
+    }
+
+    #[test]
+    fn listener_without_unlink_keeps_socket_file() {
+        let path = socket_path("keep");
+        let _ = std::fs::remove_file(&path);
+
+        let incoming = UnixIncoming::new(UnixListener::bind(&path).unwrap(), |_: DropContext| {});
+        assert_eq!(incoming.path(), Some(path.as_path()));
+        drop(incoming);
+
+        assert!(path.exists());
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn dropped_lines_shut_down_shared_socket() {
+        block_on(async {
+            let (server, mut client) = UnixStream::pair().unwrap();
+            let reason = Arc::new(Mutex::new(None));
+
+            let reason_ref = reason.clone();
+            let _writer = server.clone();
+            let mut lines = unix_lines(server, move |c: DropContext| {
+                *reason_ref.lock().unwrap() = Some(c.reason())
+            });
+
+            client.write_all(b"ping\npong\n").await.unwrap();
+            assert_eq!(lines.next().await.unwrap().unwrap(), "ping");
+            drop(lines);
+
+            let mut rest = Vec::new();
+            client.read_to_end(&mut rest).await.unwrap();
+            assert!(rest.is_empty());
+            assert_eq!(*reason.lock().unwrap(), Some(DropReason::Cancelled));
+        });
+    }
+}
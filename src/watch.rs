@@ -0,0 +1,264 @@
+use futures_core::Stream;
+use notify::{Config, Event, RecommendedWatcher, RecursiveMode, Watcher};
+use std::{
+    collections::VecDeque,
+    fmt,
+    path::{Path, PathBuf},
+    pin::Pin,
+    sync::{Arc, Mutex, MutexGuard},
+    task::{Context, Poll, Waker},
+};
+
+use crate::{ContextDropFn, ContextDropStream};
+
+#[derive(Default)]
+struct State {
+    events: VecDeque<notify::Result<Event>>,
+    waker: Option<Waker>,
+}
+
+#[derive(Default)]
+struct Shared(Mutex<State>);
+
+impl Shared {
+    fn lock(&self) -> MutexGuard<'_, State> {
+        self.0.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// Queues `event` and wakes the stream, called from the watcher thread.
+    fn push(&self, event: notify::Result<Event>) {
+        let waker = {
+            let mut state = self.lock();
+            state.events.push_back(event);
+            state.waker.take()
+        };
+
+        if let Some(waker) = waker {
+            waker.wake();
+        }
+    }
+}
+
+/// Watches `paths` and wraps the stream of their events with a closure that is called with a
+/// [`DropContext`](crate::DropContext) once it is dropped.
+///
+/// This is a shorthand for [`WatchStream::new`] followed by [`WatchStream::watch`] for every path.
+///
+/// # Errors
+///
+/// Returns the error from creating the watcher or watching any of the paths.
+#[track_caller]
+pub fn watch_paths<P: AsRef<Path>, U: ContextDropFn>(
+    paths: impl IntoIterator<Item = P>,
+    mode: RecursiveMode,
+    dropper: U,
+) -> notify::Result<ContextDropStream<WatchStream, U>> {
+    let mut stream = WatchStream::new()?;
+    for path in paths {
+        stream.watch(path.as_ref(), mode)?;
+    }
+
+    Ok(ContextDropStream::new(stream, dropper))
+}
+
+/// A stream of filesystem events from a `notify` watcher that removes its watches and stops the
+/// watcher once it is dropped.
+///
+/// Abandoning a plain channel of events leaves the watcher running, along with the OS watch
+/// descriptors it holds. Here the stream owns the watcher: dropping it removes every watch it
+/// added and then drops the watcher, which shuts its thread down. Events are queued until the
+/// stream is polled, and the stream never ends on its own.
+///
+/// Example
+/// ```
+/// # #[tokio::main(flavor = "current_thread")]
+/// # async fn main() -> notify::Result<()> {
+/// use drop_stream::{watch_paths, DropContext};
+/// use futures::StreamExt;
+/// use notify::RecursiveMode;
+///
+/// let dir = std::env::temp_dir().join(format!("drop-stream-doc-{}", std::process::id()));
+/// std::fs::create_dir_all(&dir)?;
+///
+/// let mut events = watch_paths([&dir], RecursiveMode::NonRecursive, |context: DropContext| {
+///     println!("stopped watching after {} events", context.items());
+/// })?;
+/// std::fs::write(dir.join("config.toml"), "")?;
+/// let event = events.next().await.unwrap()?;
+/// assert!(event.paths.iter().any(|path| path.ends_with("config.toml")));
+///
+/// drop(events);
+/// # std::fs::remove_dir_all(&dir)?;
+/// # Ok(())
+/// # }
+/// ```
+pub struct WatchStream<W: Watcher = RecommendedWatcher> {
+    // Declared before the queue so the watcher is stopped before the queue is dropped.
+    watcher: Watches<W>,
+    shared: Arc<Shared>,
+}
+
+impl WatchStream {
+    /// Creates a stream from the recommended watcher for the platform, without any watches.
+    ///
+    /// # Errors
+    ///
+    /// Returns the error from creating the watcher.
+    pub fn new() -> notify::Result<Self> {
+        Self::with_config(Config::default())
+    }
+}
+
+impl<W: Watcher> WatchStream<W> {
+    /// Creates a stream from a watcher of type `W` with `config`, without any watches.
+    ///
+    /// # Errors
+    ///
+    /// Returns the error from creating the watcher.
+    pub fn with_config(config: Config) -> notify::Result<Self> {
+        let shared = Arc::new(Shared::default());
+        let handler = {
+            let shared = shared.clone();
+            move |event| shared.push(event)
+        };
+
+        Ok(Self {
+            watcher: Watches {
+                watcher: W::new(handler, config)?,
+                paths: Vec::new(),
+            },
+            shared,
+        })
+    }
+
+    /// Starts watching `path`, until it is unwatched or the stream is dropped.
+    ///
+    /// # Errors
+    ///
+    /// Returns the error from the watcher, such as if `path` doesn't exist.
+    pub fn watch(&mut self, path: impl AsRef<Path>, mode: RecursiveMode) -> notify::Result<()> {
+        let path = path.as_ref();
+        self.watcher.watcher.watch(path, mode)?;
+        self.watcher.paths.push(path.to_path_buf());
+        Ok(())
+    }
+
+    /// Stops watching `path`.
+    ///
+    /// # Errors
+    ///
+    /// Returns the error from the watcher, such as if `path` isn't watched.
+    pub fn unwatch(&mut self, path: impl AsRef<Path>) -> notify::Result<()> {
+        let path = path.as_ref();
+        self.watcher.watcher.unwatch(path)?;
+        self.watcher.paths.retain(|watched| watched != path);
+        Ok(())
+    }
+
+    /// Returns the paths that are watched, in the order they were added.
+    pub fn paths(&self) -> &[PathBuf] {
+        &self.watcher.paths
+    }
+}
+
+impl<W: Watcher> Stream for WatchStream<W> {
+    type Item = notify::Result<Event>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let mut state = self.shared.lock();
+        if let Some(event) = state.events.pop_front() {
+            return Poll::Ready(Some(event));
+        }
+
+        state.waker = Some(cx.waker().clone());
+        Poll::Pending
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.shared.lock().events.len(), None)
+    }
+}
+
+impl<W: Watcher> fmt::Debug for WatchStream<W> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("WatchStream")
+            .field("paths", &self.watcher.paths)
+            .field("queued", &self.shared.lock().events.len())
+            .finish()
+    }
+}
+
+struct Watches<W: Watcher> {
+    watcher: W,
+    paths: Vec<PathBuf>,
+}
+
+impl<W: Watcher> Drop for Watches<W> {
+    fn drop(&mut self) {
+        for path in &self.paths {
+            // Fails if the path was removed meanwhile, which already dropped its watch.
+            let _ = self.watcher.unwatch(path);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        path::PathBuf,
+        sync::{Arc, Mutex},
+        time::Duration,
+    };
+
+    use crate::{watch_paths, DropContext, DropReason, WatchStream};
+    use futures::StreamExt;
+    use notify::RecursiveMode;
+
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("drop-stream-{name}-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    #[tokio::test]
+    async fn events_are_yielded_until_dropped() {
+        let dir = temp_dir("watch-events");
+        let context = Arc::new(Mutex::new(None::<DropContext>));
+
+        let context_ref = context.clone();
+        let mut events = watch_paths([&dir], RecursiveMode::NonRecursive, move |c| {
+            *context_ref.lock().unwrap() = Some(c)
+        })
+        .unwrap();
+
+        std::fs::write(dir.join("a"), "").unwrap();
+        let event = tokio::time::timeout(Duration::from_secs(5), events.next())
+            .await
+            .unwrap()
+            .unwrap()
+            .unwrap();
+        assert!(event.paths.iter().any(|path| path.ends_with("a")));
+        drop(events);
+
+        let context = context.lock().unwrap().take().unwrap();
+        assert_eq!(context.reason(), DropReason::Cancelled);
+        assert!(context.items() >= 1);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn unwatched_paths_are_forgotten() {
+        let first = temp_dir("watch-first");
+        let second = temp_dir("watch-second");
+
+        let mut events = WatchStream::new().unwrap();
+        events.watch(&first, RecursiveMode::Recursive).unwrap();
+        events.watch(&second, RecursiveMode::Recursive).unwrap();
+        events.unwatch(&first).unwrap();
+        assert_eq!(events.paths(), std::slice::from_ref(&second));
+
+        drop(events);
+        std::fs::remove_dir_all(&first).unwrap();
+        std::fs::remove_dir_all(&second).unwrap();
+    }
+}